@@ -0,0 +1,62 @@
+//! Hooks for sound. Nothing actually makes noise yet, gameplay just sends out `AudioEvent`s and
+//! whatever `AudioSink` is plugged in gets to decide what to do with them.
+
+use crate::events::AudioEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioKind {
+    Hit,
+    Death,
+    Footstep,
+    /// Only keys get picked up so far.
+    Pickup,
+    /// Nothing levels up yet.
+    LevelUp,
+}
+
+pub trait AudioSink: Send + Sync {
+    fn play(&mut self, event: &AudioEvent);
+}
+
+/// Drops everything on the floor.
+#[derive(Debug, Default)]
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn play(&mut self, _event: &AudioEvent) {}
+}
+
+/// Writes every sound out to the logs instead of playing it, for debugging.
+#[derive(Debug, Default)]
+pub struct LogSink;
+
+impl AudioSink for LogSink {
+    fn play(&mut self, event: &AudioEvent) {
+        tracing::info!(kind = ?event.kind, at = ?event.at, distance = ?event.distance, "Playing sound");
+    }
+}
+
+/// Where sounds go. Lives on its own entity in the world so the sink can be swapped out whenever.
+pub struct AudioOutput {
+    sink: Box<dyn AudioSink>,
+}
+
+impl Default for AudioOutput {
+    fn default() -> Self {
+        Self::new(Box::new(NullSink))
+    }
+}
+
+impl AudioOutput {
+    pub fn new(sink: Box<dyn AudioSink>) -> Self {
+        Self { sink }
+    }
+
+    pub fn set_sink(&mut self, sink: Box<dyn AudioSink>) {
+        self.sink = sink;
+    }
+
+    pub fn play(&mut self, event: &AudioEvent) {
+        self.sink.play(event);
+    }
+}
@@ -0,0 +1,120 @@
+//! Settings that used to be baked in, read from `config.toml` at startup.
+
+use crate::error::{DRError, DRResult};
+use hecs::World;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// Where the config gets read from, next to wherever the game is run.
+pub const CONFIG_PATH: &str = "config.toml";
+
+/// Anything left out of `config.toml` gets its default. Lives on its own entity in the world.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    /// In tiles.
+    pub console_width: u32,
+    /// In tiles.
+    pub console_height: u32,
+    /// How many pixels across a tile is.
+    pub screen_scale: u32,
+    pub max_fps: u32,
+    pub font_path: String,
+    pub window_title: String,
+    pub vsync: bool,
+    /// Gets made if it isn't there yet.
+    pub screenshot_dir: String,
+    /// Where a summary of every run gets written when the player dies. Gets made if it isn't
+    /// there yet.
+    pub morgue_dir: String,
+    /// Where world dumps for debugging go. Gets made if it isn't there yet.
+    pub dump_dir: String,
+    /// How many AI turns get worked through per frame. Anything past that waits for the next
+    /// frame, and the player waits with it.
+    pub max_ai_per_frame: usize,
+    /// How far away, in tiles, the player can hear what they can't see going on.
+    pub hearing_radius: usize,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {
+            console_width: 80,
+            console_height: 45,
+            screen_scale: 8,
+            max_fps: 12,
+            font_path: "terminal_8x8.png".to_string(),
+            window_title: "my roguelike".to_string(),
+            vsync: true,
+            screenshot_dir: "screenshots".to_string(),
+            morgue_dir: "morgue".to_string(),
+            dump_dir: "dumps".to_string(),
+            max_ai_per_frame: 100,
+            hearing_radius: 10,
+        }
+    }
+}
+
+impl GameConfig {
+    /// Falls back to the defaults if there's no file at `path`, but not if it's broken.
+    pub fn load(path: &Path) -> DRResult<GameConfig> {
+        if !path.exists() {
+            tracing::info!(?path, "No config file, using the defaults");
+            return Ok(GameConfig::default());
+        }
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn parse(text: &str) -> DRResult<GameConfig> {
+        toml::from_str(text).map_err(|e| DRError::Config(e.to_string()))
+    }
+
+    /// Whatever's in the world, or the defaults if nothing is.
+    pub fn from_world(world: &World) -> GameConfig {
+        world
+            .query::<&GameConfig>()
+            .iter()
+            .next()
+            .map(|(_id, config)| config.clone())
+            .unwrap_or_default()
+    }
+
+    pub fn screen_width(&self) -> u32 {
+        self.console_width * self.screen_scale
+    }
+
+    pub fn screen_height(&self) -> u32 {
+        self.console_height * self.screen_scale
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_fills_in_defaults() {
+        let config = GameConfig::parse("console_width = 100\nvsync = false\n").unwrap();
+        assert_eq!(config.console_width, 100);
+        assert!(!config.vsync);
+        assert_eq!(config.console_height, GameConfig::default().console_height);
+        assert_eq!(config.screen_width(), 800);
+
+        assert_eq!(GameConfig::parse("").unwrap(), GameConfig::default());
+        assert!(matches!(
+            GameConfig::parse("console_width = \"wide\""),
+            Err(DRError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_world() {
+        let mut world = World::new();
+        assert_eq!(GameConfig::from_world(&world), GameConfig::default());
+        let config = GameConfig {
+            console_width: 120,
+            ..GameConfig::default()
+        };
+        world.spawn((config.clone(),));
+        assert_eq!(GameConfig::from_world(&world), config);
+    }
+}
@@ -1,32 +1,978 @@
-use crate::models::ai::{Ai, Vision};
-use crate::models::stats::Health;
-use crate::models::{Position, Renderable};
-use hecs::World;
+use crate::models::ai::{Ai, Faction, Screamer, Vision, Windup};
+use crate::models::aura::{Aura, AuraEffect};
+use crate::models::effects::{Bleed, SlumberAttack};
+use crate::models::equipment::{DefenseBonus, EquipSlot, Equippable, MeleeBonus};
+use crate::models::input::InputState;
+use crate::models::items::{Chest, Inventory, Item, ItemKind, LootEntry, LootTable};
+use crate::models::light::{LightSource, TORCH_COLOR};
+use crate::models::map::{Fov, Map};
+use crate::models::spawn_table::{SpawnEntry, SpawnTable};
+use crate::models::traps::{Trap, TrapType};
+use crate::models::vendor::Vendor;
+use crate::models::stats::{
+    Attack, DamageKind, Defense, Gold, Health, Knockback, NaturalRegen, PlayerWallet, Regeneration,
+    Resistances, RunStats, Score, Stamina,
+};
+use crate::models::{
+    BlocksTile, Door, DungeonDepth, EntityName, GameRng, Locked, Player, Position, Renderable,
+};
+use hecs::{Entity, EntityBuilder, World};
+use rand::Rng;
+use rand::seq::SliceRandom;
+use std::collections::HashSet;
+
+/// How many monsters get put on each floor before scaling for depth.
+const MONSTERS_PER_FLOOR: usize = 5;
+/// How many hidden traps get put on each floor.
+const TRAPS_PER_FLOOR: usize = 3;
+const SPIKE_TRAP_DAMAGE: i32 = 3;
+const GOBLIN_HEALTH: (u32, u32) = (5, 10);
+const ORC_HEALTH: (u32, u32) = (20, 35);
+const RAT_HEALTH: (u32, u32) = (2, 4);
+const BANSHEE_HEALTH: (u32, u32) = (8, 14);
+const NECROMANCER_HEALTH: (u32, u32) = (10, 16);
+const PALADIN_HEALTH: (u32, u32) = (18, 26);
+/// The chance of a monster dropping gold when it dies, and how much.
+const GOBLIN_GOLD: (f64, u32) = (0.3, 5);
+const ORC_GOLD: (f64, u32) = (0.5, 15);
+const TROLL_GOLD: (f64, u32) = (1.0, 40);
+/// How many rocks the player starts off with.
+pub const STARTING_ROCKS: usize = 3;
+pub const ROCK_DAMAGE: i32 = 3;
+/// How far the player can see.
+const PLAYER_FOV_RADIUS: u32 = 8;
+/// How far the player's torch lights up.
+const PLAYER_LIGHT_RADIUS: usize = 6;
+/// Enough for ten steps of running from full.
+const PLAYER_STAMINA: f32 = 20.0;
+const PLAYER_STAMINA_REGEN: f32 = 1.0;
+
+/// Makes monsters beefier the deeper you go.
+pub fn scale_health_range((min_health, max_health): (u32, u32), depth: u32) -> (u32, u32) {
+    (min_health + depth * 2, max_health + depth * 3)
+}
+
+/// Makes floors more crowded the deeper you go.
+pub fn scale_monster_count(base: usize, depth: u32) -> usize {
+    base + depth as usize / 2
+}
+
+/// How deep the player is. Assumes the first floor if there's no player yet.
+pub fn current_depth(world: &World) -> u32 {
+    world
+        .query::<&DungeonDepth>()
+        .with::<&Player>()
+        .iter()
+        .next()
+        .map(|(_id, depth)| depth.0)
+        .unwrap_or(1)
+}
+
+/// The player's score so far. Nothing if there's no player.
+pub fn current_score(world: &World) -> u32 {
+    world
+        .query::<&Score>()
+        .with::<&Player>()
+        .iter()
+        .next()
+        .map_or(0, |(_id, score)| score.total_score)
+}
+
+/// How the run's gone so far. Empty if nothing's been keeping track.
+pub fn current_run_stats(world: &World) -> RunStats {
+    world
+        .query::<&RunStats>()
+        .iter()
+        .next()
+        .map(|(_id, run_stats)| run_stats.clone())
+        .unwrap_or_default()
+}
+
+pub fn spawn_player(world: &mut World, pos: Position) -> Entity {
+    tracing::debug!(?pos, "Spawning player...");
+    // More than fits in a single tuple.
+    let mut builder = EntityBuilder::new();
+    builder
+        .add(Player {})
+        .add(pos)
+        .add(Renderable {
+            glyph: '@',
+            color: (255, 92, 92, 255),
+            render_order: Renderable::ACTOR_ORDER,
+            tint: None,
+            bg: None,
+        })
+        .add(Health::new(15))
+        .add(NaturalRegen::new(5))
+        .add(InputState::default())
+        .add(EntityName {
+            name: "Adventurer".to_string(),
+        })
+        .add(DungeonDepth(1))
+        .add(BlocksTile)
+        .add(Fov::new(PLAYER_FOV_RADIUS))
+        .add(Inventory {
+            items: vec![
+                ItemKind::ThrowingRock {
+                    damage: ROCK_DAMAGE
+                };
+                STARTING_ROCKS
+            ],
+            equipment: Vec::new(),
+        })
+        .add(PlayerWallet::default())
+        .add(Score::default())
+        .add(Faction::Player)
+        .add(LightSource {
+            radius: PLAYER_LIGHT_RADIUS,
+            color: TORCH_COLOR,
+        })
+        .add(Stamina::new(PLAYER_STAMINA, PLAYER_STAMINA_REGEN));
+    world.spawn(builder.build())
+}
+
+/// Every open floor tile that nothing is standing on yet, shuffled so spawning can take them
+/// off the front. The player's tile is never in there since they block it.
+pub fn open_spawn_tiles(world: &World, map: &Map, rng: &mut impl Rng) -> Vec<Position> {
+    let taken: HashSet<Position> = world
+        .query::<&Position>()
+        .with::<&BlocksTile>()
+        .iter()
+        .map(|(_id, pos)| pos.clone())
+        .collect();
+    let mut tiles: Vec<Position> = (0..map.height)
+        .flat_map(|y| (0..map.width).map(move |x| Position::new(x as isize, y as isize)))
+        .filter(|pos| !map.is_blocked(pos) && !taken.contains(pos))
+        .collect();
+    tiles.shuffle(rng);
+    tiles
+}
+
+fn gold_drop((chance, amount): (f64, u32)) -> LootEntry {
+    LootEntry {
+        item: ItemKind::Gold { amount },
+        chance,
+    }
+}
 
 pub fn spawn_goblin(
     world: &mut World,
     num_goblins: usize,
-    (min_health, max_health): (u32, u32),
-    (map_width, map_height): (usize, usize),
-) {
+    base_health: (u32, u32),
+    map: &Map,
+    rng: &mut impl Rng,
+) -> Vec<Entity> {
+    let depth = current_depth(world);
+    let num_goblins = scale_monster_count(num_goblins, depth);
+    let (min_health, max_health) = scale_health_range(base_health, depth);
     tracing::debug!(?num_goblins, ?min_health, ?max_health, "spawn_goblin");
-    let goblins: Vec<_> = (0..num_goblins)
-        .map(|_| {
-            let ai = Ai::default();
+    let tiles = open_spawn_tiles(world, map, rng);
+    if tiles.len() < num_goblins {
+        tracing::warn!(
+            num_goblins,
+            open_tiles = tiles.len(),
+            "Not enough room for every goblin"
+        );
+    }
+    let goblins: Vec<_> = tiles
+        .into_iter()
+        .take(num_goblins)
+        .map(|pos| {
             let vision = Vision::new(6);
-            let pos = Position::new(
-                (rand::random::<u32>() as usize % map_width) as isize + 1,
-                (rand::random::<u32>() as usize % map_height) as isize + 1,
-            );
-            let health =
-                Health::new(rand::random::<u32>() % (max_health - min_health) + min_health);
+            let ai = Ai::new(pos.clone());
+            let health = Health::new(rng.random_range(min_health..max_health));
+            let loot_table = LootTable {
+                entries: vec![gold_drop(GOBLIN_GOLD)],
+            };
+            let name = EntityName {
+                name: "Goblin".to_string(),
+            };
             let renderable = Renderable {
                 glyph: 'G',
                 color: (92, 255, 92, 255),
+                render_order: Renderable::ACTOR_ORDER,
+                tint: None,
+                bg: None,
             };
-            (ai, pos, health, vision, renderable)
+            (
+                ai,
+                pos,
+                health,
+                vision,
+                loot_table,
+                name,
+                renderable,
+                BlocksTile,
+                Faction::Goblin,
+            )
         })
         .collect();
     tracing::trace!(?goblins);
-    world.spawn_batch(goblins);
+    world.spawn_batch(goblins).collect()
+}
+
+pub fn spawn_goblin_at(world: &mut World, pos: Position, rng: &mut impl Rng) -> Entity {
+    tracing::debug!(?pos, "spawn_goblin_at");
+    let (min_health, max_health) = scale_health_range(GOBLIN_HEALTH, current_depth(world));
+    world.spawn((
+        Ai::new(pos.clone()),
+        pos,
+        Health::new(rng.random_range(min_health..max_health)),
+        Vision::new(6),
+        LootTable {
+            entries: vec![gold_drop(GOBLIN_GOLD)],
+        },
+        EntityName {
+            name: "Goblin".to_string(),
+        },
+        Faction::Goblin,
+        Renderable {
+            glyph: 'G',
+            color: (92, 255, 92, 255),
+            render_order: Renderable::ACTOR_ORDER,
+            tint: None,
+            bg: None,
+        },
+        BlocksTile,
+    ))
+}
+
+/// Weak on their own, but goblins can't leave them alone.
+pub fn spawn_rat_at(world: &mut World, pos: Position, rng: &mut impl Rng) -> Entity {
+    tracing::debug!(?pos, "spawn_rat_at");
+    let (min_health, max_health) = scale_health_range(RAT_HEALTH, current_depth(world));
+    world.spawn((
+        Ai::new(pos.clone()),
+        pos,
+        Health::new(rng.random_range(min_health..max_health)),
+        Vision::new(4),
+        // Something in their bite makes you drowsy.
+        SlumberAttack {
+            chance: 0.2,
+            turns: 3,
+        },
+        EntityName {
+            name: "Rat".to_string(),
+        },
+        Faction::Rat,
+        Renderable {
+            glyph: 'r',
+            color: (160, 120, 80, 255),
+            render_order: Renderable::ACTOR_ORDER,
+            tint: None,
+            bg: None,
+        },
+        BlocksTile,
+    ))
+}
+
+pub fn spawn_orc(world: &mut World, num_orcs: usize, map: &Map, rng: &mut impl Rng) -> Vec<Entity> {
+    tracing::debug!(?num_orcs, "spawn_orc");
+    let tiles = open_spawn_tiles(world, map, rng);
+    if tiles.len() < num_orcs {
+        tracing::warn!(
+            num_orcs,
+            open_tiles = tiles.len(),
+            "Not enough room for every orc"
+        );
+    }
+    tiles
+        .into_iter()
+        .take(num_orcs)
+        .map(|pos| spawn_orc_at(world, pos, rng))
+        .collect()
+}
+
+pub fn spawn_orc_at(world: &mut World, pos: Position, rng: &mut impl Rng) -> Entity {
+    tracing::debug!(?pos, "spawn_orc_at");
+    let (min_health, max_health) = scale_health_range(ORC_HEALTH, current_depth(world));
+    let loot_table = LootTable {
+        entries: vec![
+            LootEntry {
+                item: ItemKind::Sword,
+                chance: 0.2,
+            },
+            gold_drop(ORC_GOLD),
+        ],
+    };
+    world.spawn((
+        Ai::new(pos.clone()),
+        pos,
+        Health::new(rng.random_range(min_health..=max_health)),
+        Attack {
+            damage_min: 3,
+            damage_max: 5,
+        },
+        Defense { value: 2 },
+        Vision::new(5),
+        EntityName {
+            name: "Orc".to_string(),
+        },
+        Faction::Orc,
+        loot_table,
+        Renderable {
+            glyph: 'O',
+            color: (200, 100, 50, 255),
+            render_order: Renderable::ACTOR_ORDER,
+            tint: None,
+            bg: None,
+        },
+        BlocksTile,
+    ))
+}
+
+pub fn spawn_troll(world: &mut World, pos: Position, rng: &mut impl Rng) -> Entity {
+    tracing::debug!(?pos, "spawn_troll");
+    world.spawn((
+        Ai::new(pos.clone()),
+        pos,
+        Health::new(50),
+        Regeneration { hp_per_turn: 2 },
+        Attack {
+            damage_min: 4,
+            damage_max: 8,
+        },
+        Defense { value: 3 },
+        // Big swings, which take a turn to get going.
+        Knockback { tiles: 1 },
+        Windup {
+            turns: 1,
+            warning: "raises its club!".to_string(),
+            interrupt_chance: 0.5,
+        },
+        // Thick skinned, but they go up like kindling.
+        Resistances::new([(DamageKind::Physical, 0.5), (DamageKind::Fire, 2.0)]),
+        // Trolls aren't known for their eyesight.
+        Vision::new(rng.random_range(4..=6)),
+        LootTable {
+            entries: vec![gold_drop(TROLL_GOLD)],
+        },
+        EntityName {
+            name: "Troll".to_string(),
+        },
+        Faction::Troll,
+        Renderable {
+            glyph: 'T',
+            color: (80, 160, 80, 255),
+            render_order: Renderable::ACTOR_ORDER,
+            tint: None,
+            bg: None,
+        },
+        BlocksTile,
+    ))
+}
+
+/// Doesn't hit very hard, but its scream sends everything around it running.
+pub fn spawn_banshee(world: &mut World, pos: Position, rng: &mut impl Rng) -> Entity {
+    tracing::debug!(?pos, "spawn_banshee");
+    let (min_health, max_health) = scale_health_range(BANSHEE_HEALTH, current_depth(world));
+    world.spawn((
+        Ai::new(pos.clone()),
+        pos,
+        Health::new(rng.random_range(min_health..=max_health)),
+        Vision::new(7),
+        Screamer {
+            radius: 5,
+            fear_turns: 4,
+            cooldown: 10,
+            cooldown_remaining: 0,
+        },
+        EntityName {
+            name: "Banshee".to_string(),
+        },
+        Renderable {
+            glyph: 'B',
+            color: (200, 200, 255, 255),
+            render_order: Renderable::ACTOR_ORDER,
+            tint: None,
+            bg: None,
+        },
+        BlocksTile,
+    ))
+}
+
+/// Frail, but the air around it is poison to the player.
+pub fn spawn_necromancer(world: &mut World, pos: Position, rng: &mut impl Rng) -> Entity {
+    tracing::debug!(?pos, "spawn_necromancer");
+    let (min_health, max_health) = scale_health_range(NECROMANCER_HEALTH, current_depth(world));
+    world.spawn((
+        Ai::new(pos.clone()),
+        pos,
+        Health::new(rng.random_range(min_health..=max_health)),
+        Vision::new(7),
+        Aura {
+            effect: AuraEffect::DamageNearby(1, DamageKind::Poison),
+            radius: 3,
+            trigger_on_player_turn: true,
+        },
+        EntityName {
+            name: "Necromancer".to_string(),
+        },
+        Renderable {
+            glyph: 'N',
+            color: (150, 80, 200, 255),
+            render_order: Renderable::ACTOR_ORDER,
+            tint: None,
+            bg: None,
+        },
+        BlocksTile,
+    ))
+}
+
+/// Keeps patching up every monster fighting alongside it, so it's best dealt with first.
+pub fn spawn_paladin(world: &mut World, pos: Position, rng: &mut impl Rng) -> Entity {
+    tracing::debug!(?pos, "spawn_paladin");
+    let (min_health, max_health) = scale_health_range(PALADIN_HEALTH, current_depth(world));
+    world.spawn((
+        Ai::new(pos.clone()),
+        pos,
+        Health::new(rng.random_range(min_health..=max_health)),
+        Defense { value: 2 },
+        Vision::new(6),
+        Aura {
+            effect: AuraEffect::HealNearby(2),
+            radius: 4,
+            trigger_on_player_turn: false,
+        },
+        EntityName {
+            name: "Paladin".to_string(),
+        },
+        Renderable {
+            glyph: 'P',
+            color: (240, 220, 130, 255),
+            render_order: Renderable::ACTOR_ORDER,
+            tint: None,
+            bg: None,
+        },
+        BlocksTile,
+    ))
+}
+
+pub fn spawn_item(world: &mut World, pos: Position, kind: ItemKind) -> Entity {
+    tracing::debug!(?pos, ?kind, "spawn_item");
+    let (name, renderable) = match kind {
+        ItemKind::Sword => (
+            "Sword",
+            Renderable {
+                glyph: '/',
+                color: (192, 192, 192, 255),
+                render_order: Renderable::FLOOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ),
+        ItemKind::Dagger => (
+            "Dagger",
+            Renderable {
+                glyph: '/',
+                color: (160, 160, 200, 255),
+                render_order: Renderable::FLOOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ),
+        ItemKind::Warhammer => (
+            "Warhammer",
+            Renderable {
+                glyph: '/',
+                color: (139, 90, 43, 255),
+                render_order: Renderable::FLOOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ),
+        ItemKind::LeatherArmor => (
+            "Leather armor",
+            Renderable {
+                glyph: '[',
+                color: (150, 100, 50, 255),
+                render_order: Renderable::FLOOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ),
+        ItemKind::ThrowingRock { .. } => (
+            "Rock",
+            Renderable {
+                glyph: '*',
+                color: (160, 140, 120, 255),
+                render_order: Renderable::FLOOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ),
+        ItemKind::Gold { .. } => (
+            "Gold",
+            Renderable {
+                glyph: '$',
+                color: (255, 215, 0, 255),
+                render_order: Renderable::FLOOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ),
+        ItemKind::Bandage => (
+            "Bandage",
+            Renderable {
+                glyph: '!',
+                color: (240, 240, 220, 255),
+                render_order: Renderable::FLOOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ),
+        ItemKind::Key { .. } => (
+            "Key",
+            Renderable {
+                glyph: 'k',
+                color: Door::LOCKED_COLOR,
+                render_order: Renderable::FLOOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ),
+    };
+    let mut builder = EntityBuilder::new();
+    builder.add_bundle((
+        Item,
+        kind,
+        pos,
+        EntityName {
+            name: name.to_string(),
+        },
+        renderable,
+    ));
+    match kind {
+        ItemKind::Sword => {
+            builder.add_bundle((
+                Equippable {
+                    slot: EquipSlot::Weapon,
+                },
+                MeleeBonus(2),
+            ));
+        }
+        ItemKind::Dagger => {
+            builder.add_bundle((
+                Equippable {
+                    slot: EquipSlot::Weapon,
+                },
+                MeleeBonus(1),
+                // Not much behind it, but it leaves a nasty cut.
+                Bleed {
+                    damage_per_stack: 1,
+                    duration_turns: 3,
+                },
+            ));
+        }
+        ItemKind::Warhammer => {
+            builder.add_bundle((
+                Equippable {
+                    slot: EquipSlot::Weapon,
+                },
+                MeleeBonus(3),
+                Knockback { tiles: 2 },
+            ));
+        }
+        ItemKind::LeatherArmor => {
+            builder.add_bundle((
+                Equippable {
+                    slot: EquipSlot::Armor,
+                },
+                DefenseBonus(1),
+            ));
+        }
+        ItemKind::Gold { amount } => {
+            builder.add(Gold { amount });
+        }
+        ItemKind::ThrowingRock { .. } | ItemKind::Key { .. } | ItemKind::Bandage => {}
+    }
+    world.spawn(builder.build())
+}
+
+/// An item that's up for sale, so it isn't anywhere on the map.
+pub fn spawn_item_for_sale(world: &mut World, kind: ItemKind) -> Entity {
+    let item = spawn_item(world, Position::new(0, 0), kind);
+    let _ = world.remove_one::<Position>(item);
+    item
+}
+
+/// Stands still and trades. `inventory` is what's for sale and for how much, see `Vendor`.
+pub fn spawn_merchant(world: &mut World, pos: Position, inventory: Vec<(Entity, u32)>) -> Entity {
+    tracing::debug!(?pos, stock = inventory.len(), "spawn_merchant");
+    world.spawn((
+        pos,
+        EntityName {
+            name: "Merchant".to_string(),
+        },
+        Vendor { inventory },
+        Faction::Neutral,
+        Renderable {
+            glyph: '@',
+            color: (255, 215, 0, 255),
+            render_order: Renderable::ACTOR_ORDER,
+            tint: None,
+            bg: None,
+        },
+        BlocksTile,
+    ))
+}
+
+pub fn spawn_gold(world: &mut World, pos: Position, amount: u32) -> Entity {
+    spawn_item(world, pos, ItemKind::Gold { amount })
+}
+
+/// Hidden traps still get a glyph, it just doesn't get drawn until they're found.
+pub fn spawn_trap(world: &mut World, pos: Position, trap: Trap) -> Entity {
+    tracing::debug!(?pos, ?trap, "spawn_trap");
+    let trap_type = trap.trap_type;
+    let color = match trap_type {
+        TrapType::Spike(_) => (192, 192, 192, 255),
+        TrapType::Poison(..) => (92, 255, 92, 255),
+        TrapType::Teleport => (192, 92, 255, 255),
+        TrapType::Alarm => (255, 255, 92, 255),
+    };
+    let mut name = trap_type.name().to_string();
+    // Same as everything else, "Spike trap" rather than "spike trap".
+    name[..1].make_ascii_uppercase();
+    world.spawn((
+        trap,
+        pos,
+        EntityName { name },
+        Renderable {
+            glyph: '^',
+            color,
+            render_order: Renderable::FLOOR_ORDER,
+            tint: None,
+            bg: None,
+        },
+    ))
+}
+
+pub fn spawn_door(world: &mut World, pos: Position, open: bool) -> Entity {
+    tracing::debug!(?pos, open, "spawn_door");
+    let door = Door { open };
+    let mut builder = EntityBuilder::new();
+    builder
+        .add(pos)
+        .add(EntityName {
+            name: "Door".to_string(),
+        })
+        .add(Renderable {
+            glyph: door.glyph(),
+            color: Door::COLOR,
+            render_order: Renderable::FLOOR_ORDER,
+            tint: None,
+            bg: None,
+        })
+        .add(door);
+    if !open {
+        builder.add(BlocksTile);
+    }
+    world.spawn(builder.build())
+}
+
+/// A closed door that needs the key for `key_id`. Drawn in a different color so it stands out.
+pub fn spawn_locked_door(world: &mut World, pos: Position, key_id: u32) -> Entity {
+    let door = spawn_door(world, pos, false);
+    if let Ok(mut renderable) = world.get::<&mut Renderable>(door) {
+        renderable.color = Door::LOCKED_COLOR;
+    }
+    let _ = world.insert_one(door, Locked { key_id });
+    door
+}
+
+pub fn spawn_chest(
+    world: &mut World,
+    pos: Position,
+    loot_table: LootTable,
+    locked: Option<u32>,
+) -> Entity {
+    tracing::debug!(?pos, ?locked, "spawn_chest");
+    let chest = Chest {
+        loot_table,
+        opened: false,
+        locked,
+    };
+    let color = if locked.is_some() {
+        Door::LOCKED_COLOR
+    } else {
+        (180, 130, 60, 255)
+    };
+    world.spawn((
+        pos,
+        EntityName {
+            name: "Chest".to_string(),
+        },
+        Renderable {
+            glyph: chest.glyph(),
+            color,
+            render_order: Renderable::FLOOR_ORDER,
+            tint: None,
+            bg: None,
+        },
+        chest,
+    ))
+}
+
+/// What goes in the chest at the end of a boss room. Always has something good in it.
+pub fn boss_chest_loot_table() -> LootTable {
+    LootTable {
+        entries: vec![
+            LootEntry::guaranteed(ItemKind::Sword),
+            LootEntry {
+                item: ItemKind::LeatherArmor,
+                chance: 0.5,
+            },
+        ],
+    }
+}
+
+/// The monsters that can show up in the dungeon, and how deep you need to be to see them.
+pub fn default_spawn_table() -> SpawnTable {
+    SpawnTable {
+        entries: vec![
+            SpawnEntry {
+                weight: 80,
+                min_floor: 1,
+                spawn_fn: Box::new(|world: &mut World, pos: Position, rng: &mut GameRng| {
+                    spawn_goblin_at(world, pos, rng)
+                }),
+            },
+            SpawnEntry {
+                weight: 40,
+                min_floor: 3,
+                spawn_fn: Box::new(|world: &mut World, pos: Position, rng: &mut GameRng| {
+                    spawn_rat_at(world, pos, rng)
+                }),
+            },
+            SpawnEntry {
+                weight: 50,
+                min_floor: 3,
+                spawn_fn: Box::new(|world: &mut World, pos: Position, rng: &mut GameRng| {
+                    spawn_orc_at(world, pos, rng)
+                }),
+            },
+            SpawnEntry {
+                weight: 20,
+                min_floor: 6,
+                spawn_fn: Box::new(|world: &mut World, pos: Position, rng: &mut GameRng| {
+                    spawn_troll(world, pos, rng)
+                }),
+            },
+            SpawnEntry {
+                weight: 20,
+                min_floor: 5,
+                spawn_fn: Box::new(|world: &mut World, pos: Position, rng: &mut GameRng| {
+                    spawn_banshee(world, pos, rng)
+                }),
+            },
+            SpawnEntry {
+                weight: 20,
+                min_floor: 4,
+                spawn_fn: Box::new(|world: &mut World, pos: Position, rng: &mut GameRng| {
+                    spawn_necromancer(world, pos, rng)
+                }),
+            },
+            SpawnEntry {
+                weight: 20,
+                min_floor: 5,
+                spawn_fn: Box::new(|world: &mut World, pos: Position, rng: &mut GameRng| {
+                    spawn_paladin(world, pos, rng)
+                }),
+            },
+        ],
+    }
+}
+
+/// Any kind of trap. Spikes can go off again and again, everything else only works once.
+fn random_trap(rng: &mut GameRng) -> Trap {
+    let trap_type = match rng.random_range(0..4) {
+        0 => TrapType::Spike(SPIKE_TRAP_DAMAGE),
+        1 => TrapType::Poison(1, 5),
+        2 => TrapType::Teleport,
+        _ => TrapType::Alarm,
+    };
+    Trap::new(trap_type, true, !matches!(trap_type, TrapType::Spike(_)))
+}
+
+/// Fills a floor of the dungeon with monsters and hidden traps. Deeper floors get nastier things.
+/// Nothing gets put on a wall, on the player, or on top of anything else that was spawned.
+pub fn populate_floor(world: &mut World, floor: u32, map: &Map, rng: &mut GameRng) {
+    tracing::debug!(?floor, "populate_floor");
+    let spawn_table = default_spawn_table();
+    let mut tiles = open_spawn_tiles(world, map, rng).into_iter();
+    let monster_count = scale_monster_count(MONSTERS_PER_FLOOR, floor);
+    for _ in 0..monster_count {
+        let Some(pos) = tiles.next() else {
+            tracing::warn!(?floor, monster_count, "Ran out of room for monsters");
+            return;
+        };
+        match spawn_table.roll(rng, floor) {
+            Some(entry) => {
+                (entry.spawn_fn)(world, pos, rng);
+            }
+            None => tracing::warn!(?floor, "Nothing in the spawn table for this floor"),
+        }
+    }
+    for _ in 0..TRAPS_PER_FLOOR {
+        let Some(pos) = tiles.next() else {
+            tracing::warn!(?floor, "Ran out of room for traps");
+            return;
+        };
+        let trap = random_trap(rng);
+        spawn_trap(world, pos, trap);
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::models::map::TileType;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn test_spawn_orc_has_all_components() {
+        let mut world = World::new();
+        let mut rng = StdRng::seed_from_u64(42);
+        let orcs = spawn_orc(&mut world, 10, &Map::new_bordered(22, 22), &mut rng);
+        assert_eq!(orcs.len(), 10);
+
+        for orc in orcs {
+            let orc = world.entity(orc).expect("Orc should've been spawned.");
+            assert!(orc.has::<Ai>());
+            assert!(orc.has::<Position>());
+            assert!(orc.has::<Attack>());
+            assert!(orc.has::<Defense>());
+            assert!(orc.has::<Vision>());
+            assert!(orc.has::<LootTable>());
+            assert!(orc.has::<Renderable>());
+            assert!(orc.has::<BlocksTile>());
+            assert_eq!(*orc.get::<&Faction>().unwrap(), Faction::Orc);
+            assert_eq!(orc.get::<&EntityName>().unwrap().name, "Orc");
+
+            // No player, so this is the first floor.
+            let (min_health, max_health) = scale_health_range(ORC_HEALTH, 1);
+            let health = orc.get::<&Health>().unwrap();
+            assert!((min_health..=max_health).contains(&health.total_health()));
+        }
+    }
+
+    fn count_named(world: &World, name: &str) -> usize {
+        world
+            .query::<&EntityName>()
+            .iter()
+            .filter(|(_, entity_name)| entity_name.name == name)
+            .count()
+    }
+
+    #[test]
+    fn test_populate_floor_uses_floor_appropriate_monsters() {
+        let mut rng = GameRng::seeded(42);
+        let map = Map::new_bordered(22, 22);
+        for floor in [1, 2] {
+            let mut world = World::new();
+            populate_floor(&mut world, floor, &map, &mut rng);
+            assert_eq!(
+                count_named(&world, "Goblin"),
+                scale_monster_count(MONSTERS_PER_FLOOR, floor)
+            );
+            let mut trap_query = world.query::<&Trap>();
+            let traps: Vec<_> = trap_query.iter().map(|(_id, trap)| trap).collect();
+            assert_eq!(traps.len(), TRAPS_PER_FLOOR);
+            assert!(traps.iter().all(|trap| trap.hidden));
+        }
+
+        // Enough floors that everything in the table should've shown up at least once.
+        let mut world = World::new();
+        for _ in 0..10 {
+            populate_floor(&mut world, 6, &map, &mut rng);
+        }
+        assert_eq!(
+            world.query::<&Ai>().iter().count(),
+            10 * scale_monster_count(MONSTERS_PER_FLOOR, 6)
+        );
+        for name in [
+            "Goblin",
+            "Rat",
+            "Orc",
+            "Troll",
+            "Banshee",
+            "Necromancer",
+            "Paladin",
+        ] {
+            assert!(count_named(&world, name) > 0, "No {name}s were spawned");
+        }
+    }
+
+    #[test]
+    fn test_spawning_on_a_cramped_map_avoids_walls_and_doubling_up() {
+        // A 3x3 room with a pillar in the middle, and the player in a corner.
+        let mut map = Map::new_bordered(5, 5);
+        map.set(&Position::new(2, 2), TileType::Wall);
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(1, 1));
+        let mut rng = GameRng::seeded(7);
+
+        // Only 7 tiles left, so not everyone fits.
+        let goblins = spawn_goblin(&mut world, 10, GOBLIN_HEALTH, &map, &mut rng);
+        assert_eq!(goblins.len(), 7);
+        let mut taken = HashSet::from([Position::new(1, 1)]);
+        for goblin in goblins {
+            assert_ne!(goblin, player);
+            let pos = (*world.get::<&Position>(goblin).unwrap()).clone();
+            assert!(!map.is_blocked(&pos), "Goblin spawned in a wall at {pos:?}");
+            assert!(taken.insert(pos.clone()), "Two things spawned at {pos:?}");
+        }
+
+        // Everything's full now.
+        assert!(spawn_orc(&mut world, 1, &map, &mut rng).is_empty());
+    }
+
+    #[test]
+    fn test_spawning_is_the_same_for_the_same_seed() {
+        let positions = |seed: u64| -> Vec<Position> {
+            let mut world = World::new();
+            let map = Map::new_bordered(12, 12);
+            populate_floor(&mut world, 1, &map, &mut GameRng::seeded(seed));
+            let mut positions: Vec<Position> = world
+                .query::<&Position>()
+                .with::<&Ai>()
+                .iter()
+                .map(|(_id, pos)| pos.clone())
+                .collect();
+            positions.sort_by_key(|pos| (pos.x, pos.y));
+            positions
+        };
+        assert_eq!(positions(3), positions(3));
+    }
+
+    #[test]
+    fn test_deeper_floors_are_harder() {
+        let average = |(min_health, max_health): (u32, u32)| (min_health + max_health) as f64 / 2.0;
+        let shallow = average(scale_health_range(GOBLIN_HEALTH, 1));
+        let deep = average(scale_health_range(GOBLIN_HEALTH, 10));
+        assert!(
+            deep >= shallow * 1.5,
+            "{deep} isn't much more than {shallow}"
+        );
+
+        assert_eq!(
+            scale_monster_count(MONSTERS_PER_FLOOR, 1),
+            MONSTERS_PER_FLOOR
+        );
+        assert!(scale_monster_count(MONSTERS_PER_FLOOR, 10) > MONSTERS_PER_FLOOR);
+    }
+
+    #[test]
+    fn test_spawns_use_players_depth() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(0, 0));
+        world.get::<&mut DungeonDepth>(player).unwrap().0 = 10;
+        let mut rng = GameRng::seeded(42);
+
+        let goblin = spawn_goblin_at(&mut world, Position::new(1, 1), &mut rng);
+        let (min_health, max_health) = scale_health_range(GOBLIN_HEALTH, 10);
+        let health = world.get::<&Health>(goblin).unwrap();
+        assert!((min_health..max_health).contains(&health.total_health()));
+    }
 }
@@ -6,7 +6,15 @@ use hecs::{ComponentError, NoSuchEntity};
 pub enum DRError {
     ComponentMissing(String),
     MissingEntity(String),
-    GameOver,
+    /// Reading or writing a save went wrong.
+    Save(String),
+    /// The save was written by a different version of the game.
+    SaveVersionMismatch {
+        found: u32,
+        expected: u32,
+    },
+    /// `config.toml` couldn't be read.
+    Config(String),
 }
 
 impl Display for DRError {
@@ -29,4 +37,22 @@ impl From<NoSuchEntity> for DRError {
     }
 }
 
+impl From<std::io::Error> for DRError {
+    fn from(err: std::io::Error) -> Self {
+        DRError::Save(err.to_string())
+    }
+}
+
+impl From<bincode::Error> for DRError {
+    fn from(err: bincode::Error) -> Self {
+        DRError::Save(err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for DRError {
+    fn from(err: serde_json::Error) -> Self {
+        DRError::Save(err.to_string())
+    }
+}
+
 pub type DRResult<T> = Result<T, DRError>;
@@ -1,7 +1,286 @@
+use crate::audio::AudioKind;
 use crate::events::Event;
-use hecs::Entity;
+use crate::models::items::ItemKind;
+use crate::models::stats::DamageKind;
+use crate::models::targeting::TargetPurpose;
+use crate::models::{DistanceMetric, EntityName, Position};
+use hecs::{Entity, World};
+use std::f64::consts::TAU;
 
 #[derive(Debug, Clone)]
 pub struct DeadEntity {
     pub entity: Entity,
 }
+
+/// Hits everything with `Health` within `radius` of `center`, going by `metric`, like an explosion.
+/// Gets split up into a `Damage` for each of them.
+#[derive(Debug, Clone)]
+pub struct AoeDamage {
+    /// Whatever set it off, which might get caught up in it too.
+    pub from: Entity,
+    pub center: Position,
+    pub radius: usize,
+    pub amount: i32,
+    pub kind: DamageKind,
+    pub metric: DistanceMetric,
+}
+
+impl AoeDamage {
+    /// Whether `pos` is close enough to `center` to get hit.
+    pub fn reaches(&self, pos: &Position) -> bool {
+        self.metric.is_within(&self.center, pos, self.radius)
+    }
+}
+
+/// Hits everything with `Health` in a cone spreading out from `origin`, like a dragon's breath.
+/// Gets split up into a `Damage` for each of them.
+#[derive(Debug, Clone)]
+pub struct ConeDamage {
+    /// Whatever breathed it out. Never gets caught up in it, since it's standing at the tip.
+    pub from: Entity,
+    pub origin: Position,
+    /// Which way the middle of the cone points, the same way `Position::angle` measures it.
+    pub direction_radians: f64,
+    /// How far off `direction_radians` something can be and still get hit, either way.
+    pub spread_radians: f64,
+    /// How far out the cone goes, by straight line distance.
+    pub range: usize,
+    pub amount: i32,
+    pub kind: DamageKind,
+}
+
+impl ConeDamage {
+    /// Whether `pos` is inside the cone.
+    pub fn reaches(&self, pos: &Position) -> bool {
+        if *pos == self.origin || self.origin.euclidean_distance(pos) > self.range as f64 {
+            return false;
+        }
+        // Goes the short way round, so something just past PI still counts as close to -PI.
+        let off_by = (self.origin.angle(pos) - self.direction_radians).rem_euclid(TAU);
+        off_by.min(TAU - off_by) <= self.spread_radians
+    }
+}
+
+/// Something got shoved from one tile to another by a hit with `Knockback` behind it.
+#[derive(Debug, Clone)]
+pub struct KnockbackOccurred {
+    pub entity: Entity,
+    pub from: Position,
+    pub to: Position,
+}
+
+/// Restores health, capped at the entity's total health.
+#[derive(Debug, Clone)]
+pub struct Heal {
+    pub to: Entity,
+    pub amount: u32,
+}
+
+/// The player's health hit zero.
+#[derive(Debug, Clone)]
+pub struct PlayerDeath {
+    pub player: Entity,
+}
+
+/// The player picked a tile while targeting.
+#[derive(Debug, Clone)]
+pub struct TargetSelected {
+    pub position: Position,
+    pub purpose: TargetPurpose,
+}
+
+/// Something made a noise, see `AudioSink`.
+#[derive(Debug, Clone)]
+pub struct AudioEvent {
+    pub kind: AudioKind,
+    /// Where it came from, if it came from somewhere in particular.
+    pub at: Option<Position>,
+    /// How far it is from the player, so quieter sounds can be played for things far away.
+    /// Filled in when it gets dispatched.
+    pub distance: Option<f64>,
+}
+
+impl AudioEvent {
+    pub fn new(kind: AudioKind, at: Option<Position>) -> Self {
+        AudioEvent {
+            kind,
+            at,
+            distance: None,
+        }
+    }
+}
+
+/// A teleport trap went off under `entity`.
+#[derive(Debug, Clone)]
+pub struct TeleportTrap {
+    pub entity: Entity,
+}
+
+/// Something loud happened at `at`. Monsters within `radius` come to have a look.
+#[derive(Debug, Clone)]
+pub struct NoiseEvent {
+    pub at: Position,
+    pub radius: u32,
+}
+
+/// `from` screamed at `at`. Anything else with a mind to lose within `radius` gets `Feared` for
+/// `fear_turns` turns, and it's loud enough to be heard too.
+#[derive(Debug, Clone)]
+pub struct Scream {
+    pub from: Entity,
+    pub at: Position,
+    pub radius: u32,
+    pub fear_turns: u32,
+}
+
+/// The player spent a turn looking for hidden traps around `around`.
+#[derive(Debug, Clone)]
+pub struct Searched {
+    pub around: Position,
+}
+
+/// `entity` stepped from `from` onto `to`. Teleporting doesn't count.
+#[derive(Debug, Clone)]
+pub struct EntityMoved {
+    pub entity: Entity,
+    pub from: Position,
+    pub to: Position,
+}
+
+/// `wearer` is putting `item` on.
+#[derive(Debug, Clone)]
+pub struct EquipItem {
+    pub wearer: Entity,
+    pub item: Entity,
+}
+
+/// `wearer` is taking off everything they have on.
+#[derive(Debug, Clone)]
+pub struct TakeOffEquipment {
+    pub wearer: Entity,
+}
+
+/// `door` got opened, by the player or by a monster.
+#[derive(Debug, Clone)]
+pub struct DoorOpened {
+    pub door: Entity,
+}
+
+/// `by` used up a key on the locked `door`, which opens it too.
+#[derive(Debug, Clone)]
+pub struct DoorUnlocked {
+    pub door: Entity,
+    pub by: Entity,
+}
+
+/// Someone's shutting the open `door`. Only gets sent once nothing's standing in the doorway.
+#[derive(Debug, Clone)]
+pub struct CloseDoor {
+    pub door: Entity,
+}
+
+/// `by` is pushing `target` one tile straight away from them.
+#[derive(Debug, Clone)]
+pub struct Shove {
+    pub by: Entity,
+    pub target: Entity,
+}
+
+/// `by` is opening `chest`, using up the key for it if it's locked.
+#[derive(Debug, Clone)]
+pub struct ChestOpened {
+    pub chest: Entity,
+    pub by: Entity,
+}
+
+/// `by` picked up `amount` gold off the floor.
+#[derive(Debug, Clone)]
+pub struct GoldCollected {
+    pub by: Entity,
+    pub amount: u32,
+}
+
+/// The player went down the stairs to the next floor.
+#[derive(Debug, Clone)]
+pub struct DescendFloor {
+    pub player: Entity,
+}
+
+/// `user` used up `item`, like by throwing it or unlocking something with it.
+#[derive(Debug, Clone)]
+pub struct UseItem {
+    pub user: Entity,
+    pub item: ItemKind,
+}
+
+/// `buyer` is buying `item` off of `vendor`.
+#[derive(Debug, Clone)]
+pub struct ItemBought {
+    pub buyer: Entity,
+    pub vendor: Entity,
+    pub item: Entity,
+}
+
+/// `seller` is selling `item` to `vendor`.
+#[derive(Debug, Clone)]
+pub struct ItemSold {
+    pub seller: Entity,
+    pub vendor: Entity,
+    pub item: Entity,
+}
+
+/// Everything but `player` is about to get despawned, like when moving on to a new floor. Anything
+/// holding onto entity ids or floor specific state should let go of it.
+#[derive(Debug, Clone)]
+pub struct WorldReset {
+    pub player: Entity,
+}
+
+/// A line for the message log about something going on at `at`, which the player only gets if
+/// they'd know about it. `seen` is the line if it's in sight, `heard` if it's only close enough
+/// to hear.
+#[derive(Debug, Clone)]
+pub struct PerceivedMessage {
+    pub at: Position,
+    pub seen: String,
+    pub heard: String,
+}
+
+/// A line for the message log.
+#[derive(Debug, Clone)]
+pub struct LogMessage {
+    pub text: String,
+}
+
+impl LogMessage {
+    /// Something like "The Goblin attacks Adventurer for 2 damage!"
+    pub fn attack(
+        world: &World,
+        attacker: Option<Entity>,
+        target: Option<Entity>,
+        damage: i32,
+    ) -> Self {
+        LogMessage {
+            text: format!(
+                "The {} attacks {} for {damage} damage!",
+                name_of(world, attacker),
+                name_of(world, target)
+            ),
+        }
+    }
+
+    /// Something like "The Troll raises its club!"
+    pub fn about(world: &World, entity: Entity, what: &str) -> Self {
+        LogMessage {
+            text: format!("The {} {what}", name_of(world, Some(entity))),
+        }
+    }
+}
+
+/// The entity's name, or "something" when we don't know who it was.
+fn name_of(world: &World, entity: Option<Entity>) -> String {
+    entity
+        .and_then(|entity| world.get::<&EntityName>(entity).ok())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| "something".to_string())
+}
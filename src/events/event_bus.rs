@@ -1,9 +1,17 @@
-use crate::events::{Event, EventHandler};
-use hecs::World;
+use crate::events::{Event, EventCtx, EventHandler};
 use std::sync::Arc;
 
+/// Token handed out when subscribing so the handler can be removed later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HandlerId(pub u64);
+
+struct RegisteredHandler<T: Event> {
+    id: HandlerId,
+    handler: Arc<dyn EventHandler<T>>,
+}
+
 pub struct EventBus<T: Event> {
-    handlers: Vec<Arc<dyn EventHandler<T>>>,
+    handlers: Vec<RegisteredHandler<T>>,
 }
 
 impl<T: Event> EventBus<T> {
@@ -13,13 +21,20 @@ impl<T: Event> EventBus<T> {
         }
     }
 
-    pub fn subscribe(&mut self, handler: Arc<dyn EventHandler<T>>) {
-        self.handlers.push(handler);
+    pub fn subscribe(&mut self, id: HandlerId, handler: Arc<dyn EventHandler<T>>) {
+        self.handlers.push(RegisteredHandler { id, handler });
+    }
+
+    /// Returns whether a handler with the given ID was found (and removed).
+    pub fn unsubscribe(&mut self, id: HandlerId) -> bool {
+        let num_handlers = self.handlers.len();
+        self.handlers.retain(|registered| registered.id != id);
+        self.handlers.len() != num_handlers
     }
 
-    pub fn publish(&self, event: &mut T, world: &mut World) {
-        for handler in &self.handlers {
-            handler.handle(event, world);
+    pub fn publish(&self, event: &mut T, ctx: &mut EventCtx) {
+        for registered in &self.handlers {
+            registered.handler.handle(event, ctx);
         }
     }
 }
@@ -1,13 +1,75 @@
-use crate::events::{Event, EventBus, EventHandler};
+use crate::events::{Event, EventBus, EventCtx, EventHandler, HandlerId};
 use hecs::World;
 use std::any::{Any, TypeId};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
 
+/// An event waiting to be dispatched, along with the function that knows what type it really is.
+struct QueuedEvent {
+    type_id: TypeId,
+    event: Box<dyn Any + Send + Sync>,
+    dispatch: fn(&EventBusManager, Box<dyn Any + Send + Sync>, &mut World),
+}
+
+impl QueuedEvent {
+    fn new<T: Event>(event: T) -> Self {
+        Self {
+            type_id: TypeId::of::<T>(),
+            event: Box::new(event),
+            dispatch: EventBusManager::dispatch_boxed::<T>,
+        }
+    }
+}
+
+/// An event held back until a later turn, see `EventBusManager::enqueue_after`.
+struct ScheduledEvent {
+    trigger_at_turn: u64,
+    queued: QueuedEvent,
+}
+
+/// How many times `dispatch_all` goes back for events queued by handlers before giving up.
+/// Only a handler that keeps feeding itself should ever get close.
+const MAX_DISPATCH_ROUNDS: usize = 100;
+
+/// A handle onto the queue that can only add events. Handlers get one of these so they can
+/// chain events without being able to dispatch from inside a dispatch.
+pub struct EventQueue<'a> {
+    queued_events: &'a Mutex<Vec<QueuedEvent>>,
+    scheduled_events: &'a Mutex<Vec<ScheduledEvent>>,
+    current_turn: u64,
+}
+
+impl EventQueue<'_> {
+    pub fn enqueue<T: Event>(&self, event: T) {
+        self.queued_events
+            .lock()
+            .expect("Tried to acquire lock for queued events to enqueue an event.")
+            .push(QueuedEvent::new(event));
+    }
+
+    /// Holds `event` back until `delay_turns` turns from now, like a bomb with a fuse.
+    pub fn enqueue_after<T: Event>(&self, event: T, delay_turns: u64) {
+        self.scheduled_events
+            .lock()
+            .expect("Tried to acquire lock for scheduled events to schedule an event.")
+            .push(ScheduledEvent {
+                trigger_at_turn: self.current_turn + delay_turns,
+                queued: QueuedEvent::new(event),
+            });
+    }
+}
+
 pub struct EventBusManager {
     buses: Mutex<HashMap<TypeId, Box<dyn Any + Send + Sync>>>,
     // world: Mutex<Arc<World>>,
-    queued_events: Mutex<Vec<Box<dyn Event>>>,
+    queued_events: Mutex<Vec<QueuedEvent>>,
+    /// Waiting for their turn to come up before they get queued.
+    scheduled_events: Mutex<Vec<ScheduledEvent>>,
+    /// The turn as of the last `tick_scheduled`, which delays are counted from.
+    current_turn: AtomicU64,
+    /// Hands out the IDs for subscribed handlers, never reused.
+    next_handler_id: AtomicU64,
 }
 
 impl EventBusManager {
@@ -15,6 +77,9 @@ impl EventBusManager {
         Self {
             buses: Mutex::new(HashMap::new()),
             queued_events: Mutex::new(Vec::new()),
+            scheduled_events: Mutex::new(Vec::new()),
+            current_turn: AtomicU64::new(0),
+            next_handler_id: AtomicU64::new(0),
         }
     }
 
@@ -38,12 +103,36 @@ impl EventBusManager {
         }
     }
 
-    /// Subscribe to an event type
-    pub fn subscribe<T: Event>(&self, handler: Arc<dyn EventHandler<T>>) {
+    /// Subscribe to an event type. Keep the returned ID around if the handler needs to be removed later.
+    pub fn subscribe<T: Event>(&self, handler: Arc<dyn EventHandler<T>>) -> HandlerId {
+        let id = HandlerId(self.next_handler_id.fetch_add(1, Ordering::Relaxed));
         let bus = self.get_or_create_bus::<T>();
         bus.lock()
             .expect("Could not establish lock to subscribe to event bus.")
-            .subscribe(handler);
+            .subscribe(id, handler);
+        id
+    }
+
+    /// Removes a previously subscribed handler. Returns whether the handler was found.
+    pub fn unsubscribe<T: Event>(&self, id: HandlerId) -> bool {
+        let bus = match self
+            .buses
+            .lock()
+            .expect("Lock could not be established to unsubscribe from event bus.")
+            .get(&TypeId::of::<T>())
+        {
+            Some(bus_any) => bus_any
+                .downcast_ref::<Arc<Mutex<EventBus<T>>>>()
+                .expect("Could not downcast event bus")
+                .to_owned(),
+            None => return false,
+        };
+        let was_found = bus
+            .lock()
+            .expect("Could not establish lock to unsubscribe from event bus.")
+            .unsubscribe(id);
+        tracing::debug!(?id, ?was_found, "unsubscribe");
+        was_found
     }
 
     /// Publish an event of any type
@@ -58,25 +147,396 @@ impl EventBusManager {
         //     .expect("Could not establish lock to world during post.")
         //     .clone();
         // let world_locked = binding.borrow_mut();
-        bus_locked.publish(&mut event, world);
+        // The queue is never locked while handlers run, so they're free to add to it.
+        let events = self.queue();
+        let mut ctx = EventCtx {
+            world,
+            events: &events,
+        };
+        bus_locked.publish(&mut event, &mut ctx);
     }
+
+    fn queue(&self) -> EventQueue<'_> {
+        EventQueue {
+            queued_events: &self.queued_events,
+            scheduled_events: &self.scheduled_events,
+            current_turn: self.current_turn.load(Ordering::Relaxed),
+        }
+    }
+
     pub fn enqueue<T: Event>(&self, event: T) {
+        self.queue().enqueue(event);
+    }
+
+    /// Holds `event` back until `delay_turns` turns from now, like a bomb with a fuse.
+    pub fn enqueue_after<T: Event>(&self, event: T, delay_turns: u64) {
+        self.queue().enqueue_after(event, delay_turns);
+    }
+
+    /// Queues up everything scheduled for `current_turn` or earlier, in the order they were
+    /// scheduled, to go out with the next dispatch.
+    pub fn tick_scheduled(&self, current_turn: u64) {
+        self.current_turn.store(current_turn, Ordering::Relaxed);
+        let due: Vec<QueuedEvent> = {
+            let mut scheduled_events = self
+                .scheduled_events
+                .lock()
+                .expect("Tried to acquire lock for scheduled events to check on them.");
+            let (due, waiting): (Vec<_>, Vec<_>) = scheduled_events
+                .drain(..)
+                .partition(|scheduled| scheduled.trigger_at_turn <= current_turn);
+            *scheduled_events = waiting;
+            due.into_iter().map(|scheduled| scheduled.queued).collect()
+        };
         self.queued_events
             .lock()
-            .expect("Tried to acquire lock for queued events to enqueue an event.")
-            .push(Box::new(event));
+            .expect("Tried to acquire lock for queued events to add scheduled ones.")
+            .extend(due);
+    }
+
+    /// How many events are being held back for a later turn.
+    pub fn scheduled_len(&self) -> usize {
+        self.scheduled_events
+            .lock()
+            .expect("Tried to acquire lock for scheduled events to count them.")
+            .len()
+    }
+
+    /// Turns a queued event back into its real type so it reaches the right bus.
+    fn dispatch_boxed<T: Event>(&self, event: Box<dyn Any + Send + Sync>, world: &mut World) {
+        match event.downcast::<T>() {
+            Ok(event) => self.post(*event, world),
+            Err(_) => tracing::error!("Queued event was not the type it was queued as!"),
+        }
+    }
+
+    /// How many events of any type are waiting to be dispatched.
+    pub fn queued_len(&self) -> usize {
+        self.queued_events
+            .lock()
+            .expect("Tried to acquire lock for queued events to count them.")
+            .len()
+    }
+
+    /// How many events of type `T` are waiting to be dispatched.
+    pub fn queued_len_of<T: Event>(&self) -> usize {
+        self.queued_events
+            .lock()
+            .expect("Tried to acquire lock for queued events to count them.")
+            .iter()
+            .filter(|queued| queued.type_id == TypeId::of::<T>())
+            .count()
+    }
+
+    /// Dispatches just the queued events of type `T`. Everything else stays queued in the same order.
+    /// Anything the handlers queue up waits for the next dispatch.
+    pub fn dispatch_only<T: Event>(&self, world: &mut World) {
+        let to_dispatch: Vec<QueuedEvent> = {
+            let mut queued_events = self
+                .queued_events
+                .lock()
+                .expect("Tried to acquire lock for queued events to dispatch them.");
+            let (to_dispatch, to_keep): (Vec<_>, Vec<_>) = queued_events
+                .drain(..)
+                .partition(|queued| queued.type_id == TypeId::of::<T>());
+            *queued_events = to_keep;
+            to_dispatch
+        };
+        for queued in to_dispatch {
+            (queued.dispatch)(self, queued.event, world);
+        }
+    }
+
+    /// Throws away every queued event without dispatching them, like when leaving a level.
+    /// Anything scheduled for later goes too.
+    pub fn clear_queue(&self) {
+        let mut queued_events = self
+            .queued_events
+            .lock()
+            .expect("Tried to acquire lock for queued events to clear them.");
+        let mut scheduled_events = self
+            .scheduled_events
+            .lock()
+            .expect("Tried to acquire lock for scheduled events to clear them.");
+        tracing::debug!(
+            discarded = queued_events.len(),
+            discarded_scheduled = scheduled_events.len(),
+            "clear_queue"
+        );
+        queued_events.clear();
+        scheduled_events.clear();
     }
 
+    /// Dispatches everything queued, including whatever the handlers queue up along the way.
     pub fn dispatch_all(&self, world: &mut hecs::World) {
-        let mut queue = self.queued_events.lock().unwrap();
-        for event_box in queue.drain(..) {
-            // We need to downcast by TypeId like before
-            let type_id = (*event_box).type_id();
-            if let Some(bus_any) = self.buses.lock().unwrap().get(&type_id) {
-                // Downcast the event back to the right type
-                // dispatch_event_to_bus(bus_any, event_box, world);
-                self.post(event_box, world);
+        for _ in 0..MAX_DISPATCH_ROUNDS {
+            // Take everything out first so the queue isn't locked while handlers run.
+            let queue: Vec<QueuedEvent> = self
+                .queued_events
+                .lock()
+                .expect("Tried to acquire lock for queued events to dispatch them.")
+                .drain(..)
+                .collect();
+            if queue.is_empty() {
+                return;
             }
+            for queued in queue {
+                (queued.dispatch)(self, queued.event, world);
+            }
+        }
+        tracing::warn!(
+            remaining = self.queued_len(),
+            "Events kept getting queued while dispatching, leaving the rest for next time"
+        );
+    }
+}
+
+mod tests {
+    use super::*;
+
+    struct TestEvent;
+
+    struct NoopHandler;
+
+    impl EventHandler<TestEvent> for NoopHandler {
+        fn handle(&self, _event: &mut TestEvent, _ctx: &mut EventCtx) {}
+    }
+
+    #[test]
+    fn test_unsubscribe() {
+        let manager = EventBusManager::new();
+        let first = manager.subscribe::<TestEvent>(Arc::new(NoopHandler));
+        let second = manager.subscribe::<TestEvent>(Arc::new(NoopHandler));
+        assert_ne!(first, second);
+
+        assert!(manager.unsubscribe::<TestEvent>(first));
+        // Already gone.
+        assert!(!manager.unsubscribe::<TestEvent>(first));
+        assert!(manager.unsubscribe::<TestEvent>(second));
+    }
+
+    #[test]
+    fn test_unsubscribe_unknown_event_type() {
+        let manager = EventBusManager::new();
+        assert!(!manager.unsubscribe::<TestEvent>(HandlerId(42)));
+    }
+
+    struct CountingHandler {
+        count: Arc<AtomicU64>,
+    }
+
+    impl EventHandler<TestEvent> for CountingHandler {
+        fn handle(&self, _event: &mut TestEvent, _ctx: &mut EventCtx) {
+            self.count.fetch_add(1, Ordering::Relaxed);
         }
     }
+
+    #[test]
+    fn test_dispatch_all_reaches_handlers() {
+        let mut world = World::new();
+        let manager = EventBusManager::new();
+        let count = Arc::new(AtomicU64::new(0));
+        manager.subscribe::<TestEvent>(Arc::new(CountingHandler {
+            count: count.clone(),
+        }));
+
+        manager.enqueue(TestEvent);
+        manager.enqueue(TestEvent);
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+
+        manager.dispatch_all(&mut world);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+
+        // Nothing left over.
+        manager.dispatch_all(&mut world);
+        assert_eq!(count.load(Ordering::Relaxed), 2);
+    }
+
+    struct OtherEvent;
+
+    struct ThirdEvent;
+
+    /// Writes down the name of every event it sees, in order.
+    struct RecordingHandler {
+        seen: Arc<Mutex<Vec<&'static str>>>,
+        name: &'static str,
+    }
+
+    impl<T: Event> EventHandler<T> for RecordingHandler {
+        fn handle(&self, _event: &mut T, _ctx: &mut EventCtx) {
+            self.seen.lock().unwrap().push(self.name);
+        }
+    }
+
+    fn recording_manager() -> (EventBusManager, Arc<Mutex<Vec<&'static str>>>) {
+        let manager = EventBusManager::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = |name| {
+            Arc::new(RecordingHandler {
+                seen: seen.clone(),
+                name,
+            })
+        };
+        manager.subscribe::<TestEvent>(recorder("test"));
+        manager.subscribe::<OtherEvent>(recorder("other"));
+        manager.subscribe::<ThirdEvent>(recorder("third"));
+        (manager, seen)
+    }
+
+    #[test]
+    fn test_dispatch_only_keeps_other_events_in_order() {
+        let mut world = World::new();
+        let (manager, seen) = recording_manager();
+        manager.enqueue(TestEvent);
+        manager.enqueue(OtherEvent);
+        manager.enqueue(ThirdEvent);
+        manager.enqueue(OtherEvent);
+        manager.enqueue(TestEvent);
+        manager.enqueue(ThirdEvent);
+        assert_eq!(manager.queued_len(), 6);
+        assert_eq!(manager.queued_len_of::<OtherEvent>(), 2);
+
+        manager.dispatch_only::<OtherEvent>(&mut world);
+        assert_eq!(*seen.lock().unwrap(), vec!["other", "other"]);
+        assert_eq!(manager.queued_len(), 4);
+        assert_eq!(manager.queued_len_of::<OtherEvent>(), 0);
+
+        manager.dispatch_all(&mut world);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec!["other", "other", "test", "third", "test", "third"]
+        );
+    }
+
+    #[test]
+    fn test_clear_queue() {
+        let mut world = World::new();
+        let (manager, seen) = recording_manager();
+        manager.enqueue(TestEvent);
+        manager.enqueue(OtherEvent);
+        manager.enqueue(ThirdEvent);
+
+        manager.clear_queue();
+        assert_eq!(manager.queued_len(), 0);
+
+        manager.dispatch_all(&mut world);
+        assert!(seen.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_scheduled_events_wait_for_their_turn() {
+        let mut world = World::new();
+        let (manager, seen) = recording_manager();
+        manager.tick_scheduled(10);
+        manager.enqueue_after(TestEvent, 3);
+        manager.enqueue_after(OtherEvent, 1);
+        assert_eq!(manager.scheduled_len(), 2);
+        assert_eq!(manager.queued_len(), 0);
+
+        manager.tick_scheduled(10);
+        manager.dispatch_all(&mut world);
+        assert!(seen.lock().unwrap().is_empty());
+
+        manager.tick_scheduled(11);
+        manager.dispatch_all(&mut world);
+        assert_eq!(*seen.lock().unwrap(), vec!["other"]);
+
+        // Skipping past the turn it was meant for still sets it off.
+        manager.tick_scheduled(20);
+        assert_eq!(manager.queued_len(), 1);
+        manager.dispatch_all(&mut world);
+        assert_eq!(*seen.lock().unwrap(), vec!["other", "test"]);
+        assert_eq!(manager.scheduled_len(), 0);
+    }
+
+    /// Lights a two turn fuse on a `ThirdEvent` every time it sees a `TestEvent`.
+    struct FuseHandler;
+
+    impl EventHandler<TestEvent> for FuseHandler {
+        fn handle(&self, _event: &mut TestEvent, ctx: &mut EventCtx) {
+            ctx.events.enqueue_after(ThirdEvent, 2);
+        }
+    }
+
+    #[test]
+    fn test_handlers_can_schedule_events() {
+        let mut world = World::new();
+        let (manager, seen) = recording_manager();
+        manager.subscribe::<TestEvent>(Arc::new(FuseHandler));
+        manager.tick_scheduled(5);
+        manager.enqueue(TestEvent);
+        manager.dispatch_all(&mut world);
+        assert_eq!(*seen.lock().unwrap(), vec!["test"]);
+
+        manager.tick_scheduled(6);
+        manager.dispatch_all(&mut world);
+        assert_eq!(*seen.lock().unwrap(), vec!["test"]);
+
+        manager.tick_scheduled(7);
+        manager.dispatch_all(&mut world);
+        assert_eq!(*seen.lock().unwrap(), vec!["test", "third"]);
+    }
+
+    #[test]
+    fn test_clear_queue_drops_scheduled_events() {
+        let (manager, _seen) = recording_manager();
+        manager.enqueue_after(TestEvent, 2);
+        manager.clear_queue();
+        manager.tick_scheduled(2);
+        assert_eq!(manager.queued_len(), 0);
+    }
+
+    /// Queues up an `OtherEvent` every time it sees a `TestEvent`.
+    struct ChainingHandler;
+
+    impl EventHandler<TestEvent> for ChainingHandler {
+        fn handle(&self, _event: &mut TestEvent, ctx: &mut EventCtx) {
+            ctx.events.enqueue(OtherEvent);
+        }
+    }
+
+    #[test]
+    fn test_dispatch_all_delivers_chained_events() {
+        let mut world = World::new();
+        let (manager, seen) = recording_manager();
+        manager.subscribe::<TestEvent>(Arc::new(ChainingHandler));
+        manager.enqueue(TestEvent);
+        manager.enqueue(ThirdEvent);
+
+        manager.dispatch_all(&mut world);
+        assert_eq!(*seen.lock().unwrap(), vec!["test", "third", "other"]);
+        assert_eq!(manager.queued_len(), 0);
+    }
+
+    #[test]
+    fn test_dispatch_only_leaves_chained_events_queued() {
+        let mut world = World::new();
+        let (manager, seen) = recording_manager();
+        manager.subscribe::<TestEvent>(Arc::new(ChainingHandler));
+        manager.enqueue(TestEvent);
+
+        manager.dispatch_only::<TestEvent>(&mut world);
+        assert_eq!(*seen.lock().unwrap(), vec!["test"]);
+        assert_eq!(manager.queued_len_of::<OtherEvent>(), 1);
+    }
+
+    /// Never stops queueing more of itself.
+    struct RunawayHandler;
+
+    impl EventHandler<TestEvent> for RunawayHandler {
+        fn handle(&self, _event: &mut TestEvent, ctx: &mut EventCtx) {
+            ctx.events.enqueue(TestEvent);
+        }
+    }
+
+    #[test]
+    fn test_dispatch_all_stops_runaway_chains() {
+        let mut world = World::new();
+        let manager = EventBusManager::new();
+        manager.subscribe::<TestEvent>(Arc::new(RunawayHandler));
+        manager.enqueue(TestEvent);
+
+        manager.dispatch_all(&mut world);
+        assert_eq!(manager.queued_len(), 1);
+    }
 }
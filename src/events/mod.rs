@@ -3,14 +3,21 @@ mod event_bus;
 mod event_bus_manager;
 
 pub use crate::events::all_events::*;
-pub use crate::events::event_bus::EventBus;
-pub use crate::events::event_bus_manager::EventBusManager;
+pub use crate::events::event_bus::{EventBus, HandlerId};
+pub use crate::events::event_bus_manager::{EventBusManager, EventQueue};
 use std::any::Any;
 use hecs::World;
 
 pub trait Event: Any + Send + Sync + 'static {}
 impl<T: Any + Send + Sync + 'static> Event for T {}
 
+/// Everything a handler gets while an event is being dispatched.
+pub struct EventCtx<'a> {
+    pub world: &'a mut World,
+    /// Follow up events go here. Handlers can queue more events but can't dispatch them.
+    pub events: &'a EventQueue<'a>,
+}
+
 pub trait EventHandler<T: Event>: Send + Sync {
-    fn handle(&self, event: &mut T, world: &mut World);
+    fn handle(&self, event: &mut T, ctx: &mut EventCtx);
 }
@@ -0,0 +1,224 @@
+//! Describing what's on a tile for the look command.
+
+use crate::models::ai::{Ai, AiState};
+use crate::models::map::{Fov, Map, TileType};
+use crate::models::stats::Health;
+use crate::models::traps::Trap;
+use crate::models::{EntityName, Player, Position, Renderable};
+use hecs::World;
+
+/// One line saying what's on `pos`, like "You see a goblin (wounded, angry) and a rock."
+/// Only works for tiles the player can see right now.
+pub fn describe_tile(world: &World, pos: &Position, fov: &Fov) -> String {
+    if !fov.can_see(pos) {
+        return "You can't see that.".to_string();
+    }
+
+    let mut things: Vec<(i32, String)> = world
+        .query::<(&Position, &EntityName, Option<&Renderable>, Option<&Trap>)>()
+        .iter()
+        .filter(|(_id, (entity_pos, _name, _render, trap))| {
+            *entity_pos == pos && trap.is_none_or(|trap| trap.is_visible())
+        })
+        .map(|(id, (_pos, name, render, _trap))| {
+            let noun = if world.get::<&Player>(id).is_ok() {
+                "yourself".to_string()
+            } else {
+                with_article(&name.name.to_lowercase())
+            };
+            let mut details = Vec::new();
+            if let Ok(health) = world.get::<&Health>(id) {
+                details.push(health_word(&health));
+            }
+            if let Ok(ai) = world.get::<&Ai>(id) {
+                details.push(mood_word(&ai.curr_state));
+            }
+            let description = if details.is_empty() {
+                noun
+            } else {
+                format!("{noun} ({})", details.join(", "))
+            };
+            (render.map_or(0, |render| render.render_order), description)
+        })
+        .collect();
+    // Whoever's standing there first, then whatever's on the floor under them.
+    things.sort_by_key(|(render_order, _description)| -render_order);
+    let things: Vec<String> = things
+        .into_iter()
+        .map(|(_render_order, description)| description)
+        .collect();
+
+    if !things.is_empty() {
+        return format!("You see {}.", join_with_and(&things));
+    }
+    let terrain = world
+        .query::<&Map>()
+        .iter()
+        .next()
+        .and_then(|(_id, map)| match map.get(pos) {
+            Some(TileType::Water) => Some("water"),
+            Some(TileType::Rubble) => Some("rubble"),
+            _ if map.is_blocked(pos) => Some("a wall"),
+            _ => None,
+        });
+    match terrain {
+        Some(terrain) => format!("You see {terrain}."),
+        None => "You see nothing interesting.".to_string(),
+    }
+}
+
+fn with_article(noun: &str) -> String {
+    let article = if noun.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    };
+    format!("{article} {noun}")
+}
+
+fn health_word(health: &Health) -> &'static str {
+    let ratio = health.get_ratio();
+    if ratio >= 1.0 {
+        "unharmed"
+    } else if ratio >= 0.5 {
+        "wounded"
+    } else {
+        "badly wounded"
+    }
+}
+
+fn mood_word(state: &AiState) -> &'static str {
+    match state {
+        AiState::Idling => "calm",
+        AiState::Angry => "angry",
+        AiState::Afraid => "fleeing",
+        AiState::Searching => "searching",
+    }
+}
+
+/// "a", "a and b", "a, b, and c".
+fn join_with_and(things: &[String]) -> String {
+    match things {
+        [] => String::new(),
+        [only] => only.clone(),
+        [first, second] => format!("{first} and {second}"),
+        [rest @ .., last] => format!("{}, and {last}", rest.join(", ")),
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::entities::{spawn_item, spawn_player};
+    use crate::models::items::ItemKind;
+    use std::collections::HashSet;
+
+    fn goblin(world: &mut World, pos: Position, current_health: i32, state: AiState) {
+        let mut health = Health::new(10);
+        health.apply_damage(10 - current_health);
+        let mut ai = Ai::new(pos.clone());
+        ai.curr_state = state;
+        world.spawn((
+            pos,
+            EntityName {
+                name: "Goblin".to_string(),
+            },
+            health,
+            ai,
+            Renderable {
+                glyph: 'g',
+                color: (0, 200, 0, 255),
+                render_order: Renderable::ACTOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ));
+    }
+
+    #[test]
+    fn test_describe_single_monster() {
+        let mut world = World::new();
+        let fov = Fov::new(8);
+        goblin(&mut world, Position::new(1, 1), 10, AiState::Idling);
+        goblin(&mut world, Position::new(2, 1), 6, AiState::Angry);
+        goblin(&mut world, Position::new(3, 1), 2, AiState::Afraid);
+
+        assert_eq!(
+            describe_tile(&world, &Position::new(1, 1), &fov),
+            "You see a goblin (unharmed, calm)."
+        );
+        assert_eq!(
+            describe_tile(&world, &Position::new(2, 1), &fov),
+            "You see a goblin (wounded, angry)."
+        );
+        assert_eq!(
+            describe_tile(&world, &Position::new(3, 1), &fov),
+            "You see a goblin (badly wounded, fleeing)."
+        );
+    }
+
+    #[test]
+    fn test_describe_monster_standing_on_items() {
+        let mut world = World::new();
+        let fov = Fov::new(8);
+        let pos = Position::new(4, 4);
+        spawn_item(&mut world, pos.clone(), ItemKind::Sword);
+        goblin(&mut world, pos.clone(), 10, AiState::Idling);
+        assert_eq!(
+            describe_tile(&world, &pos, &fov),
+            "You see a goblin (unharmed, calm) and a sword."
+        );
+
+        spawn_item(
+            &mut world,
+            pos.clone(),
+            ItemKind::ThrowingRock { damage: 3 },
+        );
+        assert_eq!(
+            describe_tile(&world, &pos, &fov),
+            "You see a goblin (unharmed, calm), a sword, and a rock."
+        );
+    }
+
+    #[test]
+    fn test_describe_player_and_empty_tiles() {
+        let mut world = World::new();
+        let fov = Fov::new(8);
+        spawn_player(&mut world, Position::new(5, 5));
+        let mut map = Map::new_bordered(10, 10);
+        map.set(&Position::new(4, 4), TileType::Water);
+        world.spawn((map,));
+
+        assert_eq!(
+            describe_tile(&world, &Position::new(5, 5), &fov),
+            "You see yourself (unharmed)."
+        );
+        assert_eq!(
+            describe_tile(&world, &Position::new(0, 0), &fov),
+            "You see a wall."
+        );
+        assert_eq!(
+            describe_tile(&world, &Position::new(4, 4), &fov),
+            "You see water."
+        );
+        assert_eq!(
+            describe_tile(&world, &Position::new(3, 3), &fov),
+            "You see nothing interesting."
+        );
+    }
+
+    #[test]
+    fn test_describe_out_of_sight() {
+        let mut world = World::new();
+        let mut fov = Fov::new(8);
+        fov.update(
+            &Position::new(5, 5),
+            0,
+            HashSet::from([Position::new(5, 5)]),
+        );
+        goblin(&mut world, Position::new(6, 5), 10, AiState::Angry);
+        assert_eq!(
+            describe_tile(&world, &Position::new(6, 5), &fov),
+            "You can't see that."
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use crate::models::Position;
+use std::collections::HashSet;
+
+/// How each octant's rows and columns map back onto the world, as (xx, xy, yx, yy).
+const OCTANTS: [(isize, isize, isize, isize); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+/// Everything that can be seen from `origin` out to `radius` tiles, using recursive shadowcasting.
+/// Opaque tiles are visible themselves, they just hide whatever is behind them.
+pub fn compute_fov(
+    origin: &Position,
+    radius: u32,
+    is_opaque: impl Fn(&Position) -> bool,
+) -> HashSet<Position> {
+    let mut visible = HashSet::new();
+    visible.insert(origin.clone());
+    for transform in OCTANTS {
+        let octant = Octant {
+            origin,
+            radius: radius as isize,
+            transform,
+            is_opaque: &is_opaque,
+        };
+        octant.cast_light(1, 1.0, 0.0, &mut visible);
+    }
+    tracing::trace!(?origin, radius, visible = visible.len(), "compute_fov");
+    visible
+}
+
+/// One of the eight slices of the circle around `origin` that get scanned separately.
+struct Octant<'a, F> {
+    origin: &'a Position,
+    radius: isize,
+    /// How its rows and columns map back onto the world, as (xx, xy, yx, yy).
+    transform: (isize, isize, isize, isize),
+    is_opaque: &'a F,
+}
+
+impl<F: Fn(&Position) -> bool> Octant<'_, F> {
+    /// Scans a row at a time from `row` out, starting over past every wall with a narrower slope.
+    fn cast_light(
+        &self,
+        row: isize,
+        mut start_slope: f64,
+        end_slope: f64,
+        visible: &mut HashSet<Position>,
+    ) {
+        if start_slope < end_slope {
+            return;
+        }
+        let (xx, xy, yx, yy) = self.transform;
+        let origin = self.origin;
+        let radius = self.radius;
+        let mut next_start_slope = start_slope;
+        for distance in row..=radius {
+            let dy = -distance;
+            let mut blocked = false;
+            for dx in -distance..=0 {
+                let left_slope = (dx as f64 - 0.5) / (dy as f64 + 0.5);
+                let right_slope = (dx as f64 + 0.5) / (dy as f64 - 0.5);
+                if start_slope < right_slope {
+                    continue;
+                } else if end_slope > left_slope {
+                    break;
+                }
+
+                let pos = Position::new(origin.x + dx * xx + dy * xy, origin.y + dx * yx + dy * yy);
+                if dx * dx + dy * dy <= radius * radius {
+                    visible.insert(pos.clone());
+                }
+
+                if blocked {
+                    if (self.is_opaque)(&pos) {
+                        next_start_slope = right_slope;
+                    } else {
+                        blocked = false;
+                        start_slope = next_start_slope;
+                    }
+                } else if (self.is_opaque)(&pos) && distance < radius {
+                    blocked = true;
+                    self.cast_light(distance + 1, start_slope, left_slope, visible);
+                    next_start_slope = right_slope;
+                }
+            }
+            if blocked {
+                break;
+            }
+        }
+    }
+}
+
+/// Every tile on the straight line from `from` to `to`, both ends included. Bresenham's line.
+pub fn line(from: &Position, to: &Position) -> Vec<Position> {
+    let (dx, dy) = ((to.x - from.x).abs(), -(to.y - from.y).abs());
+    let (step_x, step_y) = ((to.x - from.x).signum(), (to.y - from.y).signum());
+    let mut error = dx + dy;
+    let mut current = from.clone();
+    let mut points = vec![current.clone()];
+    while current != *to {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            current.x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            current.y += step_y;
+        }
+        points.push(current.clone());
+    }
+    points
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_open_field_sees_everything_in_radius() {
+        let origin = Position::new(10, 10);
+        let visible = compute_fov(&origin, 3, |_| false);
+        assert!(visible.contains(&origin));
+        assert!(visible.contains(&Position::new(13, 10)));
+        assert!(visible.contains(&Position::new(8, 12)));
+        assert!(!visible.contains(&Position::new(14, 10)));
+        // sqrt(18) is past 3.
+        assert!(!visible.contains(&Position::new(13, 13)));
+    }
+
+    #[test]
+    fn test_walls_cast_shadows() {
+        let origin = Position::new(5, 5);
+        let wall = Position::new(7, 5);
+        let visible = compute_fov(&origin, 6, |pos| *pos == wall);
+        assert!(visible.contains(&wall));
+        assert!(!visible.contains(&Position::new(8, 5)));
+        assert!(!visible.contains(&Position::new(10, 5)));
+        // Off to the side of the shadow is still fine.
+        assert!(visible.contains(&Position::new(8, 7)));
+        // Nothing on the other side of the player is in the way.
+        assert!(visible.contains(&Position::new(1, 5)));
+    }
+
+    #[test]
+    fn test_line() {
+        let from = Position::new(1, 1);
+        assert_eq!(line(&from, &from), vec![from.clone()]);
+        assert_eq!(
+            line(&from, &Position::new(4, 1)),
+            vec![
+                Position::new(1, 1),
+                Position::new(2, 1),
+                Position::new(3, 1),
+                Position::new(4, 1)
+            ]
+        );
+        assert_eq!(
+            line(&from, &Position::new(3, 3)),
+            vec![
+                Position::new(1, 1),
+                Position::new(2, 2),
+                Position::new(3, 3)
+            ]
+        );
+        let steep = line(&Position::new(5, 5), &Position::new(4, 1));
+        assert_eq!(steep.len(), 5);
+        assert_eq!(steep.last(), Some(&Position::new(4, 1)));
+    }
+}
@@ -0,0 +1,147 @@
+use crate::models::Position;
+use doryen_rs::InputApi;
+use std::cell::RefCell;
+use std::collections::HashSet;
+
+/// Somewhere keys and clicks come from, so systems don't have to care if there's a window.
+pub trait InputSource {
+    /// Whether the key is being held down.
+    fn key(&self, name: &str) -> bool;
+    /// Whether the key went down this frame.
+    fn key_pressed(&self, name: &str) -> bool;
+    /// Whether anything at all went down this frame.
+    fn any_key_pressed(&self) -> bool;
+    /// The tile that got left clicked this frame, if any.
+    fn clicked_tile(&self) -> Option<Position> {
+        None
+    }
+}
+
+/// The real input from the doryen window.
+pub struct DoryenInput<'a> {
+    // Doryen wants a mutable borrow for some of its lookups.
+    input: RefCell<&'a mut dyn InputApi>,
+}
+
+impl<'a> DoryenInput<'a> {
+    pub fn new(input: &'a mut dyn InputApi) -> Self {
+        Self {
+            input: RefCell::new(input),
+        }
+    }
+}
+
+impl InputSource for DoryenInput<'_> {
+    fn key(&self, name: &str) -> bool {
+        self.input.borrow_mut().key(name)
+    }
+
+    fn key_pressed(&self, name: &str) -> bool {
+        self.input.borrow_mut().key_pressed(name)
+    }
+
+    fn any_key_pressed(&self) -> bool {
+        self.input.borrow().keys_pressed().next().is_some()
+    }
+
+    fn clicked_tile(&self) -> Option<Position> {
+        let mut input = self.input.borrow_mut();
+        if !input.mouse_button_pressed(0) {
+            return None;
+        }
+        let (mouse_x, mouse_y) = input.mouse_pos();
+        Some(Position::new(mouse_x as isize, mouse_y as isize))
+    }
+}
+
+/// Input that's whatever the test says it is.
+#[derive(Debug, Default)]
+pub struct MockInput {
+    pub keys_down: HashSet<String>,
+    pub keys_pressed: HashSet<String>,
+    pub clicked_tile: Option<Position>,
+}
+
+impl MockInput {
+    /// Input where `name` was just pressed and is still held down.
+    pub fn pressing(name: &str) -> Self {
+        Self {
+            keys_down: HashSet::from([name.to_string()]),
+            keys_pressed: HashSet::from([name.to_string()]),
+            clicked_tile: None,
+        }
+    }
+}
+
+impl InputSource for MockInput {
+    fn key(&self, name: &str) -> bool {
+        self.keys_down.contains(name)
+    }
+
+    fn key_pressed(&self, name: &str) -> bool {
+        self.keys_pressed.contains(name)
+    }
+
+    fn any_key_pressed(&self) -> bool {
+        !self.keys_pressed.is_empty()
+    }
+
+    fn clicked_tile(&self) -> Option<Position> {
+        self.clicked_tile.clone()
+    }
+}
+
+/// Typing initials in for the high score table, one key press at a time.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NameEntry {
+    name: String,
+}
+
+impl NameEntry {
+    pub const LENGTH: usize = 3;
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Takes whatever letters got pressed this frame, and Backspace takes the last one back off.
+    /// Returns true once Enter gets pressed with the whole name typed in.
+    pub fn update(&mut self, input: &dyn InputSource) -> bool {
+        if input.key_pressed("Backspace") {
+            self.name.pop();
+        }
+        for letter in 'A'..='Z' {
+            if self.name.len() < Self::LENGTH && input.key_pressed(&format!("Key{letter}")) {
+                self.name.push(letter);
+            }
+        }
+        self.name.len() == Self::LENGTH && input.key_pressed("Enter")
+    }
+
+    /// The name so far with blanks for what's left, like "AB_".
+    pub fn prompt(&self) -> String {
+        format!("{:_<width$}", self.name, width = Self::LENGTH)
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_name_entry_takes_three_letters() {
+        let mut name_entry = NameEntry::default();
+        assert_eq!(name_entry.prompt(), "___");
+        assert!(!name_entry.update(&MockInput::pressing("KeyA")));
+        // Not done until all three are in.
+        assert!(!name_entry.update(&MockInput::pressing("Enter")));
+        name_entry.update(&MockInput::pressing("KeyB"));
+        assert_eq!(name_entry.prompt(), "AB_");
+        name_entry.update(&MockInput::pressing("Backspace"));
+        name_entry.update(&MockInput::pressing("KeyZ"));
+        name_entry.update(&MockInput::pressing("KeyQ"));
+        // Anything past three is ignored.
+        name_entry.update(&MockInput::pressing("KeyX"));
+        assert_eq!(name_entry.name(), "AZQ");
+        assert!(name_entry.update(&MockInput::pressing("Enter")));
+    }
+}
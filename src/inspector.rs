@@ -0,0 +1,217 @@
+//! Dumping the whole world out as text for debugging, and seeing what changed between dumps.
+
+use crate::error::DRResult;
+use hecs::{Component, Entity, World};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Turns one kind of component on an entity into a line of text, if the entity has one.
+type Describer = Box<dyn Fn(&World, Entity) -> Option<String>>;
+
+/// Knows how to write out whichever components got registered with it. Anything else on an
+/// entity gets left out of the dump.
+#[derive(Default)]
+pub struct WorldInspector {
+    describers: Vec<(String, Describer)>,
+}
+
+impl WorldInspector {
+    /// Has `T` show up in dumps as `name: <whatever describe says>`.
+    pub fn register<T: Component>(&mut self, name: &str, describe: fn(&T) -> String) {
+        self.describers.push((
+            name.to_string(),
+            Box::new(move |world: &World, entity: Entity| {
+                world
+                    .get::<&T>(entity)
+                    .ok()
+                    .map(|component| describe(&component))
+            }),
+        ));
+    }
+
+    /// Every entity with anything registered on it, in id order, with one indented line per
+    /// component.
+    pub fn dump(&self, world: &World) -> String {
+        let mut entities: Vec<Entity> = world.iter().map(|entity| entity.entity()).collect();
+        entities.sort_by_key(|entity| entity.id());
+        let mut text = String::new();
+        for entity in entities {
+            let lines: Vec<String> = self
+                .describers
+                .iter()
+                .filter_map(|(name, describe)| {
+                    describe(world, entity).map(|described| format!("  {name}: {described}"))
+                })
+                .collect();
+            if lines.is_empty() {
+                continue;
+            }
+            text.push_str(&format!("Entity {}\n", entity.id()));
+            for line in lines {
+                text.push_str(&line);
+                text.push('\n');
+            }
+        }
+        text
+    }
+}
+
+/// Splits a dump back up into each entity's components.
+fn parse_dump(dump: &str) -> BTreeMap<&str, BTreeMap<&str, &str>> {
+    let mut entities: BTreeMap<&str, BTreeMap<&str, &str>> = BTreeMap::new();
+    let mut current = None;
+    for line in dump.lines() {
+        match line.strip_prefix("  ") {
+            Some(component) => {
+                let (name, described) = component.split_once(": ").unwrap_or((component, ""));
+                if let Some(entity) = current {
+                    entities.entry(entity).or_default().insert(name, described);
+                }
+            }
+            None => {
+                current = Some(line);
+                entities.entry(line).or_default();
+            }
+        }
+    }
+    entities
+}
+
+/// What changed between two dumps, one line per component: `+` for new, `-` for gone, and `~`
+/// for anything that's different now.
+pub fn diff(old: &str, new: &str) -> String {
+    let old = parse_dump(old);
+    let new = parse_dump(new);
+    let no_components = BTreeMap::new();
+    let mut entities: Vec<&str> = old.keys().chain(new.keys()).copied().collect();
+    entities.sort_by_key(|entity| (entity_id(entity), *entity));
+    entities.dedup();
+
+    let mut text = String::new();
+    for entity in entities {
+        let before = old.get(entity).unwrap_or(&no_components);
+        let after = new.get(entity).unwrap_or(&no_components);
+        for (name, was) in before {
+            match after.get(name) {
+                None => text.push_str(&format!("- {entity}: {name}: {was}\n")),
+                Some(now) if now != was => {
+                    text.push_str(&format!("~ {entity}: {name}: {was} -> {now}\n"))
+                }
+                Some(_same) => {}
+            }
+        }
+        for (name, now) in after {
+            if !before.contains_key(name) {
+                text.push_str(&format!("+ {entity}: {name}: {now}\n"));
+            }
+        }
+    }
+    text
+}
+
+/// The number out of an `Entity 12` line, so entities sort the way they do in the dump.
+fn entity_id(entity: &str) -> u32 {
+    entity
+        .strip_prefix("Entity ")
+        .and_then(|id| id.parse().ok())
+        .unwrap_or(u32::MAX)
+}
+
+pub fn dump_file_name(timestamp: u64, index: u32) -> String {
+    format!("world_{timestamp}_{index:03}.txt")
+}
+
+/// Writes `dump` into a new file in `dir`, with whatever changed since the last one after it.
+pub fn write_dump(
+    dir: &Path,
+    timestamp: u64,
+    index: u32,
+    dump: &str,
+    changes: Option<&str>,
+) -> DRResult<PathBuf> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(dump_file_name(timestamp, index));
+    let mut text = dump.to_string();
+    if let Some(changes) = changes {
+        text.push_str("\nChanges since the last dump:\n");
+        text.push_str(changes);
+    }
+    std::fs::write(&path, text)?;
+    tracing::info!(?path, "Dumped the world");
+    Ok(path)
+}
+
+mod tests {
+    use super::*;
+    use crate::models::stats::Health;
+    use crate::models::{EntityName, Position};
+
+    fn inspector() -> WorldInspector {
+        let mut inspector = WorldInspector::default();
+        inspector.register::<EntityName>("Name", |name| name.name.clone());
+        inspector.register::<Position>("Position", |pos| format!("({}, {})", pos.x, pos.y));
+        inspector.register::<Health>("Health", |health| {
+            format!("{}/{}", health.current_health(), health.total_health())
+        });
+        inspector
+    }
+
+    #[test]
+    fn test_dump() {
+        let mut world = World::new();
+        world.spawn((
+            EntityName {
+                name: "Goblin".to_string(),
+            },
+            Position::new(3, 4),
+            Health::new(10),
+        ));
+        // Nothing registered, so it's left out.
+        world.spawn((5_u32,));
+        world.spawn((Position::new(1, 1),));
+
+        assert_eq!(
+            inspector().dump(&world),
+            concat!(
+                "Entity 0\n",
+                "  Name: Goblin\n",
+                "  Position: (3, 4)\n",
+                "  Health: 10/10\n",
+                "Entity 2\n",
+                "  Position: (1, 1)\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_diff_shows_what_changed() {
+        let mut world = World::new();
+        let goblin = world.spawn((Position::new(3, 4), Health::new(10)));
+        world.spawn((Position::new(1, 1),));
+        let inspector = inspector();
+        let before = inspector.dump(&world);
+
+        world.get::<&mut Health>(goblin).unwrap().apply_damage(4);
+        let after = inspector.dump(&world);
+
+        assert_eq!(diff(&before, &after), "~ Entity 0: Health: 10/10 -> 6/10\n");
+        assert_eq!(diff(&after, &after), "");
+    }
+
+    #[test]
+    fn test_diff_shows_added_and_removed() {
+        let mut world = World::new();
+        let goblin = world.spawn((Position::new(3, 4), Health::new(10)));
+        let inspector = inspector();
+        let before = inspector.dump(&world);
+
+        world.remove_one::<Health>(goblin).unwrap();
+        world.spawn((Position::new(1, 1),));
+        let after = inspector.dump(&world);
+
+        assert_eq!(
+            diff(&before, &after),
+            "- Entity 0: Health: 10/10\n+ Entity 1: Position: (1, 1)\n"
+        );
+    }
+}
@@ -0,0 +1,266 @@
+//! Things that should always be true about the world, so anything that breaks them gets noticed
+//! right away instead of a few floors later.
+
+use crate::models::ai::{Ai, Vision};
+use crate::models::input::InputState;
+use crate::models::map::Map;
+use crate::models::stats::Health;
+use crate::models::{BlocksTile, Player, Position};
+use hecs::{Entity, World};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// Which rule got broken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invariant {
+    /// Only one thing that blocks movement on each tile.
+    OneBlockerPerTile,
+    /// Anything with an `Ai` has a `Position`, `Health` and `Vision` to go with it.
+    CompleteAi,
+    /// There's exactly one player.
+    OnePlayer,
+    /// The player has an `InputState` and `Health`.
+    CompletePlayer,
+    /// Everything with a `Position` is on the map.
+    InBounds,
+    /// No `Health` starts out with nothing in it.
+    NonZeroHealth,
+}
+
+/// One broken rule, and what broke it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvariantViolation {
+    pub invariant: Invariant,
+    /// `None` when it's not down to any one entity, like when there's no player at all.
+    pub entity: Option<Entity>,
+    pub description: String,
+}
+
+impl InvariantViolation {
+    fn new(invariant: Invariant, entity: Option<Entity>, description: String) -> Self {
+        Self {
+            invariant,
+            entity,
+            description,
+        }
+    }
+}
+
+impl Display for InvariantViolation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.entity {
+            Some(entity) => write!(
+                f,
+                "{:?} (entity {}): {}",
+                self.invariant,
+                entity.id(),
+                self.description
+            ),
+            None => write!(f, "{:?}: {}", self.invariant, self.description),
+        }
+    }
+}
+
+/// Every rule the world is breaking right now. Empty if everything's fine.
+pub fn check_world(world: &World) -> Vec<InvariantViolation> {
+    let mut violations = Vec::new();
+    check_blockers(world, &mut violations);
+    check_ais(world, &mut violations);
+    check_player(world, &mut violations);
+    check_bounds(world, &mut violations);
+    check_health(world, &mut violations);
+    violations
+}
+
+fn check_blockers(world: &World, violations: &mut Vec<InvariantViolation>) {
+    let mut blockers: HashMap<Position, Entity> = HashMap::new();
+    for (id, pos) in world.query::<&Position>().with::<&BlocksTile>().iter() {
+        if let Some(other) = blockers.insert(pos.clone(), id) {
+            violations.push(InvariantViolation::new(
+                Invariant::OneBlockerPerTile,
+                Some(id),
+                format!("Is on {pos:?} along with entity {}", other.id()),
+            ));
+        }
+    }
+}
+
+fn check_ais(world: &World, violations: &mut Vec<InvariantViolation>) {
+    for (id, _ai) in world.query::<&Ai>().iter() {
+        let Ok(entity) = world.entity(id) else {
+            continue;
+        };
+        let missing: Vec<&str> = [
+            ("Position", entity.has::<Position>()),
+            ("Health", entity.has::<Health>()),
+            ("Vision", entity.has::<Vision>()),
+        ]
+        .into_iter()
+        .filter(|(_name, has)| !has)
+        .map(|(name, _has)| name)
+        .collect();
+        if !missing.is_empty() {
+            violations.push(InvariantViolation::new(
+                Invariant::CompleteAi,
+                Some(id),
+                format!("Has an Ai but no {}", missing.join(" or ")),
+            ));
+        }
+    }
+}
+
+fn check_player(world: &World, violations: &mut Vec<InvariantViolation>) {
+    let players: Vec<Entity> = world
+        .query::<&Player>()
+        .iter()
+        .map(|(id, _player)| id)
+        .collect();
+    if players.len() != 1 {
+        violations.push(InvariantViolation::new(
+            Invariant::OnePlayer,
+            players.get(1).copied(),
+            format!("There are {} players", players.len()),
+        ));
+    }
+    for player in players {
+        let Ok(entity) = world.entity(player) else {
+            continue;
+        };
+        if !entity.has::<InputState>() || !entity.has::<Health>() {
+            violations.push(InvariantViolation::new(
+                Invariant::CompletePlayer,
+                Some(player),
+                "The player needs an InputState and Health".to_string(),
+            ));
+        }
+    }
+}
+
+fn check_bounds(world: &World, violations: &mut Vec<InvariantViolation>) {
+    let mut map_query = world.query::<&Map>();
+    let Some((_id, map)) = map_query.iter().next() else {
+        return;
+    };
+    for (id, pos) in world.query::<&Position>().iter() {
+        if map.get(pos).is_none() {
+            violations.push(InvariantViolation::new(
+                Invariant::InBounds,
+                Some(id),
+                format!("Is at {pos:?}, off the {}x{} map", map.width, map.height),
+            ));
+        }
+    }
+}
+
+fn check_health(world: &World, violations: &mut Vec<InvariantViolation>) {
+    for (id, health) in world.query::<&Health>().iter() {
+        if health.total_health() == 0 {
+            violations.push(InvariantViolation::new(
+                Invariant::NonZeroHealth,
+                Some(id),
+                "Has a total health of 0".to_string(),
+            ));
+        }
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::entities::{spawn_goblin_at, spawn_item, spawn_player};
+    use crate::models::GameRng;
+    use crate::models::items::ItemKind;
+
+    /// A small floor with nothing wrong with it, plus the player and the goblin in it.
+    fn sound_world() -> (World, Entity, Entity) {
+        let mut world = World::new();
+        world.spawn((Map::new_bordered(10, 10),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let goblin = spawn_goblin_at(&mut world, Position::new(3, 3), &mut GameRng::seeded(3));
+        (world, player, goblin)
+    }
+
+    fn broken_rules(world: &World) -> Vec<Invariant> {
+        check_world(world)
+            .into_iter()
+            .map(|violation| violation.invariant)
+            .collect()
+    }
+
+    #[test]
+    fn test_sound_world_has_no_violations() {
+        let (world, _player, _goblin) = sound_world();
+        assert_eq!(check_world(&world), vec![]);
+    }
+
+    #[test]
+    fn test_two_blockers_on_one_tile() {
+        let (mut world, _player, goblin) = sound_world();
+        let other = world.spawn((Position::new(3, 3), BlocksTile));
+        let violations = check_world(&world);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].invariant, Invariant::OneBlockerPerTile);
+        assert!([Some(goblin), Some(other)].contains(&violations[0].entity));
+    }
+
+    #[test]
+    fn test_ai_without_vision() {
+        let (mut world, _player, goblin) = sound_world();
+        world.remove_one::<Vision>(goblin).unwrap();
+        let violations = check_world(&world);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].invariant, Invariant::CompleteAi);
+        assert_eq!(violations[0].entity, Some(goblin));
+        assert_eq!(violations[0].description, "Has an Ai but no Vision");
+    }
+
+    #[test]
+    fn test_wrong_number_of_players() {
+        let (mut world, player, _goblin) = sound_world();
+        let second = spawn_player(&mut world, Position::new(6, 6));
+        let violations = check_world(&world);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].invariant, Invariant::OnePlayer);
+        assert!([Some(player), Some(second)].contains(&violations[0].entity));
+
+        world.despawn(player).unwrap();
+        world.despawn(second).unwrap();
+        assert_eq!(broken_rules(&world), vec![Invariant::OnePlayer]);
+    }
+
+    #[test]
+    fn test_player_without_input() {
+        let (mut world, player, _goblin) = sound_world();
+        world.remove_one::<InputState>(player).unwrap();
+        let violations = check_world(&world);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].invariant, Invariant::CompletePlayer);
+        assert_eq!(violations[0].entity, Some(player));
+    }
+
+    #[test]
+    fn test_off_the_map() {
+        let (mut world, _player, _goblin) = sound_world();
+        let item = spawn_item(&mut world, Position::new(10, 3), ItemKind::Dagger);
+        let violations = check_world(&world);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].invariant, Invariant::InBounds);
+        assert_eq!(violations[0].entity, Some(item));
+    }
+
+    #[test]
+    fn test_zero_total_health() {
+        let (mut world, _player, _goblin) = sound_world();
+        let husk = world.spawn((Position::new(2, 2), Health::new(0)));
+        let violations = check_world(&world);
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].invariant, Invariant::NonZeroHealth);
+        assert_eq!(violations[0].entity, Some(husk));
+        assert_eq!(
+            violations[0].to_string(),
+            format!(
+                "NonZeroHealth (entity {}): Has a total health of 0",
+                husk.id()
+            )
+        );
+    }
+}
@@ -1,18 +1,69 @@
+mod audio;
+mod config;
 mod entities;
 mod error;
 mod events;
+mod examine;
+mod fov;
+mod input_source;
+mod inspector;
+mod invariants;
 mod models;
+mod pathfinding;
+mod persistence;
+mod profiler;
+mod renderer;
+mod screenshot;
+mod simulation;
 mod systems;
 
-use crate::entities::spawn_goblin;
-use crate::events::{Event, EventBusManager, EventHandler};
-use crate::models::input::InputState;
-use crate::models::stats::Health;
-use crate::models::{Player, Position, Renderable};
-use crate::systems::{AiSystem, DamageSystem, DeadCollector, InputSystem, SystemFunc};
+use crate::audio::AudioOutput;
+use crate::config::{CONFIG_PATH, GameConfig};
+use crate::entities::{
+    current_depth, current_run_stats, current_score, populate_floor, spawn_item,
+    spawn_item_for_sale, spawn_merchant, spawn_player,
+};
+use crate::events::{Event, EventHandler};
+use crate::input_source::{DoryenInput, InputSource, NameEntry};
+use crate::inspector::{WorldInspector, diff, write_dump};
+use crate::models::ai::{Ai, Faction, Screamer, Telegraph, Vision, Windup};
+use crate::models::animation::{Animation, Blink, RenderPosition};
+use crate::models::aura::Aura;
+use crate::models::effects::{
+    Bleed, Bleeding, Blinded, Burning, BurningTiles, Confused, Effects, Feared, Sleeping, Slowed,
+    SlumberAttack,
+};
+use crate::models::equipment::{DefenseBonus, Equippable, Equipped, MeleeBonus};
+use crate::models::items::{Chest, Inventory, Item, ItemKind, LootTable};
+use crate::models::light::{AmbientLight, DUNGEON_AMBIENT_LIGHT, LightSource};
+use crate::models::map::Map;
+use crate::models::minimap::Minimap;
+use crate::models::overlay::TileOverlay;
+use crate::models::running::Running;
+use crate::models::scheduler::TurnCounter;
+use crate::models::stats::{
+    Attack, Defense, EffectiveStats, EntitySpeed, Gold, Health, Knockback, NaturalRegen,
+    PlayerWallet, Regeneration, RegenerationSuppressed, Resistances, RunStats, Score, Stamina,
+    StatModifiers,
+};
+use crate::models::traps::Trap;
+use crate::models::vendor::Vendor;
+use crate::models::{
+    BlocksTile, Door, DungeonDepth, EngineRequests, EntityName, GameLog, GameRng, Locked, Player,
+    Position, Renderable, RunState,
+};
+use crate::persistence::{
+    HighScore, high_scores_path, load_scores, record_score, save_scores, write_morgue,
+};
+use crate::renderer::{
+    DoryenRenderer, Renderer, draw_aura_outlines, draw_class_select_screen, draw_death_screen,
+    draw_paused_overlay, draw_title_screen, draw_victory_screen, draw_world,
+};
+use crate::screenshot::{next_screenshot_path, timestamp};
+use crate::simulation::{SYSTEMS_PER_PAGE, Simulation};
 use doryen_rs::{App, AppOptions, DoryenApi, Engine, UpdateEvent};
-use hecs::World;
 use std::cell::RefCell;
+use std::path::Path;
 use std::sync::Arc;
 use tracing::log::{Level, LevelFilter};
 use tracing_subscriber::field::MakeExt;
@@ -33,15 +84,66 @@ Apart from the basic real-time walking, this example shows how screenshots can b
 Because it uses UpdateEvent, any combination of keys can be specified to activate it.
 */
 
-const CONSOLE_WIDTH: u32 = 80;
-const CONSOLE_HEIGHT: u32 = 45;
+/// The keys that toggle systems on and off while the debug overlay is up, in system order on
+/// whichever page is showing.
+const SYSTEM_TOGGLE_KEYS: [&str; SYSTEMS_PER_PAGE] = [
+    "Digit1", "Digit2", "Digit3", "Digit4", "Digit5", "Digit6", "Digit7", "Digit8", "Digit9",
+];
+/// Flips the debug overlay over to the next page of systems.
+const NEXT_SYSTEM_PAGE_KEY: &str = "Digit0";
 
 // type System = Box<dyn FnMut(&mut World)>;
 
+/// Which screen the game is on. Only `Playing` runs the simulation.
+#[derive(Debug, Clone, PartialEq)]
+enum GameState {
+    MainMenu,
+    ClassSelect,
+    Playing,
+    /// Still draws the world, just doesn't move it along.
+    Paused,
+    GameOver {
+        cause: String,
+    },
+    /// Nothing gets here yet, there's no way to win.
+    Victory,
+}
+
+impl GameState {
+    fn can_become(&self, next: &GameState) -> bool {
+        use GameState::*;
+        matches!(
+            (self, next),
+            (MainMenu, ClassSelect)
+                | (ClassSelect, MainMenu | Playing)
+                | (Playing, Paused | GameOver { .. } | Victory)
+                | (Paused, Playing | MainMenu)
+                | (GameOver { .. }, Playing | MainMenu)
+                | (Victory, MainMenu)
+        )
+    }
+}
+
 struct MyRoguelike {
-    world: World,
-    systems: Vec<Box<dyn SystemFunc>>,
-    event_bus_manager: EventBusManager,
+    simulation: Simulation,
+    game_state: RefCell<GameState>,
+    config: GameConfig,
+    /// Goes up with every screenshot so they never share a name.
+    screenshot_index: u32,
+    inspector: WorldInspector,
+    /// The last world dump, for showing what changed in the next one.
+    last_world_dump: Option<String>,
+    /// Goes up with every world dump so they never share a name.
+    dump_index: u32,
+    /// Handed back to doryen at the end of `update`.
+    pending_update_event: Option<UpdateEvent>,
+    /// What the current run was started from, so it ends up next to its high score.
+    seed: u64,
+    /// Set while the player's typing their initials in after dying.
+    name_entry: Option<NameEntry>,
+    high_scores: Vec<HighScore>,
+    /// Which page of systems the debug overlay is showing.
+    system_page: usize,
 }
 
 impl Engine for MyRoguelike {
@@ -50,97 +152,424 @@ impl Engine for MyRoguelike {
         api.con().register_color("white", (255, 255, 255, 255));
         api.con().register_color("red", (255, 92, 92, 255));
         api.con().register_color("blue", (192, 192, 255, 255));
-
-        let player_entity = (
-            Player {},
-            Position::new((CONSOLE_WIDTH / 2) as isize, (CONSOLE_HEIGHT / 2) as isize),
-            Renderable {
-                glyph: '@',
-                color: (255, 92, 92, 255),
-            },
-            Health::new(15),
-            InputState::default(),
-        );
-
-        tracing::debug!(?player_entity, "Spawning player...");
-        self.world.spawn(player_entity);
-
-        tracing::debug!("Spawning goblins...");
-        spawn_goblin(
-            &mut self.world,
-            5,
-            (5, 10),
-            (CONSOLE_WIDTH as usize - 2, CONSOLE_HEIGHT as usize - 2),
-        );
-
-        tracing::info!("Initializing all ECS systems...");
-        for system in self.systems.iter_mut() {
-            tracing::debug!("Initializing {}...", system.get_name());
-            system.init(&mut self.world, &mut self.event_bus_manager);
-        }
     }
     fn update(&mut self, api: &mut dyn DoryenApi) -> Option<UpdateEvent> {
-        // capture the screen
-        // if input.key("ControlLeft") && input.key_pressed("KeyS") {
-        //     self.screenshot_idx += 1;
-        //     return Some(UpdateEvent::Capture(format!(
-        //         "screenshot_{:03}.png",
-        //         self.screenshot_idx
-        //     )));
-        // }
-
         // let api = Arc::new(RefCell::new(api));
 
         // let world = Arc::new(&mut self.world);
 
-        tracing::trace!("Processing systems...");
-        for system in &mut self.systems {
-            tracing::trace!("Updating {}...", system.get_name());
-            if let Err(e) = system.call(&mut self.world, api, &mut self.event_bus_manager) {
-                tracing::error!("Got error while running system {e:?}");
+        let input = DoryenInput::new(api.input());
+        let game_state = self.game_state.borrow().clone();
+        match game_state {
+            GameState::MainMenu => {
+                if input.key_pressed("Enter") {
+                    self.to_class_select();
+                } else if input.key_pressed("Escape") {
+                    return Some(UpdateEvent::Exit);
+                }
+            }
+            GameState::ClassSelect => {
+                if input.key_pressed("Enter") {
+                    self.new_run(rand::random());
+                    self.to_playing();
+                } else if input.key_pressed("Escape") {
+                    self.to_main_menu();
+                }
+            }
+            GameState::Playing => {
+                if input.key_pressed("KeyP") {
+                    self.to_paused();
+                    return None;
+                }
+                if input.key_pressed("F3") {
+                    self.simulation.profiler.show_overlay = !self.simulation.profiler.show_overlay;
+                }
+                if self.simulation.profiler.show_overlay {
+                    if input.key_pressed(NEXT_SYSTEM_PAGE_KEY) {
+                        self.system_page = (self.system_page + 1) % self.simulation.system_pages();
+                    }
+                    for (i, key) in SYSTEM_TOGGLE_KEYS.iter().enumerate() {
+                        if input.key_pressed(key) {
+                            self.simulation
+                                .toggle_system(self.system_page * SYSTEMS_PER_PAGE + i);
+                        }
+                    }
+                }
+
+                self.simulation.tick(&input);
+                self.handle_screenshot_request();
+                self.handle_world_dump_request();
+                if self.simulation.run_state() == RunState::GameOver {
+                    let depth = current_depth(&self.simulation.world);
+                    let score = current_score(&self.simulation.world);
+                    tracing::info!(depth, score, "Run over");
+                    let cause = format!("Killed on depth {depth} with a score of {score}.");
+                    self.write_morgue_file(&cause);
+                    self.to_game_over(cause);
+                    self.name_entry = Some(NameEntry::default());
+                }
+                // sleep(Duration::from_millis(250));
+            }
+            GameState::Paused => {
+                if input.key_pressed("KeyP") {
+                    self.to_playing();
+                } else if input.key_pressed("Escape") {
+                    self.to_main_menu();
+                }
+            }
+            GameState::GameOver { .. } => {
+                if let Some(name_entry) = &mut self.name_entry {
+                    if input.key_pressed("Escape") {
+                        self.name_entry = None;
+                    } else if name_entry.update(&input) {
+                        let name = name_entry.name().to_string();
+                        self.name_entry = None;
+                        self.submit_high_score(name);
+                    }
+                } else if input.key_pressed("KeyR") {
+                    self.restart(rand::random());
+                } else if input.key_pressed("Escape") {
+                    return Some(UpdateEvent::Exit);
+                }
+            }
+            GameState::Victory => {
+                if input.key_pressed("Enter") {
+                    self.to_main_menu();
+                }
             }
         }
-        // Process all events that the systems queued up to be processed.
-        self.event_bus_manager.dispatch_all(&mut self.world);
-        // sleep(Duration::from_millis(250));
 
-        None
+        self.pending_update_event.take()
     }
     fn render(&mut self, api: &mut dyn DoryenApi) {
         tracing::trace!("Rendering Roguelike...");
-        let con = api.con();
-        con.clear(
-            Some((128, 128, 128, 255)),
-            Some((0, 0, 0, 255)),
-            Some('.' as u16),
-        );
-        // con.ascii(self.player_pos.0, self.player_pos.1, '@' as u16);
-        // con.fore(self.player_pos.0, self.player_pos.1, (255, 255, 255, 255));
-
-        for (_id, (pos, render)) in self.world.query::<(&Position, &Renderable)>().iter() {
-            con.ascii(pos.x as i32, pos.y as i32, render.glyph as u16);
-            con.fore(pos.x as i32, pos.y as i32, render.color);
+        let mut renderer = DoryenRenderer::new(api.con());
+        match &*self.game_state.borrow() {
+            GameState::MainMenu => draw_title_screen(&mut renderer, &self.high_scores),
+            GameState::ClassSelect => draw_class_select_screen(&mut renderer),
+            GameState::Playing => self.draw_playing(&mut renderer),
+            GameState::Paused => {
+                self.draw_playing(&mut renderer);
+                draw_paused_overlay(&mut renderer);
+            }
+            GameState::GameOver { cause } => draw_death_screen(
+                &mut renderer,
+                cause,
+                &current_run_stats(&self.simulation.world).to_lines(),
+                self.name_entry.as_ref().map(NameEntry::prompt).as_deref(),
+            ),
+            GameState::Victory => draw_victory_screen(&mut renderer),
         }
     }
 }
 
 impl MyRoguelike {
-    pub fn new() -> Self {
-        let world = World::new();
-        let event_bus_manager = EventBusManager::new();
-        event_bus_manager.subscribe(Arc::new(DeadCollector::default()));
+    pub fn new(config: &GameConfig) -> Self {
         Self {
-            world,
-            systems: vec![
-                Box::new(InputSystem::default()),
-                Box::new(AiSystem::new()),
-                Box::new(DamageSystem::default()),
-            ],
-            event_bus_manager,
+            simulation: Simulation::new(),
+            game_state: RefCell::new(GameState::MainMenu),
+            config: config.clone(),
+            screenshot_index: 0,
+            inspector: world_inspector(),
+            last_world_dump: None,
+            dump_index: 0,
+            pending_update_event: None,
+            seed: 0,
+            name_entry: None,
+            high_scores: high_scores_path()
+                .map(|path| load_scores(&path))
+                .unwrap_or_default(),
+            system_page: 0,
+        }
+    }
+
+    /// Throws away whatever run was going on and sets up a fresh first floor, events and all.
+    fn new_run(&mut self, seed: u64) {
+        self.simulation = Simulation::new();
+        self.seed = seed;
+        self.name_entry = None;
+        let world = &mut self.simulation.world;
+        let (width, height) = (self.config.console_width, self.config.console_height);
+        let start = Position::new((width / 2) as isize, (height / 2) as isize);
+        spawn_player(world, start.clone());
+        // Something to put on right away.
+        spawn_item(world, start.new_from_dx_dy(1, 0), ItemKind::Dagger);
+        spawn_item(world, start.new_from_dx_dy(-1, 0), ItemKind::LeatherArmor);
+        // And somewhere to spend whatever gold turns up.
+        let stock = [ItemKind::Sword, ItemKind::Warhammer, ItemKind::LeatherArmor]
+            .into_iter()
+            .map(|kind| (spawn_item_for_sale(world, kind), kind.value()))
+            .collect();
+        spawn_merchant(world, start.new_from_dx_dy(0, -3), stock);
+
+        let mut rng = GameRng::seeded(seed);
+
+        let map = Map::new_bordered(width as usize, height as usize);
+        tracing::debug!("Spawning monsters...");
+        populate_floor(world, 1, &map, &mut rng);
+        world.spawn((rng,));
+        world.spawn((GameLog::default(),));
+        world.spawn((RunState::default(),));
+        world.spawn((RunStats::default(),));
+        world.spawn((AudioOutput::default(),));
+        world.spawn((map,));
+        world.spawn((AmbientLight(DUNGEON_AMBIENT_LIGHT),));
+        world.spawn((self.config.clone(),));
+        world.spawn((EngineRequests::default(),));
+
+        self.simulation.init();
+    }
+
+    /// Takes a screenshot if the simulation asked for one. Doryen does the actual capture once
+    /// `update` hands it the event.
+    fn handle_screenshot_request(&mut self) {
+        let world = &mut self.simulation.world;
+        let Some((_id, requests)) = world.query_mut::<&mut EngineRequests>().into_iter().next()
+        else {
+            return;
+        };
+        if !std::mem::take(&mut requests.screenshot) {
+            return;
+        }
+
+        let dir = Path::new(&self.config.screenshot_dir);
+        let text = match std::fs::create_dir_all(dir) {
+            Ok(()) => {
+                let path =
+                    next_screenshot_path(dir, timestamp(), &mut self.screenshot_index, |path| {
+                        path.exists()
+                    });
+                let text = format!("Saved a screenshot to {}.", path.display());
+                self.pending_update_event =
+                    Some(UpdateEvent::Capture(path.to_string_lossy().into_owned()));
+                text
+            }
+            Err(e) => {
+                tracing::warn!(
+                    ?dir,
+                    "Could not make the screenshot folder due to error {e}"
+                );
+                format!("Couldn't take a screenshot: {e}")
+            }
+        };
+        match world.query_mut::<&mut GameLog>().into_iter().next() {
+            Some((_id, game_log)) => game_log.push(text),
+            None => tracing::warn!("No game log to write {text:?} to"),
+        }
+    }
+
+    /// Writes the world out to the dump folder if the simulation asked for it, along with what's
+    /// changed since the last time.
+    fn handle_world_dump_request(&mut self) {
+        let world = &mut self.simulation.world;
+        let Some((_id, requests)) = world.query_mut::<&mut EngineRequests>().into_iter().next()
+        else {
+            return;
+        };
+        if !std::mem::take(&mut requests.world_dump) {
+            return;
+        }
+
+        let dump = self.inspector.dump(world);
+        let changes = self.last_world_dump.as_deref().map(|last| diff(last, &dump));
+        let dir = Path::new(&self.config.dump_dir);
+        let text = match write_dump(dir, timestamp(), self.dump_index, &dump, changes.as_deref()) {
+            Ok(path) => format!("Dumped the world to {}.", path.display()),
+            Err(e) => {
+                tracing::warn!(?dir, "Could not dump the world due to error {e}");
+                format!("Couldn't dump the world: {e}")
+            }
+        };
+        self.dump_index += 1;
+        self.last_world_dump = Some(dump);
+        match world.query_mut::<&mut GameLog>().into_iter().next() {
+            Some((_id, game_log)) => game_log.push(text),
+            None => tracing::warn!("No game log to write {text:?} to"),
+        }
+    }
+
+    /// Leaves a summary of the run that just ended in the morgue folder.
+    fn write_morgue_file(&self, cause: &str) {
+        let run_stats = current_run_stats(&self.simulation.world);
+        let dir = Path::new(&self.config.morgue_dir);
+        match write_morgue(dir, timestamp(), cause, &run_stats) {
+            Ok(_path) => {}
+            Err(e) => tracing::warn!(?dir, "Could not write morgue file due to error {e}"),
+        }
+    }
+
+    /// Puts the run that just ended on the high score table under `name`, if it's good enough.
+    fn submit_high_score(&mut self, name: String) -> Option<usize> {
+        let world = &self.simulation.world;
+        let turns_survived = world
+            .query::<&Score>()
+            .with::<&Player>()
+            .iter()
+            .next()
+            .map_or(0, |(_id, score)| score.turns_survived);
+        let entry = HighScore {
+            name,
+            score: current_score(world),
+            deepest_floor: current_depth(world),
+            turns_survived,
+            seed: self.seed,
+        };
+        tracing::info!(?entry, "Recording high score");
+        let rank = record_score(&mut self.high_scores, entry);
+        if rank.is_some() {
+            match high_scores_path().and_then(|path| save_scores(&path, &self.high_scores)) {
+                Ok(()) => {}
+                Err(e) => tracing::warn!("Could not save high scores due to error {e}"),
+            }
+        }
+        rank
+    }
+
+    /// Straight back in after dying, no need to go through the menus again.
+    fn restart(&mut self, seed: u64) -> bool {
+        if !matches!(*self.game_state.borrow(), GameState::GameOver { .. }) {
+            return false;
+        }
+        tracing::info!(seed, "Restarting");
+        self.new_run(seed);
+        self.to_playing()
+    }
+
+    fn draw_playing(&self, renderer: &mut dyn Renderer) {
+        draw_world(&self.simulation.world, renderer);
+
+        let profiler = &self.simulation.profiler;
+        if profiler.show_overlay {
+            draw_aura_outlines(&self.simulation.world, renderer);
+            let lines = profiler
+                .overlay_lines()
+                .into_iter()
+                .chain(self.simulation.system_toggle_lines(self.system_page));
+            let (width, _height) = renderer.size();
+            for (i, line) in lines.enumerate() {
+                // Right aligned against the edge of the screen.
+                renderer.print(
+                    width - line.chars().count() as i32,
+                    i as i32,
+                    &line,
+                    (255, 255, 255, 255),
+                    Some((0, 0, 0, 255)),
+                );
+            }
+        }
+    }
+
+    /// Moves on to `next` if that's somewhere the current state can go. Returns whether it did.
+    fn transition(&self, next: GameState) -> bool {
+        let mut game_state = self.game_state.borrow_mut();
+        if !game_state.can_become(&next) {
+            tracing::warn!(from = ?*game_state, to = ?next, "Invalid game state transition");
+            return false;
         }
+        tracing::info!(from = ?*game_state, to = ?next, "Changing game state");
+        *game_state = next;
+        true
+    }
+
+    fn to_main_menu(&self) -> bool {
+        self.transition(GameState::MainMenu)
+    }
+
+    fn to_class_select(&self) -> bool {
+        self.transition(GameState::ClassSelect)
+    }
+
+    fn to_playing(&self) -> bool {
+        self.transition(GameState::Playing)
+    }
+
+    fn to_paused(&self) -> bool {
+        self.transition(GameState::Paused)
+    }
+
+    fn to_game_over(&self, cause: String) -> bool {
+        self.transition(GameState::GameOver { cause })
     }
 }
 
+/// Everything that's worth seeing in a world dump, and how to write each of them out.
+fn world_inspector() -> WorldInspector {
+    let mut inspector = WorldInspector::default();
+    inspector.register::<Player>("Player", |_player| "yes".to_string());
+    inspector.register::<EntityName>("Name", |name| name.name.clone());
+    inspector.register::<Position>("Position", |pos| format!("({}, {})", pos.x, pos.y));
+    inspector.register::<Health>("Health", |health| {
+        format!("{}/{}", health.current_health(), health.total_health())
+    });
+    inspector.register::<Ai>("AiState", |ai| format!("{:?}", ai.curr_state));
+    inspector.register::<Vision>("Vision", |vision| format!("{vision:?}"));
+    inspector.register::<Windup>("Windup", |windup| format!("{windup:?}"));
+    inspector.register::<Telegraph>("Telegraph", |telegraph| format!("{telegraph:?}"));
+    inspector.register::<Screamer>("Screamer", |screamer| format!("{screamer:?}"));
+    inspector.register::<Aura>("Aura", |aura| format!("{aura:?}"));
+    inspector.register::<Faction>("Faction", |faction| format!("{faction:?}"));
+    inspector.register::<Renderable>("Renderable", |renderable| format!("{renderable:?}"));
+    inspector.register::<BlocksTile>("BlocksTile", |_blocks| "yes".to_string());
+    inspector.register::<DungeonDepth>("DungeonDepth", |depth| format!("{depth:?}"));
+    inspector.register::<NaturalRegen>("NaturalRegen", |regen| format!("{regen:?}"));
+    inspector.register::<Regeneration>("Regeneration", |regen| format!("{regen:?}"));
+    inspector.register::<RegenerationSuppressed>("RegenerationSuppressed", |suppressed| {
+        format!("{suppressed:?}")
+    });
+    inspector.register::<Attack>("Attack", |attack| format!("{attack:?}"));
+    inspector.register::<Defense>("Defense", |defense| format!("{defense:?}"));
+    inspector.register::<Knockback>("Knockback", |knockback| format!("{knockback:?}"));
+    inspector.register::<Resistances>("Resistances", |resistances| format!("{resistances:?}"));
+    inspector.register::<StatModifiers>("StatModifiers", |modifiers| format!("{modifiers:?}"));
+    inspector.register::<EffectiveStats>("EffectiveStats", |stats| format!("{stats:?}"));
+    inspector.register::<Effects>("Effects", |effects| format!("{effects:?}"));
+    inspector.register::<Confused>("Confused", |confused| format!("{confused:?}"));
+    inspector.register::<Feared>("Feared", |feared| format!("{feared:?}"));
+    inspector.register::<Sleeping>("Sleeping", |sleeping| format!("{sleeping:?}"));
+    inspector.register::<Slowed>("Slowed", |slowed| format!("{slowed:?}"));
+    inspector.register::<SlumberAttack>("SlumberAttack", |slumber| format!("{slumber:?}"));
+    inspector.register::<Blinded>("Blinded", |blinded| format!("{blinded:?}"));
+    inspector.register::<Burning>("Burning", |burning| format!("{burning:?}"));
+    inspector.register::<Bleed>("Bleed", |bleed| format!("{bleed:?}"));
+    inspector.register::<Bleeding>("Bleeding", |bleeding| format!("{bleeding:?}"));
+    inspector.register::<BurningTiles>("BurningTiles", |burning_tiles| {
+        format!("{} tiles", burning_tiles.positions.len())
+    });
+    inspector.register::<EntitySpeed>("Speed", |speed| speed.base.to_string());
+    inspector.register::<Stamina>("Stamina", |stamina| {
+        format!("{}/{}", stamina.current, stamina.max)
+    });
+    inspector.register::<Running>("Running", |running| format!("{running:?}"));
+    inspector.register::<Inventory>("Inventory", |inventory| format!("{inventory:?}"));
+    inspector.register::<PlayerWallet>("Wallet", |wallet| format!("{wallet:?}"));
+    inspector.register::<Score>("Score", |score| format!("{score:?}"));
+    inspector.register::<TurnCounter>("TurnCounter", |turn_counter| turn_counter.0.to_string());
+    inspector.register::<Minimap>("Minimap", |minimap| format!("{minimap:?}"));
+    inspector.register::<TileOverlay>("TileOverlay", |tile_overlay| {
+        format!("{} tiles", tile_overlay.colored().len())
+    });
+    inspector.register::<Gold>("Gold", |gold| format!("{gold:?}"));
+    inspector.register::<Item>("Item", |_item| "yes".to_string());
+    inspector.register::<ItemKind>("ItemKind", |kind| format!("{kind:?}"));
+    inspector.register::<Equippable>("Equippable", |equippable| format!("{equippable:?}"));
+    inspector.register::<Equipped>("Equipped", |equipped| format!("{equipped:?}"));
+    inspector.register::<MeleeBonus>("MeleeBonus", |bonus| format!("{bonus:?}"));
+    inspector.register::<DefenseBonus>("DefenseBonus", |bonus| format!("{bonus:?}"));
+    inspector.register::<LootTable>("LootTable", |loot_table| format!("{loot_table:?}"));
+    inspector.register::<Chest>("Chest", |chest| format!("{chest:?}"));
+    inspector.register::<Vendor>("Vendor", |vendor| format!("{vendor:?}"));
+    inspector.register::<Door>("Door", |door| format!("{door:?}"));
+    inspector.register::<Locked>("Locked", |locked| format!("{locked:?}"));
+    inspector.register::<Trap>("Trap", |trap| format!("{trap:?}"));
+    inspector.register::<LightSource>("LightSource", |light| format!("{light:?}"));
+    inspector.register::<Animation>("Animation", |animation| format!("{animation:?}"));
+    inspector.register::<Blink>("Blink", |blink| format!("{blink:?}"));
+    inspector.register::<RenderPosition>("RenderPosition", |slide| format!("{slide:?}"));
+    inspector.register::<RunState>("RunState", |run_state| format!("{run_state:?}"));
+    inspector.register::<RunStats>("RunStats", |run_stats| format!("{run_stats:?}"));
+    inspector
+}
+
 fn setup_logger() {
     tracing_subscriber::fmt()
         .with_timer(tracing_subscriber::fmt::time::uptime())
@@ -164,25 +593,98 @@ fn setup_logger() {
 fn main() {
     // tracing::subscriber::set_global_default()
     setup_logger();
+    let config = GameConfig::load(Path::new(CONFIG_PATH)).unwrap_or_else(|e| {
+        tracing::warn!("Could not read {CONFIG_PATH} due to error {e}, using the defaults");
+        GameConfig::default()
+    });
     // here are all the available options.
     // better practise is to use default values (see other examples)
     let options = AppOptions {
-        console_width: CONSOLE_WIDTH,
-        console_height: CONSOLE_HEIGHT,
-        screen_width: CONSOLE_WIDTH * 8,
-        screen_height: CONSOLE_HEIGHT * 8,
-        window_title: "my roguelike".to_owned(),
-        font_path: "terminal_8x8.png".to_owned(),
-        vsync: true,
+        console_width: config.console_width,
+        console_height: config.console_height,
+        screen_width: config.screen_width(),
+        screen_height: config.screen_height(),
+        window_title: config.window_title.clone(),
+        font_path: config.font_path.clone(),
+        vsync: config.vsync,
         fullscreen: false,
         show_cursor: true,
         resizable: false,
         intercept_close_request: false,
-        max_fps: 12,
+        max_fps: config.max_fps as usize,
     };
     let mut app = App::new(options);
 
-    app.set_engine(Box::new(MyRoguelike::new()));
+    app.set_engine(Box::new(MyRoguelike::new(&config)));
 
     app.run();
 }
+
+mod tests {
+    use super::*;
+    use crate::invariants::check_world;
+    use crate::models::stats::Health;
+    use crate::simulation::SimInput;
+
+    #[test]
+    fn test_game_state_transitions() {
+        let game = MyRoguelike::new(&GameConfig::default());
+        // Can't skip past the menus.
+        assert!(!game.to_paused());
+        assert!(!game.to_playing());
+        assert_eq!(*game.game_state.borrow(), GameState::MainMenu);
+
+        assert!(game.to_class_select());
+        assert!(game.to_playing());
+        assert!(game.to_paused());
+        assert!(game.to_playing());
+        assert!(game.to_game_over("Killed on depth 1.".to_string()));
+        assert!(!game.to_paused());
+        assert!(game.to_playing());
+    }
+
+    #[test]
+    fn test_restart_after_death_starts_fresh() {
+        let mut game = MyRoguelike::new(&GameConfig::default());
+        // Nothing to restart yet.
+        assert!(!game.restart(1));
+
+        game.to_class_select();
+        game.new_run(1);
+        game.to_playing();
+        for (_id, health) in game
+            .simulation
+            .world
+            .query_mut::<&mut Health>()
+            .with::<&Player>()
+        {
+            health.apply_damage(i32::MAX);
+        }
+        game.simulation.tick_scripted(&SimInput::Nothing);
+        assert_eq!(game.simulation.run_state(), RunState::GameOver);
+        game.to_game_over("Killed on depth 1.".to_string());
+
+        assert!(game.restart(2));
+        assert_eq!(*game.game_state.borrow(), GameState::Playing);
+        assert_eq!(game.simulation.run_state(), RunState::Running);
+        let mut player_query = game.simulation.world.query::<&Health>().with::<&Player>();
+        let players: Vec<_> = player_query.iter().collect();
+        assert_eq!(players.len(), 1);
+        assert!(!players[0].1.is_dead());
+    }
+
+    #[test]
+    fn test_new_runs_start_out_sound() {
+        for seed in 0..5 {
+            let mut game = MyRoguelike::new(&GameConfig::default());
+            game.new_run(seed);
+            assert_eq!(check_world(&game.simulation.world), vec![], "seed {seed}");
+
+            // And stay that way for the first few turns.
+            for _ in 0..3 {
+                game.simulation.tick_scripted(&SimInput::Wait);
+            }
+            assert_eq!(check_world(&game.simulation.world), vec![], "seed {seed}");
+        }
+    }
+}
@@ -1,36 +1,145 @@
+use crate::models::effects::Feared;
+use crate::models::light::FULL_LIGHT;
 use crate::models::stats::Health;
-use crate::models::{DistanceMetric, Position};
+use crate::models::{DistanceMetric, Position, ZERO_POS};
+use crate::fov::line;
+use crate::pathfinding::DijkstraMap;
+use hecs::Entity;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+/// AIs farther than this many times their view range from the player just sit there.
+const ACTIVE_RANGE_MULTIPLIER: usize = 2;
+/// Even in pitch black you can see this much of your usual range.
+const DARK_RANGE_DIVISOR: usize = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Vision {
     view_range: usize,
+    /// How distance gets measured, which decides the shape of what can be seen.
+    #[serde(default)]
+    metric: DistanceMetric,
 }
 
 impl Vision {
     pub fn new(view_range: usize) -> Self {
         tracing::trace!(view_range = view_range, "Creating vision");
-        Vision { view_range }
+        Vision {
+            view_range,
+            metric: DistanceMetric::default(),
+        }
+    }
+
+    /// The same eyes, measuring distance by `metric` instead.
+    pub fn with_metric(self, metric: DistanceMetric) -> Self {
+        Vision { metric, ..self }
+    }
+
+    /// How far we can see in full light.
+    pub fn view_range(&self) -> usize {
+        self.view_range
+    }
+
+    pub fn set_view_range(&mut self, view_range: usize) {
+        self.view_range = view_range;
     }
-    pub fn can_see(&self, self_pos: &Position, position: &Position) -> bool {
-        let can_see = self_pos.distance(position, &DistanceMetric::EuclideanSquared)
-            <= (self.view_range.pow(2) as f64);
+
+    /// How far we can see standing somewhere lit to `light`. Full light gets the whole view range,
+    /// pitch black only gets a third of it.
+    pub fn effective_range(&self, light: u8) -> usize {
+        let dark_range = (self.view_range / DARK_RANGE_DIVISOR)
+            .max(1)
+            .min(self.view_range);
+        dark_range + (self.view_range - dark_range) * light as usize / FULL_LIGHT as usize
+    }
+
+    /// The same eyes, cut down to how much `light` there is.
+    pub fn in_light(&self, light: u8) -> Vision {
+        Vision {
+            view_range: self.effective_range(light),
+            metric: self.metric,
+        }
+    }
+
+    /// The same eyes, seeing `change` tiles further (or nearer). Anything that can't see at all
+    /// still can't.
+    pub fn with_range_change(self, change: i32) -> Vision {
+        if self.view_range == 0 {
+            return self;
+        }
+        let view_range = (self.view_range as i32 + change).max(0) as usize;
+        Vision { view_range, ..self }
+    }
+
+    /// Whether `position` is in range and there's nothing `is_opaque` in the way. Only the tiles
+    /// in between count, so something standing in a doorway can still be seen.
+    pub fn can_see(
+        &self,
+        self_pos: &Position,
+        position: &Position,
+        is_opaque: impl Fn(&Position) -> bool,
+    ) -> bool {
+        let can_see = self.metric.is_within(self_pos, position, self.view_range)
+            && line(self_pos, position)
+                .iter()
+                .filter(|pos| *pos != self_pos && *pos != position)
+                .all(|pos| !is_opaque(pos));
         tracing::debug!(can_see = can_see, self_pos = ?self_pos, position = ?position);
         can_see
     }
+
+    /// Whether `position` is close enough that it's worth thinking about at all.
+    /// Anything that can't even get into view within a couple turns can stay idle.
+    pub fn is_nearby(&self, self_pos: &Position, position: &Position) -> bool {
+        self_pos.distance_squared(position)
+            <= ((self.view_range * ACTIVE_RANGE_MULTIPLIER).pow(2) as f64)
+    }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Action {
     GoTo(Position),
     Wait,
     Attack(Position),
+    /// Scares everything nearby. Only anything with a `Screamer` does this.
+    Scream,
+}
+
+/// Screams to scare off everything within `radius` for `fear_turns` turns, then has to wait
+/// `cooldown` turns before it can do it again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Screamer {
+    pub radius: u32,
+    pub fear_turns: u32,
+    pub cooldown: u32,
+    pub cooldown_remaining: u32,
 }
 
-#[derive(Debug, PartialEq)]
+/// Heavy hitters take `turns` to wind up before an attack lands, which gives whatever they're
+/// after a chance to get out of the way.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Windup {
+    pub turns: u32,
+    /// What gets logged when the windup starts, e.g. "raises its club!".
+    pub warning: String,
+    /// Chance (between 0 and 1) of getting hit knocking it out of the windup.
+    pub interrupt_chance: f64,
+}
+
+/// Winding up to do `pending` once `turns_remaining` have gone by.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Telegraph {
+    pub turns_remaining: u32,
+    pub pending: Action,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AiState {
     Idling,
     Afraid,
     Angry,
+    /// Lost sight of the player and is checking where they were last seen.
+    Searching,
 }
 
 impl Default for AiState {
@@ -39,88 +148,345 @@ impl Default for AiState {
     }
 }
 
-#[derive(Debug, Default)]
+/// Who an entity sides with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Faction {
+    Player,
+    Goblin,
+    Orc,
+    Rat,
+    Troll,
+    /// Anything that never got a side of its own. Only has it in for the player.
+    #[default]
+    Monster,
+    /// Keeps out of everyone's way, and everyone leaves it alone.
+    Neutral,
+}
+
+/// Whether `a` and `b` go for each other on sight. Everybody's out to get the player, and goblins
+/// and rats can't stand each other. Nobody turns on their own side, or goes after anyone neutral.
+pub fn are_hostile(a: Faction, b: Faction) -> bool {
+    use Faction::*;
+    a != b
+        && a != Neutral
+        && b != Neutral
+        && matches!(
+            (a, b),
+            (Player, _) | (_, Player) | (Goblin, Rat) | (Rat, Goblin)
+        )
+}
+
+/// Something an AI could pick a fight with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetCandidate {
+    pub id: Entity,
+    pub pos: Position,
+    pub faction: Faction,
+    pub health: i32,
+}
+
+/// Whoever out of `candidates` that `me` should go after: the closest one that's still standing,
+/// on a side `my_faction` has it in for, and in plain sight going by `vision`.
+pub fn choose_target<'a>(
+    me: Entity,
+    my_pos: &Position,
+    my_faction: Faction,
+    vision: &Vision,
+    candidates: &'a [TargetCandidate],
+    is_opaque: impl Fn(&Position) -> bool,
+) -> Option<&'a TargetCandidate> {
+    candidates
+        .iter()
+        .filter(|candidate| {
+            candidate.id != me
+                && candidate.health > 0
+                && are_hostile(my_faction, candidate.faction)
+                && vision.can_see(my_pos, &candidate.pos, &is_opaque)
+        })
+        .min_by(|a, b| {
+            my_pos
+                .distance_squared(&a.pos)
+                .total_cmp(&my_pos.distance_squared(&b.pos))
+        })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ai {
     pub curr_state: AiState,
     // pub next_action: Action,
+    /// Chance (between 0 and 1) of wandering around each turn while idling.
+    pub wander_chance: f64,
+    /// Where the AI spawned.
+    pub home: Position,
+    /// How far from `home` the AI is allowed to wander.
+    pub leash_distance: usize,
+    /// Where whoever we were after was the last time we saw them.
+    pub last_seen: Option<Position>,
+    /// How many turns to look around `last_seen` before giving up.
+    pub search_turns: u32,
+    search_turns_remaining: u32,
+    /// How the AI judges distance when running away.
+    pub distance_metric: DistanceMetric,
+    /// The closed door we're stood in front of, which gets opened next turn.
+    pub waiting_at_door: Option<Position>,
+}
+
+/// Everything an AI needs to know about the turn it's taking that it doesn't keep on itself.
+pub struct AiContext<'a, R> {
+    /// The nearest thing we'd fight, if there is one. Whatever's in the way of seeing it should
+    /// already have been checked when it was picked.
+    pub target: Option<(&'a Position, Entity)>,
+    /// Everything else that's after us, which running away steers clear of too.
+    pub other_threats: &'a [Position],
+    /// Should lead to the target, or be empty to just head straight for it.
+    pub target_map: &'a DijkstraMap,
+    pub is_walkable: &'a dyn Fn(&Position) -> bool,
+    pub rng: &'a mut R,
+    /// While feared, all we do is run, and it ticks down a turn.
+    pub feared: Option<&'a mut Feared>,
+}
+
+impl Default for Ai {
+    fn default() -> Self {
+        Ai::new(ZERO_POS)
+    }
 }
 
 impl Ai {
-    fn find_position_relative_to_player(
+    pub fn new(home: Position) -> Self {
+        Ai {
+            curr_state: AiState::default(),
+            wander_chance: 0.3,
+            home,
+            leash_distance: 5,
+            last_seen: None,
+            search_turns: 5,
+            search_turns_remaining: 0,
+            distance_metric: DistanceMetric::EuclideanSquared,
+            waiting_at_door: None,
+        }
+    }
+
+    /// Maybe picks a random walkable tile next to us that doesn't take us too far from home.
+    fn wander(
+        &self,
+        my_position: &Position,
+        is_walkable: impl Fn(&Position) -> bool,
+        rng: &mut impl Rng,
+    ) -> Action {
+        if !rng.random_bool(self.wander_chance) {
+            return Action::Wait;
+        }
+        let leash_squared = self.leash_distance.pow(2) as f64;
+        let candidates: Vec<_> = my_position
+            .neighbors()
+            .into_iter()
+            .filter(|pos| is_walkable(pos) && pos.distance_squared(&self.home) <= leash_squared)
+            .collect();
+        if candidates.is_empty() {
+            return Action::Wait;
+        }
+        Action::GoTo(candidates[rng.random_range(0..candidates.len())].clone())
+    }
+
+    /// Something made a racket at `at`. Anything not already after someone goes to look.
+    pub fn hear_noise(&mut self, at: &Position) {
+        if matches!(self.curr_state, AiState::Angry | AiState::Afraid) {
+            return;
+        }
+        self.curr_state = AiState::Searching;
+        self.last_seen = Some(at.clone());
+        self.search_turns_remaining = self.search_turns;
+    }
+
+    /// Heads to where our target was last seen, then waits around there until we give up.
+    fn search(&mut self, my_position: &Position) -> Action {
+        match &self.last_seen {
+            Some(last_seen) if last_seen != my_position => Action::GoTo(last_seen.clone()),
+            _ if self.search_turns_remaining > 0 => {
+                self.search_turns_remaining -= 1;
+                Action::Wait
+            }
+            _ => {
+                tracing::debug!("Gave up searching");
+                self.curr_state = AiState::Idling;
+                self.last_seen = None;
+                Action::Wait
+            }
+        }
+    }
+
+    /// Steps away from the threat, or lashes out if it's got us cornered.
+    /// Goes uphill on `threat_map` when it can, otherwise just picks the best looking neighbor,
+    /// keeping away from `other_threats` too.
+    fn flee(
         &self,
         my_position: &Position,
-        player_position: &Position,
-        invert_angle: bool,
-        distance: Option<f64>,
-    ) -> Position {
-        let distance = distance.unwrap_or(10.0);
-        let angle = my_position.angle(player_position);
-        let pos = my_position
-            .go_distance_theta(distance, if invert_angle { 180.0 - angle } else { angle });
-        tracing::debug!("Found position relative to {player_position:?} to be {pos:?}");
-        pos
+        threat_pos: &Position,
+        other_threats: &[Position],
+        threat_map: &DijkstraMap,
+        is_walkable: impl Fn(&Position) -> bool,
+    ) -> Action {
+        let step = match threat_map.worst_step_from(my_position) {
+            Some(step) if is_walkable(&step) => Some(step),
+            _ => best_flee_step(
+                my_position,
+                threat_pos,
+                other_threats,
+                &self.distance_metric,
+                is_walkable,
+            ),
+        };
+        match step {
+            Some(step) => Action::GoTo(step),
+            None if my_position.distance_squared(threat_pos) <= 2.0 => {
+                tracing::debug!("Cornered, so fighting back");
+                Action::Attack(threat_pos.clone())
+            }
+            None => Action::Wait,
+        }
     }
 
+    /// Works out what to do about `ctx.target`, given everything else going on this turn.
     pub fn get_next_action(
         &mut self,
-        player_pos: &Position,
         my_position: &Position,
         my_health: &Health,
         my_vision: &Vision,
+        ctx: AiContext<impl Rng>,
     ) -> Action {
+        let AiContext {
+            target,
+            other_threats,
+            target_map,
+            is_walkable,
+            rng,
+            feared,
+        } = ctx;
+        let visible_target = target
+            .map(|(target_pos, _target)| target_pos)
+            .filter(|target_pos| my_vision.can_see(my_position, target_pos, |_| false));
+        if let Some(target_pos) = visible_target {
+            self.last_seen = Some(target_pos.clone());
+        }
+        // Leaves `curr_state` alone, so we pick up where we left off once it wears off.
+        if let Some(feared) = feared {
+            feared.remaining_turns = feared.remaining_turns.saturating_sub(1);
+            return match visible_target {
+                Some(target_pos) => self.flee(
+                    my_position,
+                    target_pos,
+                    other_threats,
+                    target_map,
+                    is_walkable,
+                ),
+                None => Action::Wait,
+            };
+        }
         let action_to_take = match self.curr_state {
-            AiState::Idling => {
-                if my_vision.can_see(my_position, player_pos) {
+            AiState::Idling => match visible_target {
+                Some(target_pos) => {
                     self.curr_state = AiState::Angry;
-                    Action::GoTo(player_pos.clone())
-                } else {
-                    Action::Wait
+                    Action::GoTo(target_pos.clone())
                 }
-            }
-            AiState::Afraid => {
-                if !my_vision.can_see(my_position, player_pos) {
+                None => self.wander(my_position, is_walkable, rng),
+            },
+            AiState::Afraid => match visible_target {
+                Some(target_pos) => self.flee(
+                    my_position,
+                    target_pos,
+                    other_threats,
+                    target_map,
+                    is_walkable,
+                ),
+                None => {
                     self.curr_state = AiState::Idling;
                     Action::Wait
-                } else {
-                    Action::GoTo(self.find_position_relative_to_player(
-                        player_pos,
-                        my_position,
-                        true,
-                        None,
-                    ))
                 }
-            }
-            AiState::Angry => {
-                if !my_vision.can_see(my_position, player_pos) {
-                    self.curr_state = AiState::Idling;
-                    Action::Wait
-                } else if my_health.get_ratio() < 0.25 {
+            },
+            AiState::Angry => match visible_target {
+                None => {
+                    self.curr_state = AiState::Searching;
+                    self.search_turns_remaining = self.search_turns;
+                    self.search(my_position)
+                }
+                Some(target_pos) if my_health.get_ratio() < 0.25 => {
                     self.curr_state = AiState::Afraid;
-                    Action::GoTo(self.find_position_relative_to_player(
-                        player_pos,
+                    self.flee(
                         my_position,
-                        true,
-                        None,
-                    ))
-                } else if my_position.distance_squared(player_pos) <= 2.0 {
-                    // Allow AIs to reach the player if they're diagonally next to each other.
-                    // (Have a Euclidean distance of sqrt(2))
-                    Action::Attack(player_pos.clone())
-                } else {
-                    Action::GoTo(player_pos.clone())
+                        target_pos,
+                        other_threats,
+                        target_map,
+                        is_walkable,
+                    )
                 }
-            }
+                // Allow AIs to reach their target if they're diagonally next to each other.
+                // (Have a Euclidean distance of sqrt(2))
+                Some(target_pos) if my_position.distance_squared(target_pos) <= 2.0 => {
+                    Action::Attack(target_pos.clone())
+                }
+                // Follow the map so we go around things instead of into them.
+                Some(target_pos) => Action::GoTo(
+                    target_map
+                        .best_step_from(my_position)
+                        .unwrap_or(target_pos.clone()),
+                ),
+            },
+            AiState::Searching => match visible_target {
+                Some(target_pos) => {
+                    self.curr_state = AiState::Angry;
+                    Action::GoTo(target_pos.clone())
+                }
+                None => self.search(my_position),
+            },
         };
         tracing::trace!(
-            "Given Player Pos {player_pos:?}, curr_state={:?}, my position={my_position:?}, my_health={my_health:?}, my_vision={my_vision:?} => action={action_to_take:?}",
+            "Given target {target:?}, curr_state={:?}, my position={my_position:?}, my_health={my_health:?}, my_vision={my_vision:?} => action={action_to_take:?}",
             self.curr_state
         );
         action_to_take
     }
 }
 
+/// The walkable tile next to `my_pos` that gets us furthest from `threat_pos`.
+/// Ties go to whichever of those is also furthest from the nearest of `other_threats`.
+/// `None` if no step gets us any further away, meaning we're cornered.
+pub fn best_flee_step(
+    my_pos: &Position,
+    threat_pos: &Position,
+    other_threats: &[Position],
+    metric: &DistanceMetric,
+    is_walkable: impl Fn(&Position) -> bool,
+) -> Option<Position> {
+    let current_distance = metric.distance(my_pos, threat_pos);
+    let distance_from_others = |pos: &Position| {
+        other_threats
+            .iter()
+            .map(|other| metric.distance(pos, other))
+            .fold(f64::INFINITY, f64::min)
+    };
+    my_pos
+        .neighbors()
+        .into_iter()
+        .filter(|pos| is_walkable(pos))
+        .map(|pos| {
+            (
+                metric.distance(&pos, threat_pos),
+                distance_from_others(&pos),
+                pos,
+            )
+        })
+        .filter(|(distance, _, _)| *distance > current_distance)
+        .max_by(|(a, a_others, _), (b, b_others, _)| {
+            a.total_cmp(b).then(a_others.total_cmp(b_others))
+        })
+        .map(|(_, _, pos)| pos)
+}
+
 mod tests {
     use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
 
     #[test]
     fn test_vision() {
@@ -128,31 +494,96 @@ mod tests {
         let one = Position::new(10, 10);
 
         let two = Position::new(10, 10);
-        assert!(vision.can_see(&one, &two));
-        assert!(vision.can_see(&two, &one));
+        assert!(vision.can_see(&one, &two, |_| false));
+        assert!(vision.can_see(&two, &one, |_| false));
 
         let two = Position::new(9, 10);
-        assert!(vision.can_see(&one, &two));
-        assert!(vision.can_see(&two, &one));
+        assert!(vision.can_see(&one, &two, |_| false));
+        assert!(vision.can_see(&two, &one, |_| false));
 
         let two = Position::new(9, 9);
         // Distance should be sqrt(2) so that's still within vision range.
-        assert!(vision.can_see(&one, &two));
-        assert!(vision.can_see(&two, &one));
+        assert!(vision.can_see(&one, &two, |_| false));
+        assert!(vision.can_see(&two, &one, |_| false));
 
         let two = Position::new(10, 8);
-        assert!(vision.can_see(&one, &two));
-        assert!(vision.can_see(&two, &one));
+        assert!(vision.can_see(&one, &two, |_| false));
+        assert!(vision.can_see(&two, &one, |_| false));
 
         let two = Position::new(9, 8);
         // Distance should be sqrt(5) so shouldn't be within vision range.
-        assert!(!vision.can_see(&one, &two));
-        assert!(!vision.can_see(&two, &one));
+        assert!(!vision.can_see(&one, &two, |_| false));
+        assert!(!vision.can_see(&two, &one, |_| false));
 
         let vision = Vision::new(3);
         // Sqrt(5) is between 2 and 2.5 so we should be in vision range.
-        assert!(vision.can_see(&one, &two));
-        assert!(vision.can_see(&two, &one));
+        assert!(vision.can_see(&one, &two, |_| false));
+        assert!(vision.can_see(&two, &one, |_| false));
+    }
+
+    #[test]
+    fn test_walls_block_sight() {
+        let vision = Vision::new(5);
+        let me = Position::new(10, 10);
+        let goblin = Position::new(13, 10);
+        let wall = Position::new(12, 10);
+        assert!(vision.can_see(&me, &goblin, |_| false));
+        assert!(!vision.can_see(&me, &goblin, |pos| *pos == wall));
+        assert!(!vision.can_see(&goblin, &me, |pos| *pos == wall));
+        // Off to the side of the line doesn't get in the way.
+        assert!(vision.can_see(&me, &goblin, |pos| *pos == Position::new(12, 11)));
+    }
+
+    #[test]
+    fn test_can_see_into_a_doorway() {
+        let vision = Vision::new(5);
+        let me = Position::new(10, 10);
+        let doorway = Position::new(13, 10);
+        // Neither end counts, only what's between them.
+        assert!(vision.can_see(&me, &doorway, |pos| *pos == doorway));
+        assert!(vision.can_see(&me, &doorway, |pos| *pos == me));
+    }
+
+    #[test]
+    fn test_manhattan_vision_is_a_diamond() {
+        let vision = Vision::new(3).with_metric(DistanceMetric::Manhattan);
+        assert_eq!(vision.view_range(), 3);
+        let me = Position::new(10, 10);
+        let seen: Vec<Position> = (7..=13)
+            .flat_map(|y| (7..=13).map(move |x| Position::new(x, y)))
+            .filter(|pos| vision.can_see(&me, pos, |_| false))
+            .collect();
+        // 1 + 4 + 8 + 12 tiles out to three steps away.
+        assert_eq!(seen.len(), 25);
+        assert!(seen.contains(&Position::new(13, 10)));
+        assert!(seen.contains(&Position::new(11, 12)));
+        assert!(!seen.contains(&Position::new(12, 12)));
+        // Still the same in the dark, just smaller.
+        assert!(!vision.in_light(0).can_see(&me, &Position::new(11, 12), |_| false));
+    }
+
+    #[test]
+    fn test_vision_shrinks_in_the_dark() {
+        let vision = Vision::new(6);
+        assert_eq!(vision.effective_range(FULL_LIGHT), 6);
+        assert_eq!(vision.effective_range(0), 2);
+        assert!(vision.effective_range(128) < 6);
+
+        let me = Position::new(10, 10);
+        let player = Position::new(14, 10);
+        assert!(vision.in_light(FULL_LIGHT).can_see(&me, &player, |_| false));
+        assert!(!vision.in_light(0).can_see(&me, &player, |_| false));
+    }
+
+    #[test]
+    fn test_vision_is_nearby() {
+        let vision = Vision::new(3);
+        let me = Position::new(10, 10);
+        assert!(vision.is_nearby(&me, &Position::new(16, 10)));
+        assert!(!vision.is_nearby(&me, &Position::new(17, 10)));
+        // sqrt(50) is a little over 7, so just too far.
+        assert!(!vision.is_nearby(&me, &Position::new(15, 15)));
+        assert!(vision.is_nearby(&me, &Position::new(14, 14)));
     }
 
     #[test]
@@ -160,25 +591,77 @@ mod tests {
         let player_position = Position::new(10, 10);
         let vision = Vision::new(2);
         let mut health = Health::new(10);
-        let mut ai = Ai::default();
+        let mut ai = Ai {
+            wander_chance: 0.0,
+            ..Ai::default()
+        };
         let ai_pos = Position::new(0, 0);
+        let mut rng = StdRng::seed_from_u64(42);
 
-        let action = ai.get_next_action(&player_position, &ai_pos, &health, &vision);
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&player_position, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &|_| true,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
         assert_eq!(action, Action::Wait);
         assert_eq!(ai.curr_state, AiState::Idling);
 
         let ai_pos = Position::new(9, 9);
-        let action = ai.get_next_action(&player_position, &ai_pos, &health, &vision);
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&player_position, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &|_| true,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
         assert_eq!(action, Action::GoTo(player_position.clone()));
         assert_eq!(ai.curr_state, AiState::Angry);
 
-        let action = ai.get_next_action(&player_position, &ai_pos, &health, &vision);
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&player_position, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &|_| true,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
         assert_eq!(action, Action::Attack(player_position.clone()));
         assert_eq!(ai.curr_state, AiState::Angry);
 
         // We're now big hurt
-        health.current_health = 1;
-        let action = ai.get_next_action(&player_position, &ai_pos, &health, &vision);
+        health.apply_damage(9);
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&player_position, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &|_| true,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
         match action {
             Action::GoTo(pos) => {
                 // Make sure it's not the same position as the player anymore.
@@ -191,4 +674,614 @@ mod tests {
         }
         assert_eq!(ai.curr_state, AiState::Afraid);
     }
+
+    #[test]
+    fn test_ai_wander_only_onto_walkable_tiles() {
+        let player_position = Position::new(30, 30);
+        let vision = Vision::new(2);
+        let health = Health::new(10);
+        let ai_pos = Position::new(10, 10);
+        let mut ai = Ai {
+            wander_chance: 1.0,
+            ..Ai::new(ai_pos.clone())
+        };
+        let mut rng = StdRng::seed_from_u64(42);
+        let only_open_tile = Position::new(11, 10);
+
+        for _ in 0..20 {
+            let action = ai.get_next_action(
+                &ai_pos,
+                &health,
+                &vision,
+                AiContext {
+                    target: Some((&player_position, Entity::DANGLING)),
+                    other_threats: &[],
+                    target_map: &DijkstraMap::default(),
+                    is_walkable: &|pos| *pos == only_open_tile,
+                    rng: &mut rng,
+                    feared: None,
+                },
+            );
+            assert_eq!(action, Action::GoTo(only_open_tile.clone()));
+            assert_eq!(ai.curr_state, AiState::Idling);
+        }
+
+        // Boxed in, so there's nowhere to go.
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&player_position, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &|_| false,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
+        assert_eq!(action, Action::Wait);
+    }
+
+    #[test]
+    fn test_ai_wander_stays_on_leash() {
+        let player_position = Position::new(30, 30);
+        let vision = Vision::new(2);
+        let health = Health::new(10);
+        let home = Position::new(10, 10);
+        let mut ai = Ai {
+            wander_chance: 1.0,
+            leash_distance: 1,
+            ..Ai::new(home.clone())
+        };
+        let mut rng = StdRng::seed_from_u64(7);
+        // Already at the end of the leash.
+        let ai_pos = Position::new(11, 10);
+
+        for _ in 0..50 {
+            let action = ai.get_next_action(
+                &ai_pos,
+                &health,
+                &vision,
+                AiContext {
+                    target: Some((&player_position, Entity::DANGLING)),
+                    other_threats: &[],
+                    target_map: &DijkstraMap::default(),
+                    is_walkable: &|_| true,
+                    rng: &mut rng,
+                    feared: None,
+                },
+            );
+            match action {
+                Action::GoTo(pos) => assert!(pos.distance_squared(&home) <= 1.0),
+                _ => assert!(false),
+            }
+        }
+    }
+
+    #[test]
+    fn test_ai_wander_is_deterministic_with_seed() {
+        let player_position = Position::new(30, 30);
+        let vision = Vision::new(2);
+        let health = Health::new(10);
+        let ai_pos = Position::new(10, 10);
+
+        let run = || {
+            let mut ai = Ai::new(ai_pos.clone());
+            let mut rng = StdRng::seed_from_u64(1234);
+            (0..20)
+                .map(|_| {
+                    ai.get_next_action(
+                        &ai_pos,
+                        &health,
+                        &vision,
+                        AiContext {
+                            target: Some((&player_position, Entity::DANGLING)),
+                            other_threats: &[],
+                            target_map: &DijkstraMap::default(),
+                            is_walkable: &|_| true,
+                            rng: &mut rng,
+                            feared: None,
+                        },
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        let actions = run();
+        assert_eq!(actions, run());
+        // With a 30% chance over 20 turns we should've done both.
+        assert!(actions.contains(&Action::Wait));
+        assert!(
+            actions
+                .iter()
+                .any(|action| matches!(action, Action::GoTo(_)))
+        );
+    }
+
+    #[test]
+    fn test_ai_spots_player_while_wandering() {
+        let player_position = Position::new(12, 10);
+        let vision = Vision::new(6);
+        let health = Health::new(10);
+        let ai_pos = Position::new(10, 10);
+        let mut ai = Ai {
+            wander_chance: 1.0,
+            ..Ai::new(ai_pos.clone())
+        };
+        let mut rng = StdRng::seed_from_u64(42);
+
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&player_position, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &|_| true,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
+        assert_eq!(action, Action::GoTo(player_position.clone()));
+        assert_eq!(ai.curr_state, AiState::Angry);
+    }
+
+    #[test]
+    fn test_ai_chases_last_seen_position() {
+        let vision = Vision::new(3);
+        let health = Health::new(10);
+        let mut ai = Ai::new(Position::new(0, 0));
+        let mut rng = StdRng::seed_from_u64(42);
+        let ai_pos = Position::new(10, 10);
+        let last_seen = Position::new(12, 10);
+
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&last_seen, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &|_| true,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
+        assert_eq!(action, Action::GoTo(last_seen.clone()));
+        assert_eq!(ai.curr_state, AiState::Angry);
+
+        // Player ducks around the corner, out of sight.
+        let player_position = Position::new(20, 10);
+        for ai_pos in [Position::new(10, 10), Position::new(11, 10)] {
+            let action = ai.get_next_action(
+                &ai_pos,
+                &health,
+                &vision,
+                AiContext {
+                    target: Some((&player_position, Entity::DANGLING)),
+                    other_threats: &[],
+                    target_map: &DijkstraMap::default(),
+                    is_walkable: &|_| true,
+                    rng: &mut rng,
+                    feared: None,
+                },
+            );
+            assert_eq!(action, Action::GoTo(last_seen.clone()));
+            assert_eq!(ai.curr_state, AiState::Searching);
+        }
+    }
+
+    #[test]
+    fn test_ai_search_times_out_to_idling() {
+        let vision = Vision::new(3);
+        let health = Health::new(10);
+        let mut ai = Ai {
+            wander_chance: 0.0,
+            search_turns: 2,
+            ..Ai::new(Position::new(0, 0))
+        };
+        let mut rng = StdRng::seed_from_u64(42);
+        let ai_pos = Position::new(10, 10);
+        let player_position = Position::new(20, 10);
+
+        ai.curr_state = AiState::Angry;
+        ai.last_seen = Some(ai_pos.clone());
+        for _ in 0..2 {
+            let action = ai.get_next_action(
+                &ai_pos,
+                &health,
+                &vision,
+                AiContext {
+                    target: Some((&player_position, Entity::DANGLING)),
+                    other_threats: &[],
+                    target_map: &DijkstraMap::default(),
+                    is_walkable: &|_| true,
+                    rng: &mut rng,
+                    feared: None,
+                },
+            );
+            assert_eq!(action, Action::Wait);
+            assert_eq!(ai.curr_state, AiState::Searching);
+        }
+
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&player_position, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &|_| true,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
+        assert_eq!(action, Action::Wait);
+        assert_eq!(ai.curr_state, AiState::Idling);
+        assert_eq!(ai.last_seen, None);
+    }
+
+    #[test]
+    fn test_ai_reacquiring_player_resets_search() {
+        let vision = Vision::new(3);
+        let health = Health::new(10);
+        let mut ai = Ai {
+            search_turns: 2,
+            ..Ai::new(Position::new(0, 0))
+        };
+        let mut rng = StdRng::seed_from_u64(42);
+        let ai_pos = Position::new(10, 10);
+        let hidden_player = Position::new(20, 10);
+        let visible_player = Position::new(12, 10);
+
+        ai.curr_state = AiState::Angry;
+        ai.last_seen = Some(ai_pos.clone());
+        // Burn one of the search turns.
+        ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&hidden_player, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &|_| true,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
+        assert_eq!(ai.curr_state, AiState::Searching);
+
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&visible_player, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &|_| true,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
+        assert_eq!(action, Action::GoTo(visible_player.clone()));
+        assert_eq!(ai.curr_state, AiState::Angry);
+        assert_eq!(ai.last_seen, Some(visible_player.clone()));
+
+        // Losing them again gives us the full timer back.
+        ai.last_seen = Some(ai_pos.clone());
+        for _ in 0..2 {
+            let action = ai.get_next_action(
+                &ai_pos,
+                &health,
+                &vision,
+                AiContext {
+                    target: Some((&hidden_player, Entity::DANGLING)),
+                    other_threats: &[],
+                    target_map: &DijkstraMap::default(),
+                    is_walkable: &|_| true,
+                    rng: &mut rng,
+                    feared: None,
+                },
+            );
+            assert_eq!(action, Action::Wait);
+            assert_eq!(ai.curr_state, AiState::Searching);
+        }
+    }
+
+    #[test]
+    fn test_factions() {
+        assert!(are_hostile(Faction::Goblin, Faction::Player));
+        assert!(are_hostile(Faction::Player, Faction::Monster));
+        assert!(are_hostile(Faction::Rat, Faction::Goblin));
+        assert!(!are_hostile(Faction::Goblin, Faction::Goblin));
+        assert!(!are_hostile(Faction::Player, Faction::Player));
+        assert!(!are_hostile(Faction::Orc, Faction::Rat));
+        assert!(!are_hostile(Faction::Player, Faction::Neutral));
+        assert!(!are_hostile(Faction::Neutral, Faction::Goblin));
+    }
+
+    #[test]
+    fn test_closest_visible_enemy_gets_chosen() {
+        let mut world = hecs::World::new();
+        let (me, near, far, friend) = (
+            world.spawn(()),
+            world.spawn(()),
+            world.spawn(()),
+            world.spawn(()),
+        );
+        let candidate = |id, x, faction| TargetCandidate {
+            id,
+            pos: Position::new(x, 5),
+            faction,
+            health: 10,
+        };
+        let my_pos = Position::new(5, 5);
+        let vision = Vision::new(8);
+        let mut candidates = vec![
+            candidate(me, 5, Faction::Goblin),
+            candidate(far, 10, Faction::Player),
+            candidate(friend, 6, Faction::Goblin),
+            candidate(near, 2, Faction::Rat),
+        ];
+        let choose = |candidates: &[TargetCandidate], is_opaque: &dyn Fn(&Position) -> bool| {
+            choose_target(me, &my_pos, Faction::Goblin, &vision, candidates, is_opaque)
+                .map(|target| target.id)
+        };
+
+        assert_eq!(choose(&candidates, &|_| false), Some(near));
+        // Can't go after what can't be seen.
+        let wall = Position::new(3, 5);
+        assert_eq!(choose(&candidates, &|pos| *pos == wall), Some(far));
+        // Or what's already dead.
+        candidates[3].health = 0;
+        assert_eq!(choose(&candidates, &|_| false), Some(far));
+        // Only the player's left, so it's the same as ever.
+        candidates.truncate(2);
+        assert_eq!(choose(&candidates, &|_| false), Some(far));
+        candidates[1].pos = Position::new(20, 5);
+        assert_eq!(choose(&candidates, &|_| false), None);
+    }
+
+    #[test]
+    fn test_ai_with_nobody_to_fight_wanders() {
+        let vision = Vision::new(6);
+        let health = Health::new(10);
+        let ai_pos = Position::new(10, 10);
+        let mut ai = Ai {
+            wander_chance: 1.0,
+            ..Ai::new(ai_pos.clone())
+        };
+        let mut rng = StdRng::seed_from_u64(42);
+
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: None,
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &|_| true,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
+        assert!(matches!(action, Action::GoTo(_)));
+        assert_eq!(ai.curr_state, AiState::Idling);
+    }
+
+    #[test]
+    fn test_best_flee_step_open_field() {
+        let me = Position::new(6, 5);
+        let threat = Position::new(5, 5);
+        let step = best_flee_step(&me, &threat, &[], &DistanceMetric::EuclideanSquared, |_| {
+            true
+        })
+        .expect("There's plenty of room to run.");
+        assert_eq!(step.x, 7);
+        assert_eq!(step.distance_squared(&threat), 5.0);
+
+        // Another threat down and to the right should push us up instead.
+        let step = best_flee_step(
+            &me,
+            &threat,
+            &[Position::new(8, 8)],
+            &DistanceMetric::EuclideanSquared,
+            |_| true,
+        );
+        assert_eq!(step, Some(Position::new(7, 4)));
+    }
+
+    #[test]
+    fn test_best_flee_step_corridor() {
+        let me = Position::new(6, 5);
+        let threat = Position::new(5, 5);
+        let step = best_flee_step(&me, &threat, &[], &DistanceMetric::Manhattan, |pos| {
+            pos.y == 5
+        });
+        assert_eq!(step, Some(Position::new(7, 5)));
+    }
+
+    #[test]
+    fn test_best_flee_step_cornered() {
+        let me = Position::new(0, 0);
+        let threat = Position::new(2, 2);
+        let in_bounds = |pos: &Position| pos.x >= 0 && pos.y >= 0;
+        let step = best_flee_step(
+            &me,
+            &threat,
+            &[],
+            &DistanceMetric::EuclideanSquared,
+            in_bounds,
+        );
+        assert_eq!(step, None);
+    }
+
+    #[test]
+    fn test_cornered_afraid_ai_fights_back() {
+        let vision = Vision::new(6);
+        let mut health = Health::new(10);
+        health.apply_damage(9);
+        let mut ai = Ai::new(Position::new(0, 0));
+        ai.curr_state = AiState::Afraid;
+        let mut rng = StdRng::seed_from_u64(42);
+        let ai_pos = Position::new(0, 0);
+        let in_bounds = |pos: &Position| pos.x >= 0 && pos.y >= 0;
+
+        let far_player = Position::new(3, 3);
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&far_player, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &in_bounds,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
+        assert_eq!(action, Action::Wait);
+
+        let adjacent_player = Position::new(1, 1);
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&adjacent_player, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &in_bounds,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
+        assert_eq!(action, Action::Attack(adjacent_player.clone()));
+        assert_eq!(ai.curr_state, AiState::Afraid);
+    }
+
+    #[test]
+    fn test_angry_ai_follows_dijkstra_map() {
+        let vision = Vision::new(6);
+        let health = Health::new(10);
+        let mut ai = Ai::new(Position::new(0, 0));
+        ai.curr_state = AiState::Angry;
+        let mut rng = StdRng::seed_from_u64(42);
+        let player_position = Position::new(4, 2);
+        let ai_pos = Position::new(0, 2);
+        // Wall between us with a gap at the bottom.
+        let is_walkable =
+            |pos: &Position| pos.is_within_bounds((0, 4), (0, 4)) && !(pos.x == 2 && pos.y < 4);
+        let player_map = DijkstraMap::build(&[player_position.clone()], is_walkable, 20);
+
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&player_position, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &player_map,
+                is_walkable: &is_walkable,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
+        match action {
+            Action::GoTo(step) => {
+                assert!(ai_pos.neighbors().contains(&step));
+                assert!(player_map.cost(&step) < player_map.cost(&ai_pos));
+            }
+            _ => assert!(false),
+        }
+    }
+
+    #[test]
+    fn test_afraid_ai_rolls_uphill() {
+        let vision = Vision::new(6);
+        let mut health = Health::new(10);
+        health.apply_damage(9);
+        let mut ai = Ai::new(Position::new(0, 0));
+        ai.curr_state = AiState::Afraid;
+        let mut rng = StdRng::seed_from_u64(42);
+        let player_position = Position::new(2, 2);
+        let ai_pos = Position::new(3, 2);
+        let in_bounds = |pos: &Position| pos.is_within_bounds((0, 10), (0, 10));
+        let player_map = DijkstraMap::build(&[player_position.clone()], in_bounds, 20);
+
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&player_position, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &player_map,
+                is_walkable: &in_bounds,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
+        assert_eq!(
+            action,
+            Action::GoTo(player_map.worst_step_from(&ai_pos).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_fear_overrides_anger() {
+        let vision = Vision::new(5);
+        let health = Health::new(10);
+        let mut ai = Ai::new(Position::new(10, 10));
+        ai.curr_state = AiState::Angry;
+        let mut rng = StdRng::seed_from_u64(42);
+        let ai_pos = Position::new(10, 10);
+        let player_position = Position::new(11, 10);
+        let mut feared = Feared { remaining_turns: 2 };
+
+        for remaining_turns in [1, 0] {
+            let action = ai.get_next_action(
+                &ai_pos,
+                &health,
+                &vision,
+                AiContext {
+                    target: Some((&player_position, Entity::DANGLING)),
+                    other_threats: &[],
+                    target_map: &DijkstraMap::default(),
+                    is_walkable: &|_| true,
+                    rng: &mut rng,
+                    feared: Some(&mut feared),
+                },
+            );
+            assert!(matches!(
+                action,
+                Action::GoTo(step) if step.distance_squared(&player_position) > 1.0
+            ));
+            assert_eq!(feared.remaining_turns, remaining_turns);
+            assert_eq!(ai.curr_state, AiState::Angry);
+        }
+
+        // Back to swinging once it's worn off.
+        let action = ai.get_next_action(
+            &ai_pos,
+            &health,
+            &vision,
+            AiContext {
+                target: Some((&player_position, Entity::DANGLING)),
+                other_threats: &[],
+                target_map: &DijkstraMap::default(),
+                is_walkable: &|_| true,
+                rng: &mut rng,
+                feared: None,
+            },
+        );
+        assert_eq!(action, Action::Attack(player_position.clone()));
+    }
 }
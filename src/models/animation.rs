@@ -0,0 +1,217 @@
+//! Purely cosmetic, frame based effects. These tick every frame whether or not a turn went by.
+
+use crate::models::{Position, Renderable};
+use doryen_rs::Color;
+use serde::{Deserialize, Serialize};
+
+/// How many frames something flashes for after getting hit. A quarter second at 12 fps.
+pub const HIT_FLASH_FRAMES: u32 = 3;
+pub const HIT_FLASH_COLOR: Color = (255, 255, 255, 255);
+/// How many frames it takes for something that died to fade away.
+pub const FADE_OUT_FRAMES: u32 = 6;
+/// How many frames it takes for something to slide over to the tile it just moved to.
+pub const SLIDE_FRAMES: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Animation {
+    /// Draws in `color` instead of the usual color until it runs out.
+    FlashColor { color: Color, frames_remaining: u32 },
+    /// Gets darker every frame. The entity is despawned once it's done, so only put this on
+    /// something that's just for show.
+    FadeOut { frames_remaining: u32 },
+}
+
+/// Swaps between the usual color and `alt_color` every `period_frames` frames, for anything that
+/// should catch the eye.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Blink {
+    pub period_frames: u32,
+    pub alt_color: Color,
+}
+
+impl Blink {
+    /// Which of `color` and `alt_color` gets drawn on `frame`. Starts out on `color`, and a period
+    /// of 0 never blinks at all.
+    pub fn color_at(&self, color: Color, frame: u64) -> Color {
+        if self.period_frames == 0 || (frame / self.period_frames as u64) % 2 == 0 {
+            color
+        } else {
+            self.alt_color
+        }
+    }
+}
+
+/// Where something gets drawn while it slides from `from` over to `to`, `t` of the way there.
+/// Its `Position` is already `to`, so this only changes where it shows up on screen.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenderPosition {
+    pub from: Position,
+    pub to: Position,
+    pub t: f32,
+}
+
+impl RenderPosition {
+    pub fn new(from: Position, to: Position) -> Self {
+        Self { from, to, t: 0.0 }
+    }
+
+    /// Moves the slide along by a frame. Returns whether it's got all the way there.
+    pub fn tick(&mut self) -> bool {
+        self.t = (self.t + 1.0 / SLIDE_FRAMES as f32).min(1.0);
+        self.t >= 1.0
+    }
+
+    /// The tile it's closest to right now.
+    pub fn tile(&self) -> (i32, i32) {
+        let (x, y) = interpolate(&self.from, &self.to, self.t);
+        (x.round() as i32, y.round() as i32)
+    }
+}
+
+/// The point `t` of the way along the straight line from `from` to `to`. `t` gets kept between 0
+/// and 1, so it never overshoots either end.
+pub fn interpolate(from: &Position, to: &Position, t: f32) -> (f32, f32) {
+    let t = t.clamp(0.0, 1.0);
+    let lerp = |start: isize, end: isize| start as f32 + (end - start) as f32 * t;
+    (lerp(from.x, to.x), lerp(from.y, to.y))
+}
+
+/// How many frames have gone by since the run started. Lives on its own entity.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameCounter(pub u64);
+
+impl Animation {
+    /// Moves the animation along by a frame. Returns whether it's finished, in which case the
+    /// renderable is back to its usual color.
+    pub fn tick(&mut self, renderable: &mut Renderable) -> bool {
+        match self {
+            Animation::FlashColor {
+                color,
+                frames_remaining,
+            } => {
+                if *frames_remaining == 0 {
+                    renderable.tint = None;
+                    return true;
+                }
+                renderable.tint = Some(*color);
+                *frames_remaining -= 1;
+            }
+            Animation::FadeOut { frames_remaining } => {
+                if *frames_remaining == 0 {
+                    renderable.tint = None;
+                    return true;
+                }
+                renderable.tint = Some(faded(renderable.color, *frames_remaining));
+                *frames_remaining -= 1;
+            }
+        }
+        false
+    }
+}
+
+/// `color` scaled down towards black by how much of the fade is left.
+fn faded(color: Color, frames_remaining: u32) -> Color {
+    let scale = |channel: u8| {
+        (channel as u32 * frames_remaining.min(FADE_OUT_FRAMES) / FADE_OUT_FRAMES) as u8
+    };
+    (scale(color.0), scale(color.1), scale(color.2), color.3)
+}
+
+mod tests {
+    use super::*;
+
+    fn goblin_renderable() -> Renderable {
+        Renderable {
+            glyph: 'g',
+            color: (0, 200, 0, 255),
+            render_order: Renderable::ACTOR_ORDER,
+            tint: None,
+            bg: None,
+        }
+    }
+
+    #[test]
+    fn test_flash_restores_color() {
+        let mut renderable = goblin_renderable();
+        let mut flash = Animation::FlashColor {
+            color: HIT_FLASH_COLOR,
+            frames_remaining: 2,
+        };
+
+        assert!(!flash.tick(&mut renderable));
+        assert_eq!(renderable.drawn_color(), HIT_FLASH_COLOR);
+        assert!(!flash.tick(&mut renderable));
+        assert_eq!(renderable.drawn_color(), HIT_FLASH_COLOR);
+        assert!(flash.tick(&mut renderable));
+        assert_eq!(renderable.drawn_color(), (0, 200, 0, 255));
+    }
+
+    #[test]
+    fn test_blink_swaps_colors_every_period() {
+        let color = (0, 200, 0, 255);
+        let blink = Blink {
+            period_frames: 3,
+            alt_color: (255, 255, 0, 255),
+        };
+        let colors: Vec<_> = (0..7).map(|frame| blink.color_at(color, frame)).collect();
+        assert_eq!(
+            colors,
+            vec![
+                color,
+                color,
+                color,
+                blink.alt_color,
+                blink.alt_color,
+                blink.alt_color,
+                color
+            ]
+        );
+
+        let never = Blink {
+            period_frames: 0,
+            ..blink
+        };
+        assert_eq!(never.color_at(color, 5), color);
+    }
+
+    #[test]
+    fn test_fade_out_gets_darker() {
+        let mut renderable = goblin_renderable();
+        let mut fade = Animation::FadeOut {
+            frames_remaining: FADE_OUT_FRAMES,
+        };
+
+        let mut last_green = 201;
+        for _ in 0..FADE_OUT_FRAMES {
+            assert!(!fade.tick(&mut renderable));
+            let green = renderable.drawn_color().1 as u32;
+            assert!(green < last_green);
+            last_green = green;
+        }
+        assert!(fade.tick(&mut renderable));
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let from = Position::new(2, 4);
+        let to = Position::new(6, 3);
+        assert_eq!(interpolate(&from, &to, 0.0), (2.0, 4.0));
+        assert_eq!(interpolate(&from, &to, 1.0), (6.0, 3.0));
+        assert_eq!(interpolate(&from, &to, 0.5), (4.0, 3.5));
+        assert_eq!(interpolate(&from, &to, 2.0), (6.0, 3.0));
+    }
+
+    #[test]
+    fn test_slide_gets_there() {
+        let mut slide = RenderPosition::new(Position::new(0, 0), Position::new(3, 0));
+        assert_eq!(slide.tile(), (0, 0));
+        let tiles: Vec<(i32, i32)> = (0..SLIDE_FRAMES)
+            .map(|_| {
+                slide.tick();
+                slide.tile()
+            })
+            .collect();
+        assert_eq!(tiles, vec![(1, 0), (2, 0), (3, 0)]);
+        assert!(slide.tick());
+    }
+}
@@ -0,0 +1,80 @@
+//! Things that keep going off on everything around whoever has them.
+
+use crate::models::Position;
+use crate::models::stats::DamageKind;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum AuraEffect {
+    /// Heals this much.
+    HealNearby(i32),
+    /// Hurts this much, of this kind.
+    DamageNearby(i32, DamageKind),
+    /// Slows by this factor for this many turns, the same as `Slowed`.
+    SlowNearby(f32, u32),
+}
+
+/// Goes off on everything within `radius` tiles of whoever has it, as the crow flies.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Aura {
+    pub effect: AuraEffect,
+    pub radius: usize,
+    /// Goes off once on each of the player's turns if set, otherwise on each of its owner's own.
+    pub trigger_on_player_turn: bool,
+}
+
+impl Aura {
+    /// Whether anything at `pos` gets caught up in it, when it's coming from `center`.
+    pub fn reaches(&self, center: &Position, pos: &Position) -> bool {
+        center.euclidean_distance(pos) <= self.radius as f64
+    }
+}
+
+/// The edge of a circle `radius` tiles out from `center`, leaving every other tile out so it comes
+/// out dotted.
+pub fn aura_outline(center: &Position, radius: usize) -> Vec<Position> {
+    let reach = radius as isize;
+    let mut outline = Vec::new();
+    for dy in -reach..=reach {
+        for dx in -reach..=reach {
+            let pos = center.new_from_dx_dy(dx, dy);
+            let on_edge = (center.euclidean_distance(&pos) - radius as f64).abs() < 0.5;
+            if on_edge && (dx + dy) % 2 == 0 {
+                outline.push(pos);
+            }
+        }
+    }
+    outline
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aura_reaches_in_a_circle() {
+        let aura = Aura {
+            effect: AuraEffect::HealNearby(2),
+            radius: 3,
+            trigger_on_player_turn: true,
+        };
+        let center = Position::new(10, 10);
+        assert!(aura.reaches(&center, &Position::new(13, 10)));
+        assert!(aura.reaches(&center, &Position::new(12, 12)));
+        // Still three tiles away going diagonally, but further than that as the crow flies.
+        assert!(!aura.reaches(&center, &Position::new(13, 13)));
+    }
+
+    #[test]
+    fn test_aura_outline_is_dotted_around_the_edge() {
+        let center = Position::new(10, 10);
+        let outline = aura_outline(&center, 4);
+        assert!(outline.contains(&Position::new(14, 10)));
+        assert!(!outline.contains(&center));
+        for pos in &outline {
+            assert!((center.euclidean_distance(pos) - 4.0).abs() < 0.5);
+            // No two dots touch side on.
+            assert!(!outline.contains(&pos.new_from_dx_dy(1, 0)));
+            assert!(!outline.contains(&pos.new_from_dx_dy(0, 1)));
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Components for timed effects on entities.
+
+use crate::models::Position;
+use crate::models::map::{Map, TileType};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::f64::consts::FRAC_PI_4;
+
+/// The chance, each turn, of a fire catching on each floor tile next to it.
+pub const FIRE_SPREAD_CHANCE: f64 = 0.1;
+/// The chance, each turn, of a tile that's on fire going out on its own.
+pub const FIRE_BURN_OUT_CHANCE: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EffectKind {
+    /// Deals `magnitude` damage every turn.
+    Poison,
+    /// Heals `magnitude` health every turn.
+    Regen,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Effect {
+    pub kind: EffectKind,
+    pub turns_remaining: u32,
+    pub magnitude: i32,
+}
+
+/// All the effects currently active on an entity.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Effects {
+    pub active: Vec<Effect>,
+}
+
+/// Can't walk straight. Goes away once `remaining_turns` have gone by.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Confused {
+    pub remaining_turns: u32,
+}
+
+/// Too scared to do anything but run, however healthy it is. Goes away once `remaining_turns`
+/// have gone by.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Feared {
+    pub remaining_turns: u32,
+}
+
+/// Every turn takes `factor` times as long, so everything else gets more of them. Goes away once
+/// `remaining_turns` have gone by.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Slowed {
+    pub factor: f32,
+    pub remaining_turns: u32,
+}
+
+impl Slowed {
+    /// How many ticks a turn that would've taken `ticks` takes now. Never speeds anything up.
+    pub fn slow_down(&self, ticks: u64) -> u64 {
+        (ticks as f32 * self.factor.max(1.0)).round() as u64
+    }
+}
+
+/// Out cold, so no turns get taken. Goes away once `remaining_turns` have gone by, or on getting
+/// hurt if `wake_on_damage`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sleeping {
+    pub remaining_turns: u32,
+    pub wake_on_damage: bool,
+}
+
+/// Hits from whatever has this have a `chance` (between 0 and 1) of putting the target to sleep
+/// for `turns` turns. Hurting them wakes them back up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlumberAttack {
+    pub chance: f64,
+    pub turns: u32,
+}
+
+/// Can't see a thing. The first time `BlindSystem` sees this it takes away the sight and keeps
+/// however far it used to reach in `saved_range`, to give back once `remaining_turns` have gone by.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Blinded {
+    pub remaining_turns: u32,
+    pub saved_range: Option<usize>,
+}
+
+/// On fire. Takes `damage_per_turn` fire damage every turn until `remaining_turns` have gone by.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Burning {
+    pub damage_per_turn: i32,
+    pub remaining_turns: u32,
+}
+
+/// Hits from whatever has this (or has it on) open up a wound, which bleeds for
+/// `damage_per_stack` every turn for `duration_turns` turns. Every hit adds to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bleed {
+    pub damage_per_stack: i32,
+    pub duration_turns: u32,
+}
+
+/// Bleeding from `stacks` wounds, taking `damage_per_stack` for each one every turn until
+/// `duration_turns` have gone by.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bleeding {
+    pub stacks: u32,
+    pub damage_per_stack: i32,
+    pub duration_turns: u32,
+}
+
+impl Bleeding {
+    pub fn new(bleed: &Bleed) -> Self {
+        Bleeding {
+            stacks: 1,
+            damage_per_stack: bleed.damage_per_stack,
+            duration_turns: bleed.duration_turns,
+        }
+    }
+
+    /// Opens another wound. The bleeding goes on for as long as the worst of them does.
+    pub fn add_stack(&mut self, bleed: &Bleed) {
+        self.stacks += 1;
+        self.damage_per_stack = self.damage_per_stack.max(bleed.damage_per_stack);
+        self.duration_turns = self.duration_turns.max(bleed.duration_turns);
+    }
+
+    /// How much it bleeds for this turn.
+    pub fn damage(&self) -> i32 {
+        self.stacks as i32 * self.damage_per_stack
+    }
+
+    /// How much redder whatever's bleeding gets drawn.
+    pub fn red_boost(&self) -> u8 {
+        (self.stacks * 20).min(100) as u8
+    }
+}
+
+/// Every tile that's on fire. Lives on its own entity in the world.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BurningTiles {
+    pub positions: HashSet<Position>,
+}
+
+impl BurningTiles {
+    /// Sets `pos` on fire, as long as it's floor with no water next to it.
+    pub fn ignite(&mut self, map: &Map, pos: Position) {
+        if map.get(&pos) == Some(TileType::Floor) && !is_next_to_water(map, &pos) {
+            self.positions.insert(pos);
+        }
+    }
+
+    /// A turn's worth of fire: some of it spreads to the floor around it, and some of it goes out.
+    pub fn spread(&mut self, map: &Map, rng: &mut impl Rng) {
+        // Sorted so the same seed always burns the same way.
+        let mut burning: Vec<Position> = self.positions.iter().cloned().collect();
+        burning.sort_by_key(|pos| (pos.y, pos.x));
+        for pos in &burning {
+            for next in pos.cardinal_neighbors() {
+                if !self.positions.contains(&next) && rng.random_bool(FIRE_SPREAD_CHANCE) {
+                    self.ignite(map, next);
+                }
+            }
+        }
+        for pos in burning {
+            if rng.random_bool(FIRE_BURN_OUT_CHANCE) {
+                self.positions.remove(&pos);
+            }
+        }
+        self.positions.retain(|pos| !is_next_to_water(map, pos));
+    }
+}
+
+fn is_next_to_water(map: &Map, pos: &Position) -> bool {
+    pos.cardinal_neighbors()
+        .iter()
+        .any(|next| map.get(next) == Some(TileType::Water))
+}
+
+/// `(dx, dy)` turned `eighths` eighths of the way around, so 2 is a quarter turn. Diagonals and
+/// straight steps turn into each other, and it's still only ever one tile.
+pub fn rotate_step((dx, dy): (isize, isize), eighths: u32) -> (isize, isize) {
+    let (sin, cos) = (eighths as f64 * FRAC_PI_4).sin_cos();
+    let (dx, dy) = (dx as f64, dy as f64);
+    let rotated_x = (dx * cos - dy * sin).round() as isize;
+    let rotated_y = (dx * sin + dy * cos).round() as isize;
+    (rotated_x.clamp(-1, 1), rotated_y.clamp(-1, 1))
+}
+
+mod tests {
+    use super::*;
+    use crate::models::GameRng;
+
+    #[test]
+    fn test_slowed_turns_take_longer() {
+        let slowed = |factor| Slowed {
+            factor,
+            remaining_turns: 1,
+        };
+        assert_eq!(slowed(2.0).slow_down(10), 20);
+        assert_eq!(slowed(1.5).slow_down(5), 8);
+        // Being slowed never hurries anything along.
+        assert_eq!(slowed(0.5).slow_down(10), 10);
+    }
+
+    #[test]
+    fn test_bleeding_stacks_up() {
+        let dagger = Bleed {
+            damage_per_stack: 1,
+            duration_turns: 3,
+        };
+        let mut bleeding = Bleeding::new(&dagger);
+        assert_eq!(bleeding.damage(), 1);
+        assert_eq!(bleeding.red_boost(), 20);
+
+        bleeding.duration_turns = 1;
+        bleeding.add_stack(&dagger);
+        assert_eq!(
+            bleeding,
+            Bleeding {
+                stacks: 2,
+                damage_per_stack: 1,
+                duration_turns: 3,
+            }
+        );
+        assert_eq!(bleeding.damage(), 2);
+
+        bleeding.stacks = 9;
+        assert_eq!(bleeding.red_boost(), 100);
+    }
+
+    #[test]
+    fn test_rotate_step() {
+        assert_eq!(rotate_step((1, 0), 0), (1, 0));
+        assert_eq!(rotate_step((1, 0), 1), (1, 1));
+        assert_eq!(rotate_step((1, 0), 2), (0, 1));
+        assert_eq!(rotate_step((1, 1), 1), (0, 1));
+        assert_eq!(rotate_step((0, -1), 4), (0, 1));
+        // All the way around ends up back where it started.
+        assert_eq!(rotate_step((-1, 1), 8), (-1, 1));
+        let all_ways: std::collections::HashSet<_> =
+            (0..8).map(|eighths| rotate_step((0, 1), eighths)).collect();
+        assert_eq!(all_ways.len(), 8);
+    }
+
+    #[test]
+    fn test_fire_only_spreads_over_floor() {
+        let map = Map::new_bordered(8, 8);
+        let mut rng = GameRng::seeded(4);
+        let mut burning_tiles = BurningTiles::default();
+        burning_tiles.ignite(&map, Position::new(0, 3));
+        assert!(burning_tiles.positions.is_empty());
+
+        let mut ever_burned = HashSet::new();
+        for _ in 0..100 {
+            if burning_tiles.positions.is_empty() {
+                burning_tiles.ignite(&map, Position::new(1, 1));
+            }
+            burning_tiles.spread(&map, &mut rng);
+            ever_burned.extend(burning_tiles.positions.iter().cloned());
+        }
+        assert!(ever_burned.len() > 1);
+        assert!(
+            ever_burned
+                .iter()
+                .all(|pos| map.get(pos) == Some(TileType::Floor))
+        );
+    }
+
+    #[test]
+    fn test_water_puts_out_fire() {
+        let mut map = Map::new_bordered(8, 8);
+        let mut burning_tiles = BurningTiles::default();
+        burning_tiles.ignite(&map, Position::new(3, 3));
+        assert_eq!(burning_tiles.positions.len(), 1);
+
+        map.set(&Position::new(3, 4), TileType::Water);
+        burning_tiles.spread(&map, &mut GameRng::seeded(1));
+        assert!(burning_tiles.positions.is_empty());
+        // Can't even get going next to it.
+        burning_tiles.ignite(&map, Position::new(2, 4));
+        assert!(burning_tiles.positions.is_empty());
+    }
+}
@@ -0,0 +1,229 @@
+//! Weapons and armor, and what they do for whoever's wearing them.
+
+use crate::error::DRResult;
+use crate::models::Position;
+use crate::models::items::Inventory;
+use crate::models::stats::{StatKind, stat_change};
+use hecs::{Entity, World};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum EquipSlot {
+    Weapon,
+    Armor,
+}
+
+/// Items that can be worn, and where they go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Equippable {
+    pub slot: EquipSlot,
+}
+
+/// Extra damage on every melee hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MeleeBonus(pub i32);
+
+/// Takes this much off of every melee hit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DefenseBonus(pub i32);
+
+/// Marks an item as being worn by `by`. Worn items don't have a `Position`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Equipped {
+    pub by: Entity,
+    pub slot: EquipSlot,
+}
+
+/// Whatever `wearer` has on in `slot`.
+pub fn equipped_in(world: &World, wearer: Entity, slot: EquipSlot) -> Option<Entity> {
+    world
+        .query::<&Equipped>()
+        .iter()
+        .find(|(_id, equipped)| equipped.by == wearer && equipped.slot == slot)
+        .map(|(id, _equipped)| id)
+}
+
+/// Everything `wearer` has on.
+pub fn all_equipped(world: &World, wearer: Entity) -> Vec<Entity> {
+    world
+        .query::<&Equipped>()
+        .iter()
+        .filter(|(_id, equipped)| equipped.by == wearer)
+        .map(|(id, _equipped)| id)
+        .collect()
+}
+
+/// Adds up the bonuses from everything `entity` has on in `slot`. Weapons count their
+/// `MeleeBonus` and armor counts its `DefenseBonus`.
+pub fn equipment_bonus(world: &World, entity: Entity, slot: EquipSlot) -> i32 {
+    world
+        .query::<(&Equipped, Option<&MeleeBonus>, Option<&DefenseBonus>)>()
+        .iter()
+        .filter(|(_id, (equipped, _melee, _defense))| {
+            equipped.by == entity && equipped.slot == slot
+        })
+        .map(|(_id, (_equipped, melee, defense))| match slot {
+            EquipSlot::Weapon => melee.map_or(0, |bonus| bonus.0),
+            EquipSlot::Armor => defense.map_or(0, |bonus| bonus.0),
+        })
+        .sum()
+}
+
+/// How hard `attacker` hits `defender` with a melee attack that would do `base_damage` with
+/// nothing on. Good enough armor can stop a hit completely. Hits don't get rolled, so only the
+/// `AttackMin` and `Defense` stat modifiers count.
+pub fn melee_damage(world: &World, attacker: Entity, defender: Entity, base_damage: i32) -> i32 {
+    let damage = base_damage
+        + equipment_bonus(world, attacker, EquipSlot::Weapon)
+        + stat_change(world, attacker, StatKind::AttackMin)
+        - equipment_bonus(world, defender, EquipSlot::Armor)
+        - stat_change(world, defender, StatKind::Defense);
+    damage.max(0)
+}
+
+/// Puts `item` on `wearer`, picking it up off the floor if that's where it was. Whatever was
+/// already in that slot comes off first and is handed back.
+pub fn equip(world: &mut World, wearer: Entity, item: Entity) -> DRResult<Option<Entity>> {
+    let slot = world.get::<&Equippable>(item)?.slot;
+    let previous = equipped_in(world, wearer, slot);
+    if let Some(previous) = previous {
+        unequip(world, previous)?;
+    }
+    let _ = world.remove_one::<Position>(item);
+    if let Ok(mut inventory) = world.get::<&mut Inventory>(wearer) {
+        inventory.equipment.retain(|carried| *carried != item);
+    }
+    world.insert_one(item, Equipped { by: wearer, slot })?;
+    tracing::debug!(?wearer, ?item, ?slot, ?previous, "Equipped item");
+    Ok(previous)
+}
+
+/// Takes `item` off and puts it back in the wearer's inventory. Anyone without an inventory
+/// drops it where they're standing instead.
+pub fn unequip(world: &mut World, item: Entity) -> DRResult<()> {
+    let equipped = world.remove_one::<Equipped>(item)?;
+    let put_away = world
+        .get::<&mut Inventory>(equipped.by)
+        .map(|mut inventory| inventory.equipment.push(item))
+        .is_ok();
+    if !put_away {
+        let pos = (*world.get::<&Position>(equipped.by)?).clone();
+        world.insert_one(item, pos)?;
+    }
+    tracing::debug!(?item, ?equipped, "Unequipped item");
+    Ok(())
+}
+
+/// Leaves `item` on the floor under `holder`, taking it off first if it's being worn.
+pub fn drop_item(world: &mut World, holder: Entity, item: Entity) -> DRResult<()> {
+    if world.get::<&Equipped>(item).is_ok() {
+        unequip(world, item)?;
+    }
+    if let Ok(mut inventory) = world.get::<&mut Inventory>(holder) {
+        inventory.equipment.retain(|carried| *carried != item);
+    }
+    let pos = (*world.get::<&Position>(holder)?).clone();
+    world.insert_one(item, pos)?;
+    tracing::debug!(?holder, ?item, "Dropped item");
+    Ok(())
+}
+
+mod tests {
+    use super::*;
+    use crate::entities::{spawn_goblin_at, spawn_item, spawn_player};
+    use crate::models::GameRng;
+    use crate::models::items::ItemKind;
+
+    #[test]
+    fn test_equipping_swaps_out_whatever_was_there() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let dagger = spawn_item(&mut world, Position::new(5, 5), ItemKind::Dagger);
+        let sword = spawn_item(&mut world, Position::new(5, 5), ItemKind::Sword);
+        let armor = spawn_item(&mut world, Position::new(5, 5), ItemKind::LeatherArmor);
+
+        assert_eq!(equip(&mut world, player, dagger).unwrap(), None);
+        assert_eq!(equip(&mut world, player, armor).unwrap(), None);
+        assert!(world.get::<&Position>(dagger).is_err());
+        assert_eq!(equipped_in(&world, player, EquipSlot::Weapon), Some(dagger));
+
+        assert_eq!(equip(&mut world, player, sword).unwrap(), Some(dagger));
+        assert_eq!(equipped_in(&world, player, EquipSlot::Weapon), Some(sword));
+        assert_eq!(equipped_in(&world, player, EquipSlot::Armor), Some(armor));
+        assert!(world.get::<&Equipped>(dagger).is_err());
+        assert_eq!(
+            world.get::<&Inventory>(player).unwrap().equipment,
+            vec![dagger]
+        );
+
+        // Putting it back on takes it out of the inventory again.
+        equip(&mut world, player, dagger).unwrap();
+        assert_eq!(
+            world.get::<&Inventory>(player).unwrap().equipment,
+            vec![sword]
+        );
+    }
+
+    #[test]
+    fn test_bonuses_change_melee_damage() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut GameRng::seeded(1));
+        assert_eq!(melee_damage(&world, player, goblin, 2), 2);
+
+        let sword = spawn_item(&mut world, Position::new(5, 5), ItemKind::Sword);
+        equip(&mut world, player, sword).unwrap();
+        assert_eq!(equipment_bonus(&world, player, EquipSlot::Weapon), 2);
+        assert_eq!(melee_damage(&world, player, goblin, 2), 4);
+
+        let armor = spawn_item(&mut world, Position::new(5, 5), ItemKind::LeatherArmor);
+        equip(&mut world, player, armor).unwrap();
+        assert_eq!(equipment_bonus(&world, player, EquipSlot::Armor), 1);
+        // Weapons don't count towards defense and armor doesn't count towards attack.
+        assert_eq!(equipment_bonus(&world, player, EquipSlot::Weapon), 2);
+        assert_eq!(melee_damage(&world, goblin, player, 1), 0);
+    }
+
+    #[test]
+    fn test_dropping_equipped_item_takes_it_off() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let armor = spawn_item(&mut world, Position::new(5, 5), ItemKind::LeatherArmor);
+        equip(&mut world, player, armor).unwrap();
+        world.get::<&mut Position>(player).unwrap().x = 7;
+
+        drop_item(&mut world, player, armor).unwrap();
+        assert!(world.get::<&Equipped>(armor).is_err());
+        assert_eq!(equipment_bonus(&world, player, EquipSlot::Armor), 0);
+        assert!(
+            world
+                .get::<&Inventory>(player)
+                .unwrap()
+                .equipment
+                .is_empty()
+        );
+        assert_eq!(*world.get::<&Position>(armor).unwrap(), Position::new(7, 5));
+    }
+
+    #[test]
+    fn test_monsters_can_wear_equipment() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(1);
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut rng);
+        let dagger = spawn_item(&mut world, Position::new(6, 5), ItemKind::Dagger);
+        let sword = spawn_item(&mut world, Position::new(6, 5), ItemKind::Sword);
+
+        equip(&mut world, goblin, dagger).unwrap();
+        assert_eq!(melee_damage(&world, goblin, player, 1), 2);
+
+        // Goblins don't have anywhere to put the dagger, so it goes on the floor.
+        equip(&mut world, goblin, sword).unwrap();
+        assert_eq!(melee_damage(&world, goblin, player, 1), 3);
+        assert_eq!(
+            *world.get::<&Position>(dagger).unwrap(),
+            Position::new(6, 5)
+        );
+        assert_eq!(all_equipped(&world, goblin), vec![sword]);
+    }
+}
@@ -1,9 +1,12 @@
 //! Components for input handling.
 
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Player;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InputState {
     /// Really jank way of forcing the AIs to not update in real time.
     pub was_input_handled_this_frame: bool,
@@ -15,4 +18,114 @@ impl Default for InputState {
             was_input_handled_this_frame: false,
         }
     }
-}
\ No newline at end of file
+}
+
+/// Things the player can ask to do, independent of which key does it.
+/// Attacking is done by moving into something, so it shares the move keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum KeyAction {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Wait,
+    /// Nothing listens for this until there's an inventory to open.
+    Inventory,
+    /// Pick something to throw a rock at.
+    Throw,
+    /// Pick something to shoot at.
+    Fire,
+    /// Goes with whatever's being targeted.
+    Confirm,
+    /// Backs out of targeting or examining.
+    Cancel,
+    /// Jumps the targeting cursor to the next hostile in view.
+    CycleTarget,
+    /// Look at what's on a tile.
+    Examine,
+    /// Saves what's on screen to `GameConfig::screenshot_dir`.
+    Screenshot,
+    /// Spend a turn looking for hidden traps next to the player.
+    Search,
+    /// Held along with a direction to open the door that way without walking into it.
+    Open,
+    /// Put on whatever weapon or armor is lying under the player.
+    Wear,
+    /// Take off everything the player has on.
+    TakeOff,
+    /// Open a chest or trade with a merchant next to the player.
+    Interact,
+    /// Opens (and closes) the full message log.
+    MessageLog,
+    /// Scrolls the message log a whole page back.
+    PageUp,
+    /// Scrolls the message log a whole page forward.
+    PageDown,
+    /// Held along with a direction to keep going that way until something comes up.
+    Run,
+    /// On the trading screen, switches the number keys over to selling.
+    Sell,
+    /// Writes the whole world out to `GameConfig::dump_dir`, for debugging.
+    DumpWorld,
+    /// Shows (and hides) the minimap.
+    Minimap,
+    /// Use up a bandage to stop bleeding.
+    Bandage,
+}
+
+/// Which key does what. Lives on its own entity in the world.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyBindings {
+    bindings: HashMap<KeyAction, String>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> KeyBindings {
+        KeyBindings {
+            bindings: HashMap::from([
+                (KeyAction::MoveUp, "ArrowUp".to_string()),
+                (KeyAction::MoveDown, "ArrowDown".to_string()),
+                (KeyAction::MoveLeft, "ArrowLeft".to_string()),
+                (KeyAction::MoveRight, "ArrowRight".to_string()),
+                (KeyAction::Wait, "Space".to_string()),
+                (KeyAction::Inventory, "KeyI".to_string()),
+                (KeyAction::Throw, "KeyT".to_string()),
+                (KeyAction::Fire, "KeyF".to_string()),
+                (KeyAction::Confirm, "Enter".to_string()),
+                (KeyAction::Cancel, "Escape".to_string()),
+                (KeyAction::CycleTarget, "Tab".to_string()),
+                (KeyAction::Examine, "KeyX".to_string()),
+                (KeyAction::Screenshot, "F12".to_string()),
+                (KeyAction::Search, "KeyS".to_string()),
+                (KeyAction::Open, "KeyO".to_string()),
+                (KeyAction::Wear, "KeyW".to_string()),
+                (KeyAction::TakeOff, "KeyR".to_string()),
+                (KeyAction::Interact, "KeyE".to_string()),
+                (KeyAction::MessageLog, "KeyL".to_string()),
+                (KeyAction::PageUp, "PageUp".to_string()),
+                (KeyAction::PageDown, "PageDown".to_string()),
+                (KeyAction::Run, "ShiftLeft".to_string()),
+                // Only while trading, so it doesn't get in the way of searching.
+                (KeyAction::Sell, "KeyS".to_string()),
+                (KeyAction::DumpWorld, "F5".to_string()),
+                (KeyAction::Minimap, "KeyM".to_string()),
+                (KeyAction::Bandage, "KeyB".to_string()),
+            ]),
+        }
+    }
+}
+
+impl KeyBindings {
+    /// The key name (as doryen calls it) bound to `action`.
+    pub fn key_for(&self, action: KeyAction) -> &str {
+        self.bindings
+            .get(&action)
+            .map(String::as_str)
+            .unwrap_or_default()
+    }
+
+    pub fn rebind(&mut self, action: KeyAction, key: &str) {
+        tracing::debug!(?action, ?key, "Rebinding key");
+        self.bindings.insert(action, key.to_string());
+    }
+}
@@ -0,0 +1,210 @@
+//! Everything the player can do to what's right next to them with the interact key.
+
+use crate::models::ai::Ai;
+use crate::models::items::Chest;
+use crate::models::map::Map;
+use crate::models::spatial_index::SpatialIndex;
+use crate::models::vendor::Vendor;
+use crate::models::{Door, EntityName, Position};
+use hecs::{Entity, World};
+
+/// One kind of thing the player can do to something next to them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interaction {
+    Trade,
+    OpenChest,
+    OpenDoor,
+    CloseDoor,
+    /// Push a monster back a tile, if there's room behind it.
+    Shove,
+}
+
+/// `interaction`, done to `target`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InteractionOption {
+    pub interaction: Interaction,
+    pub target: Entity,
+}
+
+impl Interaction {
+    /// Every kind of interaction, in the order they get offered.
+    pub const ALL: [Interaction; 5] = [
+        Interaction::Trade,
+        Interaction::OpenChest,
+        Interaction::OpenDoor,
+        Interaction::CloseDoor,
+        Interaction::Shove,
+    ];
+
+    /// Everything next to `player_pos` this could be done to.
+    pub fn is_available(&self, world: &World, player_pos: &Position) -> Vec<InteractionOption> {
+        let targets: Vec<Entity> = match self {
+            Interaction::Trade => world
+                .query::<&Position>()
+                .with::<&Vendor>()
+                .iter()
+                .filter(|(_id, pos)| is_next_to(pos, player_pos))
+                .map(|(id, _pos)| id)
+                .collect(),
+            Interaction::OpenChest => world
+                .query::<(&Position, &Chest)>()
+                .iter()
+                .filter(|(_id, (pos, chest))| !chest.opened && is_next_to(pos, player_pos))
+                .map(|(id, _)| id)
+                .collect(),
+            Interaction::OpenDoor | Interaction::CloseDoor => {
+                let wants_open = *self == Interaction::CloseDoor;
+                world
+                    .query::<(&Position, &Door)>()
+                    .iter()
+                    .filter(|(_id, (pos, door))| {
+                        door.open == wants_open && is_next_to(pos, player_pos)
+                    })
+                    .map(|(id, _)| id)
+                    .collect()
+            }
+            Interaction::Shove => world
+                .query::<&Position>()
+                .with::<&Ai>()
+                .iter()
+                .filter(|(_id, pos)| is_next_to(pos, player_pos))
+                .map(|(id, _pos)| id)
+                .collect(),
+        };
+        targets
+            .into_iter()
+            .map(|target| InteractionOption {
+                interaction: *self,
+                target,
+            })
+            .collect()
+    }
+}
+
+impl InteractionOption {
+    /// Something like "Shove the Goblin", for picking it out of a list.
+    pub fn describe(&self, world: &World) -> String {
+        let name = world
+            .get::<&EntityName>(self.target)
+            .map_or("thing".to_string(), |name| name.to_string());
+        match self.interaction {
+            Interaction::Trade => format!("Trade with the {name}"),
+            Interaction::OpenChest => "Open the chest".to_string(),
+            Interaction::OpenDoor => "Open the door".to_string(),
+            Interaction::CloseDoor => "Close the door".to_string(),
+            Interaction::Shove => format!("Shove the {name}"),
+        }
+    }
+}
+
+/// Whether `pos` is one of the eight tiles around `center`.
+fn is_next_to(pos: &Position, center: &Position) -> bool {
+    pos != center && (pos.x - center.x).abs() <= 1 && (pos.y - center.y).abs() <= 1
+}
+
+/// Everything the player could do from `player_pos`, in the order it'd get listed.
+pub fn available_interactions(world: &World, player_pos: &Position) -> Vec<InteractionOption> {
+    Interaction::ALL
+        .iter()
+        .flat_map(|interaction| interaction.is_available(world, player_pos))
+        .collect()
+}
+
+/// Where `target` would end up if it got shoved by something at `from`, straight away from it.
+/// Hands back why not if there's a wall or something else already standing there.
+pub fn shove_destination(
+    world: &World,
+    from: &Position,
+    target: Entity,
+) -> Result<Position, String> {
+    let Ok(start) = world.get::<&Position>(target) else {
+        return Err("There's nothing there to shove.".to_string());
+    };
+    let destination =
+        start.new_from_dx_dy((start.x - from.x).signum(), (start.y - from.y).signum());
+    let mut map_query = world.query::<&Map>();
+    let map = map_query.iter().next().map(|(_id, map)| map);
+    if map.is_some_and(|map| map.is_blocked(&destination)) {
+        return Err("There's a wall in the way.".to_string());
+    }
+    let mut spatial_index_query = world.query::<&SpatialIndex>();
+    let occupied = spatial_index_query
+        .iter()
+        .next()
+        .is_some_and(|(_id, spatial_index)| spatial_index.is_occupied(&destination));
+    if occupied {
+        return Err("There's something behind it.".to_string());
+    }
+    Ok(destination)
+}
+
+mod tests {
+    use super::*;
+    use crate::entities::{spawn_door, spawn_goblin_at, spawn_player};
+    use crate::models::GameRng;
+
+    #[test]
+    fn test_options_next_to_the_player() {
+        let mut world = World::new();
+        world.spawn((Map::new_bordered(10, 10),));
+        spawn_player(&mut world, Position::new(5, 5));
+        let open = spawn_door(&mut world, Position::new(4, 5), true);
+        let closed = spawn_door(&mut world, Position::new(6, 6), false);
+        let goblin = spawn_goblin_at(&mut world, Position::new(5, 4), &mut GameRng::seeded(1));
+        // Too far away to reach.
+        spawn_door(&mut world, Position::new(7, 5), false);
+
+        assert_eq!(
+            available_interactions(&world, &Position::new(5, 5)),
+            vec![
+                InteractionOption {
+                    interaction: Interaction::OpenDoor,
+                    target: closed,
+                },
+                InteractionOption {
+                    interaction: Interaction::CloseDoor,
+                    target: open,
+                },
+                InteractionOption {
+                    interaction: Interaction::Shove,
+                    target: goblin,
+                },
+            ]
+        );
+        assert!(available_interactions(&world, &Position::new(2, 2)).is_empty());
+    }
+
+    #[test]
+    fn test_shove_needs_room_behind() {
+        let mut world = World::new();
+        world.spawn((Map::new_bordered(10, 10),));
+        let goblin = spawn_goblin_at(&mut world, Position::new(1, 5), &mut GameRng::seeded(1));
+        let spatial_index = world.spawn((SpatialIndex::from_world(&world),));
+
+        // Straight into the wall.
+        assert_eq!(
+            shove_destination(&world, &Position::new(2, 5), goblin),
+            Err("There's a wall in the way.".to_string())
+        );
+
+        *world.get::<&mut Position>(goblin).unwrap() = Position::new(3, 4);
+        world
+            .get::<&mut SpatialIndex>(spatial_index)
+            .unwrap()
+            .move_entity(goblin, &Position::new(3, 4));
+        assert_eq!(
+            shove_destination(&world, &Position::new(2, 5), goblin),
+            Ok(Position::new(4, 3))
+        );
+
+        let other = spawn_goblin_at(&mut world, Position::new(4, 3), &mut GameRng::seeded(2));
+        world
+            .get::<&mut SpatialIndex>(spatial_index)
+            .unwrap()
+            .insert(other, Position::new(4, 3));
+        assert_eq!(
+            shove_destination(&world, &Position::new(2, 5), goblin),
+            Err("There's something behind it.".to_string())
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! Components for items and what monsters drop.
+
+use hecs::Entity;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Marker for entities that can be picked up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Item;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ItemKind {
+    Sword,
+    Dagger,
+    /// Hits hard enough to knock whatever it hits back.
+    Warhammer,
+    LeatherArmor,
+    ThrowingRock {
+        damage: i32,
+    },
+    /// Unlocks any door that's `Locked` with the same `key_id`. Used up once it has.
+    Key {
+        key_id: u32,
+    },
+    /// Stops any bleeding. Used up once it has.
+    Bandage,
+    /// Goes straight into the wallet instead of the inventory.
+    Gold {
+        amount: u32,
+    },
+}
+
+impl ItemKind {
+    /// How much gold it's worth, which is what merchants sell it for.
+    pub fn value(&self) -> u32 {
+        match self {
+            ItemKind::Sword => 40,
+            ItemKind::Dagger => 15,
+            ItemKind::Warhammer => 60,
+            ItemKind::LeatherArmor => 25,
+            ItemKind::ThrowingRock { .. } => 3,
+            ItemKind::Key { .. } => 0,
+            ItemKind::Bandage => 10,
+            ItemKind::Gold { amount } => *amount,
+        }
+    }
+}
+
+/// Whatever an entity is carrying around.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Inventory {
+    pub items: Vec<ItemKind>,
+    /// Weapons and armor that are being carried but aren't being worn.
+    pub equipment: Vec<Entity>,
+}
+
+impl Inventory {
+    /// Takes the first rock out of the inventory, if there is one.
+    pub fn take_throwing_rock(&mut self) -> Option<ItemKind> {
+        let i = self
+            .items
+            .iter()
+            .position(|item| matches!(item, ItemKind::ThrowingRock { .. }))?;
+        Some(self.items.remove(i))
+    }
+
+    /// Drops any carried equipment that isn't around anymore.
+    pub fn forget_missing(&mut self, exists: impl Fn(Entity) -> bool) {
+        self.equipment.retain(|item| exists(*item));
+    }
+
+    pub fn has_key(&self, key_id: u32) -> bool {
+        self.items.contains(&ItemKind::Key { key_id })
+    }
+
+    /// Takes a bandage out of the inventory. False if there wasn't one.
+    pub fn use_bandage(&mut self) -> bool {
+        let Some(i) = self
+            .items
+            .iter()
+            .position(|item| *item == ItemKind::Bandage)
+        else {
+            return false;
+        };
+        self.items.remove(i);
+        true
+    }
+
+    /// Takes the key for `key_id` out of the inventory. False if there wasn't one.
+    pub fn use_key(&mut self, key_id: u32) -> bool {
+        let Some(i) = self
+            .items
+            .iter()
+            .position(|item| *item == ItemKind::Key { key_id })
+        else {
+            return false;
+        };
+        self.items.remove(i);
+        true
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LootEntry {
+    pub item: ItemKind,
+    /// Between 0 and 1.
+    pub chance: f64,
+}
+
+impl LootEntry {
+    /// Always drops.
+    pub fn guaranteed(item: ItemKind) -> Self {
+        LootEntry { item, chance: 1.0 }
+    }
+}
+
+/// What an entity might drop when it dies. Every entry is rolled separately.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LootTable {
+    pub entries: Vec<LootEntry>,
+}
+
+impl LootTable {
+    pub fn roll(&self, rng: &mut impl Rng) -> Vec<ItemKind> {
+        let drops: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|entry| rng.random_bool(entry.chance))
+            .map(|entry| entry.item)
+            .collect();
+        tracing::debug!(?drops, "LootTable::roll");
+        drops
+    }
+}
+
+/// Gets rolled for loot once, the first time it's opened.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Chest {
+    pub loot_table: LootTable,
+    pub opened: bool,
+    /// The key it needs, if it's locked.
+    pub locked: Option<u32>,
+}
+
+impl Chest {
+    // The font doesn't have anything fancier.
+    pub const CLOSED_GLYPH: char = '=';
+    pub const OPEN_GLYPH: char = '_';
+
+    pub fn glyph(&self) -> char {
+        if self.opened {
+            Chest::OPEN_GLYPH
+        } else {
+            Chest::CLOSED_GLYPH
+        }
+    }
+}
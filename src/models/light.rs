@@ -0,0 +1,242 @@
+//! How well lit each tile is.
+
+use crate::models::Position;
+use crate::models::map::Map;
+use doryen_rs::Color;
+use hecs::World;
+use serde::{Deserialize, Serialize};
+
+/// As bright as it gets. Light levels go from 0 (pitch black) up to this.
+pub const FULL_LIGHT: u8 = u8::MAX;
+/// How dim the dungeon is away from any torches.
+pub const DUNGEON_AMBIENT_LIGHT: u8 = 64;
+/// The warm orange of a torch.
+pub const TORCH_COLOR: Color = (255, 200, 120, 255);
+/// How much of its own color something keeps in pitch black, so nothing goes fully invisible.
+const MIN_TINT: f64 = 0.35;
+
+/// How bright it is anywhere nothing's lighting up. Lives on its own entity. Without one,
+/// everywhere counts as fully lit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AmbientLight(pub u8);
+
+/// Lights up everything within `radius` in `color`, fading out towards the edge. Doesn't care
+/// about walls. Anything carrying one takes the light with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightSource {
+    pub radius: usize,
+    pub color: Color,
+}
+
+impl LightSource {
+    /// How much light reaches `pos` from this when it's sat on `source_pos`.
+    pub fn light_at(&self, source_pos: &Position, pos: &Position) -> Color {
+        let distance = source_pos.distance_squared(pos).sqrt();
+        if distance >= self.radius as f64 {
+            return (0, 0, 0, 255);
+        }
+        let falloff = 1.0 - distance / self.radius as f64;
+        let fade = |channel: u8| (channel as f64 * falloff).round() as u8;
+        (
+            fade(self.color.0),
+            fade(self.color.1),
+            fade(self.color.2),
+            255,
+        )
+    }
+}
+
+/// Adds all the `lights` on top of `ambient`, one channel at a time, topping out at full.
+pub fn accumulate_light(ambient: u8, lights: impl IntoIterator<Item = Color>) -> Color {
+    lights.into_iter().fold(
+        (ambient, ambient, ambient, 255),
+        |(r, g, b, a), (light_r, light_g, light_b, _)| {
+            (
+                r.saturating_add(light_r),
+                g.saturating_add(light_g),
+                b.saturating_add(light_b),
+                a,
+            )
+        },
+    )
+}
+
+/// How bright `light` is overall, going by its brightest channel.
+pub fn brightness((r, g, b, _a): Color) -> u8 {
+    r.max(g).max(b)
+}
+
+/// `color` as it looks under `light`.
+pub fn tint(color: Color, light: Color) -> Color {
+    let lit = |channel: u8, light: u8| {
+        let factor = MIN_TINT + (1.0 - MIN_TINT) * light as f64 / FULL_LIGHT as f64;
+        (channel as f64 * factor).round() as u8
+    };
+    (
+        lit(color.0, light.0),
+        lit(color.1, light.1),
+        lit(color.2, light.2),
+        color.3,
+    )
+}
+
+/// Every light in the world at one point in time, for looking up how lit a tile is.
+#[derive(Debug, Clone)]
+pub struct LightLevels {
+    ambient: u8,
+    sources: Vec<(Position, LightSource)>,
+}
+
+impl LightLevels {
+    pub fn from_world(world: &World) -> Self {
+        LightLevels {
+            ambient: world
+                .query::<&AmbientLight>()
+                .iter()
+                .next()
+                .map_or(FULL_LIGHT, |(_id, ambient)| ambient.0),
+            sources: world
+                .query::<(&Position, &LightSource)>()
+                .iter()
+                .map(|(_id, (pos, source))| (pos.clone(), *source))
+                .collect(),
+        }
+    }
+
+    /// Everything shining on `pos` added together.
+    pub fn color_at(&self, pos: &Position) -> Color {
+        accumulate_light(
+            self.ambient,
+            self.sources
+                .iter()
+                .map(|(source_pos, source)| source.light_at(source_pos, pos)),
+        )
+    }
+
+    /// How bright `pos` is overall.
+    pub fn at(&self, pos: &Position) -> u8 {
+        brightness(self.color_at(pos))
+    }
+}
+
+/// The light on every tile of the map, for drawing. Kept up to date by the `LightingSystem`.
+/// Lives on its own entity.
+#[derive(Debug, Clone, Default)]
+pub struct LightMap {
+    width: usize,
+    tiles: Vec<Color>,
+    /// The lights that were around when `tiles` was worked out.
+    computed_for: Vec<(Position, LightSource)>,
+    ambient: u8,
+}
+
+impl LightMap {
+    /// Whether the lights have moved or changed since this was worked out.
+    pub fn is_stale(&self, levels: &LightLevels, map: &Map) -> bool {
+        self.computed_for != levels.sources
+            || self.ambient != levels.ambient
+            || self.tiles.len() != map.width * map.height
+    }
+
+    pub fn update(&mut self, levels: &LightLevels, map: &Map) {
+        self.width = map.width;
+        self.tiles = (0..map.width * map.height)
+            .map(|i| {
+                levels.color_at(&Position::new(
+                    (i % map.width) as isize,
+                    (i / map.width) as isize,
+                ))
+            })
+            .collect();
+        self.computed_for = levels.sources.clone();
+        self.ambient = levels.ambient;
+    }
+
+    /// `None` if `pos` is off the map or nothing's been worked out yet.
+    pub fn color_at(&self, pos: &Position) -> Option<Color> {
+        if pos.x < 0 || pos.y < 0 || pos.x as usize >= self.width {
+            return None;
+        }
+        self.tiles
+            .get(pos.y as usize * self.width + pos.x as usize)
+            .copied()
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_light_fades_out_from_source() {
+        let mut world = World::new();
+        world.spawn((AmbientLight(20),));
+        world.spawn((
+            Position::new(5, 5),
+            LightSource {
+                radius: 4,
+                color: (200, 200, 200, 255),
+            },
+        ));
+        let light = LightLevels::from_world(&world);
+
+        assert_eq!(light.at(&Position::new(5, 5)), 220);
+        assert_eq!(light.at(&Position::new(7, 5)), 120);
+        // Past the edge of the light it's as dark as everywhere else.
+        assert_eq!(light.at(&Position::new(9, 5)), 20);
+        assert_eq!(light.at(&Position::new(30, 30)), 20);
+    }
+
+    #[test]
+    fn test_overlapping_lights_add_up_and_clamp() {
+        assert_eq!(
+            accumulate_light(10, [(100, 200, 0, 255), (100, 100, 50, 255)]),
+            (210, 255, 60, 255)
+        );
+        assert_eq!(accumulate_light(10, []), (10, 10, 10, 255));
+    }
+
+    #[test]
+    fn test_no_ambient_light_means_fully_lit() {
+        let light = LightLevels::from_world(&World::new());
+        assert_eq!(light.at(&Position::new(3, 3)), FULL_LIGHT);
+    }
+
+    #[test]
+    fn test_light_map_follows_the_torch() {
+        let map = Map::new_bordered(10, 10);
+        let mut world = World::new();
+        world.spawn((AmbientLight(0),));
+        let torch = world.spawn((
+            Position::new(2, 2),
+            LightSource {
+                radius: 2,
+                color: TORCH_COLOR,
+            },
+        ));
+        let mut light_map = LightMap::default();
+        let levels = LightLevels::from_world(&world);
+        assert!(light_map.is_stale(&levels, &map));
+        light_map.update(&levels, &map);
+        assert!(!light_map.is_stale(&levels, &map));
+        assert_eq!(light_map.color_at(&Position::new(2, 2)), Some(TORCH_COLOR));
+        assert_eq!(
+            light_map.color_at(&Position::new(7, 7)),
+            Some((0, 0, 0, 255))
+        );
+
+        world.get::<&mut Position>(torch).unwrap().x = 7;
+        world.get::<&mut Position>(torch).unwrap().y = 7;
+        let levels = LightLevels::from_world(&world);
+        assert!(light_map.is_stale(&levels, &map));
+        light_map.update(&levels, &map);
+        assert_eq!(light_map.color_at(&Position::new(7, 7)), Some(TORCH_COLOR));
+        assert_eq!(light_map.color_at(&Position::new(20, 2)), None);
+    }
+
+    #[test]
+    fn test_tint_darkens_but_never_hides() {
+        let color = (200, 100, 0, 255);
+        assert_eq!(tint(color, (255, 255, 255, 255)), color);
+        assert_eq!(tint(color, (0, 0, 0, 255)), (70, 35, 0, 255));
+    }
+}
@@ -0,0 +1,231 @@
+//! The dungeon layout itself.
+
+use crate::models::Position;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TileType {
+    Floor,
+    Wall,
+    /// Can be waded through, slowly.
+    Water,
+    /// Can be climbed over, slowly.
+    Rubble,
+}
+
+impl TileType {
+    /// How many times longer than usual it takes to step onto this. Walls can't be stepped onto
+    /// at all, so they're left at 1.
+    pub fn movement_cost(&self) -> u32 {
+        match self {
+            TileType::Floor | TileType::Wall => 1,
+            TileType::Rubble => 2,
+            TileType::Water => 3,
+        }
+    }
+}
+
+/// Every tile on the current floor. Lives on its own entity in the world.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Map {
+    pub width: usize,
+    pub height: usize,
+    tiles: Vec<TileType>,
+    /// Goes up every time a tile changes, so anything worked out from the map knows when it's stale.
+    revision: u64,
+}
+
+impl Map {
+    /// An open floor with walls all the way around the edge.
+    pub fn new_bordered(width: usize, height: usize) -> Self {
+        let mut map = Map {
+            width,
+            height,
+            tiles: vec![TileType::Floor; width * height],
+            revision: 0,
+        };
+        for x in 0..width {
+            map.set(&Position::new(x as isize, 0), TileType::Wall);
+            map.set(
+                &Position::new(x as isize, height as isize - 1),
+                TileType::Wall,
+            );
+        }
+        for y in 0..height {
+            map.set(&Position::new(0, y as isize), TileType::Wall);
+            map.set(
+                &Position::new(width as isize - 1, y as isize),
+                TileType::Wall,
+            );
+        }
+        map
+    }
+
+    fn index(&self, pos: &Position) -> Option<usize> {
+        if pos.x < 0 || pos.y < 0 || pos.x as usize >= self.width || pos.y as usize >= self.height {
+            return None;
+        }
+        Some(pos.y as usize * self.width + pos.x as usize)
+    }
+
+    /// `None` if `pos` is off the map.
+    pub fn get(&self, pos: &Position) -> Option<TileType> {
+        self.index(pos).map(|i| self.tiles[i])
+    }
+
+    /// Does nothing if `pos` is off the map.
+    pub fn set(&mut self, pos: &Position, tile: TileType) {
+        if let Some(i) = self.index(pos) {
+            self.tiles[i] = tile;
+            self.revision += 1;
+        }
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Whether nothing can stand on `pos`. Anything off the map counts.
+    pub fn is_blocked(&self, pos: &Position) -> bool {
+        matches!(self.get(pos), Some(TileType::Wall) | None)
+    }
+
+    /// How many times longer than usual it takes to step onto `pos`. Anything off the map is 1.
+    pub fn movement_cost(&self, pos: &Position) -> u32 {
+        self.get(pos).map_or(1, |tile| tile.movement_cost())
+    }
+
+    pub fn positions_of(&self, tile: TileType) -> impl Iterator<Item = Position> + '_ {
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(move |(_, t)| **t == tile)
+            .map(|(i, _)| Position::new((i % self.width) as isize, (i / self.width) as isize))
+    }
+
+    pub fn wall_positions(&self) -> impl Iterator<Item = Position> + '_ {
+        self.positions_of(TileType::Wall)
+    }
+}
+
+/// What the player can see right now. Lives on the player.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Fov {
+    pub radius: u32,
+    visible: HashSet<Position>,
+    /// Where the player was and which map revision it was when `visible` was worked out.
+    computed_for: Option<(Position, u64)>,
+    /// Set when something that isn't on the map, like a door, changed what can be seen through.
+    #[serde(skip)]
+    invalidated: bool,
+    /// Everything that's been seen on this floor so far, whether it still can be or not.
+    #[serde(default)]
+    revealed: HashSet<Position>,
+}
+
+impl Fov {
+    pub fn new(radius: u32) -> Self {
+        Fov {
+            radius,
+            visible: HashSet::new(),
+            computed_for: None,
+            invalidated: false,
+            revealed: HashSet::new(),
+        }
+    }
+
+    /// Whether `visible` needs working out again because the player moved or the map changed.
+    pub fn is_stale(&self, origin: &Position, map_revision: u64) -> bool {
+        self.invalidated || self.computed_for != Some((origin.clone(), map_revision))
+    }
+
+    /// Forces the next check to work `visible` out again. What's visible now stays until then.
+    pub fn invalidate(&mut self) {
+        self.invalidated = true;
+    }
+
+    pub fn update(&mut self, origin: &Position, map_revision: u64, visible: HashSet<Position>) {
+        self.revealed.extend(visible.iter().cloned());
+        self.visible = visible;
+        self.computed_for = Some((origin.clone(), map_revision));
+        self.invalidated = false;
+    }
+
+    /// Nothing gets hidden until the FOV has been worked out at least once.
+    pub fn can_see(&self, pos: &Position) -> bool {
+        self.computed_for.is_none() || self.visible.contains(pos)
+    }
+
+    pub fn revealed(&self) -> &HashSet<Position> {
+        &self.revealed
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bordered_map() {
+        let map = Map::new_bordered(5, 4);
+        assert_eq!(map.get(&Position::new(0, 0)), Some(TileType::Wall));
+        assert_eq!(map.get(&Position::new(4, 3)), Some(TileType::Wall));
+        assert_eq!(map.get(&Position::new(2, 2)), Some(TileType::Floor));
+        assert_eq!(map.get(&Position::new(5, 0)), None);
+        assert!(map.is_blocked(&Position::new(-1, 2)));
+        assert!(!map.is_blocked(&Position::new(1, 1)));
+        // 5 + 5 along the top and bottom, 2 + 2 down the sides.
+        assert_eq!(map.wall_positions().count(), 14);
+    }
+
+    #[test]
+    fn test_slow_terrain() {
+        let mut map = Map::new_bordered(5, 4);
+        map.set(&Position::new(1, 1), TileType::Water);
+        map.set(&Position::new(2, 1), TileType::Rubble);
+        assert!(!map.is_blocked(&Position::new(1, 1)));
+        assert!(!map.is_blocked(&Position::new(2, 1)));
+        assert_eq!(map.movement_cost(&Position::new(1, 1)), 3);
+        assert_eq!(map.movement_cost(&Position::new(2, 1)), 2);
+        assert_eq!(map.movement_cost(&Position::new(3, 1)), 1);
+        assert_eq!(
+            map.positions_of(TileType::Water).collect::<Vec<_>>(),
+            vec![Position::new(1, 1)]
+        );
+    }
+
+    #[test]
+    fn test_fov_goes_stale() {
+        let mut map = Map::new_bordered(5, 4);
+        let mut fov = Fov::new(3);
+        let origin = Position::new(2, 2);
+        assert!(fov.is_stale(&origin, map.revision()));
+        assert!(fov.can_see(&Position::new(100, 100)));
+
+        fov.update(&origin, map.revision(), HashSet::from([origin.clone()]));
+        assert!(!fov.is_stale(&origin, map.revision()));
+        assert!(!fov.can_see(&Position::new(100, 100)));
+        assert!(fov.is_stale(&Position::new(1, 2), map.revision()));
+
+        map.set(&Position::new(1, 1), TileType::Wall);
+        assert!(fov.is_stale(&origin, map.revision()));
+
+        fov.update(&origin, map.revision(), HashSet::from([origin.clone()]));
+        fov.invalidate();
+        assert!(fov.is_stale(&origin, map.revision()));
+        assert!(fov.can_see(&origin));
+    }
+
+    #[test]
+    fn test_fov_remembers_what_its_seen() {
+        let mut fov = Fov::new(3);
+        for origin in [Position::new(1, 1), Position::new(2, 1)] {
+            fov.update(&origin, 0, HashSet::from([origin.clone()]));
+        }
+        assert!(!fov.can_see(&Position::new(1, 1)));
+        assert_eq!(
+            *fov.revealed(),
+            HashSet::from([Position::new(1, 1), Position::new(2, 1)])
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! A shrunk down picture of the floor, for finding your way back around it.
+
+use crate::models::Position;
+use crate::models::map::Map;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// How many map tiles go across (and down) each minimap cell.
+pub const MINIMAP_FACTOR: usize = 4;
+
+/// What one cell of the minimap shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MiniCell {
+    Unseen,
+    Floor,
+    Wall,
+}
+
+/// Whether the minimap's being drawn. Lives on its own entity in the world.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Minimap {
+    pub shown: bool,
+}
+
+/// How many cells across and down `map` takes up on the minimap. Blocks on the right and bottom
+/// edges can be smaller than `factor`, but still get a cell.
+pub fn minimap_size(map: &Map, factor: usize) -> (usize, usize) {
+    let factor = factor.max(1);
+    (map.width.div_ceil(factor), map.height.div_ceil(factor))
+}
+
+/// Squashes every `factor` by `factor` block of `map` down into one cell, row by row. Each cell is
+/// whatever most of its block is, going by only the tiles in `revealed`. Ties go to walls, then
+/// floor, so anything that's been half seen still shows up.
+pub fn downsample(map: &Map, revealed: &HashSet<Position>, factor: usize) -> Vec<MiniCell> {
+    let factor = factor.max(1);
+    let (width, height) = minimap_size(map, factor);
+    let mut cells = Vec::with_capacity(width * height);
+    for cell_y in 0..height {
+        for cell_x in 0..width {
+            let (mut walls, mut floors, mut unseen) = (0, 0, 0);
+            for y in cell_y * factor..((cell_y + 1) * factor).min(map.height) {
+                for x in cell_x * factor..((cell_x + 1) * factor).min(map.width) {
+                    let pos = Position::new(x as isize, y as isize);
+                    if !revealed.contains(&pos) {
+                        unseen += 1;
+                    } else if map.is_blocked(&pos) {
+                        walls += 1;
+                    } else {
+                        floors += 1;
+                    }
+                }
+            }
+            cells.push(if unseen > walls.max(floors) {
+                MiniCell::Unseen
+            } else if walls >= floors {
+                MiniCell::Wall
+            } else {
+                MiniCell::Floor
+            });
+        }
+    }
+    cells
+}
+
+mod tests {
+    use super::*;
+    use crate::models::map::TileType;
+
+    fn everything(map: &Map) -> HashSet<Position> {
+        (0..map.height)
+            .flat_map(|y| (0..map.width).map(move |x| Position::new(x as isize, y as isize)))
+            .collect()
+    }
+
+    #[test]
+    fn test_downsample_evenly_divided() {
+        let map = Map::new_bordered(4, 4);
+        assert_eq!(minimap_size(&map, 2), (2, 2));
+        // Every corner block is three walls to one floor.
+        assert_eq!(
+            downsample(&map, &everything(&map), 2),
+            vec![MiniCell::Wall; 4]
+        );
+
+        // Nothing to shrink, so it's just the map.
+        let cells = downsample(&map, &everything(&map), 1);
+        assert_eq!(cells.len(), 16);
+        assert_eq!(cells[5], MiniCell::Floor);
+        assert_eq!(cells[0], MiniCell::Wall);
+    }
+
+    #[test]
+    fn test_downsample_with_smaller_edge_blocks() {
+        use MiniCell::{Floor, Wall};
+        let map = Map::new_bordered(7, 7);
+        assert_eq!(minimap_size(&map, 3), (3, 3));
+        // The last column and row of blocks are only the border, one tile wide.
+        assert_eq!(
+            downsample(&map, &everything(&map), 3),
+            vec![Wall, Floor, Wall, Floor, Floor, Wall, Wall, Wall, Wall]
+        );
+
+        let map = Map::new_bordered(10, 5);
+        assert_eq!(minimap_size(&map, 4), (3, 2));
+        assert_eq!(downsample(&map, &everything(&map), 4).len(), 6);
+
+        // Bigger than the whole map is just the one cell.
+        let mut map = Map::new_bordered(5, 5);
+        for x in 1..4 {
+            map.set(&Position::new(x, 2), TileType::Water);
+        }
+        assert_eq!(downsample(&map, &everything(&map), 8), vec![Wall]);
+    }
+
+    #[test]
+    fn test_downsample_only_shows_whats_been_seen() {
+        use MiniCell::{Floor, Unseen, Wall};
+        let map = Map::new_bordered(7, 7);
+        assert_eq!(downsample(&map, &HashSet::new(), 3), vec![Unseen; 9]);
+
+        let left_side: HashSet<Position> = everything(&map)
+            .into_iter()
+            .filter(|pos| pos.x < 3)
+            .collect();
+        assert_eq!(
+            downsample(&map, &left_side, 3),
+            vec![
+                Wall, Unseen, Unseen, Floor, Unseen, Unseen, Wall, Unseen, Unseen
+            ]
+        );
+
+        // One floor tile out of nine isn't enough to go on.
+        let one_tile = HashSet::from([Position::new(1, 1)]);
+        assert_eq!(downsample(&map, &one_tile, 3)[0], Unseen);
+    }
+}
@@ -1,24 +1,49 @@
+use crate::config::GameConfig;
 use doryen_rs::Color;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::ops::{Deref, DerefMut};
 
 pub mod ai;
+pub mod animation;
+pub mod aura;
+pub mod effects;
+pub mod equipment;
 pub mod input;
+pub mod interaction;
+pub mod items;
+pub mod light;
+pub mod map;
+pub mod minimap;
+pub mod overlay;
+pub mod perception;
+pub mod running;
+pub mod scheduler;
+pub mod spatial_index;
+pub mod spawn_table;
 pub mod stats;
+pub mod targeting;
+pub mod traps;
+pub mod vendor;
 
-use crate::{CONSOLE_HEIGHT, CONSOLE_WIDTH};
 pub use input::Player;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub enum DistanceMetric {
     /// Manhattan Distance (abs(dx) + abs(dy)). Use if you want things to be box like.
     Manhattan,
     /// Euclidean Distance. Slow to run, but use if you want things to be circular.
     Euclidean,
     /// Squared Euclidean Distance. Faster than Euclidean (cuz no sqrt) but you need to square your comparison. Can be used in the same way as Euclidean.
+    #[default]
     EuclideanSquared,
 }
 
 /// World Coordinates
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Position {
     pub x: isize,
     pub y: isize,
@@ -34,6 +59,17 @@ impl DistanceMetric {
         tracing::trace!(?out_distance, ?pos, ?other);
         out_distance
     }
+
+    /// Whether `other` is no more than `range` tiles from `pos`. Takes care of squaring `range`
+    /// for `EuclideanSquared`.
+    pub fn is_within(&self, pos: &Position, other: &Position, range: usize) -> bool {
+        let range = range as f64;
+        let range = match self {
+            DistanceMetric::EuclideanSquared => range * range,
+            DistanceMetric::Manhattan | DistanceMetric::Euclidean => range,
+        };
+        self.distance(pos, other) <= range
+    }
 }
 
 impl Position {
@@ -46,12 +82,13 @@ impl Position {
         let (dy, dx) = angle.sin_cos();
         let (adx, ady) = (dx.abs(), dy.abs());
         let (sdx, sdy) = (dx.signum(), dy.signum());
-        let out_pos = if adx > ady {
+        // Going exactly diagonally doesn't always come out of sin/cos exactly equal.
+        let out_pos = if adx - ady > 1e-9 {
             Position {
                 x: self.x + sdx as isize * 1,
                 y: self.y,
             }
-        } else if ady > adx {
+        } else if ady - adx > 1e-9 {
             Position {
                 x: self.x,
                 y: self.y + sdy as isize * 1,
@@ -68,6 +105,36 @@ impl Position {
         // self.go_distance_theta(distance as f64, angle)
     }
 
+    /// Like `go_towards`, but if that step `is_blocked` it tries going along the other axis
+    /// instead, as long as that still gets closer to `goal`. A blocked diagonal gets tried one
+    /// axis at a time. If none of that works either, it's the same step `go_towards` would take.
+    pub fn go_towards_avoiding(
+        &self,
+        goal: &Position,
+        is_blocked: impl Fn(&Position) -> bool,
+    ) -> Position {
+        let preferred = self.go_towards(goal);
+        if !is_blocked(&preferred) {
+            return preferred;
+        }
+        let (dx, dy) = (preferred.x - self.x, preferred.y - self.y);
+        let detours = match (dx, dy) {
+            (0, _) => vec![self.new_from_dx_dy((goal.x - self.x).signum(), 0)],
+            (_, 0) => vec![self.new_from_dx_dy(0, (goal.y - self.y).signum())],
+            _ => vec![self.new_from_dx_dy(dx, 0), self.new_from_dx_dy(0, dy)],
+        };
+        match detours
+            .into_iter()
+            .find(|detour| detour != self && !is_blocked(detour))
+        {
+            Some(detour) => {
+                tracing::trace!(?preferred, ?detour, "Going around");
+                detour
+            }
+            None => preferred,
+        }
+    }
+
     /// Inclusive bounds.
     pub fn is_within_bounds(&self, (min_x, max_x): (u32, u32), (min_y, max_y): (u32, u32)) -> bool {
         self.x >= min_x as isize
@@ -76,14 +143,19 @@ impl Position {
             && self.y <= max_y as isize
     }
 
-    pub fn is_within_console_bounds(&self) -> bool {
-        self.is_within_bounds((1, CONSOLE_WIDTH - 2), (1, CONSOLE_HEIGHT - 2))
+    /// Inside the console, not counting the border around the edge.
+    pub fn is_within_console_bounds(&self, config: &GameConfig) -> bool {
+        self.is_within_bounds(
+            (1, config.console_width - 2),
+            (1, config.console_height - 2),
+        )
     }
 
+    /// Goes `distance` tiles along `theta` (radians, same as `angle`), rounding to the nearest tile.
     pub fn go_distance_theta(&self, distance: f64, theta: f64) -> Position {
-        let (dx, dy) = theta.sin_cos();
-        let x = (dx * distance) as isize;
-        let y = (dy * distance) as isize;
+        let (dy, dx) = theta.sin_cos();
+        let x = (dx * distance).round() as isize;
+        let y = (dy * distance).round() as isize;
         let out = Position::new(x + self.x, y + self.y);
         tracing::trace!(?out, ?self, ?distance, ?theta, ?dy, ?dx);
         out
@@ -93,6 +165,35 @@ impl Position {
         Position::new(self.x + dx, self.y + dy)
     }
 
+    /// The four positions you can reach with a single arrow key press.
+    pub fn cardinal_neighbors(&self) -> [Position; 4] {
+        [
+            self.new_from_dx_dy(-1, 0),
+            self.new_from_dx_dy(1, 0),
+            self.new_from_dx_dy(0, -1),
+            self.new_from_dx_dy(0, 1),
+        ]
+    }
+
+    /// All eight positions around this one, diagonals included.
+    pub fn neighbors(&self) -> [Position; 8] {
+        [
+            self.new_from_dx_dy(-1, -1),
+            self.new_from_dx_dy(0, -1),
+            self.new_from_dx_dy(1, -1),
+            self.new_from_dx_dy(-1, 0),
+            self.new_from_dx_dy(1, 0),
+            self.new_from_dx_dy(-1, 1),
+            self.new_from_dx_dy(0, 1),
+            self.new_from_dx_dy(1, 1),
+        ]
+    }
+
+    /// Whether `other` is exactly one cardinal step away.
+    pub fn is_adjacent(&self, other: &Position) -> bool {
+        self.fast_distance(other) == 1.0
+    }
+
     /// Output is in radians. Uses Manhattan distance by default.
     pub fn angle(&self, other: &Position) -> f64 {
         let dx = (other.x - self.x) as f64;
@@ -149,16 +250,260 @@ pub struct WindowCoordinates {
 #[derive(Debug)]
 pub struct Motion;
 
+/// Marker for entities that nothing else can walk through (the player, monsters).
+/// Things like items and corpses leave this off so they can share a tile.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlocksTile;
+
+/// Closed doors also have `BlocksTile`, which comes off once they're opened.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Door {
+    pub open: bool,
+}
+
+impl Door {
+    pub const CLOSED_GLYPH: char = '+';
+    pub const OPEN_GLYPH: char = '-';
+    pub const COLOR: Color = (150, 100, 50, 255);
+    pub const LOCKED_COLOR: Color = (220, 180, 40, 255);
+
+    pub fn glyph(&self) -> char {
+        if self.open {
+            Door::OPEN_GLYPH
+        } else {
+            Door::CLOSED_GLYPH
+        }
+    }
+}
+
+/// Goes on a door that won't open without a key with the same `key_id`. Monsters can't get
+/// through locked doors at all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Locked {
+    pub key_id: u32,
+}
+
+/// The RNG everything in the game should pull from so runs can be reproduced from a seed.
+/// Lives on its own entity in the world.
 #[derive(Debug)]
+pub struct GameRng(StdRng);
+
+impl GameRng {
+    pub fn seeded(seed: u64) -> Self {
+        tracing::info!(?seed, "Seeding game RNG");
+        GameRng(StdRng::seed_from_u64(seed))
+    }
+}
+
+impl Deref for GameRng {
+    type Target = StdRng;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for GameRng {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntityName {
+    pub name: String,
+}
+
+impl Display for EntityName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
+/// Which floor of the dungeon the player is on, starting at 1. Lives on the player.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DungeonDepth(pub u32);
+
+/// How many lines the log hangs onto before the oldest start dropping off.
+pub const LOG_HISTORY_LIMIT: usize = 1000;
+
+/// One line of the log. Saying the same thing again straight after just bumps `count`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub text: String,
+    pub count: u32,
+}
+
+impl Display for LogEntry {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if self.count > 1 {
+            write!(f, "{} (x{})", self.text, self.count)
+        } else {
+            write!(f, "{}", self.text)
+        }
+    }
+}
+
+/// Everything that's been said to the player this run, oldest first, up to `LOG_HISTORY_LIMIT`
+/// lines. Lives on its own entity in the world.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct GameLog {
+    entries: VecDeque<LogEntry>,
+}
+
+impl GameLog {
+    pub fn push(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        if let Some(last) = self.entries.back_mut() {
+            if last.text == text {
+                last.count += 1;
+                return;
+            }
+        }
+        self.entries.push_back(LogEntry { text, count: 1 });
+        if self.entries.len() > LOG_HISTORY_LIMIT {
+            self.entries.pop_front();
+        }
+    }
+
+    /// How many lines there are, with repeats only counting once.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every line the way it gets shown, oldest first.
+    pub fn lines(&self) -> impl DoubleEndedIterator<Item = String> + '_ {
+        self.entries.iter().map(LogEntry::to_string)
+    }
+
+    /// The `count` lines that would be on screen when scrolled `scroll` lines back from the
+    /// newest, oldest first.
+    pub fn page(&self, scroll: usize, count: usize) -> Vec<String> {
+        let end = self.len().saturating_sub(scroll);
+        let start = end.saturating_sub(count);
+        self.entries
+            .range(start..end)
+            .map(LogEntry::to_string)
+            .collect()
+    }
+
+    /// How many lines of the log fit in the full screen viewer, leaving room for its title.
+    pub fn viewer_page_lines(screen_height: usize) -> usize {
+        screen_height.saturating_sub(2)
+    }
+
+    /// Where `scroll` ends up after moving it `by` lines back, without going past the newest line
+    /// or so far back that a page of `page_lines` has empty space at the top.
+    pub fn scroll_by(&self, scroll: usize, by: isize, page_lines: usize) -> usize {
+        let max_scroll = self.len().saturating_sub(page_lines);
+        scroll.saturating_add_signed(by).min(max_scroll)
+    }
+}
+
+/// Whether the run is still going. Lives on its own entity in the world. Menus and pausing are
+/// handled outside the simulation, this only knows whether the player is dead yet.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RunState {
+    #[default]
+    Running,
+    GameOver,
+}
+
+/// Things the simulation wants done that only the engine can do, like grabbing the screen.
+/// The engine clears these once it's dealt with them. Lives on its own entity in the world.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EngineRequests {
+    pub screenshot: bool,
+    /// Write everything in the world out to a file, for debugging.
+    pub world_dump: bool,
+    /// Swap the world out for the last save. The `Simulation` sees to this one rather than the
+    /// engine, since every system has to start over on the new world too.
+    pub load_game: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Renderable {
     pub glyph: char,
     pub color: Color,
+    /// Whatever's highest gets drawn on top when things share a tile.
+    pub render_order: i32,
+    /// Drawn instead of `color` while set, so animations don't lose the real color.
+    pub tint: Option<Color>,
+    /// Fills in behind the glyph, for making something stand out. Left alone when unset.
+    pub bg: Option<Color>,
+}
+
+impl Renderable {
+    /// Corpses, items, and anything else lying on the floor.
+    pub const FLOOR_ORDER: i32 = 0;
+    /// The player and monsters.
+    pub const ACTOR_ORDER: i32 = 1;
+
+    pub fn drawn_color(&self) -> Color {
+        self.tint.unwrap_or(self.color)
+    }
 }
 
 mod tests {
     use super::*;
     // use crate::models::Position;
 
+    #[test]
+    fn test_game_log_coalesces_repeats() {
+        let mut game_log = GameLog::default();
+        for _ in 0..3 {
+            game_log.push("Goblin hits you for 1");
+        }
+        game_log.push("You hit the Goblin for 2");
+        game_log.push("Goblin hits you for 1");
+
+        assert_eq!(
+            game_log.lines().collect::<Vec<_>>(),
+            vec![
+                "Goblin hits you for 1 (x3)",
+                "You hit the Goblin for 2",
+                "Goblin hits you for 1",
+            ]
+        );
+        assert_eq!(
+            game_log.page(0, 2),
+            game_log.lines().skip(1).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_game_log_drops_oldest_past_limit() {
+        let mut game_log = GameLog::default();
+        for i in 0..LOG_HISTORY_LIMIT + 5 {
+            game_log.push(format!("Message {i}"));
+        }
+        assert_eq!(game_log.len(), LOG_HISTORY_LIMIT);
+        assert_eq!(game_log.lines().next().unwrap(), "Message 5");
+        assert_eq!(
+            game_log.lines().next_back().unwrap(),
+            format!("Message {}", LOG_HISTORY_LIMIT + 4)
+        );
+    }
+
+    #[test]
+    fn test_game_log_scroll_is_clamped() {
+        let mut game_log = GameLog::default();
+        for i in 0..30 {
+            game_log.push(format!("Message {i}"));
+        }
+        assert_eq!(game_log.scroll_by(0, -1, 10), 0);
+        assert_eq!(game_log.scroll_by(0, 5, 10), 5);
+        assert_eq!(game_log.scroll_by(15, 10, 10), 20);
+        assert_eq!(game_log.scroll_by(20, -25, 10), 0);
+        assert_eq!(game_log.page(20, 10)[0], "Message 0");
+        // Everything fits on one page, so there's nowhere to scroll.
+        assert_eq!(game_log.scroll_by(0, 3, 50), 0);
+    }
+
     #[test]
     fn test_position_create() {
         let pos = Position::new(10, 10);
@@ -287,6 +632,24 @@ mod tests {
         assert_eq!(angle2, 180.0_f64.to_radians());
     }
 
+    #[test]
+    fn test_go_distance_theta() {
+        let start = Position::new(5, 5);
+        assert_eq!(start.go_distance_theta(2.0, 0.0), Position::new(7, 5));
+        assert_eq!(
+            start.go_distance_theta(1.0, 90.0_f64.to_radians()),
+            Position::new(5, 6)
+        );
+        assert_eq!(
+            start.go_distance_theta(1.0, 180.0_f64.to_radians()),
+            Position::new(4, 5)
+        );
+        assert_eq!(
+            start.go_distance_theta(1.0, 45.0_f64.to_radians()),
+            Position::new(6, 6)
+        );
+    }
+
     #[test]
     fn test_go_towards() {
         let initial_start = Position::new(0, 0);
@@ -306,4 +669,59 @@ mod tests {
             curr_pos = next_pos;
         }
     }
+
+    #[test]
+    fn test_go_towards_every_diagonal() {
+        let start = Position::new(15, 3);
+        for (dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            let step = start.new_from_dx_dy(dx, dy);
+            assert_eq!(start.go_towards(&step), step);
+        }
+    }
+
+    #[test]
+    fn test_go_towards_avoiding() {
+        let start = Position::new(5, 5);
+        let goal = Position::new(9, 6);
+        assert_eq!(start.go_towards(&goal), Position::new(6, 5));
+        assert_eq!(
+            start.go_towards_avoiding(&goal, |_| false),
+            Position::new(6, 5)
+        );
+
+        // Straight ahead's blocked, so it goes down first instead.
+        let wall = Position::new(6, 5);
+        assert_eq!(
+            start.go_towards_avoiding(&goal, |pos| *pos == wall),
+            Position::new(5, 6)
+        );
+
+        // Right in line with the goal, there's no other way that gets any closer.
+        let in_line = Position::new(9, 5);
+        assert_eq!(
+            start.go_towards_avoiding(&in_line, |pos| *pos == wall),
+            wall
+        );
+    }
+
+    #[test]
+    fn test_go_towards_avoiding_blocked_diagonal() {
+        let start = Position::new(5, 5);
+        let goal = Position::new(8, 8);
+        let blocked = |pos: &Position| *pos == Position::new(6, 6);
+        let metric = DistanceMetric::Euclidean;
+
+        // Walks the whole way there instead of getting stuck behind the one tile.
+        let mut pos = start;
+        for _ in 0..6 {
+            if pos == goal {
+                break;
+            }
+            let next = pos.go_towards_avoiding(&goal, blocked);
+            assert!(!blocked(&next), "Walked into the obstacle from {pos:?}");
+            assert!(next.distance(&goal, &metric) < pos.distance(&goal, &metric));
+            pos = next;
+        }
+        assert_eq!(pos, goal);
+    }
 }
@@ -0,0 +1,134 @@
+//! Background colors drawn under whatever's on a tile, for showing what's happened there or what's
+//! about to.
+
+use crate::models::Position;
+use doryen_rs::Color;
+use std::collections::HashMap;
+
+/// Left behind wherever something took a hit.
+pub const BLOOD_COLOR: Color = (90, 0, 0, 255);
+/// Everywhere the player could aim at while targeting.
+pub const TARGETING_RANGE_COLOR: Color = (20, 50, 20, 255);
+/// Where a big hit that's winding up is going to come down.
+pub const TELEGRAPH_COLOR: Color = (110, 55, 0, 255);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Overlay {
+    color: Color,
+    /// `None` stays until it gets cleared.
+    ttl_turns: Option<u32>,
+}
+
+/// Background colors for tiles, drawn over the map but under anything on it. Lives on its own
+/// entity in the world.
+///
+/// Highlights are kept apart from everything else, and get drawn over it. They only last as long
+/// as whatever they're pointing out, so they get swapped out wholesale, leaving what's underneath
+/// alone.
+///
+/// None of it goes into saves, on purpose. It's only there for show, so losing the blood on a load
+/// doesn't change anything, and the windup warnings get redone from each `Telegraph` every frame
+/// anyway.
+#[derive(Debug, Clone, Default)]
+pub struct TileOverlay {
+    tiles: HashMap<Position, Overlay>,
+    highlights: HashMap<Position, Color>,
+}
+
+impl TileOverlay {
+    /// Colors in `pos`, replacing whatever color it had. Goes away after `ttl_turns` turns, or
+    /// stays until it's cleared if that's `None`.
+    pub fn set_overlay(&mut self, pos: Position, color: Color, ttl_turns: Option<u32>) {
+        self.tiles.insert(pos, Overlay { color, ttl_turns });
+    }
+
+    pub fn clear_overlay(&mut self, pos: &Position) {
+        self.tiles.remove(pos);
+    }
+
+    /// Takes everything off, highlights included, like for a new floor.
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+        self.highlights.clear();
+    }
+
+    /// Swaps out every highlight for `highlights`.
+    pub fn set_highlights(&mut self, highlights: impl IntoIterator<Item = (Position, Color)>) {
+        self.highlights = highlights.into_iter().collect();
+    }
+
+    /// Counts a turn off of everything that doesn't stay, clearing whatever's run out.
+    pub fn tick(&mut self) {
+        for overlay in self.tiles.values_mut() {
+            if let Some(ttl_turns) = overlay.ttl_turns.as_mut() {
+                *ttl_turns = ttl_turns.saturating_sub(1);
+            }
+        }
+        self.tiles
+            .retain(|_pos, overlay| overlay.ttl_turns.is_none_or(|turns| turns > 0));
+    }
+
+    /// What `pos` gets drawn with, highlights first.
+    pub fn color_at(&self, pos: &Position) -> Option<Color> {
+        self.highlights
+            .get(pos)
+            .or_else(|| self.tiles.get(pos).map(|overlay| &overlay.color))
+            .copied()
+    }
+
+    /// Every tile with a color, and what color that comes out as.
+    pub fn colored(&self) -> Vec<(Position, Color)> {
+        let mut colored: HashMap<Position, Color> = self
+            .tiles
+            .iter()
+            .map(|(pos, overlay)| (pos.clone(), overlay.color))
+            .collect();
+        colored.extend(
+            self.highlights
+                .iter()
+                .map(|(pos, color)| (pos.clone(), *color)),
+        );
+        colored.into_iter().collect()
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overlays_run_out_unless_they_stay() {
+        let mut overlay = TileOverlay::default();
+        let blood = Position::new(3, 3);
+        let flash = Position::new(4, 4);
+        overlay.set_overlay(blood.clone(), BLOOD_COLOR, None);
+        overlay.set_overlay(flash.clone(), TELEGRAPH_COLOR, Some(2));
+
+        overlay.tick();
+        assert_eq!(overlay.color_at(&flash), Some(TELEGRAPH_COLOR));
+        overlay.tick();
+        assert_eq!(overlay.color_at(&flash), None);
+        for _ in 0..10 {
+            overlay.tick();
+        }
+        assert_eq!(overlay.color_at(&blood), Some(BLOOD_COLOR));
+
+        overlay.clear_overlay(&blood);
+        assert_eq!(overlay.color_at(&blood), None);
+    }
+
+    #[test]
+    fn test_highlights_go_over_without_replacing() {
+        let mut overlay = TileOverlay::default();
+        let pos = Position::new(3, 3);
+        overlay.set_overlay(pos.clone(), BLOOD_COLOR, None);
+        overlay.set_highlights([(pos.clone(), TARGETING_RANGE_COLOR)]);
+        assert_eq!(overlay.color_at(&pos), Some(TARGETING_RANGE_COLOR));
+        assert_eq!(
+            overlay.colored(),
+            vec![(pos.clone(), TARGETING_RANGE_COLOR)]
+        );
+
+        overlay.set_highlights([]);
+        assert_eq!(overlay.color_at(&pos), Some(BLOOD_COLOR));
+    }
+}
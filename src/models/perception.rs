@@ -0,0 +1,113 @@
+//! What the player could actually know about, so the log doesn't give away fights three rooms
+//! over.
+
+use crate::models::map::Fov;
+use crate::models::{DistanceMetric, Position};
+use std::collections::HashSet;
+
+/// How much of something the player caught.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Perceived {
+    Seen,
+    /// Out of sight, but close enough to hear.
+    Heard,
+    Unknown,
+}
+
+/// Works out what the player would pick up on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Perception {
+    /// In tiles, going by Manhattan distance.
+    pub hearing_radius: usize,
+}
+
+impl Perception {
+    pub fn new(hearing_radius: usize) -> Self {
+        Self { hearing_radius }
+    }
+
+    /// Whether the player at `player` with `viewshed` would see or hear something at `event_pos`.
+    pub fn classify(&self, player: &Position, event_pos: &Position, viewshed: &Fov) -> Perceived {
+        if viewshed.can_see(event_pos) {
+            Perceived::Seen
+        } else if DistanceMetric::Manhattan.is_within(player, event_pos, self.hearing_radius) {
+            Perceived::Heard
+        } else {
+            Perceived::Unknown
+        }
+    }
+}
+
+/// Everything the player's heard but not seen this turn, so the same noise only gets logged once.
+/// Lives on its own entity in the world.
+#[derive(Debug, Clone, Default)]
+pub struct HeardThisTurn {
+    turn: u64,
+    heard: HashSet<String>,
+}
+
+impl HeardThisTurn {
+    /// Whether `what` is new as of `turn`. Anything heard on an earlier turn is forgotten.
+    pub fn first_time(&mut self, turn: u64, what: &str) -> bool {
+        if turn != self.turn {
+            self.turn = turn;
+            self.heard.clear();
+        }
+        self.heard.insert(what.to_string())
+    }
+}
+
+mod tests {
+    use super::*;
+
+    fn viewshed() -> Fov {
+        let mut fov = Fov::new(3);
+        let visible = (3..=7)
+            .flat_map(|x| (3..=7).map(move |y| Position::new(x, y)))
+            .collect();
+        fov.update(&Position::new(5, 5), 0, visible);
+        fov
+    }
+
+    #[test]
+    fn test_seen_heard_and_unknown() {
+        let perception = Perception::new(10);
+        let player = Position::new(5, 5);
+        let fov = viewshed();
+        assert_eq!(
+            perception.classify(&player, &Position::new(7, 6), &fov),
+            Perceived::Seen
+        );
+        assert_eq!(
+            perception.classify(&player, &Position::new(10, 10), &fov),
+            Perceived::Heard
+        );
+        // Manhattan, so the corners are further than they look.
+        assert_eq!(
+            perception.classify(&player, &Position::new(11, 11), &fov),
+            Perceived::Unknown
+        );
+        assert_eq!(
+            Perception::new(0).classify(&player, &Position::new(8, 5), &fov),
+            Perceived::Unknown
+        );
+    }
+
+    #[test]
+    fn test_nothing_hidden_before_the_fov_is_worked_out() {
+        let perception = Perception::new(10);
+        assert_eq!(
+            perception.classify(&Position::new(5, 5), &Position::new(50, 50), &Fov::new(3)),
+            Perceived::Seen
+        );
+    }
+
+    #[test]
+    fn test_hearing_the_same_thing_twice_in_a_turn() {
+        let mut heard = HeardThisTurn::default();
+        assert!(heard.first_time(1, "You hear fighting nearby."));
+        assert!(!heard.first_time(1, "You hear fighting nearby."));
+        assert!(heard.first_time(1, "You hear something die nearby."));
+        assert!(heard.first_time(2, "You hear fighting nearby."));
+    }
+}
@@ -0,0 +1,222 @@
+//! Running: stepping the same way turn after turn until something's worth stopping for.
+
+use crate::config::GameConfig;
+use crate::models::ai::Ai;
+use crate::models::map::{Fov, Map};
+use crate::models::stats::Health;
+use crate::models::{BlocksTile, Position};
+use hecs::{Entity, World};
+use std::collections::HashSet;
+
+/// Why a run came to an end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// Something's in the way of the next step.
+    Blocked,
+    /// The walls either side opened up or closed in, like at a side passage.
+    Junction,
+    /// Something hostile came into view.
+    NewlySeen,
+    /// The runner got hurt, or healed.
+    HealthChanged,
+    /// A key got pressed.
+    Cancelled,
+    /// Not enough stamina left for another step.
+    Exhausted,
+}
+
+/// Keeps the player stepping `direction` every turn by themselves. Lives on the player, and the
+/// `RunningSystem` decides when it starts and stops.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Running {
+    pub direction: (isize, isize),
+    pub active: bool,
+    /// Whichever hostiles were in view on the last step.
+    pub seen: HashSet<Entity>,
+    /// Health on the last step.
+    pub last_health: Option<i32>,
+    /// Whether the left and right of the last step were open.
+    pub last_sides: (bool, bool),
+}
+
+impl Running {
+    /// Starts `runner` off going `direction` from wherever they are now.
+    pub fn start(world: &World, runner: Entity, direction: (isize, isize)) -> Self {
+        let mut running = Running {
+            direction,
+            active: true,
+            seen: HashSet::new(),
+            last_health: None,
+            last_sides: (false, false),
+        };
+        running.remember(world, runner);
+        running
+    }
+
+    /// Takes note of how things look around `runner` now, to check the next step against.
+    pub fn remember(&mut self, world: &World, runner: Entity) {
+        self.seen = visible_hostiles(world, runner);
+        self.last_health = world
+            .get::<&Health>(runner)
+            .ok()
+            .map(|health| health.current_health());
+        self.last_sides = world
+            .get::<&Position>(runner)
+            .map_or((false, false), |pos| {
+                open_sides(world, &pos, self.direction)
+            });
+    }
+}
+
+/// Every hostile `viewer` can see right now. Anyone without a `Fov` sees everything.
+pub fn visible_hostiles(world: &World, viewer: Entity) -> HashSet<Entity> {
+    let fov = world.get::<&Fov>(viewer).ok();
+    world
+        .query::<&Position>()
+        .with::<&Ai>()
+        .iter()
+        .filter(|(_id, pos)| fov.as_ref().is_none_or(|fov| fov.can_see(pos)))
+        .map(|(id, _pos)| id)
+        .collect()
+}
+
+/// Whether there's a wall, the edge of the screen, or anything solid on `pos`.
+fn is_blocked(world: &World, pos: &Position) -> bool {
+    let in_wall = world
+        .query::<&Map>()
+        .iter()
+        .next()
+        .is_some_and(|(_id, map)| map.is_blocked(pos));
+    in_wall
+        || !pos.is_within_console_bounds(&GameConfig::from_world(world))
+        || world
+            .query::<&Position>()
+            .with::<&BlocksTile>()
+            .iter()
+            .any(|(_id, blocker)| blocker == pos)
+}
+
+/// Whether the tiles to the left and right of `pos`, facing `direction`, are open floor. Only
+/// walls count, so a goblin off to the side doesn't look like a junction.
+fn open_sides(world: &World, pos: &Position, (dx, dy): (isize, isize)) -> (bool, bool) {
+    world
+        .query::<&Map>()
+        .iter()
+        .next()
+        .map_or((true, true), |(_id, map)| {
+            (
+                !map.is_blocked(&pos.new_from_dx_dy(dy, -dx)),
+                !map.is_blocked(&pos.new_from_dx_dy(-dy, dx)),
+            )
+        })
+}
+
+/// Whether `player` should stop running before taking another step, going by what's changed
+/// since the last one. Anyone missing their `Running` or `Position` can't run at all.
+pub fn should_stop_running(
+    world: &World,
+    player: Entity,
+    prev_visible: &HashSet<Entity>,
+) -> Option<StopReason> {
+    let (Ok(running), Ok(pos)) = (
+        world.get::<&Running>(player),
+        world.get::<&Position>(player),
+    ) else {
+        return Some(StopReason::Blocked);
+    };
+    let health = world
+        .get::<&Health>(player)
+        .ok()
+        .map(|health| health.current_health());
+    if health != running.last_health {
+        Some(StopReason::HealthChanged)
+    } else if !visible_hostiles(world, player).is_subset(prev_visible) {
+        Some(StopReason::NewlySeen)
+    } else if open_sides(world, &pos, running.direction) != running.last_sides {
+        Some(StopReason::Junction)
+    } else if is_blocked(
+        world,
+        &pos.new_from_dx_dy(running.direction.0, running.direction.1),
+    ) {
+        Some(StopReason::Blocked)
+    } else {
+        None
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::entities::{spawn_goblin_at, spawn_player};
+    use crate::models::GameRng;
+    use crate::models::map::TileType;
+
+    /// A corridor along y = 5 from x = 1 to x = 10 with nothing but wall around it.
+    fn corridor() -> Map {
+        let mut map = Map::new_bordered(20, 12);
+        for x in 0..20 {
+            for y in 0..12 {
+                if y != 5 || !(1..=10).contains(&x) {
+                    map.set(&Position::new(x, y), TileType::Wall);
+                }
+            }
+        }
+        map
+    }
+
+    #[test]
+    fn test_nothing_changed_keeps_running() {
+        let mut world = World::new();
+        world.spawn((corridor(),));
+        let player = spawn_player(&mut world, Position::new(2, 5));
+        let running = Running::start(&world, player, (1, 0));
+        assert_eq!(running.last_sides, (false, false));
+        world.insert_one(player, running).unwrap();
+
+        world.get::<&mut Position>(player).unwrap().x = 3;
+        assert_eq!(should_stop_running(&world, player, &HashSet::new()), None);
+        world.get::<&mut Position>(player).unwrap().x = 10;
+        assert_eq!(
+            should_stop_running(&world, player, &HashSet::new()),
+            Some(StopReason::Blocked)
+        );
+    }
+
+    #[test]
+    fn test_side_passage_health_and_goblins_stop_running() {
+        let mut map = corridor();
+        map.set(&Position::new(5, 4), TileType::Floor);
+        let mut world = World::new();
+        world.spawn((map,));
+        let player = spawn_player(&mut world, Position::new(4, 5));
+        let running = Running::start(&world, player, (1, 0));
+        world.insert_one(player, running).unwrap();
+
+        world.get::<&mut Position>(player).unwrap().x = 5;
+        assert_eq!(
+            should_stop_running(&world, player, &HashSet::new()),
+            Some(StopReason::Junction)
+        );
+
+        world.get::<&mut Position>(player).unwrap().x = 4;
+        world.get::<&mut Health>(player).unwrap().apply_damage(1);
+        assert_eq!(
+            should_stop_running(&world, player, &HashSet::new()),
+            Some(StopReason::HealthChanged)
+        );
+
+        world
+            .get::<&mut Running>(player)
+            .unwrap()
+            .remember(&world, player);
+        let goblin = spawn_goblin_at(&mut world, Position::new(9, 5), &mut GameRng::seeded(1));
+        assert_eq!(
+            should_stop_running(&world, player, &HashSet::new()),
+            Some(StopReason::NewlySeen)
+        );
+        // Already knew it was there.
+        assert_eq!(
+            should_stop_running(&world, player, &HashSet::from([goblin])),
+            None
+        );
+    }
+}
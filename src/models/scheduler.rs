@@ -0,0 +1,144 @@
+//! Turn scheduling: who gets to act, and how often, as the player's turns go by.
+
+use hecs::Entity;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+
+/// How many turns the player's finished this run. Lives on its own entity in the world, and only
+/// goes up once whatever the player did has been dealt with, however many frames that took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TurnCounter(pub u64);
+
+/// Keeps track of when everything's next turn comes up. Lives on its own entity in the world.
+///
+/// Time is counted in ticks. The player moves `current_tick` on each time they act, and
+/// everything whose turn falls before the player's next one gets to go, as many times as fits.
+/// Those turns can get spread over a few frames, and the player's next turn doesn't come until
+/// they're all done.
+#[derive(Debug, Clone, Default)]
+pub struct TurnScheduler {
+    queue: BinaryHeap<Reverse<(u64, Entity)>>,
+    scheduled: HashSet<Entity>,
+    /// Turns that came up this time around but haven't been taken yet, in order.
+    pending: VecDeque<Entity>,
+    pub current_tick: u64,
+}
+
+impl TurnScheduler {
+    /// Gives `entity` a turn at tick `at`, if it doesn't already have one coming up.
+    pub fn schedule(&mut self, entity: Entity, at: u64) {
+        if self.scheduled.insert(entity) {
+            self.queue.push(Reverse((at, entity)));
+        }
+    }
+
+    pub fn is_scheduled(&self, entity: Entity) -> bool {
+        self.scheduled.contains(&entity)
+    }
+
+    /// Moves time on by `ticks`, handing back every turn that came up along the way, in order.
+    /// Anything fast enough shows up more than once. `ticks_per_turn` says how long each entity
+    /// waits between turns, or `None` if it's gone and shouldn't get any more.
+    pub fn advance(
+        &mut self,
+        ticks: u64,
+        mut ticks_per_turn: impl FnMut(Entity) -> Option<u64>,
+    ) -> Vec<Entity> {
+        let until = self.current_tick + ticks;
+        let mut turns = Vec::new();
+        while let Some(Reverse((next_tick, entity))) = self.queue.peek().copied() {
+            if next_tick >= until {
+                break;
+            }
+            self.queue.pop();
+            match ticks_per_turn(entity) {
+                Some(wait) => {
+                    turns.push(entity);
+                    self.queue.push(Reverse((next_tick + wait.max(1), entity)));
+                }
+                None => {
+                    self.scheduled.remove(&entity);
+                }
+            }
+        }
+        self.current_tick = until;
+        turns
+    }
+
+    /// Moves time on by `ticks` like `advance`, keeping the turns that come up to be handed out
+    /// bit by bit with `next_batch`.
+    pub fn begin_turn(&mut self, ticks: u64, ticks_per_turn: impl FnMut(Entity) -> Option<u64>) {
+        let turns = self.advance(ticks, ticks_per_turn);
+        self.pending.extend(turns);
+    }
+
+    /// Up to `max` of the turns still waiting to be taken.
+    pub fn next_batch(&mut self, max: usize) -> Vec<Entity> {
+        let count = max.min(self.pending.len());
+        self.pending.drain(..count).collect()
+    }
+
+    /// Whether every turn since the player last acted has been taken.
+    pub fn is_turn_complete(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+mod tests {
+    use super::*;
+    use hecs::World;
+
+    #[test]
+    fn test_faster_entities_get_more_turns() {
+        let mut world = World::new();
+        let fast = world.spawn(());
+        let slow = world.spawn(());
+        let mut scheduler = TurnScheduler::default();
+        scheduler.schedule(fast, 0);
+        scheduler.schedule(slow, 0);
+        let wait = |entity: Entity| Some(if entity == fast { 5 } else { 20 });
+
+        let first = scheduler.advance(10, wait);
+        assert_eq!(first.iter().filter(|e| **e == fast).count(), 2);
+        assert_eq!(first.iter().filter(|e| **e == slow).count(), 1);
+
+        let second = scheduler.advance(10, wait);
+        assert_eq!(second, vec![fast, fast]);
+        assert_eq!(scheduler.current_tick, 20);
+    }
+
+    #[test]
+    fn test_turns_get_handed_out_in_batches() {
+        let mut world = World::new();
+        let entities: Vec<Entity> = (0..5).map(|_| world.spawn(())).collect();
+        let mut scheduler = TurnScheduler::default();
+        for entity in &entities {
+            scheduler.schedule(*entity, 0);
+        }
+        assert!(scheduler.is_turn_complete());
+
+        scheduler.begin_turn(10, |_| Some(10));
+        assert_eq!(scheduler.next_batch(2), entities[..2]);
+        assert!(!scheduler.is_turn_complete());
+        assert_eq!(scheduler.next_batch(2), entities[2..4]);
+        assert_eq!(scheduler.next_batch(2), entities[4..]);
+        assert!(scheduler.is_turn_complete());
+        assert!(scheduler.next_batch(2).is_empty());
+    }
+
+    #[test]
+    fn test_gone_entities_drop_out() {
+        let mut world = World::new();
+        let entity = world.spawn(());
+        let mut scheduler = TurnScheduler::default();
+        scheduler.schedule(entity, 0);
+        // Scheduling twice doesn't double up on turns.
+        scheduler.schedule(entity, 0);
+
+        assert_eq!(scheduler.advance(10, |_| Some(10)), vec![entity]);
+        assert!(scheduler.advance(10, |_| None).is_empty());
+        assert!(!scheduler.is_scheduled(entity));
+        assert!(scheduler.advance(10, |_| Some(10)).is_empty());
+    }
+}
@@ -0,0 +1,139 @@
+//! Quick lookups for what's standing where.
+
+use crate::models::{BlocksTile, Position};
+use hecs::{Entity, World};
+use std::collections::HashMap;
+
+/// Where everything that blocks movement is standing. Lives on its own entity in the world.
+/// Nothing rebuilds this, so anything that moves or gets rid of a blocking entity needs to
+/// update it as it goes.
+#[derive(Debug, Clone, Default)]
+pub struct SpatialIndex {
+    by_position: HashMap<Position, Entity>,
+    by_entity: HashMap<Entity, Position>,
+}
+
+impl SpatialIndex {
+    /// Starts off with everything in `world` that blocks movement.
+    pub fn from_world(world: &World) -> Self {
+        let mut spatial_index = SpatialIndex::default();
+        for (entity, pos) in world.query::<&Position>().with::<&BlocksTile>().iter() {
+            spatial_index.insert(entity, pos.clone());
+        }
+        spatial_index
+    }
+
+    /// Puts `entity` at `pos`, taking it off wherever it was before.
+    pub fn insert(&mut self, entity: Entity, pos: Position) {
+        self.remove(entity);
+        self.by_position.insert(pos.clone(), entity);
+        self.by_entity.insert(entity, pos);
+    }
+
+    /// Returns where `entity` was, if it was in here at all.
+    pub fn remove(&mut self, entity: Entity) -> Option<Position> {
+        let pos = self.by_entity.remove(&entity)?;
+        if self.by_position.get(&pos) == Some(&entity) {
+            self.by_position.remove(&pos);
+        }
+        Some(pos)
+    }
+
+    /// Same as `insert`, just reads better when something is walking around.
+    pub fn move_entity(&mut self, entity: Entity, to: &Position) {
+        self.insert(entity, to.clone());
+    }
+
+    pub fn at(&self, pos: &Position) -> Option<Entity> {
+        self.by_position.get(pos).copied()
+    }
+
+    pub fn is_occupied(&self, pos: &Position) -> bool {
+        self.by_position.contains_key(pos)
+    }
+
+    /// Everything within `radius` tiles of `pos` in any direction, not counting whatever is on `pos`.
+    pub fn neighbors(&self, pos: &Position, radius: u32) -> Vec<(Position, Entity)> {
+        let radius = radius as isize;
+        let mut found = Vec::new();
+        for dy in -radius..=radius {
+            for dx in -radius..=radius {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let neighbor = pos.new_from_dx_dy(dx, dy);
+                if let Some(entity) = self.at(&neighbor) {
+                    found.push((neighbor, entity));
+                }
+            }
+        }
+        found
+    }
+
+    /// Every occupied tile and who is on it.
+    pub fn positions(&self) -> &HashMap<Position, Entity> {
+        &self.by_position
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_moving_updates_index() {
+        let mut world = World::new();
+        let goblin = world.spawn(());
+        let mut index = SpatialIndex::default();
+        index.insert(goblin, Position::new(1, 1));
+
+        index.move_entity(goblin, &Position::new(2, 1));
+        assert_eq!(index.at(&Position::new(1, 1)), None);
+        assert_eq!(index.at(&Position::new(2, 1)), Some(goblin));
+        assert_eq!(index.positions().len(), 1);
+    }
+
+    #[test]
+    fn test_removed_entities_are_gone() {
+        let mut world = World::new();
+        let goblin = world.spawn(());
+        let orc = world.spawn(());
+        let mut index = SpatialIndex::default();
+        index.insert(goblin, Position::new(1, 1));
+        index.insert(orc, Position::new(3, 3));
+
+        assert_eq!(index.remove(goblin), Some(Position::new(1, 1)));
+        assert!(!index.is_occupied(&Position::new(1, 1)));
+        assert_eq!(index.remove(goblin), None);
+        assert_eq!(index.at(&Position::new(3, 3)), Some(orc));
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let mut world = World::new();
+        let mut index = SpatialIndex::default();
+        let center = Position::new(5, 5);
+        index.insert(world.spawn(()), center.clone());
+        let close = world.spawn(());
+        index.insert(close, Position::new(6, 6));
+        let farther = world.spawn(());
+        index.insert(farther, Position::new(3, 5));
+        index.insert(world.spawn(()), Position::new(9, 5));
+
+        let mut near_center: Vec<Entity> = index
+            .neighbors(&center, 1)
+            .into_iter()
+            .map(|(_, entity)| entity)
+            .collect();
+        assert_eq!(near_center, vec![close]);
+
+        near_center = index
+            .neighbors(&center, 2)
+            .into_iter()
+            .map(|(_, entity)| entity)
+            .collect();
+        near_center.sort();
+        let mut expected = vec![close, farther];
+        expected.sort();
+        assert_eq!(near_center, expected);
+    }
+}
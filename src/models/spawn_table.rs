@@ -0,0 +1,89 @@
+//! Picking which monster to put in each spawn slot.
+
+use crate::models::{GameRng, Position};
+use hecs::{Entity, World};
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
+
+pub type SpawnFn = Box<dyn Fn(&mut World, Position, &mut GameRng) -> Entity>;
+
+pub struct SpawnEntry {
+    /// How likely this entry is compared to the others on the same floor.
+    pub weight: u32,
+    /// The shallowest floor this entry can show up on.
+    pub min_floor: u32,
+    pub spawn_fn: SpawnFn,
+}
+
+#[derive(Default)]
+pub struct SpawnTable {
+    pub entries: Vec<SpawnEntry>,
+}
+
+impl SpawnTable {
+    /// Picks one of the entries allowed on `floor`, weighted by their `weight`.
+    /// `None` if nothing can spawn on that floor.
+    pub fn roll(&self, rng: &mut GameRng, floor: u32) -> Option<&SpawnEntry> {
+        let allowed: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.min_floor <= floor)
+            .collect();
+        let weights = WeightedIndex::new(allowed.iter().map(|entry| entry.weight)).ok()?;
+        Some(allowed[weights.sample(rng)])
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::models::EntityName;
+
+    fn named(name: &'static str, weight: u32, min_floor: u32) -> SpawnEntry {
+        SpawnEntry {
+            weight,
+            min_floor,
+            spawn_fn: Box::new(
+                move |world: &mut World, pos: Position, _rng: &mut GameRng| {
+                    world.spawn((
+                        pos,
+                        EntityName {
+                            name: name.to_string(),
+                        },
+                    ))
+                },
+            ),
+        }
+    }
+
+    #[test]
+    fn test_spawn_table_filters_by_floor() {
+        let table = SpawnTable {
+            entries: vec![named("Goblin", 1, 1), named("Dragon", 1000, 10)],
+        };
+        let mut rng = GameRng::seeded(42);
+        for _ in 0..50 {
+            let entry = table.roll(&mut rng, 1).expect("Goblins can always spawn.");
+            assert_eq!(entry.min_floor, 1);
+        }
+        assert!(table.roll(&mut rng, 0).is_none());
+    }
+
+    #[test]
+    fn test_spawn_table_respects_weights() {
+        let table = SpawnTable {
+            entries: vec![named("Goblin", 90, 1), named("Orc", 10, 1)],
+        };
+        let mut rng = GameRng::seeded(42);
+        let mut world = World::new();
+        for _ in 0..1000 {
+            let entry = table.roll(&mut rng, 1).unwrap();
+            (entry.spawn_fn)(&mut world, Position::new(0, 0), &mut rng);
+        }
+        let goblins = world
+            .query::<&EntityName>()
+            .iter()
+            .filter(|(_, name)| name.name == "Goblin")
+            .count();
+        assert!((850..=950).contains(&goblins), "Got {goblins} goblins");
+    }
+}
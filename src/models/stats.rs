@@ -1,11 +1,25 @@
 use std::any::TypeId;
 use std::ptr::NonNull;
-use hecs::{Bundle, Entity, MissingComponent, TypeInfo};
+use hecs::{Bundle, Entity, MissingComponent, TypeInfo, World};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Health {
-    pub total_health: u32,
-    pub current_health: i32,
+    total_health: u32,
+    current_health: i32,
+}
+
+/// What a hit ended up doing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DamageOutcome {
+    Damaged {
+        remaining: i32,
+    },
+    /// How much more it took than it had left.
+    Killed {
+        overkill: u32,
+    },
 }
 
 impl Health {
@@ -20,13 +34,458 @@ impl Health {
         tracing::trace!(?ratio, ?self, "get_ratio");
         ratio
     }
+
+    pub(crate) fn current_health(&self) -> i32 {
+        self.current_health
+    }
+
+    pub(crate) fn total_health(&self) -> u32 {
+        self.total_health
+    }
+
+    /// Takes `amount` off, never going below 0. Negative damage gets passed on to `heal`, which is
+    /// how regeneration works. Hitting something that's already dead is all overkill.
+    pub fn apply_damage(&mut self, amount: i32) -> DamageOutcome {
+        if amount < 0 {
+            self.heal(amount.unsigned_abs());
+            return DamageOutcome::Damaged {
+                remaining: self.current_health,
+            };
+        }
+        if amount >= self.current_health {
+            let overkill = amount.abs_diff(self.current_health);
+            self.current_health = 0;
+            return DamageOutcome::Killed { overkill };
+        }
+        self.current_health -= amount;
+        DamageOutcome::Damaged {
+            remaining: self.current_health,
+        }
+    }
+
+    /// Adds `amount` back on, topping out at `total_health`. Returns how much actually went on.
+    /// Nothing brings back the dead.
+    pub fn heal(&mut self, amount: u32) -> u32 {
+        if self.is_dead() {
+            return 0;
+        }
+        let before = self.current_health;
+        let amount = i32::try_from(amount).unwrap_or(i32::MAX);
+        self.current_health = self
+            .current_health
+            .saturating_add(amount)
+            .clamp(0, self.max_health());
+        self.current_health.abs_diff(before)
+    }
+
+    /// Raises `total_health` by `amount`, and `current_health` with it if `also_heal` is set.
+    pub fn increase_max(&mut self, amount: u32, also_heal: bool) {
+        self.total_health = self.total_health.saturating_add(amount);
+        if also_heal {
+            self.heal(amount);
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current_health <= 0
+    }
+
+    fn max_health(&self) -> i32 {
+        i32::try_from(self.total_health).unwrap_or(i32::MAX)
+    }
+}
+
+/// How hard an entity hits, the actual damage is rolled between the two (inclusive).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Attack {
+    pub damage_min: i32,
+    pub damage_max: i32,
+}
+
+/// Hits from whatever has this shove the target up to `tiles` tiles straight away from it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Knockback {
+    pub tiles: i32,
+}
+
+/// How many ticks a turn takes for something at speed 1. Everything else divides this by its
+/// speed, so the default speed gets a turn every 10 ticks.
+pub const BASE_TICKS: u64 = 100;
+
+/// How quickly something gets its turns. Twice the speed means twice as many turns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntitySpeed {
+    pub base: u32,
+}
+
+impl EntitySpeed {
+    pub fn new(base: u32) -> Self {
+        Self { base }
+    }
+
+    /// How long after one turn the next one comes around.
+    pub fn ticks_per_turn(&self) -> u64 {
+        BASE_TICKS / u64::from(self.base.max(1))
+    }
+}
+
+impl Default for EntitySpeed {
+    fn default() -> Self {
+        Self { base: 10 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Defense {
+    pub value: i32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DamageKind {
+    Physical,
+    Fire,
+    Poison,
+    /// Gets through no matter what.
+    Pure,
+}
+
+/// How much of each kind of damage actually gets through, as a multiplier. Less than 1 resists it,
+/// more than 1 is a weakness, and anything that isn't listed gets through as is.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Resistances {
+    pub multipliers: BTreeMap<DamageKind, f32>,
+}
+
+impl Resistances {
+    pub fn new(multipliers: impl IntoIterator<Item = (DamageKind, f32)>) -> Self {
+        Self {
+            multipliers: multipliers.into_iter().collect(),
+        }
+    }
+
+    pub fn multiplier(&self, kind: DamageKind) -> f32 {
+        match kind {
+            DamageKind::Pure => 1.0,
+            _ => self.multipliers.get(&kind).copied().unwrap_or(1.0),
+        }
+    }
+
+    /// How much of `damage` gets through, rounded half away from zero. Never goes below zero.
+    pub fn apply(&self, damage: i32, kind: DamageKind) -> i32 {
+        if kind == DamageKind::Pure {
+            return damage;
+        }
+        (damage as f32 * self.multiplier(kind)).round().max(0.0) as i32
+    }
+}
+
+/// The numbers a `StatModifier` can change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum StatKind {
+    AttackMin,
+    AttackMax,
+    Defense,
+    Speed,
+    VisionRange,
+    /// In percent.
+    CritChance,
+}
+
+impl StatKind {
+    pub const ALL: [StatKind; 6] = [
+        StatKind::AttackMin,
+        StatKind::AttackMax,
+        StatKind::Defense,
+        StatKind::Speed,
+        StatKind::VisionRange,
+        StatKind::CritChance,
+    ];
+}
+
+/// A buff or debuff on one stat. `additive` goes on first, then the whole thing gets multiplied by
+/// `multiplicative`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StatModifier {
+    pub target_stat: StatKind,
+    pub additive: i32,
+    pub multiplicative: f32,
+    /// `None` lasts until whatever put it there takes it off again, like worn gear.
+    pub remaining_turns: Option<u32>,
+    /// Whatever put it there, so it can be taken back off.
+    pub source_id: u32,
+}
+
+/// Every `StatModifier` on an entity. An entity can only have one of each component, so they all
+/// live in here.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct StatModifiers {
+    pub active: Vec<StatModifier>,
+}
+
+impl StatModifiers {
+    /// Takes off everything `source_id` put on.
+    pub fn remove_from(&mut self, source_id: u32) {
+        self.active
+            .retain(|modifier| modifier.source_id != source_id);
+    }
+
+    /// Counts a turn off of everything timed, dropping whatever's run out.
+    pub fn tick(&mut self) {
+        for modifier in self.active.iter_mut() {
+            if let Some(remaining_turns) = modifier.remaining_turns.as_mut() {
+                *remaining_turns = remaining_turns.saturating_sub(1);
+            }
+        }
+        self.active
+            .retain(|modifier| modifier.remaining_turns.is_none_or(|turns| turns > 0));
+    }
+
+    /// `base` with every modifier on `kind` counted. All the additives go on before any of the
+    /// multipliers, so the order they were put on in doesn't matter.
+    pub fn apply(&self, kind: StatKind, base: i32) -> i32 {
+        let on_kind = || {
+            self.active
+                .iter()
+                .filter(move |modifier| modifier.target_stat == kind)
+        };
+        let additive: i32 = on_kind().map(|modifier| modifier.additive).sum();
+        let multiplicative: f32 = on_kind().map(|modifier| modifier.multiplicative).product();
+        ((base + additive) as f32 * multiplicative).round() as i32
+    }
+}
+
+/// What an entity's stats come to with its `StatModifiers` counted, worked out by
+/// `ApplyStatModifiersSystem`. Keeps the unmodified stats too, so anything that doesn't go off of
+/// the base components can still tell how much the modifiers moved things.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EffectiveStats {
+    pub base: BTreeMap<StatKind, i32>,
+    pub values: BTreeMap<StatKind, i32>,
+}
+
+impl EffectiveStats {
+    pub fn new(base: BTreeMap<StatKind, i32>, modifiers: &StatModifiers) -> Self {
+        let values = base
+            .iter()
+            .map(|(kind, value)| (*kind, modifiers.apply(*kind, *value)))
+            .collect();
+        Self { base, values }
+    }
+
+    pub fn get(&self, kind: StatKind) -> i32 {
+        self.values.get(&kind).copied().unwrap_or_default()
+    }
+
+    /// How far the modifiers moved `kind` from where it started.
+    pub fn change(&self, kind: StatKind) -> i32 {
+        self.get(kind) - self.base.get(&kind).copied().unwrap_or_default()
+    }
+}
+
+/// How far `entity`'s modifiers have moved `kind`, which is nothing for anything without
+/// `EffectiveStats`.
+pub fn stat_change(world: &World, entity: Entity, kind: StatKind) -> i32 {
+    world
+        .get::<&EffectiveStats>(entity)
+        .map_or(0, |stats| stats.change(kind))
+}
+
+/// Heals the entity every player turn.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Regeneration {
+    pub hp_per_turn: i32,
+}
+
+/// Slowly heals the player while they stay out of fights.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NaturalRegen {
+    pub turns_per_hp: u32,
+    pub turns_since_damage: u32,
+}
+
+impl NaturalRegen {
+    pub fn new(turns_per_hp: u32) -> Self {
+        NaturalRegen {
+            turns_per_hp,
+            turns_since_damage: 0,
+        }
+    }
+}
+
+/// Stops `Regeneration` from doing anything while it's around (like while burning).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegenerationSuppressed {
+    pub turns_remaining: u32,
+}
+
+/// What running and special abilities get paid for with. Lives on the player.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stamina {
+    pub current: f32,
+    pub max: f32,
+    /// How much comes back every turn.
+    pub regen_per_turn: f32,
+}
+
+impl Stamina {
+    /// What each step of running (holding the run key) costs.
+    pub const SPRINT_COST: f32 = 2.0;
+
+    /// Starts off full.
+    pub fn new(max: f32, regen_per_turn: f32) -> Self {
+        Stamina {
+            current: max,
+            max,
+            regen_per_turn,
+        }
+    }
+
+    pub fn can_afford(&self, cost: f32) -> bool {
+        self.current >= cost
+    }
+
+    /// Takes `cost` off if there's enough. Returns whether there was.
+    pub fn try_spend(&mut self, cost: f32) -> bool {
+        if !self.can_afford(cost) {
+            return false;
+        }
+        self.current -= cost;
+        true
+    }
+
+    /// Adds a turn's worth back, topping out at `max`.
+    pub fn regen(&mut self) {
+        self.current = (self.current + self.regen_per_turn).min(self.max);
+    }
+
+    pub fn get_ratio(&self) -> f32 {
+        if self.max <= 0.0 {
+            return 0.0;
+        }
+        self.current / self.max
+    }
+}
+
+/// Special moves that cost stamina to pull off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Ability {
+    Leap,
+    Blink,
+    Grapple,
+}
+
+impl Ability {
+    pub fn stamina_cost(&self) -> f32 {
+        match self {
+            Ability::Leap => 4.0,
+            Ability::Blink => 6.0,
+            Ability::Grapple => 3.0,
+        }
+    }
+
+    /// Pays for the ability out of `stamina`. Returns false, without taking anything, if there
+    /// isn't enough for it.
+    pub fn try_activate(&self, stamina: &mut Stamina) -> bool {
+        let activated = stamina.try_spend(self.stamina_cost());
+        tracing::debug!(ability = ?self, activated, ?stamina, "try_activate");
+        activated
+    }
+}
+
+/// Money lying on the floor, waiting to be walked over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Gold {
+    pub amount: u32,
+}
+
+/// All the gold the player has picked up. Lives on the player.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlayerWallet {
+    pub total: u32,
+}
+
+/// How well the run's going. Lives on the player.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Score {
+    pub kills: u32,
+    pub gold_collected: u32,
+    pub floors_descended: u32,
+    pub total_score: u32,
+    /// Doesn't count towards the score, it's just for the high score table.
+    pub turns_survived: u64,
+}
+
+impl Score {
+    pub const POINTS_PER_KILL: u32 = 10;
+    pub const POINTS_PER_FLOOR: u32 = 100;
+
+    /// Works `total_score` out again from everything else. Gold is worth a point a piece.
+    pub fn recompute(&mut self) {
+        self.total_score = self.kills * Self::POINTS_PER_KILL
+            + self.gold_collected
+            + self.floors_descended * Self::POINTS_PER_FLOOR;
+    }
+}
+
+/// Everything worth knowing about how the run went, for the death screen and the morgue file.
+/// Lives on its own entity in the world.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunStats {
+    pub turns_survived: u64,
+    /// How many of each monster died, by name.
+    pub kills_by_name: BTreeMap<String, u32>,
+    pub damage_dealt: u64,
+    pub damage_taken: u64,
+    pub deepest_depth: u32,
+    pub items_used: u32,
+}
+
+impl Default for RunStats {
+    fn default() -> Self {
+        RunStats {
+            turns_survived: 0,
+            kills_by_name: BTreeMap::new(),
+            damage_dealt: 0,
+            damage_taken: 0,
+            // Everyone starts on the first floor.
+            deepest_depth: 1,
+            items_used: 0,
+        }
+    }
+}
+
+impl RunStats {
+    pub fn total_kills(&self) -> u32 {
+        self.kills_by_name.values().sum()
+    }
+
+    /// The summary the way it gets shown and written out, one line at a time.
+    pub fn to_lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("{:<16}{:>8}", "Turns survived", self.turns_survived),
+            format!("{:<16}{:>8}", "Deepest depth", self.deepest_depth),
+            format!("{:<16}{:>8}", "Damage dealt", self.damage_dealt),
+            format!("{:<16}{:>8}", "Damage taken", self.damage_taken),
+            format!("{:<16}{:>8}", "Items used", self.items_used),
+            format!("{:<16}{:>8}", "Monsters killed", self.total_kills()),
+        ];
+        lines.extend(
+            self.kills_by_name
+                .iter()
+                .map(|(name, count)| format!("  {name:<14}{count:>8}")),
+        );
+        lines
+    }
 }
 
 #[derive(Debug)]
 pub struct Damage {
+    /// Whatever did it, which might be a trap rather than a monster. It might not be around
+    /// anymore either.
     pub from: Entity,
     pub to: Entity,
     pub damage: i32,
+    pub kind: DamageKind,
 }
 
 // impl Bundle for Damage {
@@ -45,3 +504,270 @@ pub struct Damage {
 //         todo!()
 //     }
 // }
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resistances_by_kind() {
+        let troll = Resistances::new([(DamageKind::Physical, 0.5), (DamageKind::Fire, 2.0)]);
+        assert_eq!(troll.apply(8, DamageKind::Physical), 4);
+        assert_eq!(troll.apply(8, DamageKind::Fire), 16);
+        // Nothing listed for poison, so all of it gets through.
+        assert_eq!(troll.apply(8, DamageKind::Poison), 8);
+        assert_eq!(troll.multiplier(DamageKind::Poison), 1.0);
+        assert_eq!(troll.apply(8, DamageKind::Pure), 8);
+
+        let nothing = Resistances::default();
+        for kind in [
+            DamageKind::Physical,
+            DamageKind::Fire,
+            DamageKind::Poison,
+            DamageKind::Pure,
+        ] {
+            assert_eq!(nothing.apply(7, kind), 7);
+        }
+    }
+
+    #[test]
+    fn test_resisted_damage_rounds_half_away_from_zero() {
+        let halved = Resistances::new([(DamageKind::Physical, 0.5)]);
+        assert_eq!(halved.apply(5, DamageKind::Physical), 3);
+        assert_eq!(halved.apply(3, DamageKind::Physical), 2);
+        assert_eq!(halved.apply(1, DamageKind::Physical), 1);
+        let mostly = Resistances::new([(DamageKind::Fire, 0.3)]);
+        assert_eq!(mostly.apply(4, DamageKind::Fire), 1);
+        assert_eq!(mostly.apply(1, DamageKind::Fire), 0);
+    }
+
+    #[test]
+    fn test_immunity_floors_damage_at_zero() {
+        let immune = Resistances::new([(DamageKind::Poison, 0.0), (DamageKind::Fire, -1.0)]);
+        assert_eq!(immune.apply(10, DamageKind::Poison), 0);
+        assert_eq!(immune.apply(10, DamageKind::Fire), 0);
+        // Even being immune to everything else doesn't stop pure damage.
+        let immune = Resistances::new([
+            (DamageKind::Physical, 0.0),
+            (DamageKind::Fire, 0.0),
+            (DamageKind::Poison, 0.0),
+            (DamageKind::Pure, 0.0),
+        ]);
+        assert_eq!(immune.apply(10, DamageKind::Pure), 10);
+    }
+
+    #[test]
+    fn test_damage_leaves_whats_remaining() {
+        let mut health = Health::new(10);
+        assert_eq!(
+            health.apply_damage(4),
+            DamageOutcome::Damaged { remaining: 6 }
+        );
+        assert_eq!(health.current_health(), 6);
+        assert!(!health.is_dead());
+        assert_eq!(
+            health.apply_damage(0),
+            DamageOutcome::Damaged { remaining: 6 }
+        );
+    }
+
+    #[test]
+    fn test_overkill_stops_at_zero() {
+        let mut health = Health::new(10);
+        assert_eq!(
+            health.apply_damage(25),
+            DamageOutcome::Killed { overkill: 15 }
+        );
+        assert_eq!(health.current_health(), 0);
+        assert!(health.is_dead());
+
+        assert_eq!(
+            health.apply_damage(i32::MAX),
+            DamageOutcome::Killed {
+                overkill: i32::MAX as u32
+            }
+        );
+        assert_eq!(health.current_health(), 0);
+        assert!(health.is_dead());
+    }
+
+    #[test]
+    fn test_exact_killing_blow_has_no_overkill() {
+        let mut health = Health::new(10);
+        assert_eq!(
+            health.apply_damage(10),
+            DamageOutcome::Killed { overkill: 0 }
+        );
+        assert!(health.is_dead());
+    }
+
+    #[test]
+    fn test_overhealing_stops_at_total_health() {
+        let mut health = Health::new(10);
+        health.apply_damage(4);
+
+        assert_eq!(health.heal(100), 4);
+        assert_eq!(health.current_health(), 10);
+        assert_eq!(health.heal(u32::MAX), 0);
+        assert_eq!(health.current_health(), 10);
+        // Negative damage is just healing, and still can't go over.
+        assert_eq!(
+            health.apply_damage(-5),
+            DamageOutcome::Damaged { remaining: 10 }
+        );
+        assert_eq!(health.current_health(), 10);
+    }
+
+    #[test]
+    fn test_healing_full_health_does_nothing() {
+        let mut health = Health::new(10);
+        assert_eq!(health.heal(3), 0);
+        assert_eq!(health.current_health(), 10);
+    }
+
+    #[test]
+    fn test_healing_the_dead_does_nothing() {
+        let mut health = Health::new(10);
+        health.apply_damage(10);
+        assert_eq!(health.heal(5), 0);
+        assert_eq!(
+            health.apply_damage(-5),
+            DamageOutcome::Damaged { remaining: 0 }
+        );
+        assert!(health.is_dead());
+    }
+
+    #[test]
+    fn test_stamina_spending_and_regen() {
+        let mut stamina = Stamina::new(5.0, 1.5);
+        assert!(stamina.try_spend(Stamina::SPRINT_COST));
+        assert!(stamina.try_spend(Stamina::SPRINT_COST));
+        assert_eq!(stamina.current, 1.0);
+        // Not enough left, so nothing comes off.
+        assert!(!stamina.try_spend(Stamina::SPRINT_COST));
+        assert_eq!(stamina.current, 1.0);
+
+        for _ in 0..5 {
+            stamina.regen();
+        }
+        assert_eq!(stamina.current, 5.0);
+    }
+
+    #[test]
+    fn test_abilities_need_enough_stamina() {
+        let mut stamina = Stamina::new(10.0, 1.0);
+        assert!(Ability::Blink.try_activate(&mut stamina));
+        assert_eq!(stamina.current, 4.0);
+        // Exactly enough is enough.
+        assert!(Ability::Leap.try_activate(&mut stamina));
+        assert!(!Ability::Grapple.try_activate(&mut stamina));
+        assert_eq!(stamina.current, 0.0);
+    }
+
+    #[test]
+    fn test_run_stats_lines() {
+        let run_stats = RunStats {
+            turns_survived: 120,
+            kills_by_name: BTreeMap::from([("Goblin".to_string(), 3), ("Rat".to_string(), 1)]),
+            damage_dealt: 40,
+            damage_taken: 25,
+            deepest_depth: 2,
+            items_used: 1,
+        };
+        assert_eq!(
+            run_stats.to_lines(),
+            vec![
+                "Turns survived       120",
+                "Deepest depth          2",
+                "Damage dealt          40",
+                "Damage taken          25",
+                "Items used             1",
+                "Monsters killed        4",
+                "  Goblin               3",
+                "  Rat                  1",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_entity_speed_ticks_per_turn() {
+        assert_eq!(EntitySpeed::default().ticks_per_turn(), 10);
+        assert_eq!(EntitySpeed::new(20).ticks_per_turn(), 5);
+        assert_eq!(EntitySpeed::new(5).ticks_per_turn(), 20);
+        // Nothing gets to stop time.
+        assert_eq!(EntitySpeed::new(0).ticks_per_turn(), BASE_TICKS);
+    }
+
+    #[test]
+    fn test_increase_max() {
+        let mut health = Health::new(10);
+        health.apply_damage(5);
+        health.increase_max(5, false);
+        assert_eq!(health.total_health(), 15);
+        assert_eq!(health.current_health(), 5);
+
+        health.increase_max(5, true);
+        assert_eq!(health.total_health(), 20);
+        assert_eq!(health.current_health(), 10);
+        assert_eq!(health.get_ratio(), 0.5);
+    }
+
+    fn modifier(target_stat: StatKind, additive: i32, multiplicative: f32) -> StatModifier {
+        StatModifier {
+            target_stat,
+            additive,
+            multiplicative,
+            remaining_turns: None,
+            source_id: 1,
+        }
+    }
+
+    #[test]
+    fn test_stat_modifiers_add_then_multiply() {
+        let modifiers = StatModifiers {
+            active: vec![
+                modifier(StatKind::Defense, 2, 1.5),
+                modifier(StatKind::Defense, 1, 1.0),
+                modifier(StatKind::Speed, -4, 1.0),
+            ],
+        };
+        assert_eq!(modifiers.apply(StatKind::Defense, 3), 9);
+        assert_eq!(modifiers.apply(StatKind::Speed, 10), 6);
+        assert_eq!(modifiers.apply(StatKind::VisionRange, 8), 8);
+
+        let stats = EffectiveStats::new(
+            BTreeMap::from([(StatKind::Defense, 3), (StatKind::Speed, 10)]),
+            &modifiers,
+        );
+        assert_eq!(stats.get(StatKind::Defense), 9);
+        assert_eq!(stats.change(StatKind::Defense), 6);
+        assert_eq!(stats.change(StatKind::Speed), -4);
+        assert_eq!(stats.change(StatKind::CritChance), 0);
+    }
+
+    #[test]
+    fn test_timed_stat_modifiers_run_out() {
+        let mut modifiers = StatModifiers {
+            active: vec![
+                StatModifier {
+                    remaining_turns: Some(2),
+                    ..modifier(StatKind::AttackMin, 1, 1.0)
+                },
+                StatModifier {
+                    source_id: 2,
+                    ..modifier(StatKind::AttackMax, 1, 1.0)
+                },
+            ],
+        };
+        modifiers.tick();
+        assert_eq!(modifiers.active.len(), 2);
+        modifiers.tick();
+        assert_eq!(modifiers.active.len(), 1);
+        // Gear stays on until it comes off.
+        for _ in 0..10 {
+            modifiers.tick();
+        }
+        assert_eq!(modifiers.active.len(), 1);
+        modifiers.remove_from(2);
+        assert!(modifiers.active.is_empty());
+    }
+}
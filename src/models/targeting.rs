@@ -0,0 +1,161 @@
+//! Picking a tile for ranged attacks, or just to look at.
+
+use crate::fov::line;
+use crate::models::Position;
+use crate::models::interaction::InteractionOption;
+use hecs::Entity;
+
+/// How far away the player can pick a target.
+pub const TARGETING_RANGE: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetPurpose {
+    Throw,
+    Fire,
+}
+
+/// What the keyboard is driving right now. Lives on its own entity in the world.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum UiMode {
+    #[default]
+    Normal,
+    /// Moving a cursor around to pick a target. Turns don't go by until it's picked or cancelled.
+    Targeting {
+        max_range: u32,
+        purpose: TargetPurpose,
+        cursor: Position,
+    },
+    /// Moving a cursor around to look at things. Like targeting, turns don't go by.
+    Examining { cursor: Position },
+    /// Reading back through the whole log. `scroll` is how many lines back from the newest.
+    ReadingLog { scroll: usize },
+    /// Buying from, or with `selling`, selling to `vendor`. No turns go by while trading.
+    Trading { vendor: Entity, selling: bool },
+    /// More than one thing to interact with, so the next number key picks which. No turns go by
+    /// until one's picked.
+    Choosing { options: Vec<InteractionOption> },
+}
+
+pub fn is_in_range(origin: &Position, target: &Position, max_range: u32) -> bool {
+    origin.distance_squared(target) <= (max_range as f64).powi(2)
+}
+
+/// Whether something at `origin` could hit `target`: it's in range, it isn't `origin` itself,
+/// and there's nothing opaque on the line between them (or on `target`).
+pub fn is_valid_target(
+    origin: &Position,
+    target: &Position,
+    max_range: u32,
+    is_opaque: impl Fn(&Position) -> bool,
+) -> bool {
+    target != origin
+        && is_in_range(origin, target, max_range)
+        && line(origin, target)
+            .iter()
+            .skip(1)
+            .all(|pos| !is_opaque(pos))
+}
+
+/// Where the cursor ends up after moving by `(dx, dy)`. Moves that would take it off the map or
+/// out of range of `origin` don't happen.
+pub fn move_cursor(
+    cursor: &Position,
+    (dx, dy): (isize, isize),
+    origin: &Position,
+    max_range: u32,
+    (width, height): (usize, usize),
+) -> Position {
+    let moved = cursor.new_from_dx_dy(dx, dy);
+    let on_map =
+        moved.x >= 0 && moved.y >= 0 && (moved.x as usize) < width && (moved.y as usize) < height;
+    if on_map && is_in_range(origin, &moved, max_range) {
+        moved
+    } else {
+        cursor.clone()
+    }
+}
+
+/// The hostile to jump the cursor to next, closest to `origin` first and wrapping back around.
+pub fn next_target(
+    cursor: &Position,
+    origin: &Position,
+    hostiles: &[Position],
+) -> Option<Position> {
+    let mut hostiles = hostiles.to_vec();
+    hostiles.sort_by_key(|pos| {
+        let (dx, dy) = (pos.x - origin.x, pos.y - origin.y);
+        (dx * dx + dy * dy, pos.y, pos.x)
+    });
+    let next = match hostiles.iter().position(|pos| pos == cursor) {
+        Some(i) => (i + 1) % hostiles.len(),
+        None => 0,
+    };
+    hostiles.get(next).cloned()
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_valid_target() {
+        let origin = Position::new(5, 5);
+        let wall = Position::new(7, 5);
+        let is_opaque = |pos: &Position| *pos == wall;
+
+        assert!(is_valid_target(&origin, &Position::new(6, 5), 3, is_opaque));
+        assert!(is_valid_target(&origin, &Position::new(5, 8), 3, is_opaque));
+        // Behind the wall, and the wall itself.
+        assert!(!is_valid_target(
+            &origin,
+            &Position::new(8, 5),
+            3,
+            is_opaque
+        ));
+        assert!(!is_valid_target(&origin, &wall, 3, is_opaque));
+        // Too far.
+        assert!(!is_valid_target(
+            &origin,
+            &Position::new(5, 9),
+            3,
+            is_opaque
+        ));
+        // Can't throw something at yourself.
+        assert!(!is_valid_target(&origin, &origin, 3, is_opaque));
+    }
+
+    #[test]
+    fn test_move_cursor_is_clamped() {
+        let origin = Position::new(1, 1);
+        let cursor = Position::new(1, 1);
+        assert_eq!(
+            move_cursor(&cursor, (1, 0), &origin, 2, (10, 10)),
+            Position::new(2, 1)
+        );
+        // Off the top of the map.
+        let top = Position::new(1, 0);
+        assert_eq!(move_cursor(&top, (0, -1), &origin, 2, (10, 10)), top);
+        assert_eq!(
+            move_cursor(&Position::new(0, 1), (-1, 0), &origin, 5, (10, 10)),
+            Position::new(0, 1)
+        );
+        // Out of range.
+        let edge = Position::new(3, 1);
+        assert_eq!(move_cursor(&edge, (1, 0), &origin, 2, (10, 10)), edge);
+    }
+
+    #[test]
+    fn test_next_target_cycles_closest_first() {
+        let origin = Position::new(5, 5);
+        let far = Position::new(9, 5);
+        let close = Position::new(6, 6);
+        let hostiles = [far.clone(), close.clone()];
+
+        assert_eq!(
+            next_target(&origin, &origin, &hostiles),
+            Some(close.clone())
+        );
+        assert_eq!(next_target(&close, &origin, &hostiles), Some(far.clone()));
+        assert_eq!(next_target(&far, &origin, &hostiles), Some(close));
+        assert_eq!(next_target(&origin, &origin, &[]), None);
+    }
+}
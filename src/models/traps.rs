@@ -0,0 +1,55 @@
+//! Things on the floor that go off when stepped on.
+
+use serde::{Deserialize, Serialize};
+
+/// How far an alarm trap can be heard.
+pub const ALARM_RADIUS: u32 = 10;
+/// The chance each turn of the player spotting a hidden trap next to them without searching.
+pub const NOTICE_CHANCE: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TrapType {
+    /// Deals this much damage.
+    Spike(i32),
+    /// Poisons for this much damage a turn, for this many turns.
+    Poison(i32, u32),
+    /// Sends whoever stepped on it somewhere random on the floor.
+    Teleport,
+    /// Lets every monster in earshot know where you are.
+    Alarm,
+}
+
+impl TrapType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrapType::Spike(_) => "spike trap",
+            TrapType::Poison(..) => "poison trap",
+            TrapType::Teleport => "teleport trap",
+            TrapType::Alarm => "alarm trap",
+        }
+    }
+}
+
+/// Goes off whenever something steps onto its tile.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trap {
+    pub trap_type: TrapType,
+    /// Hidden traps look like the floor until they go off or the player spots them.
+    pub hidden: bool,
+    /// Single use traps are gone once they've gone off.
+    pub single_use: bool,
+}
+
+impl Trap {
+    pub fn new(trap_type: TrapType, hidden: bool, single_use: bool) -> Self {
+        Trap {
+            trap_type,
+            hidden,
+            single_use,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        !self.hidden
+    }
+}
@@ -0,0 +1,231 @@
+//! Merchants, and buying and selling with them.
+
+use crate::entities::ROCK_DAMAGE;
+use crate::error::DRResult;
+use crate::models::equipment::Equippable;
+use crate::models::items::{Inventory, ItemKind};
+use crate::models::stats::PlayerWallet;
+use hecs::{Entity, World};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// The keys for picking something off the trading screen, first item first.
+pub const TRADE_KEYS: [&str; 9] = [
+    "Digit1", "Digit2", "Digit3", "Digit4", "Digit5", "Digit6", "Digit7", "Digit8", "Digit9",
+];
+/// How many items a merchant gets back every time the player goes down a floor.
+pub const RESTOCK_ITEMS: usize = 2;
+/// What can turn up in a merchant's stock when they restock.
+const RESTOCK_KINDS: [ItemKind; 4] = [
+    ItemKind::Sword,
+    ItemKind::Dagger,
+    ItemKind::LeatherArmor,
+    ItemKind::ThrowingRock {
+        damage: ROCK_DAMAGE,
+    },
+];
+
+/// Sells things for gold and buys weapons and armor for half of what they're worth. Whatever's
+/// for sale is an item entity without a `Position`, with what it costs.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vendor {
+    pub inventory: Vec<(Entity, u32)>,
+}
+
+impl Vendor {
+    /// Whether there's room for more on the trading screen.
+    pub fn is_full(&self) -> bool {
+        self.inventory.len() >= TRADE_KEYS.len()
+    }
+}
+
+/// What a merchant will give for `item`. Only weapons and armor sell, merchants don't want rocks.
+pub fn sell_price(world: &World, item: Entity) -> Option<u32> {
+    world.get::<&Equippable>(item).ok()?;
+    let kind = world.get::<&ItemKind>(item).ok()?;
+    Some(kind.value() / 2)
+}
+
+/// Everything `seller` is carrying that a merchant would buy, and what they'd pay for it, in the
+/// order it shows up on the trading screen.
+pub fn sellable(world: &World, seller: Entity) -> Vec<(Entity, u32)> {
+    world
+        .get::<&Inventory>(seller)
+        .map_or(Vec::new(), |inventory| {
+            inventory
+                .equipment
+                .iter()
+                .filter_map(|item| sell_price(world, *item).map(|price| (*item, price)))
+                .collect()
+        })
+}
+
+/// Has `buyer` pay for `item` and takes it out of `vendor`'s stock. Weapons and armor go in with
+/// the rest of the equipment, anything else goes in the inventory as is. Nothing happens if the
+/// item isn't for sale or `buyer` can't afford it. Hands back what it cost.
+pub fn buy(
+    world: &mut World,
+    buyer: Entity,
+    vendor: Entity,
+    item: Entity,
+) -> DRResult<Option<u32>> {
+    let Some(i) = world
+        .get::<&Vendor>(vendor)?
+        .inventory
+        .iter()
+        .position(|(stocked, _price)| *stocked == item)
+    else {
+        return Ok(None);
+    };
+    let price = world.get::<&Vendor>(vendor)?.inventory[i].1;
+    {
+        let mut wallet = world.get::<&mut PlayerWallet>(buyer)?;
+        if wallet.total < price {
+            return Ok(None);
+        }
+        wallet.total -= price;
+    }
+    world.get::<&mut Vendor>(vendor)?.inventory.remove(i);
+    let is_equipment = world.get::<&Equippable>(item).is_ok();
+    let kind = *world.get::<&ItemKind>(item)?;
+    if is_equipment {
+        world.get::<&mut Inventory>(buyer)?.equipment.push(item);
+    } else {
+        world.get::<&mut Inventory>(buyer)?.items.push(kind);
+        world.despawn(item)?;
+    }
+    tracing::debug!(?buyer, ?vendor, ?item, price, "Bought item");
+    Ok(Some(price))
+}
+
+/// Has `vendor` pay `seller` for `item` out of their equipment, and puts it up for sale at full
+/// price. Nothing happens if `seller` isn't carrying it or it's not something that sells. Hands
+/// back what it went for.
+pub fn sell(
+    world: &mut World,
+    seller: Entity,
+    vendor: Entity,
+    item: Entity,
+) -> DRResult<Option<u32>> {
+    let Some(price) = sell_price(world, item) else {
+        return Ok(None);
+    };
+    {
+        let mut inventory = world.get::<&mut Inventory>(seller)?;
+        let Some(i) = inventory
+            .equipment
+            .iter()
+            .position(|carried| *carried == item)
+        else {
+            return Ok(None);
+        };
+        inventory.equipment.remove(i);
+    }
+    world.get::<&mut PlayerWallet>(seller)?.total += price;
+    let value = world.get::<&ItemKind>(item)?.value();
+    world
+        .get::<&mut Vendor>(vendor)?
+        .inventory
+        .push((item, value));
+    tracing::debug!(?seller, ?vendor, ?item, price, "Sold item");
+    Ok(Some(price))
+}
+
+/// Picks out `count` things for a merchant to restock with.
+pub fn roll_restock(count: usize, rng: &mut impl Rng) -> Vec<ItemKind> {
+    (0..count)
+        .map(|_| RESTOCK_KINDS[rng.random_range(0..RESTOCK_KINDS.len())])
+        .collect()
+}
+
+mod tests {
+    use super::*;
+    use crate::entities::{spawn_item_for_sale, spawn_merchant, spawn_player};
+    use crate::models::GameRng;
+    use crate::models::Position;
+
+    #[test]
+    fn test_buying_takes_gold_and_stock() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let sword = spawn_item_for_sale(&mut world, ItemKind::Sword);
+        let rock = spawn_item_for_sale(
+            &mut world,
+            ItemKind::ThrowingRock {
+                damage: ROCK_DAMAGE,
+            },
+        );
+        let merchant = spawn_merchant(
+            &mut world,
+            Position::new(5, 4),
+            vec![(sword, 40), (rock, 3)],
+        );
+        world.get::<&mut PlayerWallet>(player).unwrap().total = 42;
+
+        assert_eq!(buy(&mut world, player, merchant, sword).unwrap(), Some(40));
+        assert_eq!(world.get::<&PlayerWallet>(player).unwrap().total, 2);
+        assert!(
+            world
+                .get::<&Inventory>(player)
+                .unwrap()
+                .equipment
+                .contains(&sword)
+        );
+        // Can't afford the rock any more.
+        assert_eq!(buy(&mut world, player, merchant, rock).unwrap(), None);
+        assert_eq!(
+            world.get::<&Vendor>(merchant).unwrap().inventory,
+            vec![(rock, 3)]
+        );
+
+        world.get::<&mut PlayerWallet>(player).unwrap().total = 3;
+        assert_eq!(buy(&mut world, player, merchant, rock).unwrap(), Some(3));
+        assert!(!world.contains(rock));
+        assert!(
+            world
+                .get::<&Inventory>(player)
+                .unwrap()
+                .items
+                .contains(&ItemKind::ThrowingRock {
+                    damage: ROCK_DAMAGE
+                })
+        );
+    }
+
+    #[test]
+    fn test_selling_pays_half_and_restocks_merchant() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let merchant = spawn_merchant(&mut world, Position::new(5, 4), Vec::new());
+        let dagger = spawn_item_for_sale(&mut world, ItemKind::Dagger);
+        world
+            .get::<&mut Inventory>(player)
+            .unwrap()
+            .equipment
+            .push(dagger);
+        let start = world.get::<&PlayerWallet>(player).unwrap().total;
+
+        let price = ItemKind::Dagger.value() / 2;
+        assert_eq!(
+            sell(&mut world, player, merchant, dagger).unwrap(),
+            Some(price)
+        );
+        assert_eq!(
+            world.get::<&PlayerWallet>(player).unwrap().total,
+            start + price
+        );
+        assert_eq!(
+            world.get::<&Vendor>(merchant).unwrap().inventory,
+            vec![(dagger, ItemKind::Dagger.value())]
+        );
+        // It's not on the player any more, so it can't be sold twice.
+        assert_eq!(sell(&mut world, player, merchant, dagger).unwrap(), None);
+    }
+
+    #[test]
+    fn test_restock_only_rolls_things_merchants_sell() {
+        let kinds = roll_restock(20, &mut GameRng::seeded(3));
+        assert_eq!(kinds.len(), 20);
+        assert!(kinds.iter().all(|kind| RESTOCK_KINDS.contains(kind)));
+    }
+}
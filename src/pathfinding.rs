@@ -0,0 +1,282 @@
+use crate::models::Position;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+/// A* over the four cardinal directions (the same moves the player can make).
+///
+/// The returned path excludes `start` and ends on `goal`. The goal itself is always treated as
+/// reachable so a path can end on an occupied tile (like something we want to attack).
+/// `is_walkable` is expected to reject anything outside the map, otherwise the search won't end
+/// when there's no path. `movement_cost` is what stepping onto a tile costs, and has to be at
+/// least 1 for the path to come out the cheapest.
+pub fn find_path(
+    start: &Position,
+    goal: &Position,
+    is_walkable: impl Fn(&Position) -> bool,
+    movement_cost: impl Fn(&Position) -> u32,
+) -> Option<Vec<Position>> {
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<Position, Position> = HashMap::new();
+    let mut g_score: HashMap<Position, usize> = HashMap::new();
+
+    g_score.insert(start.clone(), 0);
+    open.push(Reverse((
+        start.fast_distance(goal) as usize,
+        0usize,
+        start.x,
+        start.y,
+    )));
+
+    while let Some(Reverse((_, cost, x, y))) = open.pop() {
+        let current = Position::new(x, y);
+        if &current == goal {
+            let path = reconstruct_path(&came_from, start, current);
+            tracing::trace!(?start, ?goal, ?path, "find_path");
+            return Some(path);
+        }
+        if cost > *g_score.get(&current).unwrap_or(&usize::MAX) {
+            // Stale entry, we already found a cheaper way here.
+            continue;
+        }
+        for next in current.cardinal_neighbors() {
+            if &next != goal && !is_walkable(&next) {
+                continue;
+            }
+            let next_cost = cost + movement_cost(&next) as usize;
+            if next_cost < *g_score.get(&next).unwrap_or(&usize::MAX) {
+                g_score.insert(next.clone(), next_cost);
+                came_from.insert(next.clone(), current.clone());
+                let heuristic = next.fast_distance(goal) as usize;
+                open.push(Reverse((next_cost + heuristic, next_cost, next.x, next.y)));
+            }
+        }
+    }
+    tracing::trace!(?start, ?goal, "find_path found no path");
+    None
+}
+
+fn reconstruct_path(
+    came_from: &HashMap<Position, Position>,
+    start: &Position,
+    goal: Position,
+) -> Vec<Position> {
+    let mut path = vec![goal];
+    while let Some(prev) = came_from.get(path.last().unwrap()) {
+        if prev == start {
+            break;
+        }
+        path.push(prev.clone());
+    }
+    path.reverse();
+    path
+}
+
+/// How many steps every tile is from the closest goal, moving in all eight directions.
+/// Build one of these when lots of things are heading for (or away from) the same place
+/// instead of running `find_path` for each of them.
+#[derive(Debug, Default)]
+pub struct DijkstraMap {
+    costs: HashMap<Position, u32>,
+}
+
+impl DijkstraMap {
+    /// Tiles further than `max_depth` steps from every goal are left out, same as unreachable ones.
+    /// Goals are always included even if they aren't walkable (like the tile the player is on).
+    pub fn build(
+        goals: &[Position],
+        is_walkable: impl Fn(&Position) -> bool,
+        max_depth: u32,
+    ) -> Self {
+        let mut costs = HashMap::new();
+        let mut frontier = VecDeque::new();
+        for goal in goals {
+            costs.insert(goal.clone(), 0);
+            frontier.push_back(goal.clone());
+        }
+        // Every step costs the same, so a plain breadth first search is enough.
+        while let Some(current) = frontier.pop_front() {
+            let next_cost = costs[&current] + 1;
+            if next_cost > max_depth {
+                continue;
+            }
+            for next in current.neighbors() {
+                if costs.contains_key(&next) || !is_walkable(&next) {
+                    continue;
+                }
+                costs.insert(next.clone(), next_cost);
+                frontier.push_back(next);
+            }
+        }
+        tracing::trace!(?goals, tiles = costs.len(), "DijkstraMap::build");
+        DijkstraMap { costs }
+    }
+
+    /// How many steps `pos` is from the closest goal. `None` if it can't get to one.
+    pub fn cost(&self, pos: &Position) -> Option<u32> {
+        self.costs.get(pos).copied()
+    }
+
+    /// The neighbor that gets closest to a goal (rolling downhill).
+    pub fn best_step_from(&self, pos: &Position) -> Option<Position> {
+        let current = self.cost(pos)?;
+        pos.neighbors()
+            .into_iter()
+            .filter_map(|next| self.cost(&next).map(|cost| (cost, next)))
+            .filter(|(cost, _)| *cost < current)
+            .min_by_key(|(cost, _)| *cost)
+            .map(|(_, next)| next)
+    }
+
+    /// The neighbor that gets furthest from every goal (rolling uphill), for running away.
+    pub fn worst_step_from(&self, pos: &Position) -> Option<Position> {
+        let current = self.cost(pos)?;
+        pos.neighbors()
+            .into_iter()
+            .filter_map(|next| self.cost(&next).map(|cost| (cost, next)))
+            .filter(|(cost, _)| *cost > current)
+            .max_by_key(|(cost, _)| *cost)
+            .map(|(_, next)| next)
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_path_straight_line() {
+        let start = Position::new(0, 0);
+        let goal = Position::new(3, 0);
+        let path = find_path(
+            &start,
+            &goal,
+            |pos| pos.is_within_bounds((0, 5), (0, 5)),
+            |_| 1,
+        );
+        assert_eq!(
+            path,
+            Some(vec![
+                Position::new(1, 0),
+                Position::new(2, 0),
+                Position::new(3, 0)
+            ])
+        );
+    }
+
+    #[test]
+    fn test_find_path_around_wall() {
+        let start = Position::new(0, 1);
+        let goal = Position::new(2, 1);
+        // Wall straight down the middle except at the bottom.
+        let is_walkable = |pos: &Position| {
+            pos.is_within_bounds((0, 2), (0, 2)) && !(pos.x == 1 && pos.y < 2)
+        };
+        let path = find_path(&start, &goal, is_walkable, |_| 1).expect("There should be a path.");
+        assert_eq!(path.len(), 4);
+        assert_eq!(path.last(), Some(&goal));
+        assert!(path.contains(&Position::new(1, 2)));
+    }
+
+    #[test]
+    fn test_find_path_no_path() {
+        let start = Position::new(0, 0);
+        let goal = Position::new(2, 0);
+        let is_walkable = |pos: &Position| pos.is_within_bounds((0, 2), (0, 2)) && pos.x != 1;
+        assert_eq!(find_path(&start, &goal, is_walkable, |_| 1), None);
+    }
+
+    #[test]
+    fn test_find_path_goes_around_slow_tiles() {
+        let start = Position::new(0, 0);
+        let goal = Position::new(4, 0);
+        let is_walkable = |pos: &Position| pos.is_within_bounds((0, 4), (0, 1));
+        // Water along the straight line, so it's cheaper to go down a row and back up.
+        let movement_cost = |pos: &Position| if pos.y == 0 && pos.x != 4 { 5 } else { 1 };
+        let path =
+            find_path(&start, &goal, is_walkable, movement_cost).expect("There should be a path.");
+        assert_eq!(path.len(), 6);
+        assert_eq!(path.last(), Some(&goal));
+        assert!(path[..4].iter().all(|pos| pos.y == 1));
+    }
+
+    #[test]
+    fn test_dijkstra_map_costs() {
+        let goal = Position::new(2, 2);
+        let map = DijkstraMap::build(
+            &[goal.clone()],
+            |pos| pos.is_within_bounds((0, 4), (0, 4)),
+            10,
+        );
+        assert_eq!(map.cost(&goal), Some(0));
+        assert_eq!(map.cost(&Position::new(3, 3)), Some(1));
+        assert_eq!(map.cost(&Position::new(0, 4)), Some(2));
+        assert_eq!(map.cost(&Position::new(5, 5)), None);
+    }
+
+    #[test]
+    fn test_dijkstra_map_rolls_downhill_and_uphill() {
+        let goal = Position::new(0, 1);
+        // A corridor three tiles tall with a wall in the middle, open at the bottom.
+        let is_walkable =
+            |pos: &Position| pos.is_within_bounds((0, 2), (0, 2)) && !(pos.x == 1 && pos.y < 2);
+        let map = DijkstraMap::build(&[goal.clone()], is_walkable, 10);
+
+        let start = Position::new(2, 0);
+        let mut pos = start.clone();
+        let mut steps = 0;
+        while let Some(next) = map.best_step_from(&pos) {
+            assert!(is_walkable(&next) || next == goal);
+            pos = next;
+            steps += 1;
+        }
+        assert_eq!(pos, goal);
+        assert_eq!(steps, map.cost(&start).unwrap());
+
+        let gap = Position::new(1, 2);
+        let away = map
+            .worst_step_from(&gap)
+            .expect("Should be able to back away from the goal.");
+        assert!(map.cost(&away) > map.cost(&gap));
+        // Nowhere further away to go from the far corner.
+        assert_eq!(map.worst_step_from(&Position::new(2, 0)), None);
+    }
+
+    #[test]
+    fn test_dijkstra_map_unreachable() {
+        let goal = Position::new(0, 0);
+        let is_walkable = |pos: &Position| pos.is_within_bounds((0, 4), (0, 4)) && pos.x != 2;
+        let map = DijkstraMap::build(&[goal], is_walkable, 10);
+        let walled_off = Position::new(4, 4);
+        assert_eq!(map.cost(&walled_off), None);
+        assert_eq!(map.best_step_from(&walled_off), None);
+        assert_eq!(map.worst_step_from(&walled_off), None);
+    }
+
+    #[test]
+    fn test_dijkstra_map_max_depth() {
+        let map = DijkstraMap::build(&[Position::new(0, 0)], |_| true, 3);
+        assert_eq!(map.cost(&Position::new(3, -3)), Some(3));
+        assert_eq!(map.cost(&Position::new(4, 0)), None);
+    }
+
+    #[test]
+    fn test_dijkstra_map_shared_by_a_crowd() {
+        let player = Position::new(25, 25);
+        let in_bounds = |pos: &Position| pos.is_within_bounds((0, 50), (0, 50));
+        // One map for everyone instead of a path search per goblin.
+        let map = DijkstraMap::build(&[player.clone()], in_bounds, 60);
+
+        for i in 0..100 {
+            let goblin = Position::new(i % 50, (i * 7) % 50);
+            if goblin == player {
+                continue;
+            }
+            let step = map
+                .best_step_from(&goblin)
+                .expect("Every goblin should be able to get closer.");
+            assert_eq!(map.cost(&step).unwrap() + 1, map.cost(&goblin).unwrap());
+            // Same distance a path search would find when moving diagonally is allowed.
+            let chebyshev = (goblin.x - player.x).abs().max((goblin.y - player.y).abs());
+            assert_eq!(map.cost(&goblin).unwrap() as isize, chebyshev);
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// How many frames the rolling averages are taken over.
+const DEFAULT_WINDOW_SIZE: usize = 60;
+
+#[derive(Debug)]
+struct RollingSamples {
+    samples: VecDeque<Duration>,
+    window_size: usize,
+}
+
+impl RollingSamples {
+    fn new(window_size: usize) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(window_size),
+            window_size,
+        }
+    }
+
+    fn push(&mut self, sample: Duration) {
+        if self.samples.len() == self.window_size {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    fn average(&self) -> Duration {
+        if self.samples.is_empty() {
+            return Duration::ZERO;
+        }
+        self.samples.iter().sum::<Duration>() / self.samples.len() as u32
+    }
+
+    fn worst(&self) -> Duration {
+        self.samples.iter().max().copied().unwrap_or(Duration::ZERO)
+    }
+}
+
+/// Keeps track of how long each system takes to run over the last few frames.
+#[derive(Debug)]
+pub struct SystemProfiler {
+    /// Kept in the order the systems were first recorded so the overlay doesn't jump around.
+    systems: Vec<(String, RollingSamples)>,
+    frame: RollingSamples,
+    window_size: usize,
+    pub show_overlay: bool,
+}
+
+impl Default for SystemProfiler {
+    fn default() -> Self {
+        Self::new(DEFAULT_WINDOW_SIZE)
+    }
+}
+
+impl SystemProfiler {
+    pub fn new(window_size: usize) -> Self {
+        Self {
+            systems: Vec::new(),
+            frame: RollingSamples::new(window_size),
+            window_size,
+            show_overlay: false,
+        }
+    }
+
+    /// Records how long the system with the given name took this frame.
+    pub fn record(&mut self, system_name: &str, elapsed: Duration) {
+        match self
+            .systems
+            .iter_mut()
+            .find(|(name, _)| name.as_str() == system_name)
+        {
+            Some((_, samples)) => samples.push(elapsed),
+            None => {
+                let mut samples = RollingSamples::new(self.window_size);
+                samples.push(elapsed);
+                self.systems.push((system_name.to_string(), samples));
+            }
+        }
+    }
+
+    /// Records how long the whole update took this frame.
+    pub fn record_frame(&mut self, elapsed: Duration) {
+        self.frame.push(elapsed);
+    }
+
+    /// (system name, average, worst) for every system that's been recorded.
+    pub fn report(&self) -> Vec<(String, Duration, Duration)> {
+        self.systems
+            .iter()
+            .map(|(name, samples)| (name.clone(), samples.average(), samples.worst()))
+            .collect()
+    }
+
+    /// (average, worst) for the whole frame.
+    pub fn frame_report(&self) -> (Duration, Duration) {
+        (self.frame.average(), self.frame.worst())
+    }
+
+    /// The lines the debug overlay draws. Only call this when the overlay is actually shown.
+    pub fn overlay_lines(&self) -> Vec<String> {
+        let (frame_avg, frame_worst) = self.frame_report();
+        self.report()
+            .into_iter()
+            .map(|(name, avg, worst)| {
+                format!(
+                    "{name}: {:.2}ms / {:.2}ms",
+                    avg.as_secs_f64() * 1000.0,
+                    worst.as_secs_f64() * 1000.0
+                )
+            })
+            .chain(std::iter::once(format!(
+                "Frame: {:.2}ms / {:.2}ms",
+                frame_avg.as_secs_f64() * 1000.0,
+                frame_worst.as_secs_f64() * 1000.0
+            )))
+            .collect()
+    }
+}
+
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiler_records_every_system() {
+        let mut profiler = SystemProfiler::default();
+        let system_names = ["InputSystem", "AISystem", "DamageSystem"];
+        for _ in 0..3 {
+            for name in system_names {
+                profiler.record(name, Duration::from_millis(1));
+            }
+        }
+
+        let report = profiler.report();
+        assert_eq!(report.len(), system_names.len());
+        for ((name, avg, worst), expected_name) in report.iter().zip(system_names) {
+            assert_eq!(name, expected_name);
+            assert_eq!(*avg, Duration::from_millis(1));
+            assert_eq!(*worst, Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn test_profiler_window_evicts_old_samples() {
+        let mut profiler = SystemProfiler::new(2);
+        profiler.record("AISystem", Duration::from_millis(100));
+        profiler.record("AISystem", Duration::from_millis(2));
+        profiler.record("AISystem", Duration::from_millis(4));
+
+        let (_, avg, worst) = &profiler.report()[0];
+        // The 100ms sample should have fallen out of the window.
+        assert_eq!(*avg, Duration::from_millis(3));
+        assert_eq!(*worst, Duration::from_millis(4));
+    }
+
+    #[test]
+    fn test_profiler_frame_report() {
+        let mut profiler = SystemProfiler::new(2);
+        assert_eq!(profiler.frame_report(), (Duration::ZERO, Duration::ZERO));
+        profiler.record_frame(Duration::from_millis(10));
+        profiler.record_frame(Duration::from_millis(20));
+        assert_eq!(
+            profiler.frame_report(),
+            (Duration::from_millis(15), Duration::from_millis(20))
+        );
+    }
+}
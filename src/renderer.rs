@@ -0,0 +1,1412 @@
+use crate::examine::describe_tile;
+use crate::fov::line;
+use crate::models::ai::Telegraph;
+use crate::models::animation::{Blink, FrameCounter, RenderPosition};
+use crate::models::aura::{Aura, AuraEffect, aura_outline};
+use crate::models::effects::{Bleeding, BurningTiles, Confused, Feared};
+use crate::models::light::{LightMap, tint};
+use crate::models::map::{Fov, Map, TileType};
+use crate::models::minimap::{MINIMAP_FACTOR, MiniCell, Minimap, downsample, minimap_size};
+use crate::models::overlay::TileOverlay;
+use crate::models::stats::{Health, PlayerWallet, Score, Stamina};
+use crate::models::targeting::{UiMode, is_valid_target};
+use crate::models::traps::Trap;
+use crate::models::vendor::{TRADE_KEYS, Vendor, sellable};
+use crate::models::{EntityName, GameLog, Player, Position, Renderable};
+use crate::persistence::HighScore;
+use doryen_rs::{Color, Console, TextAlign};
+use hecs::{Entity, World};
+
+/// How many of the latest log messages are drawn at the bottom of the screen.
+const LOG_LINES: usize = 5;
+/// What anything winding up for a big hit gets drawn in, so there's time to see it coming.
+const WINDUP_COLOR: Color = (255, 140, 0, 255);
+/// Gets mixed into the color of anything that's `Feared`.
+const FEAR_TINT: Color = (160, 60, 220, 255);
+const WATER_COLOR: Color = (64, 96, 255, 255);
+const RUBBLE_COLOR: Color = (140, 110, 80, 255);
+const FIRE_COLOR: Color = (255, 100, 0, 255);
+const MINIMAP_BORDER_COLOR: Color = (192, 192, 192, 255);
+const MINIMAP_PLAYER_COLOR: Color = (255, 255, 92, 255);
+
+/// Somewhere to draw the game, so the drawing code doesn't need a window.
+pub trait Renderer {
+    /// How many tiles across and down there are to draw on.
+    fn size(&self) -> (i32, i32);
+
+    fn clear(&mut self, fore: Color, back: Color, fill: char);
+
+    fn put_char(&mut self, x: i32, y: i32, glyph: char, color: Color);
+
+    /// Sets the background of (x, y) without touching what's drawn on it.
+    fn put_back(&mut self, x: i32, y: i32, color: Color);
+
+    /// Writes `text` left to right starting at (x, y). Not every renderer cares about `back`.
+    fn print(&mut self, x: i32, y: i32, text: &str, fore: Color, back: Option<Color>) {
+        for (i, glyph) in text.chars().enumerate() {
+            self.put_char(x + i as i32, y, glyph, fore);
+        }
+    }
+}
+
+/// Draws straight onto the doryen console.
+pub struct DoryenRenderer<'a> {
+    con: &'a mut Console,
+}
+
+impl<'a> DoryenRenderer<'a> {
+    pub fn new(con: &'a mut Console) -> Self {
+        Self { con }
+    }
+}
+
+impl Renderer for DoryenRenderer<'_> {
+    fn size(&self) -> (i32, i32) {
+        (self.con.get_width() as i32, self.con.get_height() as i32)
+    }
+
+    fn clear(&mut self, fore: Color, back: Color, fill: char) {
+        self.con.clear(Some(fore), Some(back), Some(fill as u16));
+    }
+
+    fn put_char(&mut self, x: i32, y: i32, glyph: char, color: Color) {
+        self.con.ascii(x, y, glyph as u16);
+        self.con.fore(x, y, color);
+    }
+
+    fn put_back(&mut self, x: i32, y: i32, color: Color) {
+        self.con.back(x, y, color);
+    }
+
+    fn print(&mut self, x: i32, y: i32, text: &str, fore: Color, back: Option<Color>) {
+        self.con
+            .print(x, y, text, TextAlign::Left, Some(fore), back);
+    }
+}
+
+/// Keeps a grid of what got drawn where so tests can look at it.
+#[derive(Debug)]
+pub struct RecordingRenderer {
+    width: i32,
+    height: i32,
+    glyphs: Vec<char>,
+    colors: Vec<Color>,
+    backs: Vec<Color>,
+}
+
+impl RecordingRenderer {
+    pub fn new(width: i32, height: i32) -> Self {
+        let size = (width * height) as usize;
+        Self {
+            width,
+            height,
+            glyphs: vec![' '; size],
+            colors: vec![(0, 0, 0, 255); size],
+            backs: vec![(0, 0, 0, 255); size],
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x >= self.width || y >= self.height {
+            return None;
+        }
+        Some((y * self.width + x) as usize)
+    }
+
+    pub fn glyph_at(&self, x: i32, y: i32) -> Option<char> {
+        self.index(x, y).map(|i| self.glyphs[i])
+    }
+
+    pub fn color_at(&self, x: i32, y: i32) -> Option<Color> {
+        self.index(x, y).map(|i| self.colors[i])
+    }
+
+    pub fn back_at(&self, x: i32, y: i32) -> Option<Color> {
+        self.index(x, y).map(|i| self.backs[i])
+    }
+}
+
+impl Renderer for RecordingRenderer {
+    fn size(&self) -> (i32, i32) {
+        (self.width, self.height)
+    }
+
+    fn clear(&mut self, fore: Color, back: Color, fill: char) {
+        self.glyphs.fill(fill);
+        self.colors.fill(fore);
+        self.backs.fill(back);
+    }
+
+    fn put_char(&mut self, x: i32, y: i32, glyph: char, color: Color) {
+        // Same as doryen, anything off screen just doesn't get drawn.
+        if let Some(i) = self.index(x, y) {
+            self.glyphs[i] = glyph;
+            self.colors[i] = color;
+        }
+    }
+
+    fn put_back(&mut self, x: i32, y: i32, color: Color) {
+        if let Some(i) = self.index(x, y) {
+            self.backs[i] = color;
+        }
+    }
+}
+
+/// Puts higher `render_order`s last so they get drawn over whatever they share a tile with.
+/// Whatever else is paired up with each renderable comes along with it.
+/// `color` halfway to `FEAR_TINT`, so it's still recognizable but clearly scared.
+fn feared_color(color: Color) -> Color {
+    let mix = |channel: u8, tint: u8| ((channel as u16 + tint as u16) / 2) as u8;
+    (
+        mix(color.0, FEAR_TINT.0),
+        mix(color.1, FEAR_TINT.1),
+        mix(color.2, FEAR_TINT.2),
+        color.3,
+    )
+}
+
+/// Anything bleeding gets redder the more it's bleeding.
+fn bleeding_color(color: Color, bleeding: &Bleeding) -> Color {
+    (
+        color.0.saturating_add(bleeding.red_boost()),
+        color.1,
+        color.2,
+        color.3,
+    )
+}
+
+fn sort_by_render_order<T>(drawables: &mut [(T, &Renderable)]) {
+    drawables.sort_by_key(|(_pos, renderable)| renderable.render_order);
+}
+
+/// Draws the map, everything on it, and the latest log messages.
+pub fn draw_world(world: &World, renderer: &mut dyn Renderer) {
+    if draw_log_viewer(world, renderer) || draw_trading_screen(world, renderer) {
+        return;
+    }
+    let floor_color = (128, 128, 128, 255);
+    renderer.clear(floor_color, (0, 0, 0, 255), '.');
+
+    let mut light_map_query = world.query::<&LightMap>();
+    let light_map = light_map_query
+        .iter()
+        .next()
+        .map(|(_id, light_map)| light_map);
+    // Everything gets drawn in whatever light is on its tile. Without a light map it's all lit.
+    let lit = |pos: &Position, color| {
+        light_map
+            .and_then(|light_map| light_map.color_at(pos))
+            .map_or(color, |light| tint(color, light))
+    };
+
+    if let Some((_id, map)) = world.query::<&Map>().iter().next() {
+        if light_map.is_some() {
+            for y in 0..map.height {
+                for x in 0..map.width {
+                    let pos = Position::new(x as isize, y as isize);
+                    if !map.is_blocked(&pos) {
+                        renderer.put_char(x as i32, y as i32, '.', lit(&pos, floor_color));
+                    }
+                }
+            }
+        }
+        for pos in map.wall_positions() {
+            renderer.put_char(
+                pos.x as i32,
+                pos.y as i32,
+                '#',
+                lit(&pos, (192, 192, 192, 255)),
+            );
+        }
+        for pos in map.positions_of(TileType::Water) {
+            renderer.put_char(pos.x as i32, pos.y as i32, '~', lit(&pos, WATER_COLOR));
+        }
+        for pos in map.positions_of(TileType::Rubble) {
+            renderer.put_char(pos.x as i32, pos.y as i32, ':', lit(&pos, RUBBLE_COLOR));
+        }
+    }
+
+    let mut fov_query = world.query::<&Fov>();
+    let fov = fov_query.iter().next().map(|(_id, fov)| fov);
+    // Under anything standing on them, and only where the player can see.
+    if let Some((_id, tile_overlay)) = world.query::<&TileOverlay>().iter().next() {
+        for (pos, color) in tile_overlay.colored() {
+            if fov.is_none_or(|fov| fov.can_see(&pos)) {
+                renderer.put_back(pos.x as i32, pos.y as i32, color);
+            }
+        }
+    }
+    // Fire gives off its own light, so the dark doesn't touch it. Walls still hide it.
+    if let Some((_id, burning_tiles)) = world.query::<&BurningTiles>().iter().next() {
+        for pos in &burning_tiles.positions {
+            if fov.is_none_or(|fov| fov.can_see(pos)) {
+                renderer.put_char(pos.x as i32, pos.y as i32, '^', FIRE_COLOR);
+            }
+        }
+    }
+    let frame = world
+        .query::<&FrameCounter>()
+        .iter()
+        .next()
+        .map_or(0, |(_id, frame_counter)| frame_counter.0);
+    let mut query = world.query::<(
+        &Position,
+        &Renderable,
+        Option<&Trap>,
+        Option<&Blink>,
+        Option<&Telegraph>,
+        Option<&Feared>,
+        Option<&Bleeding>,
+        Option<&RenderPosition>,
+    )>();
+    // Blind or not, the player always knows where they are.
+    let player_pos = world
+        .query::<&Position>()
+        .with::<&Player>()
+        .iter()
+        .next()
+        .map(|(_id, pos)| pos.clone());
+    // Anything the player can't see stays hidden, walls aside. So do traps nobody's found yet.
+    let mut drawables: Vec<_> = query
+        .iter()
+        .filter(|(_id, (_pos, _render, trap, ..))| trap.is_none_or(|trap| trap.is_visible()))
+        .map(
+            |(_id, (pos, render, _trap, blink, telegraph, feared, bleeding, slide))| {
+                ((pos, blink, telegraph, feared, bleeding, slide), render)
+            },
+        )
+        .filter(|((pos, ..), _)| {
+            player_pos.as_ref() == Some(*pos) || fov.is_none_or(|fov| fov.can_see(pos))
+        })
+        .collect();
+    sort_by_render_order(&mut drawables);
+    for ((pos, blink, telegraph, feared, bleeding, slide), render) in drawables {
+        // A flash from getting hit wins out over winding up, which wins out over blinking.
+        let color = match (render.tint, telegraph, blink) {
+            (None, Some(_telegraph), _) => WINDUP_COLOR,
+            (None, None, Some(blink)) => blink.color_at(render.color, frame),
+            _ => render.drawn_color(),
+        };
+        let color = match feared {
+            Some(_feared) => feared_color(color),
+            None => color,
+        };
+        let color = match bleeding {
+            Some(bleeding) => bleeding_color(color, bleeding),
+            None => color,
+        };
+        // Anything still sliding over from its last tile gets drawn partway there.
+        let (x, y) = slide.map_or((pos.x as i32, pos.y as i32), |slide| slide.tile());
+        renderer.put_char(x, y, render.glyph, lit(pos, color));
+        // Highlights are meant to stand out, so the dark doesn't touch them.
+        if let Some(bg) = render.bg {
+            renderer.put_back(x, y, bg);
+        }
+    }
+
+    draw_targeting(world, renderer);
+    draw_minimap(world, renderer);
+    draw_status(world, renderer);
+    if draw_examining(world, renderer) || draw_choosing(world, renderer) {
+        return;
+    }
+
+    let (_width, height) = renderer.size();
+    if let Some((_id, game_log)) = world.query::<&GameLog>().iter().next() {
+        for (i, message) in game_log.page(0, LOG_LINES).iter().enumerate() {
+            renderer.print(
+                1,
+                height - LOG_LINES as i32 + i as i32,
+                message,
+                (255, 255, 255, 255),
+                None,
+            );
+        }
+    }
+}
+
+/// The whole log taking up the screen, newest at the bottom, with where it's scrolled to up top.
+/// Returns whether it drew anything.
+fn draw_log_viewer(world: &World, renderer: &mut dyn Renderer) -> bool {
+    let mut ui_mode_query = world.query::<&UiMode>();
+    let Some((_id, UiMode::ReadingLog { scroll })) = ui_mode_query.iter().next() else {
+        return false;
+    };
+    let mut game_log_query = world.query::<&GameLog>();
+    let Some((_id, game_log)) = game_log_query.iter().next() else {
+        return false;
+    };
+    renderer.clear((255, 255, 255, 255), (0, 0, 0, 255), ' ');
+    let (width, height) = renderer.size();
+    let page_lines = GameLog::viewer_page_lines(height as usize);
+    let page = game_log.page(*scroll, page_lines);
+
+    renderer.print(1, 0, "Message log", (255, 255, 92, 255), None);
+    let last_line = game_log.len().saturating_sub(*scroll);
+    let first_line = (last_line + 1).saturating_sub(page.len().max(1));
+    let indicator = format!("{first_line}-{last_line} of {}", game_log.len());
+    renderer.print(
+        width - 1 - indicator.chars().count() as i32,
+        0,
+        &indicator,
+        (192, 192, 192, 255),
+        None,
+    );
+    // Short logs sit at the bottom, right above the hint, like the band does.
+    let top = 1 + (page_lines - page.len()) as i32;
+    for (i, message) in page.iter().enumerate() {
+        renderer.print(1, top + i as i32, message, (255, 255, 255, 255), None);
+    }
+    renderer.print(
+        1,
+        height - 1,
+        "Arrows or PgUp/PgDn to scroll, Escape to close.",
+        (192, 192, 192, 255),
+        None,
+    );
+    true
+}
+
+/// "Sword", or "thing" if it doesn't have a name.
+fn name_of(world: &World, item: Entity) -> String {
+    world
+        .get::<&EntityName>(item)
+        .map_or("thing".to_string(), |name| name.name.clone())
+}
+
+/// What the merchant has for sale, and what the player could sell them, over the whole screen.
+/// Draws nothing and hands back false unless the player's trading.
+fn draw_trading_screen(world: &World, renderer: &mut dyn Renderer) -> bool {
+    let mut ui_mode_query = world.query::<&UiMode>();
+    let Some((_id, UiMode::Trading { vendor, selling })) = ui_mode_query.iter().next() else {
+        return false;
+    };
+    let Ok(stock) = world.get::<&Vendor>(*vendor) else {
+        return false;
+    };
+    let mut player_query = world.query::<&PlayerWallet>().with::<&Player>();
+    let Some((player, wallet)) = player_query.iter().next() else {
+        return false;
+    };
+    let gold = wallet.total;
+    renderer.clear((255, 255, 255, 255), (0, 0, 0, 255), ' ');
+    let (width, height) = renderer.size();
+    let header = (255, 255, 92, 255);
+    let picking = (255, 255, 255, 255);
+    let waiting = (128, 128, 128, 255);
+
+    renderer.print(1, 0, &name_of(world, *vendor), header, None);
+    let gold_text = format!("Gold: {gold}");
+    renderer.print(
+        width - 1 - gold_text.chars().count() as i32,
+        0,
+        &gold_text,
+        (255, 215, 0, 255),
+        None,
+    );
+
+    renderer.print(1, 2, "For sale", header, None);
+    let buy_color = if *selling { waiting } else { picking };
+    for (i, (item, price)) in stock.inventory.iter().take(TRADE_KEYS.len()).enumerate() {
+        let line = format!("{}. {} - {price} gold", i + 1, name_of(world, *item));
+        renderer.print(3, 3 + i as i32, &line, buy_color, None);
+    }
+    if stock.inventory.is_empty() {
+        renderer.print(3, 3, "Nothing left.", waiting, None);
+    }
+
+    let sell_top = 4 + TRADE_KEYS.len() as i32;
+    renderer.print(1, sell_top, "Yours to sell", header, None);
+    let sell_color = if *selling { picking } else { waiting };
+    let yours = sellable(world, player);
+    for (i, (item, price)) in yours.iter().take(TRADE_KEYS.len()).enumerate() {
+        let line = format!("{}. {} - {price} gold", i + 1, name_of(world, *item));
+        renderer.print(3, sell_top + 1 + i as i32, &line, sell_color, None);
+    }
+    if yours.is_empty() {
+        renderer.print(3, sell_top + 1, "Nothing they'd want.", waiting, None);
+    }
+
+    let hint = if *selling {
+        "Number to sell, S to go back to buying, Escape to leave."
+    } else {
+        "Number to buy, S then a number to sell, Escape to leave."
+    };
+    renderer.print(1, height - 1, hint, (192, 192, 192, 255), None);
+    true
+}
+
+/// How many tiles wide the stamina bar is, not counting its ends.
+const STAMINA_BAR_WIDTH: usize = 10;
+const STAMINA_BAR_COLOR: Color = (255, 255, 0, 255);
+const CONFUSED_COLOR: Color = (200, 120, 255, 255);
+
+/// Something like `[######    ]`, filled in as far as `ratio` (between 0 and 1) goes.
+fn bar(ratio: f32, width: usize) -> String {
+    let filled = ((ratio.clamp(0.0, 1.0) * width as f32).round() as usize).min(width);
+    format!("[{}{}]", "#".repeat(filled), " ".repeat(width - filled))
+}
+
+/// The player's health, stamina, gold, and score, on the line just above the log.
+fn draw_status(world: &World, renderer: &mut dyn Renderer) {
+    let mut player_query = world
+        .query::<(
+            &Health,
+            &PlayerWallet,
+            Option<&Stamina>,
+            Option<&Score>,
+            Option<&Confused>,
+        )>()
+        .with::<&Player>();
+    let Some((_id, (health, wallet, stamina, score, confused))) = player_query.iter().next()
+    else {
+        return;
+    };
+    let status_color = (255, 215, 0, 255);
+    let (_width, height) = renderer.size();
+    let y = height - LOG_LINES as i32 - 1;
+    let mut x = 1;
+    let mut print = |renderer: &mut dyn Renderer, text: &str, color: Color| {
+        renderer.print(x, y, text, color, None);
+        x += text.chars().count() as i32;
+    };
+
+    print(
+        renderer,
+        &format!(
+            "HP: {}/{}  ",
+            health.current_health(),
+            health.total_health()
+        ),
+        status_color,
+    );
+    if let Some(stamina) = stamina {
+        print(
+            renderer,
+            &bar(stamina.get_ratio(), STAMINA_BAR_WIDTH),
+            STAMINA_BAR_COLOR,
+        );
+        print(renderer, "  ", status_color);
+    }
+    let mut rest = format!("Gold: {}", wallet.total);
+    if let Some(score) = score {
+        rest.push_str(&format!("  Score: {}", score.total_score));
+    }
+    print(renderer, &rest, status_color);
+    if confused.is_some() {
+        print(renderer, "  Confused", CONFUSED_COLOR);
+    }
+}
+
+/// The examine cursor, with what's under it described where the log would go.
+/// Returns whether it drew anything.
+fn draw_examining(world: &World, renderer: &mut dyn Renderer) -> bool {
+    let mut ui_mode_query = world.query::<&UiMode>();
+    let Some((_id, UiMode::Examining { cursor })) = ui_mode_query.iter().next() else {
+        return false;
+    };
+    let mut fov_query = world.query::<&Fov>().with::<&Player>();
+    let Some((_id, fov)) = fov_query.iter().next() else {
+        return false;
+    };
+    renderer.put_char(cursor.x as i32, cursor.y as i32, 'X', (255, 255, 92, 255));
+    let (_width, height) = renderer.size();
+    renderer.print(
+        1,
+        height - LOG_LINES as i32,
+        &describe_tile(world, cursor, fov),
+        (255, 255, 92, 255),
+        None,
+    );
+    true
+}
+
+/// What there is to interact with, numbered, where the log would go. Returns whether it drew
+/// anything.
+fn draw_choosing(world: &World, renderer: &mut dyn Renderer) -> bool {
+    let mut ui_mode_query = world.query::<&UiMode>();
+    let Some((_id, UiMode::Choosing { options })) = ui_mode_query.iter().next() else {
+        return false;
+    };
+    let (_width, height) = renderer.size();
+    let top = height - LOG_LINES as i32;
+    renderer.print(
+        1,
+        top,
+        "Do what? (number to pick, Escape to cancel)",
+        (255, 255, 92, 255),
+        None,
+    );
+    for (i, option) in options.iter().take(LOG_LINES - 1).enumerate() {
+        let line = format!("{}. {}", i + 1, option.describe(world));
+        renderer.print(3, top + 1 + i as i32, &line, (255, 255, 255, 255), None);
+    }
+    true
+}
+
+/// What's been seen of the floor, shrunk down into a box in the top right corner, with the
+/// player on it. Stops short of the status line if the floor's too tall to fit.
+fn draw_minimap(world: &World, renderer: &mut dyn Renderer) {
+    let mut minimap_query = world.query::<&Minimap>();
+    if !minimap_query
+        .iter()
+        .next()
+        .is_some_and(|(_id, minimap)| minimap.shown)
+    {
+        return;
+    }
+    let mut map_query = world.query::<&Map>();
+    let Some((_id, map)) = map_query.iter().next() else {
+        return;
+    };
+    let mut player_query = world.query::<(&Position, &Fov)>().with::<&Player>();
+    let Some((_id, (player_pos, fov))) = player_query.iter().next() else {
+        return;
+    };
+    let cells = downsample(map, fov.revealed(), MINIMAP_FACTOR);
+    let (cells_across, cells_down) = minimap_size(map, MINIMAP_FACTOR);
+    let (width, height) = renderer.size();
+    let status_line = height - LOG_LINES as i32 - 1;
+    let rows = (cells_down as i32).min(status_line - 2);
+    if rows <= 0 {
+        return;
+    }
+    let left = width - cells_across as i32 - 2;
+    let right = width - 1;
+    let bottom = rows + 1;
+
+    let black = (0, 0, 0, 255);
+    let put = |renderer: &mut dyn Renderer, x, y, glyph, color| {
+        renderer.put_char(x, y, glyph, color);
+        renderer.put_back(x, y, black);
+    };
+    for x in left..=right {
+        let edge = if x == left || x == right { '+' } else { '-' };
+        put(renderer, x, 0, edge, MINIMAP_BORDER_COLOR);
+        put(renderer, x, bottom, edge, MINIMAP_BORDER_COLOR);
+    }
+    for y in 1..bottom {
+        put(renderer, left, y, '|', MINIMAP_BORDER_COLOR);
+        put(renderer, right, y, '|', MINIMAP_BORDER_COLOR);
+    }
+    for (i, cell) in cells.iter().enumerate() {
+        let (cell_x, cell_y) = ((i % cells_across) as i32, (i / cells_across) as i32);
+        if cell_y >= rows {
+            break;
+        }
+        let (glyph, color) = match cell {
+            MiniCell::Unseen => (' ', black),
+            MiniCell::Floor => ('.', (128, 128, 128, 255)),
+            MiniCell::Wall => ('#', (192, 192, 192, 255)),
+        };
+        put(renderer, left + 1 + cell_x, 1 + cell_y, glyph, color);
+    }
+    let player_cell = (
+        player_pos.x as i32 / MINIMAP_FACTOR as i32,
+        player_pos.y as i32 / MINIMAP_FACTOR as i32,
+    );
+    if player_cell.1 < rows {
+        put(
+            renderer,
+            left + 1 + player_cell.0,
+            1 + player_cell.1,
+            '@',
+            MINIMAP_PLAYER_COLOR,
+        );
+    }
+}
+
+/// The line out to the targeting cursor, green if it can be hit and red if it can't.
+fn draw_targeting(world: &World, renderer: &mut dyn Renderer) {
+    let mut ui_mode_query = world.query::<&UiMode>();
+    let Some((
+        _id,
+        UiMode::Targeting {
+            max_range, cursor, ..
+        },
+    )) = ui_mode_query.iter().next()
+    else {
+        return;
+    };
+    let mut player_query = world.query::<&Position>().with::<&Player>();
+    let Some((_id, player_pos)) = player_query.iter().next() else {
+        return;
+    };
+    let mut map_query = world.query::<&Map>();
+    let map = map_query.iter().next().map(|(_id, map)| map);
+
+    let is_valid = is_valid_target(player_pos, cursor, *max_range, |pos| {
+        map.is_some_and(|map| map.is_blocked(pos))
+    });
+    let color = if is_valid {
+        (92, 255, 92, 255)
+    } else {
+        (255, 92, 92, 255)
+    };
+    for pos in line(player_pos, cursor).iter().skip(1) {
+        let glyph = if pos == cursor { 'X' } else { '*' };
+        renderer.put_char(pos.x as i32, pos.y as i32, glyph, color);
+    }
+}
+
+/// Dots in the edge of every `Aura`, for the debug overlay. Heals are gold, damage is green, and
+/// slows are blue.
+pub fn draw_aura_outlines(world: &World, renderer: &mut dyn Renderer) {
+    for (_id, (pos, aura)) in world.query::<(&Position, &Aura)>().iter() {
+        let color = match aura.effect {
+            AuraEffect::HealNearby(_) => (120, 100, 20, 255),
+            AuraEffect::DamageNearby(..) => (30, 100, 30, 255),
+            AuraEffect::SlowNearby(..) => (30, 50, 130, 255),
+        };
+        for dot in aura_outline(pos, aura.radius) {
+            renderer.put_back(dot.x as i32, dot.y as i32, color);
+        }
+    }
+}
+
+/// Clears the screen and writes `lines` one under the other, centered, starting just above the
+/// middle.
+fn draw_centered_screen(renderer: &mut dyn Renderer, lines: &[(&str, Color)]) {
+    renderer.clear((255, 255, 255, 255), (0, 0, 0, 255), ' ');
+    let (width, height) = renderer.size();
+    for (i, (text, color)) in lines.iter().enumerate() {
+        renderer.print(
+            (width - text.chars().count() as i32) / 2,
+            height / 2 - 1 + i as i32,
+            text,
+            *color,
+            None,
+        );
+    }
+}
+
+/// The title, with the high score table underneath if anyone's on it.
+pub fn draw_title_screen(renderer: &mut dyn Renderer, high_scores: &[HighScore]) {
+    draw_centered_screen(
+        renderer,
+        &[
+            ("Duke Roguelike", (255, 255, 255, 255)),
+            (
+                "Press Enter to start, or Escape to quit.",
+                (192, 192, 192, 255),
+            ),
+        ],
+    );
+    if high_scores.is_empty() {
+        return;
+    }
+    let header = format!(
+        "{:>4}  {:<4} {:>6} {:>5}  {}",
+        "Rank", "Name", "Score", "Floor", "Seed"
+    );
+    let rows = high_scores.iter().enumerate().map(|(i, high_score)| {
+        format!(
+            "{:>4}  {:<4} {:>6} {:>5}  {}",
+            i + 1,
+            high_score.name,
+            high_score.score,
+            high_score.deepest_floor,
+            high_score.seed
+        )
+    });
+    let (width, height) = renderer.size();
+    // Lined up on the header, since the seeds make every row a different length.
+    let x = (width - header.chars().count() as i32 - 10) / 2;
+    let top = height / 2 + 3;
+    renderer.print(x, top, &header, (255, 255, 92, 255), None);
+    for (i, row) in rows.enumerate() {
+        renderer.print(x, top + 1 + i as i32, &row, (192, 192, 192, 255), None);
+    }
+}
+
+pub fn draw_class_select_screen(renderer: &mut dyn Renderer) {
+    draw_centered_screen(
+        renderer,
+        &[
+            ("Everyone is an Adventurer for now.", (255, 255, 255, 255)),
+            (
+                "Press Enter to head in, or Escape to go back.",
+                (192, 192, 192, 255),
+            ),
+        ],
+    );
+}
+
+/// Replaces the whole screen once the player is dead, with `run_summary` (see `RunStats::to_lines`)
+/// underneath. Asks for initials for the high score table while `name_prompt` is set.
+pub fn draw_death_screen(
+    renderer: &mut dyn Renderer,
+    cause: &str,
+    run_summary: &[String],
+    name_prompt: Option<&str>,
+) {
+    let last_line = match name_prompt {
+        Some(name) => format!("Enter your initials: {name}  (Enter to save, Escape to skip)"),
+        None => "Press R to try again, or Escape to quit.".to_string(),
+    };
+    draw_centered_screen(
+        renderer,
+        &[
+            ("You have died.", (255, 92, 92, 255)),
+            (cause, (192, 192, 192, 255)),
+            (&last_line, (255, 255, 255, 255)),
+        ],
+    );
+    let (width, height) = renderer.size();
+    // Lined up on the widest line so the numbers stay in a column.
+    let table_width = run_summary
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0) as i32;
+    let x = (width - table_width) / 2;
+    let top = height / 2 + 3;
+    for (i, line) in run_summary.iter().enumerate() {
+        renderer.print(x, top + i as i32, line, (192, 192, 192, 255), None);
+    }
+}
+
+pub fn draw_victory_screen(renderer: &mut dyn Renderer) {
+    draw_centered_screen(
+        renderer,
+        &[
+            ("You made it out alive!", (92, 255, 92, 255)),
+            ("Press Enter to go back to the menu.", (255, 255, 255, 255)),
+        ],
+    );
+}
+
+/// Goes over the top of the world, which is still drawn underneath.
+pub fn draw_paused_overlay(renderer: &mut dyn Renderer) {
+    let text = "PAUSED";
+    let (width, height) = renderer.size();
+    renderer.print(
+        (width - text.chars().count() as i32) / 2,
+        height / 2,
+        text,
+        (255, 255, 92, 255),
+        Some((0, 0, 0, 255)),
+    );
+}
+
+mod tests {
+    use super::*;
+    use crate::config::GameConfig;
+    use crate::entities::{
+        spawn_door, spawn_goblin_at, spawn_item, spawn_item_for_sale, spawn_merchant, spawn_player,
+        spawn_trap,
+    };
+    use crate::models::GameRng;
+    use crate::models::ai::Action;
+    use crate::models::interaction::{Interaction, InteractionOption};
+    use crate::models::items::{Inventory, ItemKind};
+    use crate::models::light::{AmbientLight, LightLevels, TORCH_COLOR};
+    use crate::models::overlay::BLOOD_COLOR;
+    use crate::models::stats::RunStats;
+    use crate::models::traps::TrapType;
+    use std::collections::HashSet;
+
+    /// Same size as the real console.
+    fn full_screen_renderer() -> RecordingRenderer {
+        let config = GameConfig::default();
+        RecordingRenderer::new(config.console_width as i32, config.console_height as i32)
+    }
+
+    fn row(renderer: &RecordingRenderer, y: i32) -> String {
+        let (width, _height) = renderer.size();
+        (0..width).filter_map(|x| renderer.glyph_at(x, y)).collect()
+    }
+
+    #[test]
+    fn test_draw_world_places_glyphs() {
+        let mut world = World::new();
+        spawn_player(&mut world, Position::new(4, 2));
+        world.spawn((
+            Position::new(1, 1),
+            Renderable {
+                glyph: 'G',
+                color: (92, 255, 92, 255),
+                render_order: Renderable::ACTOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ));
+        // Off the edge of the screen, shouldn't blow up.
+        world.spawn((
+            Position::new(100, 100),
+            Renderable {
+                glyph: 'X',
+                color: (255, 255, 255, 255),
+                render_order: Renderable::ACTOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ));
+        let mut renderer = RecordingRenderer::new(8, 4);
+
+        draw_world(&world, &mut renderer);
+
+        assert_eq!(renderer.glyph_at(4, 2), Some('@'));
+        assert_eq!(renderer.color_at(4, 2), Some((255, 92, 92, 255)));
+        assert_eq!(renderer.glyph_at(1, 1), Some('G'));
+        assert_eq!(renderer.glyph_at(0, 0), Some('.'));
+        assert_eq!(renderer.glyph_at(8, 0), None);
+    }
+
+    #[test]
+    fn test_sliding_glyphs_get_drawn_partway() {
+        let mut world = World::new();
+        spawn_player(&mut world, Position::new(7, 3));
+        let goblin = world.spawn((
+            Position::new(4, 1),
+            Renderable {
+                glyph: 'G',
+                color: (92, 255, 92, 255),
+                render_order: Renderable::ACTOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+            RenderPosition {
+                from: Position::new(1, 1),
+                to: Position::new(4, 1),
+                t: 0.4,
+            },
+        ));
+        let mut renderer = RecordingRenderer::new(8, 4);
+        draw_world(&world, &mut renderer);
+        assert_eq!(renderer.glyph_at(2, 1), Some('G'));
+        assert_eq!(renderer.glyph_at(4, 1), Some('.'));
+
+        world.remove_one::<RenderPosition>(goblin).unwrap();
+        let mut renderer = RecordingRenderer::new(8, 4);
+        draw_world(&world, &mut renderer);
+        assert_eq!(renderer.glyph_at(4, 1), Some('G'));
+    }
+
+    #[test]
+    fn test_draw_world_fills_in_backgrounds() {
+        let mut world = World::new();
+        let highlight = (128, 0, 0, 255);
+        world.spawn((
+            Position::new(1, 1),
+            Renderable {
+                glyph: 'G',
+                color: (92, 255, 92, 255),
+                render_order: Renderable::ACTOR_ORDER,
+                tint: None,
+                bg: Some(highlight),
+            },
+        ));
+        world.spawn((
+            Position::new(2, 1),
+            Renderable {
+                glyph: 'g',
+                color: (92, 255, 92, 255),
+                render_order: Renderable::ACTOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ));
+        let mut renderer = RecordingRenderer::new(8, 4);
+
+        draw_world(&world, &mut renderer);
+
+        assert_eq!(renderer.glyph_at(1, 1), Some('G'));
+        assert_eq!(renderer.back_at(1, 1), Some(highlight));
+        assert_eq!(renderer.back_at(2, 1), Some((0, 0, 0, 255)));
+    }
+
+    #[test]
+    fn test_draw_world_hides_what_player_cant_see() {
+        let mut world = World::new();
+        let player_pos = Position::new(2, 2);
+        let player = spawn_player(&mut world, player_pos.clone());
+        let hidden = Position::new(6, 2);
+        spawn_item(&mut world, Position::new(3, 2), ItemKind::Sword);
+        spawn_item(&mut world, hidden.clone(), ItemKind::Sword);
+        let visible = HashSet::from([player_pos.clone(), Position::new(3, 2)]);
+        world
+            .get::<&mut Fov>(player)
+            .unwrap()
+            .update(&player_pos, 0, visible);
+        let mut renderer = RecordingRenderer::new(8, 4);
+
+        draw_world(&world, &mut renderer);
+
+        assert_eq!(renderer.glyph_at(2, 2), Some('@'));
+        assert_ne!(renderer.glyph_at(3, 2), Some('.'));
+        assert_eq!(renderer.glyph_at(6, 2), Some('.'));
+    }
+
+    #[test]
+    fn test_tile_overlays_only_show_where_the_player_can_see() {
+        let mut world = World::new();
+        let player_pos = Position::new(2, 2);
+        let player = spawn_player(&mut world, player_pos.clone());
+        let visible = HashSet::from([player_pos.clone(), Position::new(3, 2)]);
+        world
+            .get::<&mut Fov>(player)
+            .unwrap()
+            .update(&player_pos, 0, visible);
+        let mut tile_overlay = TileOverlay::default();
+        for pos in [player_pos.clone(), Position::new(3, 2), Position::new(6, 2)] {
+            tile_overlay.set_overlay(pos, BLOOD_COLOR, None);
+        }
+        world.spawn((tile_overlay,));
+        let mut renderer = RecordingRenderer::new(8, 4);
+
+        draw_world(&world, &mut renderer);
+
+        // Still under the player, not drawn over them.
+        assert_eq!(renderer.glyph_at(2, 2), Some('@'));
+        assert_eq!(renderer.back_at(2, 2), Some(BLOOD_COLOR));
+        assert_eq!(renderer.back_at(3, 2), Some(BLOOD_COLOR));
+        assert_eq!(renderer.back_at(6, 2), Some((0, 0, 0, 255)));
+    }
+
+    #[test]
+    fn test_minimap_shows_whats_been_seen() {
+        let mut world = World::new();
+        // Too tall to fit, so it gets cut off above the status line.
+        let map = Map::new_bordered(40, 200);
+        let player_pos = Position::new(9, 6);
+        let player = spawn_player(&mut world, player_pos.clone());
+        let left_half = (0..200)
+            .flat_map(|y| (0..20).map(move |x| Position::new(x, y)))
+            .collect();
+        world
+            .get::<&mut Fov>(player)
+            .unwrap()
+            .update(&player_pos, map.revision(), left_half);
+        world.spawn((map,));
+        let minimap = world.spawn((Minimap::default(),));
+        let mut renderer = full_screen_renderer();
+        draw_world(&world, &mut renderer);
+        assert_eq!(renderer.glyph_at(68, 0), Some('.'));
+
+        world.get::<&mut Minimap>(minimap).unwrap().shown = true;
+        draw_world(&world, &mut renderer);
+
+        let (width, height) = renderer.size();
+        let status_line = height - LOG_LINES as i32 - 1;
+        // Ten cells across, plus the border.
+        assert_eq!(renderer.glyph_at(width - 12, 0), Some('+'));
+        assert_eq!(renderer.glyph_at(width - 1, 0), Some('+'));
+        assert_eq!(renderer.glyph_at(width - 12, 5), Some('|'));
+        assert_eq!(renderer.glyph_at(width - 1, status_line - 1), Some('+'));
+        assert!(row(&renderer, status_line)[1..].starts_with("HP:"));
+        assert_eq!(renderer.glyph_at(width - 9, 2), Some('@'));
+        assert_eq!(renderer.glyph_at(width - 10, 2), Some('.'));
+        // The right half hasn't been seen yet.
+        assert_eq!(renderer.glyph_at(width - 3, 2), Some(' '));
+    }
+
+    #[test]
+    fn test_slow_terrain_gets_drawn() {
+        let mut world = World::new();
+        let mut map = Map::new_bordered(8, 4);
+        map.set(&Position::new(2, 1), TileType::Water);
+        map.set(&Position::new(3, 1), TileType::Rubble);
+        world.spawn((map,));
+        let mut renderer = RecordingRenderer::new(8, 4);
+
+        draw_world(&world, &mut renderer);
+
+        assert_eq!(renderer.glyph_at(0, 0), Some('#'));
+        assert_eq!(renderer.glyph_at(2, 1), Some('~'));
+        assert_eq!(renderer.color_at(2, 1), Some(WATER_COLOR));
+        assert_eq!(renderer.glyph_at(3, 1), Some(':'));
+    }
+
+    #[test]
+    fn test_blinded_player_still_sees_themselves() {
+        let mut world = World::new();
+        let player_pos = Position::new(2, 2);
+        let player = spawn_player(&mut world, player_pos.clone());
+        spawn_item(&mut world, Position::new(3, 2), ItemKind::Sword);
+        world
+            .get::<&mut Fov>(player)
+            .unwrap()
+            .update(&player_pos, 0, HashSet::new());
+        let mut renderer = RecordingRenderer::new(8, 4);
+
+        draw_world(&world, &mut renderer);
+
+        assert_eq!(renderer.glyph_at(2, 2), Some('@'));
+        assert_eq!(renderer.glyph_at(3, 2), Some('.'));
+    }
+
+    #[test]
+    fn test_recording_renderer_print() {
+        let mut renderer = RecordingRenderer::new(8, 1);
+        renderer.print(2, 0, "Hello!", (255, 255, 255, 255), None);
+        let line: String = (0..8).filter_map(|x| renderer.glyph_at(x, 0)).collect();
+        assert_eq!(line, "  Hello!");
+    }
+
+    #[test]
+    fn test_death_screen_is_centered() {
+        let mut renderer = full_screen_renderer();
+        draw_death_screen(&mut renderer, "Killed on depth 1.", &[], None);
+        let (width, height) = renderer.size();
+        let line = row(&renderer, height / 2 - 1);
+        assert_eq!(line.trim(), "You have died.");
+        let first_letter = line.find('Y').unwrap() as i32;
+        assert_eq!(first_letter, (width - 14) / 2);
+    }
+
+    #[test]
+    fn test_death_screen_asks_for_initials() {
+        let mut renderer = full_screen_renderer();
+        draw_death_screen(&mut renderer, "Killed on depth 1.", &[], Some("AB_"));
+        let (_width, height) = renderer.size();
+        assert!(
+            row(&renderer, height / 2 + 1)
+                .trim()
+                .starts_with("Enter your initials: AB_")
+        );
+    }
+
+    #[test]
+    fn test_death_screen_shows_run_summary() {
+        let mut renderer = full_screen_renderer();
+        let run_summary = RunStats::default().to_lines();
+        draw_death_screen(&mut renderer, "Killed on depth 1.", &run_summary, None);
+        let (_width, height) = renderer.size();
+        for (i, line) in run_summary.iter().enumerate() {
+            assert_eq!(row(&renderer, height / 2 + 3 + i as i32).trim(), line.trim());
+        }
+    }
+
+    #[test]
+    fn test_title_screen_lists_high_scores() {
+        let high_scores = vec![
+            HighScore {
+                name: "BOB".to_string(),
+                score: 320,
+                deepest_floor: 3,
+                turns_survived: 400,
+                seed: 42,
+            },
+            HighScore {
+                name: "AMY".to_string(),
+                score: 90,
+                deepest_floor: 1,
+                turns_survived: 50,
+                seed: 7,
+            },
+        ];
+        let mut renderer = full_screen_renderer();
+        draw_title_screen(&mut renderer, &high_scores);
+
+        let (_width, height) = renderer.size();
+        let top = height / 2 + 3;
+        assert!(row(&renderer, top).contains("Rank  Name"));
+        let first: Vec<String> = row(&renderer, top + 1)
+            .split_whitespace()
+            .map(str::to_string)
+            .collect();
+        assert_eq!(first, vec!["1", "BOB", "320", "3", "42"]);
+        assert!(row(&renderer, top + 2).contains("AMY"));
+        assert!(row(&renderer, top + 3).trim().is_empty());
+    }
+
+    #[test]
+    fn test_examining_describes_under_cursor() {
+        let mut world = World::new();
+        spawn_player(&mut world, Position::new(4, 2));
+        spawn_item(&mut world, Position::new(6, 2), ItemKind::Sword);
+        world.spawn((UiMode::Examining {
+            cursor: Position::new(6, 2),
+        },));
+        let mut renderer = full_screen_renderer();
+        draw_world(&world, &mut renderer);
+
+        assert_eq!(renderer.glyph_at(6, 2), Some('X'));
+        let (_width, height) = renderer.size();
+        let line = row(&renderer, height - LOG_LINES as i32);
+        assert!(line[1..].starts_with("You see a sword."));
+    }
+
+    #[test]
+    fn test_choosing_lists_interactions_in_the_log_band() {
+        let mut world = World::new();
+        spawn_player(&mut world, Position::new(4, 2));
+        let door = spawn_door(&mut world, Position::new(3, 2), true);
+        let goblin = spawn_goblin_at(&mut world, Position::new(5, 2), &mut GameRng::seeded(1));
+        world.spawn((UiMode::Choosing {
+            options: vec![
+                InteractionOption {
+                    interaction: Interaction::CloseDoor,
+                    target: door,
+                },
+                InteractionOption {
+                    interaction: Interaction::Shove,
+                    target: goblin,
+                },
+            ],
+        },));
+        let mut renderer = full_screen_renderer();
+        draw_world(&world, &mut renderer);
+
+        let (_width, height) = renderer.size();
+        let top = height - LOG_LINES as i32;
+        assert!(row(&renderer, top)[1..].starts_with("Do what?"));
+        assert!(row(&renderer, top + 1)[3..].starts_with("1. Close the door"));
+        assert!(row(&renderer, top + 2)[3..].starts_with("2. Shove the Goblin"));
+    }
+
+    #[test]
+    fn test_status_line_shows_gold() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(4, 2));
+        world.get::<&mut PlayerWallet>(player).unwrap().total = 27;
+        world.get::<&mut Score>(player).unwrap().total_score = 57;
+        let mut renderer = full_screen_renderer();
+        draw_world(&world, &mut renderer);
+
+        let (_width, height) = renderer.size();
+        let line = row(&renderer, height - LOG_LINES as i32 - 1);
+        assert!(line[1..].starts_with("HP: 15/15  [##########]  Gold: 27  Score: 57"));
+    }
+
+    #[test]
+    fn test_status_line_shows_stamina_bar() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(4, 2));
+        {
+            let mut stamina = world.get::<&mut Stamina>(player).unwrap();
+            stamina.current = stamina.max * 0.3;
+        }
+        let mut renderer = full_screen_renderer();
+        draw_world(&world, &mut renderer);
+
+        let (_width, height) = renderer.size();
+        let y = height - LOG_LINES as i32 - 1;
+        let line = row(&renderer, y);
+        assert!(line[1..].starts_with("HP: 15/15  [###       ]  Gold: 0"));
+        let bar_start = 1 + "HP: 15/15  ".len() as i32;
+        assert_eq!(renderer.color_at(bar_start, y), Some(STAMINA_BAR_COLOR));
+        assert_eq!(renderer.color_at(1, y), Some((255, 215, 0, 255)));
+    }
+
+    #[test]
+    fn test_feared_things_look_purple() {
+        let mut world = World::new();
+        spawn_player(&mut world, Position::new(1, 1));
+        let goblin = world.spawn((
+            Position::new(2, 1),
+            Renderable {
+                glyph: 'g',
+                color: (0, 200, 0, 255),
+                render_order: Renderable::ACTOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+            Feared { remaining_turns: 2 },
+        ));
+        let mut renderer = RecordingRenderer::new(4, 4);
+        draw_world(&world, &mut renderer);
+        assert_eq!(renderer.color_at(2, 1), Some((80, 130, 110, 255)));
+
+        world.remove_one::<Feared>(goblin).unwrap();
+        draw_world(&world, &mut renderer);
+        assert_eq!(renderer.color_at(2, 1), Some((0, 200, 0, 255)));
+    }
+
+    #[test]
+    fn test_status_line_shows_confusion() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(4, 2));
+        world
+            .insert_one(player, Confused { remaining_turns: 3 })
+            .unwrap();
+        let mut renderer = full_screen_renderer();
+        draw_world(&world, &mut renderer);
+
+        let (_width, height) = renderer.size();
+        let line = row(&renderer, height - LOG_LINES as i32 - 1);
+        assert!(line[1..].starts_with("HP: 15/15  [##########]  Gold: 0  Score: 0  Confused"));
+    }
+
+    #[test]
+    fn test_log_viewer_shows_scrolled_page() {
+        let mut world = World::new();
+        spawn_player(&mut world, Position::new(4, 2));
+        let mut game_log = GameLog::default();
+        for i in 0..100 {
+            game_log.push(format!("Message {i}"));
+        }
+        world.spawn((game_log,));
+        world.spawn((UiMode::ReadingLog { scroll: 10 },));
+        let mut renderer = full_screen_renderer();
+        draw_world(&world, &mut renderer);
+
+        let (_width, height) = renderer.size();
+        let page_lines = GameLog::viewer_page_lines(height as usize);
+        assert!(
+            row(&renderer, 0)
+                .trim_end()
+                .ends_with(&format!("{}-90 of 100", 91 - page_lines))
+        );
+        assert!(row(&renderer, height - 2)[1..].starts_with("Message 89"));
+        assert!(row(&renderer, 1)[1..].starts_with(&format!("Message {}", 90 - page_lines)));
+        // The map isn't drawn underneath.
+        assert_ne!(renderer.glyph_at(4, 2), Some('@'));
+    }
+
+    #[test]
+    fn test_trading_screen_lists_both_sides() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(4, 2));
+        world.get::<&mut PlayerWallet>(player).unwrap().total = 12;
+        let dagger = spawn_item_for_sale(&mut world, ItemKind::Dagger);
+        world
+            .get::<&mut Inventory>(player)
+            .unwrap()
+            .equipment
+            .push(dagger);
+        let sword = spawn_item_for_sale(&mut world, ItemKind::Sword);
+        let merchant = spawn_merchant(&mut world, Position::new(4, 1), vec![(sword, 40)]);
+        world.spawn((UiMode::Trading {
+            vendor: merchant,
+            selling: true,
+        },));
+        let mut renderer = full_screen_renderer();
+        draw_world(&world, &mut renderer);
+
+        assert!(row(&renderer, 0)[1..].starts_with("Merchant"));
+        assert!(row(&renderer, 0).trim_end().ends_with("Gold: 12"));
+        assert!(row(&renderer, 3)[3..].starts_with("1. Sword - 40 gold"));
+        let sell_top = 4 + TRADE_KEYS.len() as i32;
+        assert!(row(&renderer, sell_top + 1)[3..].starts_with("1. Dagger - 7 gold"));
+        // Whichever side the number keys are on stands out.
+        assert_eq!(
+            renderer.color_at(3, sell_top + 1),
+            Some((255, 255, 255, 255))
+        );
+        assert_eq!(renderer.color_at(3, 3), Some((128, 128, 128, 255)));
+    }
+
+    #[test]
+    fn test_torchlight_brightens_its_surroundings() {
+        let mut world = World::new();
+        let map = Map::new_bordered(20, 10);
+        world.spawn((AmbientLight(0),));
+        let player = spawn_player(&mut world, Position::new(4, 4));
+        let mut light_map = LightMap::default();
+        light_map.update(&LightLevels::from_world(&world), &map);
+        world.spawn((light_map,));
+        world.spawn((map,));
+        let mut renderer = full_screen_renderer();
+        draw_world(&world, &mut renderer);
+
+        let near = renderer.color_at(5, 4).unwrap();
+        let far = renderer.color_at(15, 4).unwrap();
+        assert!(near.0 > far.0);
+        assert_eq!(far, tint((128, 128, 128, 255), (0, 0, 0, 255)));
+        let player_color = world.get::<&Renderable>(player).unwrap().color;
+        assert_eq!(
+            renderer.color_at(4, 4),
+            Some(tint(player_color, TORCH_COLOR))
+        );
+    }
+
+    #[test]
+    fn test_paused_overlay_keeps_the_world() {
+        let mut world = World::new();
+        spawn_player(&mut world, Position::new(4, 2));
+        let mut renderer = full_screen_renderer();
+        draw_world(&world, &mut renderer);
+        draw_paused_overlay(&mut renderer);
+
+        assert_eq!(renderer.glyph_at(4, 2), Some('@'));
+        let (_width, height) = renderer.size();
+        let line = row(&renderer, height / 2);
+        assert!(line.contains("PAUSED"));
+    }
+
+    #[test]
+    fn test_sort_by_render_order() {
+        let pos = Position::new(1, 1);
+        let goblin = Renderable {
+            glyph: 'G',
+            color: (92, 255, 92, 255),
+            render_order: Renderable::ACTOR_ORDER,
+            tint: None,
+            bg: None,
+        };
+        let sword = Renderable {
+            glyph: '/',
+            color: (192, 192, 192, 255),
+            render_order: Renderable::FLOOR_ORDER,
+            tint: None,
+            bg: None,
+        };
+        let mut drawables = vec![(&pos, &goblin), (&pos, &sword)];
+
+        sort_by_render_order(&mut drawables);
+
+        let glyphs: Vec<_> = drawables.iter().map(|(_, render)| render.glyph).collect();
+        assert_eq!(glyphs, vec!['/', 'G']);
+    }
+
+    #[test]
+    fn test_actors_draw_over_items() {
+        let mut world = World::new();
+        // Spawn the player first so they'd be drawn under the sword without sorting.
+        spawn_player(&mut world, Position::new(2, 2));
+        spawn_item(&mut world, Position::new(2, 2), ItemKind::Sword);
+        let mut renderer = RecordingRenderer::new(4, 4);
+
+        draw_world(&world, &mut renderer);
+
+        assert_eq!(renderer.glyph_at(2, 2), Some('@'));
+    }
+
+    #[test]
+    fn test_hidden_traps_look_like_floor() {
+        let mut world = World::new();
+        let hidden_spikes = Trap::new(TrapType::Spike(3), true, false);
+        spawn_trap(&mut world, Position::new(1, 1), hidden_spikes.clone());
+        let found = spawn_trap(&mut world, Position::new(2, 1), hidden_spikes);
+        world.get::<&mut Trap>(found).unwrap().hidden = false;
+        let mut renderer = RecordingRenderer::new(4, 4);
+
+        draw_world(&world, &mut renderer);
+
+        assert_eq!(renderer.glyph_at(1, 1), Some('.'));
+        assert_eq!(renderer.glyph_at(2, 1), Some('^'));
+    }
+
+    #[test]
+    fn test_winding_up_changes_color() {
+        let mut world = World::new();
+        spawn_player(&mut world, Position::new(1, 1));
+        let troll = world.spawn((
+            Position::new(2, 1),
+            Renderable {
+                glyph: 'T',
+                color: (80, 160, 80, 255),
+                render_order: Renderable::ACTOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ));
+        let mut renderer = RecordingRenderer::new(4, 4);
+        draw_world(&world, &mut renderer);
+        assert_eq!(renderer.color_at(2, 1), Some((80, 160, 80, 255)));
+
+        world
+            .insert_one(
+                troll,
+                Telegraph {
+                    turns_remaining: 1,
+                    pending: Action::Attack(Position::new(1, 1)),
+                },
+            )
+            .unwrap();
+        draw_world(&world, &mut renderer);
+        assert_eq!(renderer.color_at(2, 1), Some(WINDUP_COLOR));
+    }
+}
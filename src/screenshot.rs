@@ -0,0 +1,70 @@
+//! Naming screenshots so they don't land on top of each other.
+
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Seconds since the epoch, which is plenty to tell runs apart.
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
+
+pub fn screenshot_file_name(timestamp: u64, index: u32) -> String {
+    format!("screenshot_{timestamp}_{index:03}.png")
+}
+
+/// The next path in `dir` that `is_taken` says is free. `index` goes up with every screenshot, so
+/// two in the same second still get different names.
+pub fn next_screenshot_path(
+    dir: &Path,
+    timestamp: u64,
+    index: &mut u32,
+    is_taken: impl Fn(&Path) -> bool,
+) -> PathBuf {
+    loop {
+        let path = dir.join(screenshot_file_name(timestamp, *index));
+        *index += 1;
+        if !is_taken(&path) {
+            return path;
+        }
+    }
+}
+
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_screenshot_file_name() {
+        assert_eq!(
+            screenshot_file_name(1700000000, 7),
+            "screenshot_1700000000_007.png"
+        );
+    }
+
+    #[test]
+    fn test_same_second_screenshots_dont_collide() {
+        let dir = Path::new("screenshots");
+        let mut index = 0;
+        let first = next_screenshot_path(dir, 42, &mut index, |_path| false);
+        let second = next_screenshot_path(dir, 42, &mut index, |_path| false);
+        assert_ne!(first, second);
+        assert_eq!(first, dir.join("screenshot_42_000.png"));
+        assert_eq!(index, 2);
+    }
+
+    #[test]
+    fn test_skips_files_that_already_exist() {
+        let dir = Path::new("screenshots");
+        // Left over from an earlier run that started in the same second.
+        let taken = HashSet::from([
+            dir.join("screenshot_42_000.png"),
+            dir.join("screenshot_42_001.png"),
+        ]);
+        let mut index = 0;
+        let path = next_screenshot_path(dir, 42, &mut index, |path| taken.contains(path));
+        assert_eq!(path, dir.join("screenshot_42_002.png"));
+        assert_eq!(index, 3);
+    }
+}
@@ -0,0 +1,970 @@
+use crate::error::DRResult;
+use crate::events::{
+    CloseDoor, DeadEntity, DescendFloor, DoorOpened, DoorUnlocked, EntityMoved, EquipItem,
+    EventBusManager, GoldCollected, ItemBought, ItemSold, KnockbackOccurred, LogMessage,
+    TakeOffEquipment, UseItem,
+};
+use crate::input_source::InputSource;
+use crate::invariants::check_world;
+use crate::models::input::{KeyAction, KeyBindings};
+use crate::models::scheduler::TurnCounter;
+use crate::models::stats::Damage;
+use crate::models::{EngineRequests, Position, RunState};
+use crate::persistence::load_from_disk;
+use crate::profiler::SystemProfiler;
+use crate::systems::{
+    AiSystem, AnimationSystem, AoeDamageHandler, ApplyStatModifiersSystem, AudioDispatchHandler,
+    AuraSystem, BandageHandler, BleedHandler, BlindSystem, ChestHandler, ConeDamageHandler,
+    DamageHandler, DamageSystem, DeadCollector, DeathFadeHandler, DeathLogHandler, DeathSystem,
+    DoorHandler, EquipmentHandler, FireHandler, FireSpreadSystem, FovSystem, GameLogHandler,
+    GameOverHandler, HealHandler, HitFlashHandler, InputSystem, KnockbackHandler, LightingSystem,
+    NaturalRegenResetHandler, NaturalRegenSystem, NoiseHandler, PerceptionHandler, PickupHandler,
+    RegenerationSystem, RunStatsTracker, RunningSystem, ScoreTracker, ScreamHandler, SearchHandler,
+    ShoveHandler, SleepWakeBreaker, SlideHandler, SlumberAttackHandler, StaminaRegenSystem,
+    StatusEffectSystem, SystemFunc, TargetingSystem, TeleportHandler, ThrowHandler,
+    TileDecorationSystem, TradeHandler, TrapHandler, TrapSystem, TurnCounterSystem,
+    VendorRestockSystem, WindupInterruptHandler, WorldResetHandler, reset_world_keeping_player,
+    sort_by_dependencies,
+};
+use hecs::World;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// How many systems fit on a page of the debug overlay, one for each of the keys 1 through 9.
+pub const SYSTEMS_PER_PAGE: usize = 9;
+
+/// Everything the systems need to know about what the player did this frame.
+/// The window and the headless simulation both boil their input down to this.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum SimInput {
+    #[default]
+    Nothing,
+    Move {
+        dx: isize,
+        dy: isize,
+    },
+    /// Keep moving this way until something comes up.
+    Run {
+        dx: isize,
+        dy: isize,
+    },
+    /// Clicked on a tile.
+    Click(Position),
+    /// Skip a turn.
+    Wait,
+    /// Look around for hidden traps, which also takes a turn.
+    Search,
+    /// Put on what's underfoot.
+    Wear,
+    /// Take everything off.
+    TakeOff,
+    /// Use up a bandage on whatever's bleeding.
+    Bandage,
+    /// Open a chest next to the player.
+    Interact,
+}
+
+impl SimInput {
+    /// Works out what the player did from whatever keys they're pressing.
+    pub fn from_input(input: &dyn InputSource, key_bindings: &KeyBindings) -> Self {
+        let is_down = |action| input.key(key_bindings.key_for(action));
+        let running = is_down(KeyAction::Run);
+        let step = |dx, dy| {
+            if running {
+                SimInput::Run { dx, dy }
+            } else {
+                SimInput::Move { dx, dy }
+            }
+        };
+        if is_down(KeyAction::MoveLeft) {
+            step(-1, 0)
+        } else if is_down(KeyAction::MoveRight) {
+            step(1, 0)
+        } else if is_down(KeyAction::MoveUp) {
+            step(0, -1)
+        } else if is_down(KeyAction::MoveDown) {
+            step(0, 1)
+        } else if is_down(KeyAction::Wait) {
+            SimInput::Wait
+        } else if is_down(KeyAction::Search) {
+            SimInput::Search
+        } else if is_down(KeyAction::Wear) {
+            SimInput::Wear
+        } else if is_down(KeyAction::TakeOff) {
+            SimInput::TakeOff
+        } else if is_down(KeyAction::Bandage) {
+            SimInput::Bandage
+        } else if is_down(KeyAction::Interact)
+            // Enter picks targets too, so it only counts the frame it goes down. Otherwise
+            // confirming a throw would go straight on to interacting with whatever's nearby.
+            || input.key_pressed(key_bindings.key_for(KeyAction::Confirm))
+        {
+            SimInput::Interact
+        } else if let Some(target) = input.clicked_tile() {
+            SimInput::Click(target)
+        } else {
+            SimInput::Nothing
+        }
+    }
+
+    fn key_action(&self) -> Option<KeyAction> {
+        match self {
+            SimInput::Move { dx: -1, dy: 0 } => Some(KeyAction::MoveLeft),
+            SimInput::Move { dx: 1, dy: 0 } => Some(KeyAction::MoveRight),
+            SimInput::Move { dx: 0, dy: -1 } => Some(KeyAction::MoveUp),
+            SimInput::Move { dx: 0, dy: 1 } => Some(KeyAction::MoveDown),
+            SimInput::Wait => Some(KeyAction::Wait),
+            SimInput::Search => Some(KeyAction::Search),
+            SimInput::Wear => Some(KeyAction::Wear),
+            SimInput::TakeOff => Some(KeyAction::TakeOff),
+            SimInput::Bandage => Some(KeyAction::Bandage),
+            SimInput::Interact => Some(KeyAction::Interact),
+            _ => None,
+        }
+    }
+
+    /// Holds down whatever keys `key_bindings` has for this.
+    pub fn pressed_with(self, key_bindings: KeyBindings) -> ScriptedInput {
+        ScriptedInput {
+            input: self,
+            key_bindings,
+        }
+    }
+
+    /// Whether `name` is one of the keys `key_bindings` has down for this.
+    fn presses(&self, name: &str, key_bindings: &KeyBindings) -> bool {
+        if let SimInput::Run { dx, dy } = self {
+            // Held along with the direction.
+            return key_bindings.key_for(KeyAction::Run) == name
+                || SimInput::Move { dx: *dx, dy: *dy }.presses(name, key_bindings);
+        }
+        self.key_action()
+            .is_some_and(|action| key_bindings.key_for(action) == name)
+    }
+}
+
+/// Lets scripted input be fed through the same path as the keyboard, pressing whichever keys it's
+/// bound to.
+pub struct ScriptedInput {
+    input: SimInput,
+    key_bindings: KeyBindings,
+}
+
+impl InputSource for ScriptedInput {
+    fn key(&self, name: &str) -> bool {
+        self.input.presses(name, &self.key_bindings)
+    }
+
+    fn key_pressed(&self, name: &str) -> bool {
+        self.key(name)
+    }
+
+    fn any_key_pressed(&self) -> bool {
+        !matches!(self.input, SimInput::Nothing | SimInput::Click(_))
+    }
+
+    fn clicked_tile(&self) -> Option<Position> {
+        match &self.input {
+            SimInput::Click(target) => Some(target.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// The game without a window. Owns the world and runs the systems one turn at a time.
+pub struct Simulation {
+    pub world: World,
+    systems: Vec<Box<dyn SystemFunc>>,
+    event_bus_manager: EventBusManager,
+    pub profiler: SystemProfiler,
+}
+
+impl Simulation {
+    pub fn new() -> Self {
+        let event_bus_manager = EventBusManager::new();
+        // The fade has to copy the dead entity before the collector gets rid of it, and the run
+        // stats and the log need its name.
+        event_bus_manager.subscribe(Arc::new(DeathFadeHandler));
+        event_bus_manager.subscribe::<DeadEntity>(Arc::new(RunStatsTracker));
+        event_bus_manager.subscribe(Arc::new(DeathLogHandler));
+        event_bus_manager.subscribe(Arc::new(DeadCollector::default()));
+        event_bus_manager.subscribe(Arc::new(AoeDamageHandler));
+        event_bus_manager.subscribe(Arc::new(ConeDamageHandler));
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe::<Damage>(Arc::new(RunStatsTracker));
+        event_bus_manager.subscribe(Arc::new(HitFlashHandler));
+        event_bus_manager.subscribe(Arc::new(KnockbackHandler));
+        event_bus_manager.subscribe::<KnockbackOccurred>(Arc::new(SlideHandler));
+        event_bus_manager.subscribe::<EntityMoved>(Arc::new(SlideHandler));
+        event_bus_manager.subscribe(Arc::new(WindupInterruptHandler));
+        // Has to wake them up before they can get put back to sleep.
+        event_bus_manager.subscribe(Arc::new(SleepWakeBreaker));
+        event_bus_manager.subscribe(Arc::new(SlumberAttackHandler));
+        event_bus_manager.subscribe(Arc::new(BleedHandler));
+        event_bus_manager.subscribe(Arc::new(HealHandler));
+        event_bus_manager.subscribe(Arc::new(PerceptionHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        event_bus_manager.subscribe(Arc::new(NaturalRegenResetHandler));
+        event_bus_manager.subscribe(Arc::new(GameOverHandler));
+        event_bus_manager.subscribe(Arc::new(ThrowHandler));
+        event_bus_manager.subscribe(Arc::new(FireHandler));
+        event_bus_manager.subscribe(Arc::new(AudioDispatchHandler));
+        event_bus_manager.subscribe(Arc::new(TeleportHandler));
+        event_bus_manager.subscribe(Arc::new(ScreamHandler));
+        event_bus_manager.subscribe(Arc::new(NoiseHandler));
+        event_bus_manager.subscribe(Arc::new(SearchHandler));
+        event_bus_manager.subscribe::<DoorOpened>(Arc::new(DoorHandler));
+        event_bus_manager.subscribe::<DoorUnlocked>(Arc::new(DoorHandler));
+        event_bus_manager.subscribe::<CloseDoor>(Arc::new(DoorHandler));
+        event_bus_manager.subscribe(Arc::new(ShoveHandler));
+        event_bus_manager.subscribe(Arc::new(TrapHandler));
+        event_bus_manager.subscribe::<EquipItem>(Arc::new(EquipmentHandler));
+        event_bus_manager.subscribe::<TakeOffEquipment>(Arc::new(EquipmentHandler));
+        event_bus_manager.subscribe(Arc::new(PickupHandler));
+        event_bus_manager.subscribe(Arc::new(ChestHandler));
+        event_bus_manager.subscribe::<ItemBought>(Arc::new(TradeHandler));
+        event_bus_manager.subscribe::<ItemSold>(Arc::new(TradeHandler));
+        event_bus_manager.subscribe::<DeadEntity>(Arc::new(ScoreTracker));
+        event_bus_manager.subscribe::<GoldCollected>(Arc::new(ScoreTracker));
+        event_bus_manager.subscribe::<DescendFloor>(Arc::new(ScoreTracker));
+        event_bus_manager.subscribe::<DescendFloor>(Arc::new(RunStatsTracker));
+        event_bus_manager.subscribe::<UseItem>(Arc::new(RunStatsTracker));
+        event_bus_manager.subscribe(Arc::new(BandageHandler));
+        event_bus_manager.subscribe(Arc::new(WorldResetHandler));
+        Self {
+            world: World::new(),
+            // Anything with dependencies gets moved after them, otherwise this is the order they run in.
+            systems: sort_by_dependencies(vec![
+                Box::new(DeathSystem::default()),
+                // Has to decide whether the player keeps running before the input gets handled.
+                Box::new(RunningSystem::default()),
+                // Buffs and debuffs have to be counted up before anyone fights or moves with them.
+                Box::new(ApplyStatModifiersSystem::default()),
+                Box::new(InputSystem::default()),
+                Box::new(TargetingSystem::default()),
+                // Everything that happens once a turn goes by this, so it comes as early as it can.
+                Box::new(TurnCounterSystem::default()),
+                // Blinded AIs shouldn't get to see anything on the turn they're blinded.
+                Box::new(BlindSystem::default()),
+                Box::new(AiSystem::new()),
+                Box::new(FovSystem::default()),
+                Box::new(LightingSystem::default()),
+                // Every timed status ticks down here, before anything it does gets dealt.
+                Box::new(StatusEffectSystem::default()),
+                Box::new(AuraSystem::default()),
+                Box::new(TileDecorationSystem::default()),
+                Box::new(FireSpreadSystem::default()),
+                Box::new(RegenerationSystem::default()),
+                Box::new(StaminaRegenSystem::default()),
+                Box::new(NaturalRegenSystem::default()),
+                Box::new(DamageSystem::default()),
+                Box::new(AnimationSystem::default()),
+                Box::new(TrapSystem::default()),
+                Box::new(VendorRestockSystem::default()),
+            ]),
+            event_bus_manager,
+            profiler: SystemProfiler::default(),
+        }
+    }
+
+    /// Call this once the player, the game RNG, and the rest of the floor have been spawned.
+    pub fn init(&mut self) {
+        tracing::info!("Initializing all ECS systems...");
+        for system in self.systems.iter_mut() {
+            tracing::debug!("Initializing {}...", system.get_name());
+            system.init(&mut self.world, &mut self.event_bus_manager);
+        }
+    }
+
+    /// Clears out everything but the player and starts the systems over on what's left, ready for
+    /// a new floor to get spawned in. Call `init` again once it has been.
+    pub fn reset_keeping_player(&mut self) -> DRResult<()> {
+        for system in self.systems.iter_mut() {
+            system.on_reset(&mut self.world);
+        }
+        reset_world_keeping_player(&mut self.world, &mut self.event_bus_manager)
+    }
+
+    /// Swaps the world out for `loaded`, like a save that's just been read in. Nothing that was
+    /// queued up or scheduled in the old world goes off in the new one, and every system starts
+    /// over on it.
+    pub fn load(&mut self, loaded: World) {
+        for system in self.systems.iter_mut() {
+            system.on_reset(&mut self.world);
+        }
+        self.event_bus_manager.clear_queue();
+        self.world = loaded;
+        self.init();
+    }
+
+    /// Loads the last save if something asked for it this frame, saying how that went in the log.
+    fn handle_load_request(&mut self) {
+        let requested = self
+            .world
+            .query_mut::<&mut EngineRequests>()
+            .into_iter()
+            .next()
+            .is_some_and(|(_id, requests)| std::mem::take(&mut requests.load_game));
+        if !requested {
+            return;
+        }
+        let text = match load_from_disk() {
+            Ok(loaded) => {
+                self.load(loaded);
+                "Loaded the game.".to_string()
+            }
+            Err(e) => {
+                tracing::warn!("Could not load the game due to error {e}");
+                format!("Couldn't load the game: {e}")
+            }
+        };
+        self.event_bus_manager.enqueue(LogMessage { text });
+    }
+
+    /// Counts as still running if nobody added a `RunState` to the world.
+    pub fn run_state(&self) -> RunState {
+        self.world
+            .query::<&RunState>()
+            .iter()
+            .next()
+            .map(|(_id, run_state)| *run_state)
+            .unwrap_or_default()
+    }
+
+    /// How many turns the player's been through, which is what scheduled events go by.
+    fn current_turn(&self) -> u64 {
+        self.world
+            .query::<&TurnCounter>()
+            .iter()
+            .next()
+            .map_or(0, |(_id, turn_counter)| turn_counter.0)
+    }
+
+    /// Every system's name and whether it's on, in the order they run.
+    pub fn system_states(&self) -> Vec<(&'static str, bool)> {
+        self.systems
+            .iter()
+            .map(|system| (system.get_name(), system.is_enabled()))
+            .collect()
+    }
+
+    /// How many pages of systems there are to flip through on the debug overlay.
+    pub fn system_pages(&self) -> usize {
+        self.systems.len().div_ceil(SYSTEMS_PER_PAGE).max(1)
+    }
+
+    /// One line per system on `page` of the debug overlay, numbered by the key that toggles it,
+    /// under a line saying which page it is.
+    pub fn system_toggle_lines(&self, page: usize) -> Vec<String> {
+        let header = format!("Systems {}/{} [0 for more]", page + 1, self.system_pages());
+        let lines = self
+            .system_states()
+            .into_iter()
+            .skip(page * SYSTEMS_PER_PAGE)
+            .take(SYSTEMS_PER_PAGE)
+            .enumerate()
+            .map(|(i, (name, enabled))| {
+                format!("{} {name} [{}]", i + 1, if enabled { "on" } else { "off" })
+            });
+        std::iter::once(header).chain(lines).collect()
+    }
+
+    /// Flips the system at `index` on or off. Returns whether it's on now, or `None` if there's no
+    /// system there. Systems that can't be turned off stay on.
+    pub fn toggle_system(&mut self, index: usize) -> Option<bool> {
+        let system = self.systems.get_mut(index)?;
+        system.set_enabled(!system.is_enabled());
+        tracing::info!(
+            system = system.get_name(),
+            enabled = system.is_enabled(),
+            "Toggled system"
+        );
+        Some(system.is_enabled())
+    }
+
+    /// Runs a tick on scripted input, pressing whatever keys the world's `KeyBindings` has for it.
+    pub fn tick_scripted(&mut self, input: &SimInput) {
+        let key_bindings = self
+            .world
+            .query::<&KeyBindings>()
+            .iter()
+            .next()
+            .map(|(_id, key_bindings)| key_bindings.clone())
+            .unwrap_or_default();
+        self.tick(&input.clone().pressed_with(key_bindings));
+    }
+
+    /// Runs every system once with the given input, then processes everything they queued up.
+    pub fn tick(&mut self, input: &dyn InputSource) {
+        tracing::trace!("Processing systems...");
+        let frame_start = Instant::now();
+        // Anything whose turn has come goes out with everything else this frame.
+        self.event_bus_manager.tick_scheduled(self.current_turn());
+        for system in &mut self.systems {
+            if !system.is_enabled() {
+                continue;
+            }
+            let system_name = system.get_name();
+            tracing::trace!("Updating {system_name}...");
+            let system_start = Instant::now();
+            let result = system.call(&mut self.world, input, &mut self.event_bus_manager);
+            self.profiler.record(system_name, system_start.elapsed());
+            if let Err(e) = result {
+                tracing::error!("Got error while running system {e:?}");
+            }
+        }
+        // Process all events that the systems queued up to be processed.
+        self.event_bus_manager.dispatch_all(&mut self.world);
+        self.handle_load_request();
+        self.profiler.record_frame(frame_start.elapsed());
+        if cfg!(debug_assertions) {
+            // Whatever broke it happened this frame, which narrows things down a lot.
+            for violation in check_world(&self.world) {
+                tracing::error!("Broken invariant! {violation}");
+            }
+        }
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::entities::{spawn_door, spawn_goblin_at, spawn_player};
+    use crate::models::effects::{
+        Bleed, Bleeding, Blinded, Burning, Confused, Effect, EffectKind, Effects, Sleeping, Slowed,
+    };
+    use crate::models::map::{Fov, Map, TileType};
+    use crate::models::running::Running;
+    use crate::models::stats::{DamageKind, Health, Score, Stamina};
+    use crate::models::{BlocksTile, Door, GameLog, GameRng};
+    use crate::persistence::{deserialize_world, serialize_world};
+
+    fn new_simulation(player_pos: Position) -> (Simulation, hecs::Entity) {
+        let mut simulation = Simulation::new();
+        let player = spawn_player(&mut simulation.world, player_pos);
+        simulation.world.spawn((GameRng::seeded(42),));
+        simulation.world.spawn((GameLog::default(),));
+        simulation.init();
+        (simulation, player)
+    }
+
+    #[test]
+    fn test_loading_throws_away_whatever_the_old_world_had_coming() {
+        let (mut simulation, player) = new_simulation(Position::new(5, 5));
+        let saved = serialize_world(&simulation.world).unwrap();
+        let hit = || Damage {
+            from: player,
+            to: player,
+            damage: 5,
+            kind: DamageKind::Physical,
+        };
+        simulation.event_bus_manager.enqueue(hit());
+        simulation.event_bus_manager.enqueue_after(hit(), 2);
+
+        simulation.load(deserialize_world(&saved).unwrap());
+        assert_eq!(simulation.event_bus_manager.queued_len(), 0);
+        assert_eq!(simulation.event_bus_manager.scheduled_len(), 0);
+        for _ in 0..3 {
+            simulation.tick_scripted(&SimInput::Wait);
+        }
+        let health = simulation.world.get::<&Health>(player).unwrap();
+        assert_eq!(health.current_health(), health.total_health() as i32);
+    }
+
+    #[test]
+    fn test_simulation_moves_player() {
+        let (mut simulation, player) = new_simulation(Position::new(5, 5));
+
+        for input in [
+            SimInput::Move { dx: 1, dy: 0 },
+            SimInput::Wait,
+            SimInput::Move { dx: 0, dy: -1 },
+            SimInput::Nothing,
+            SimInput::Move { dx: 1, dy: 0 },
+        ] {
+            simulation.tick_scripted(&input);
+        }
+
+        assert_eq!(
+            *simulation.world.get::<&Position>(player).unwrap(),
+            Position::new(7, 4)
+        );
+    }
+
+    #[test]
+    fn test_simulation_player_blocked_by_entity() {
+        let mut simulation = Simulation::new();
+        let player = spawn_player(&mut simulation.world, Position::new(5, 5));
+        // Something in the way to the right that will just soak up the attack.
+        simulation.world.spawn((Position::new(6, 5), BlocksTile));
+        simulation.world.spawn((GameRng::seeded(42),));
+        simulation.world.spawn((GameLog::default(),));
+        simulation.init();
+
+        simulation.tick_scripted(&SimInput::Move { dx: 1, dy: 0 });
+        simulation.tick_scripted(&SimInput::Move { dx: 0, dy: 1 });
+        simulation.tick_scripted(&SimInput::Move { dx: 1, dy: 0 });
+
+        assert_eq!(
+            *simulation.world.get::<&Position>(player).unwrap(),
+            Position::new(6, 6)
+        );
+    }
+
+    #[test]
+    fn test_simulation_ais_open_doors_in_their_way() {
+        let mut simulation = Simulation::new();
+        spawn_player(&mut simulation.world, Position::new(4, 5));
+        let mut map = Map::new_bordered(20, 20);
+        for y in 1..19 {
+            if y != 5 {
+                map.set(&Position::new(6, y), TileType::Wall);
+            }
+        }
+        let door = spawn_door(&mut simulation.world, Position::new(6, 5), false);
+        let goblin = spawn_goblin_at(
+            &mut simulation.world,
+            Position::new(7, 5),
+            &mut GameRng::seeded(1),
+        );
+        simulation.world.spawn((map,));
+        simulation.world.spawn((GameRng::seeded(42),));
+        simulation.world.spawn((GameLog::default(),));
+        simulation.init();
+
+        // Spots the player and waits at the door.
+        simulation.tick_scripted(&SimInput::Wait);
+        assert!(!simulation.world.get::<&Door>(door).unwrap().open);
+        assert_eq!(
+            *simulation.world.get::<&Position>(goblin).unwrap(),
+            Position::new(7, 5)
+        );
+        // Then opens it.
+        simulation.tick_scripted(&SimInput::Wait);
+        assert!(simulation.world.get::<&Door>(door).unwrap().open);
+        // And comes on through.
+        simulation.tick_scripted(&SimInput::Wait);
+        assert_eq!(
+            *simulation.world.get::<&Position>(goblin).unwrap(),
+            Position::new(6, 5)
+        );
+    }
+
+    #[test]
+    fn test_simulation_ais_dont_walk_through_walls() {
+        let mut simulation = Simulation::new();
+        spawn_player(&mut simulation.world, Position::new(8, 5));
+        let mut map = Map::new_bordered(20, 20);
+        for y in 1..9 {
+            map.set(&Position::new(6, y), TileType::Wall);
+        }
+        let goblin = spawn_goblin_at(
+            &mut simulation.world,
+            Position::new(5, 5),
+            &mut GameRng::seeded(1),
+        );
+        simulation.world.spawn((map.clone(),));
+        simulation.world.spawn((GameRng::seeded(42),));
+        simulation.world.spawn((GameLog::default(),));
+        simulation.init();
+
+        for _ in 0..10 {
+            simulation.tick_scripted(&SimInput::Wait);
+            let goblin_pos = simulation.world.get::<&Position>(goblin).unwrap().clone();
+            assert!(
+                !map.is_blocked(&goblin_pos),
+                "Goblin walked into {goblin_pos:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_simulation_player_death_ends_game() {
+        let (mut simulation, player) = new_simulation(Position::new(5, 5));
+        simulation.world.spawn((RunState::default(),));
+        simulation.tick_scripted(&SimInput::Nothing);
+        assert_eq!(simulation.run_state(), RunState::Running);
+
+        simulation
+            .world
+            .get::<&mut Health>(player)
+            .unwrap()
+            .apply_damage(i32::MAX);
+        simulation.tick_scripted(&SimInput::Nothing);
+        assert_eq!(simulation.run_state(), RunState::GameOver);
+    }
+
+    #[test]
+    fn test_simulation_missing_player_ends_game() {
+        let (mut simulation, player) = new_simulation(Position::new(5, 5));
+        simulation.world.spawn((RunState::default(),));
+        simulation.world.despawn(player).unwrap();
+        simulation.tick_scripted(&SimInput::Move { dx: 1, dy: 0 });
+        assert_eq!(simulation.run_state(), RunState::GameOver);
+    }
+
+    /// A simulation with the player at the west end of a corridor running along y = 5, with
+    /// whatever extra floor `open` says cut out of the rock around it.
+    fn corridor_simulation(open: &[Position]) -> (Simulation, hecs::Entity) {
+        let mut map = Map::new_bordered(20, 12);
+        for x in 0..20 {
+            for y in 0..12 {
+                if y != 5 || !(1..=10).contains(&x) {
+                    map.set(&Position::new(x, y), TileType::Wall);
+                }
+            }
+        }
+        for pos in open {
+            map.set(pos, TileType::Floor);
+        }
+        let mut simulation = Simulation::new();
+        let player = spawn_player(&mut simulation.world, Position::new(1, 5));
+        simulation.world.spawn((map,));
+        simulation.world.spawn((GameRng::seeded(42),));
+        simulation.world.spawn((GameLog::default(),));
+        simulation.init();
+        (simulation, player)
+    }
+
+    fn is_running(simulation: &Simulation, player: hecs::Entity) -> bool {
+        simulation
+            .world
+            .get::<&Running>(player)
+            .is_ok_and(|running| running.active)
+    }
+
+    #[test]
+    fn test_running_goes_to_the_end_of_a_corridor() {
+        let (mut simulation, player) = corridor_simulation(&[]);
+        simulation.tick_scripted(&SimInput::Run { dx: 1, dy: 0 });
+        for _ in 0..20 {
+            simulation.tick_scripted(&SimInput::Nothing);
+        }
+        assert_eq!(
+            *simulation.world.get::<&Position>(player).unwrap(),
+            Position::new(10, 5)
+        );
+        assert!(!is_running(&simulation, player));
+    }
+
+    #[test]
+    fn test_running_stops_at_a_side_passage() {
+        let (mut simulation, player) = corridor_simulation(&[Position::new(6, 6)]);
+        simulation.tick_scripted(&SimInput::Run { dx: 1, dy: 0 });
+        for _ in 0..20 {
+            simulation.tick_scripted(&SimInput::Nothing);
+        }
+        assert_eq!(
+            *simulation.world.get::<&Position>(player).unwrap(),
+            Position::new(6, 5)
+        );
+    }
+
+    #[test]
+    fn test_running_stops_when_a_goblin_shows_up() {
+        let (mut simulation, player) = corridor_simulation(&[]);
+        simulation.tick_scripted(&SimInput::Run { dx: 1, dy: 0 });
+        simulation.tick_scripted(&SimInput::Nothing);
+        assert!(is_running(&simulation, player));
+        let pos = (*simulation.world.get::<&Position>(player).unwrap()).clone();
+
+        let mut rng = GameRng::seeded(1);
+        spawn_goblin_at(&mut simulation.world, Position::new(9, 5), &mut rng);
+        simulation.tick_scripted(&SimInput::Nothing);
+        assert_eq!(*simulation.world.get::<&Position>(player).unwrap(), pos);
+        assert!(!is_running(&simulation, player));
+    }
+
+    #[test]
+    fn test_any_key_stops_running() {
+        let (mut simulation, player) = corridor_simulation(&[]);
+        simulation.tick_scripted(&SimInput::Run { dx: 1, dy: 0 });
+        simulation.tick_scripted(&SimInput::Wait);
+        assert!(!is_running(&simulation, player));
+        assert_eq!(
+            *simulation.world.get::<&Position>(player).unwrap(),
+            Position::new(2, 5)
+        );
+    }
+
+    fn set_stamina(simulation: &mut Simulation, player: hecs::Entity, stamina: Stamina) {
+        *simulation.world.get::<&mut Stamina>(player).unwrap() = stamina;
+    }
+
+    #[test]
+    fn test_running_stops_when_out_of_stamina() {
+        let (mut simulation, player) = corridor_simulation(&[]);
+        set_stamina(&mut simulation, player, Stamina::new(5.0, 0.0));
+        simulation.tick_scripted(&SimInput::Run { dx: 1, dy: 0 });
+        for _ in 0..5 {
+            simulation.tick_scripted(&SimInput::Nothing);
+        }
+        // Two steps' worth, then it's back to walking.
+        assert_eq!(
+            *simulation.world.get::<&Position>(player).unwrap(),
+            Position::new(3, 5)
+        );
+        assert!(!is_running(&simulation, player));
+        assert_eq!(simulation.world.get::<&Stamina>(player).unwrap().current, 1.0);
+    }
+
+    #[test]
+    fn test_running_without_stamina_is_just_a_step() {
+        let (mut simulation, player) = corridor_simulation(&[]);
+        set_stamina(&mut simulation, player, Stamina::new(0.0, 0.0));
+        simulation.tick_scripted(&SimInput::Run { dx: 1, dy: 0 });
+        simulation.tick_scripted(&SimInput::Nothing);
+        assert_eq!(
+            *simulation.world.get::<&Position>(player).unwrap(),
+            Position::new(2, 5)
+        );
+        assert!(!is_running(&simulation, player));
+    }
+
+    #[test]
+    fn test_scripted_input_follows_rebound_keys() {
+        let (mut simulation, player) = new_simulation(Position::new(5, 5));
+        for (_id, key_bindings) in simulation.world.query_mut::<&mut KeyBindings>() {
+            key_bindings.rebind(KeyAction::MoveLeft, "KeyA");
+        }
+
+        simulation.tick_scripted(&SimInput::Move { dx: -1, dy: 0 });
+        assert_eq!(
+            *simulation.world.get::<&Position>(player).unwrap(),
+            Position::new(4, 5)
+        );
+    }
+
+    #[test]
+    fn test_stamina_comes_back_each_turn() {
+        let (mut simulation, player) = new_simulation(Position::new(5, 5));
+        let mut stamina = Stamina::new(10.0, 1.5);
+        stamina.current = 0.0;
+        set_stamina(&mut simulation, player, stamina);
+        for input in [SimInput::Wait, SimInput::Nothing, SimInput::Wait] {
+            simulation.tick_scripted(&input);
+        }
+        assert_eq!(simulation.world.get::<&Stamina>(player).unwrap().current, 3.0);
+    }
+
+    #[test]
+    fn test_simulation_counts_turns_taken() {
+        let (mut simulation, player) = new_simulation(Position::new(5, 5));
+        for input in [
+            SimInput::Move { dx: 1, dy: 0 },
+            SimInput::Nothing,
+            SimInput::Wait,
+            SimInput::Nothing,
+        ] {
+            simulation.tick_scripted(&input);
+        }
+        assert_eq!(
+            simulation
+                .world
+                .get::<&Score>(player)
+                .unwrap()
+                .turns_survived,
+            2
+        );
+    }
+
+    #[test]
+    fn test_scheduled_events_go_off_once_enough_turns_pass() {
+        let (mut simulation, player) = new_simulation(Position::new(5, 5));
+        let health = |simulation: &Simulation| {
+            simulation
+                .world
+                .get::<&Health>(player)
+                .unwrap()
+                .current_health()
+        };
+        let full_health = health(&simulation);
+        simulation.event_bus_manager.enqueue_after(
+            Damage {
+                from: player,
+                to: player,
+                damage: 3,
+                kind: DamageKind::Fire,
+            },
+            2,
+        );
+
+        // Waiting around doesn't count for anything until it's actually a turn.
+        for input in [SimInput::Wait, SimInput::Nothing, SimInput::Wait] {
+            simulation.tick_scripted(&input);
+            assert_eq!(health(&simulation), full_health);
+        }
+        simulation.tick_scripted(&SimInput::Nothing);
+        assert!(health(&simulation) < full_health);
+        assert_eq!(simulation.event_bus_manager.scheduled_len(), 0);
+    }
+
+    #[test]
+    fn test_only_real_actions_count_as_turns() {
+        let (mut simulation, _player) = new_simulation(Position::new(5, 5));
+        assert_eq!(simulation.current_turn(), 0);
+
+        simulation.tick_scripted(&SimInput::Nothing);
+        assert_eq!(simulation.current_turn(), 0);
+
+        simulation.tick_scripted(&SimInput::Move { dx: 1, dy: 0 });
+        assert_eq!(simulation.current_turn(), 1);
+        simulation.tick_scripted(&SimInput::Nothing);
+        assert_eq!(simulation.current_turn(), 1);
+    }
+
+    #[test]
+    fn test_every_status_runs_out_on_time() {
+        let (mut simulation, player) = new_simulation(Position::new(5, 5));
+        let full_sight = simulation.world.get::<&Fov>(player).unwrap().radius;
+        let mut rng = GameRng::seeded(1);
+        // Far enough off that none of them are going to bother the player.
+        let goblins: Vec<hecs::Entity> = (0..6)
+            .map(|i| {
+                let goblin =
+                    spawn_goblin_at(&mut simulation.world, Position::new(40 + i, 40), &mut rng);
+                *simulation.world.get::<&mut Health>(goblin).unwrap() = Health::new(30);
+                goblin
+            })
+            .collect();
+        let world = &mut simulation.world;
+        let poison = Effect {
+            kind: EffectKind::Poison,
+            turns_remaining: 3,
+            magnitude: 1,
+        };
+        world
+            .insert_one(
+                goblins[0],
+                Effects {
+                    active: vec![poison],
+                },
+            )
+            .unwrap();
+        world
+            .insert_one(
+                goblins[1],
+                Burning {
+                    damage_per_turn: 1,
+                    remaining_turns: 3,
+                },
+            )
+            .unwrap();
+        let bleed = Bleed {
+            damage_per_stack: 1,
+            duration_turns: 3,
+        };
+        world.insert_one(goblins[2], Bleeding::new(&bleed)).unwrap();
+        world
+            .insert_one(goblins[3], Confused { remaining_turns: 3 })
+            .unwrap();
+        world
+            .insert_one(
+                goblins[4],
+                Sleeping {
+                    remaining_turns: 3,
+                    wake_on_damage: false,
+                },
+            )
+            .unwrap();
+        world
+            .insert_one(
+                goblins[5],
+                Slowed {
+                    factor: 2.0,
+                    remaining_turns: 3,
+                },
+            )
+            .unwrap();
+        world
+            .insert_one(
+                player,
+                Blinded {
+                    remaining_turns: 3,
+                    saved_range: None,
+                },
+            )
+            .unwrap();
+        let statuses = |world: &World| {
+            [
+                world.get::<&Effects>(goblins[0]).is_ok(),
+                world.get::<&Burning>(goblins[1]).is_ok(),
+                world.get::<&Bleeding>(goblins[2]).is_ok(),
+                world.get::<&Confused>(goblins[3]).is_ok(),
+                world.get::<&Sleeping>(goblins[4]).is_ok(),
+                world.get::<&Slowed>(goblins[5]).is_ok(),
+                world.get::<&Blinded>(player).is_ok(),
+            ]
+        };
+
+        for _ in 0..2 {
+            simulation.tick_scripted(&SimInput::Wait);
+            assert_eq!(statuses(&simulation.world), [true; 7]);
+        }
+        // Waiting around without taking a turn doesn't count.
+        simulation.tick_scripted(&SimInput::Nothing);
+        assert_eq!(statuses(&simulation.world), [true; 7]);
+        simulation.tick_scripted(&SimInput::Wait);
+        assert_eq!(statuses(&simulation.world), [false; 7]);
+
+        // Whatever hurt got hurt every turn it was on.
+        for goblin in &goblins[..3] {
+            assert_eq!(
+                simulation
+                    .world
+                    .get::<&Health>(*goblin)
+                    .unwrap()
+                    .current_health(),
+                27
+            );
+        }
+        assert_eq!(
+            simulation.world.get::<&Fov>(player).unwrap().radius,
+            full_sight
+        );
+    }
+
+    #[test]
+    fn test_system_toggle_lines_come_a_page_at_a_time() {
+        let simulation = Simulation::new();
+        let states = simulation.system_states();
+        assert!(states.len() > SYSTEMS_PER_PAGE);
+        assert_eq!(
+            simulation.system_toggle_lines(0).len(),
+            SYSTEMS_PER_PAGE + 1
+        );
+
+        let second_page = simulation.system_toggle_lines(1);
+        assert_eq!(
+            second_page[0],
+            format!("Systems 2/{} [0 for more]", simulation.system_pages())
+        );
+        // Numbered from 1 again, since that's the key that toggles it now.
+        let (name, _enabled) = states[SYSTEMS_PER_PAGE];
+        assert!(second_page[1].starts_with(&format!("1 {name} [")));
+    }
+
+    #[test]
+    fn test_disabled_systems_are_skipped() {
+        let (mut simulation, player) = new_simulation(Position::new(5, 5));
+        let input_index = simulation
+            .system_states()
+            .iter()
+            .position(|(name, _)| *name == "InputSystem")
+            .unwrap();
+
+        assert_eq!(simulation.toggle_system(input_index), Some(false));
+        simulation.tick_scripted(&SimInput::Move { dx: 1, dy: 0 });
+        assert_eq!(
+            *simulation.world.get::<&Position>(player).unwrap(),
+            Position::new(5, 5)
+        );
+
+        assert_eq!(simulation.toggle_system(input_index), Some(true));
+        simulation.tick_scripted(&SimInput::Move { dx: 1, dy: 0 });
+        assert_eq!(
+            *simulation.world.get::<&Position>(player).unwrap(),
+            Position::new(6, 5)
+        );
+        assert_eq!(simulation.toggle_system(100), None);
+    }
+}
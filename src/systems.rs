@@ -1,52 +1,477 @@
+use crate::audio::{AudioKind, AudioOutput};
+use crate::config::GameConfig;
+use crate::entities::{current_depth, spawn_item, spawn_item_for_sale};
 use crate::error::{DRError, DRResult};
 use crate::events::EventBusManager;
-use crate::events::{DeadEntity, EventBus, EventHandler};
-use crate::models::ai::{Action, Ai, Vision};
-use crate::models::input::InputState;
-use crate::models::stats::{Damage, Health};
-use crate::models::{Player, Position};
-use crate::{CONSOLE_HEIGHT, CONSOLE_WIDTH};
-use doryen_rs::DoryenApi;
-use hecs::{Entity, PreparedQuery, Ref, With, World};
+use crate::events::{
+    AoeDamage, AudioEvent, ChestOpened, CloseDoor, ConeDamage, DeadEntity, DescendFloor,
+    DoorOpened, DoorUnlocked, EntityMoved, EquipItem, EventBus, EventCtx, EventHandler,
+    GoldCollected, Heal, ItemBought, ItemSold, KnockbackOccurred, LogMessage, NoiseEvent,
+    PerceivedMessage, PlayerDeath, Scream, Searched, Shove, TakeOffEquipment, TargetSelected,
+    TeleportTrap, UseItem, WorldReset,
+};
+use crate::fov::compute_fov;
+use crate::input_source::InputSource;
+use crate::models::ai::{
+    Action, Ai, AiContext, AiState, Faction, Screamer, TargetCandidate, Telegraph, Vision, Windup,
+    are_hostile, choose_target,
+};
+use crate::models::animation::{
+    Animation, FADE_OUT_FRAMES, FrameCounter, HIT_FLASH_COLOR, HIT_FLASH_FRAMES, RenderPosition,
+};
+use crate::models::aura::{Aura, AuraEffect};
+use crate::models::effects::{
+    Bleed, Bleeding, Blinded, Burning, BurningTiles, Confused, Effect, EffectKind, Effects, Feared,
+    Sleeping, Slowed, SlumberAttack, rotate_step,
+};
+use crate::models::equipment::{
+    Equippable, Equipped, all_equipped, drop_item, equip, melee_damage, unequip,
+};
+use crate::models::input::{InputState, KeyAction, KeyBindings};
+use crate::models::interaction::{
+    Interaction, InteractionOption, available_interactions, shove_destination,
+};
+use crate::models::items::{Chest, Inventory, ItemKind, LootTable};
+use crate::models::light::{LightLevels, LightMap};
+use crate::models::map::{Fov, Map};
+use crate::models::minimap::Minimap;
+use crate::models::overlay::{BLOOD_COLOR, TARGETING_RANGE_COLOR, TELEGRAPH_COLOR, TileOverlay};
+use crate::models::perception::{HeardThisTurn, Perceived, Perception};
+use crate::models::running::{Running, StopReason, should_stop_running};
+use crate::models::scheduler::{TurnCounter, TurnScheduler};
+use crate::models::spatial_index::SpatialIndex;
+use crate::models::stats::{
+    Attack, Damage, DamageKind, Defense, EffectiveStats, EntitySpeed, Gold, Health, Knockback,
+    NaturalRegen, PlayerWallet, Regeneration, RegenerationSuppressed, Resistances, RunStats, Score,
+    Stamina, StatKind, StatModifier, StatModifiers, stat_change,
+};
+use crate::models::targeting::{
+    TARGETING_RANGE, TargetPurpose, UiMode, is_in_range, is_valid_target, move_cursor, next_target,
+};
+use crate::models::traps::{ALARM_RADIUS, NOTICE_CHANCE, Trap, TrapType};
+use crate::models::vendor::{RESTOCK_ITEMS, TRADE_KEYS, Vendor, buy, roll_restock, sell, sellable};
+use crate::models::{
+    BlocksTile, Door, EngineRequests, EntityName, GameLog, GameRng, Locked, RunState, Player,
+    Position, Renderable,
+};
+use crate::pathfinding::{DijkstraMap, find_path};
+use crate::persistence::save_to_disk;
+use crate::simulation::SimInput;
+use doryen_rs::Color;
+use hecs::{Component, Entity, PreparedQuery, Ref, With, World};
+use rand::Rng;
+use std::any::{Any, TypeId};
 use std::borrow::Borrow;
-use std::borrow::BorrowMut;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::ops::Deref;
 use std::sync::Arc;
 use tracing::{event, warn};
 
-fn get_entity_locations(world: &World) -> HashMap<Position, Entity> {
-    let positions = world
-        .query::<&Position>()
-        .view()
-        .into_iter()
-        .map(|(id, pos)| (pos.clone(), id))
-        .collect::<HashMap<Position, Entity>>();
-    tracing::trace!(?positions, "get_entity_locations");
+/// Every entity on every tile, blocking or not. Use this for things like picking items up.
+pub fn get_all_entity_locations(world: &World) -> HashMap<Position, Vec<Entity>> {
+    let mut positions: HashMap<Position, Vec<Entity>> = HashMap::new();
+    for (id, pos) in world.query::<&Position>().iter() {
+        positions.entry(pos.clone()).or_default().push(id);
+    }
+    tracing::trace!(?positions, "get_all_entity_locations");
     positions
 }
 
-pub trait SystemFunc {
+/// What trying to step onto (or clicking on) a tile ends up doing for the player.
+#[derive(Debug, PartialEq)]
+pub enum MoveOrAttack {
+    Move(Position),
+    Attack(Entity),
+}
+
+/// Stepping onto `target` attacks whatever is blocking it, otherwise we just move there.
+/// `entity_locations` should only have the blocking entities in it.
+pub fn resolve_step(
+    target: &Position,
+    entity_locations: &HashMap<Position, Entity>,
+    config: &GameConfig,
+) -> Option<MoveOrAttack> {
+    if let Some(entity) = entity_locations.get(target) {
+        Some(MoveOrAttack::Attack(*entity))
+    } else if target.is_within_console_bounds(config) {
+        Some(MoveOrAttack::Move(target.clone()))
+    } else {
+        None
+    }
+}
+
+/// Figures out what the player should do when they click on `target`.
+/// Adjacent tiles get moved into or attacked, anything farther gets one step along the path to it.
+/// Clicks outside the map (or on the player) don't do anything. The path steers around slow
+/// terrain on `map` if there is one.
+pub fn resolve_click(
+    player_pos: &Position,
+    target: &Position,
+    entity_locations: &HashMap<Position, Entity>,
+    config: &GameConfig,
+    map: Option<&Map>,
+) -> Option<MoveOrAttack> {
+    if !target.is_within_console_bounds(config) || target == player_pos {
+        return None;
+    }
+    let action = if player_pos.is_adjacent(target) {
+        resolve_step(target, entity_locations, config)?
+    } else {
+        let path = find_path(
+            player_pos,
+            target,
+            |pos| pos.is_within_console_bounds(config) && !entity_locations.contains_key(pos),
+            |pos| map.map_or(1, |map| map.movement_cost(pos)),
+        )?;
+        MoveOrAttack::Move(path.first()?.clone())
+    };
+    tracing::debug!(?player_pos, ?target, ?action, "resolve_click");
+    Some(action)
+}
+
+/// How long fire damage stops regeneration for.
+const BURN_TURNS: u32 = 3;
+/// How much damage a turn, and for how many turns, something burns for after walking into fire.
+const FIRE_DAMAGE: i32 = 1;
+const FIRE_TURNS: u32 = 3;
+/// How long the player has to go without getting hurt before `NaturalRegen` kicks in.
+const OUT_OF_COMBAT_TURNS: u32 = 10;
+/// How far out the AIs' shared map to the player goes.
+const PLAYER_MAP_DEPTH: u32 = 30;
+/// How hard the player hits with nothing equipped.
+const PLAYER_BASE_DAMAGE: i32 = 2;
+/// How hard monsters hit with nothing equipped.
+const MONSTER_BASE_DAMAGE: i32 = 1;
+
+/// Whether the player did something this frame, i.e. whether a turn has passed.
+fn was_input_handled_this_frame(world: &World, player: Entity) -> bool {
+    let input_state = world
+        .get::<&InputState>(player)
+        .expect("Could not get input state!");
+    input_state.was_input_handled_this_frame
+}
+
+/// How many turns the player's finished, going by the `TurnCounter`.
+fn current_turn(world: &World) -> u64 {
+    world
+        .query::<&TurnCounter>()
+        .iter()
+        .next()
+        .map_or(0, |(_id, turn_counter)| turn_counter.0)
+}
+
+/// Whether a turn's gone by since `last_turn`, catching `last_turn` up if it has. For systems
+/// that should only do anything once a turn, no matter how many frames go by in between.
+fn turn_went_by(world: &World, last_turn: &mut u64) -> bool {
+    let turn = current_turn(world);
+    let went_by = turn > *last_turn;
+    *last_turn = turn;
+    went_by
+}
+
+pub trait SystemFunc: Any {
     fn call(
         &mut self,
         world: &mut World,
-        api: &mut dyn DoryenApi,
+        input: &dyn InputSource,
         event_bus_manager: &mut EventBusManager,
     ) -> DRResult<()>;
 
     fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {}
 
-    fn get_name(&self) -> String;
+    /// Comes right before `reset_world_keeping_player`. Anything holding onto ids for what's about
+    /// to go should forget them here, since hecs will hand those ids back out. `init` gets called
+    /// again once the reset's done.
+    fn on_reset(&mut self, world: &mut World) {}
+
+    fn get_name(&self) -> &'static str;
+
+    /// Disabled systems get skipped instead of called.
+    fn is_enabled(&self) -> bool {
+        true
+    }
+
+    /// Systems that can't be turned off (the default) just ignore this.
+    fn set_enabled(&mut self, enabled: bool) {
+        tracing::warn!("{} can't be disabled", self.get_name());
+    }
+
+    /// The systems that have to run before this one, as in `TypeId::of::<InputSystem>()`.
+    fn dependencies(&self) -> Vec<TypeId> {
+        Vec::new()
+    }
+}
+
+/// Keeps track of whether a system is turned on. Embed it in a system and forward `is_enabled`
+/// and `set_enabled` to it so the system can be switched off at runtime.
+#[derive(Debug, Clone)]
+pub struct SystemBase {
+    enabled: bool,
+}
+
+impl Default for SystemBase {
+    fn default() -> Self {
+        SystemBase { enabled: true }
+    }
+}
+
+impl SystemBase {
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+}
+
+/// Puts `systems` in an order where everything runs after what it depends on.
+/// Systems that don't care about each other keep the order they were given in.
+/// Panics if there's a dependency cycle or something depends on a system that isn't there.
+pub fn sort_by_dependencies(systems: Vec<Box<dyn SystemFunc>>) -> Vec<Box<dyn SystemFunc>> {
+    let type_ids: Vec<TypeId> = systems
+        .iter()
+        .map(|system| system.as_ref().type_id())
+        .collect();
+    // How many unsorted systems each one is still waiting on, and who's waiting on it.
+    let mut waiting_on = vec![0; systems.len()];
+    let mut needed_by: Vec<Vec<usize>> = vec![Vec::new(); systems.len()];
+    for (i, system) in systems.iter().enumerate() {
+        for dependency in system.dependencies() {
+            let Some(j) = type_ids.iter().position(|id| *id == dependency) else {
+                panic!(
+                    "{} depends on a system that was never added!",
+                    system.get_name()
+                );
+            };
+            waiting_on[i] += 1;
+            needed_by[j].push(i);
+        }
+    }
+
+    // Kahn's algorithm, always taking whichever ready system came first.
+    let mut ready: BTreeSet<usize> = (0..systems.len()).filter(|i| waiting_on[*i] == 0).collect();
+    let mut order = Vec::with_capacity(systems.len());
+    while let Some(i) = ready.pop_first() {
+        order.push(i);
+        for &dependent in &needed_by[i] {
+            waiting_on[dependent] -= 1;
+            if waiting_on[dependent] == 0 {
+                ready.insert(dependent);
+            }
+        }
+    }
+    if order.len() != systems.len() {
+        let stuck: Vec<&str> = (0..systems.len())
+            .filter(|i| waiting_on[*i] > 0)
+            .map(|i| systems[i].get_name())
+            .collect();
+        panic!("Dependency cycle between systems: {}", stuck.join(", "));
+    }
+
+    let mut systems: Vec<Option<Box<dyn SystemFunc>>> = systems.into_iter().map(Some).collect();
+    order
+        .into_iter()
+        .map(|i| {
+            systems[i]
+                .take()
+                .expect("Each system only gets sorted once.")
+        })
+        .collect()
+}
+
+/// The entity holding the `T` resource, spawning a default one if nobody has added it yet.
+fn find_or_spawn_resource<T: hecs::Component + Default>(world: &mut World) -> Entity {
+    let existing = world.query::<&T>().iter().next().map(|(id, _)| id);
+    existing.unwrap_or_else(|| world.spawn((T::default(),)))
+}
+
+/// Whether the AIs are still partway through their turns from the last time the player acted, in
+/// which case the player has to wait for them.
+fn ai_turn_in_progress(world: &World) -> bool {
+    world
+        .query::<&TurnScheduler>()
+        .iter()
+        .next()
+        .is_some_and(|(_id, scheduler)| !scheduler.is_turn_complete())
+}
+
+/// Whether `entity` has run out of health, even if it hasn't been cleared away yet.
+fn is_dead(world: &World, entity: Entity) -> bool {
+    world
+        .get::<&Health>(entity)
+        .is_ok_and(|health| health.is_dead())
+}
+
+/// How fast `entity` is, going with the default for anything that doesn't say.
+fn speed_of(world: &World, entity: Entity) -> EntitySpeed {
+    world
+        .get::<&EntitySpeed>(entity)
+        .map_or(EntitySpeed::default(), |speed| *speed)
+}
+
+/// `speed_of`, with any `StatModifiers` on speed counted.
+fn modified_speed_of(world: &World, entity: Entity) -> EntitySpeed {
+    let speed = speed_of(world, entity).base as i32 + stat_change(world, entity, StatKind::Speed);
+    EntitySpeed::new(speed.max(0) as u32)
+}
+
+/// How long until `entity` gets to go again. Slow terrain on `map` makes the turn spent stepping
+/// onto it take that many times longer, and so does being `Slowed`.
+fn ticks_until_next_turn(world: &World, map: Option<&Map>, entity: Entity) -> u64 {
+    let cost = match (map, world.get::<&Position>(entity)) {
+        (Some(map), Ok(pos)) => map.movement_cost(&pos),
+        _ => 1,
+    };
+    let ticks = modified_speed_of(world, entity).ticks_per_turn() * u64::from(cost);
+    match world.get::<&Slowed>(entity) {
+        Ok(slowed) => slowed.slow_down(ticks),
+        Err(_) => ticks,
+    }
+}
+
+/// The entity holding the `SpatialIndex`, building one from the world if there isn't one yet.
+fn find_or_build_spatial_index(world: &mut World) -> Entity {
+    let existing = world
+        .query::<&SpatialIndex>()
+        .iter()
+        .next()
+        .map(|(id, _)| id);
+    existing.unwrap_or_else(|| {
+        let spatial_index = SpatialIndex::from_world(world);
+        world.spawn((spatial_index,))
+    })
+}
+
+/// The closed door blocking `pos`, if that's what's there.
+fn closed_door_at(world: &World, spatial_index: &SpatialIndex, pos: &Position) -> Option<Entity> {
+    spatial_index
+        .at(pos)
+        .filter(|entity| world.get::<&Door>(*entity).is_ok_and(|door| !door.open))
+}
+
+/// Has `player` open `door`, unlocking it first if it's locked and they have the key.
+/// Whether that took their turn.
+fn open_door(
+    world: &World,
+    door: Entity,
+    player: Entity,
+    event_bus_manager: &EventBusManager,
+) -> bool {
+    let Ok(key_id) = world.get::<&Locked>(door).map(|locked| locked.key_id) else {
+        event_bus_manager.enqueue(DoorOpened { door });
+        return true;
+    };
+    let has_key = world
+        .get::<&Inventory>(player)
+        .is_ok_and(|inventory| inventory.has_key(key_id));
+    if has_key {
+        event_bus_manager.enqueue(DoorUnlocked { door, by: player });
+    } else {
+        event_bus_manager.enqueue(LogMessage {
+            text: "Locked! You need a key.".to_string(),
+        });
+    }
+    has_key
+}
+
+/// Has `player` do `option`. Hands back whether that took their turn, and what the keyboard
+/// should be doing afterwards.
+fn interact(
+    world: &World,
+    player: Entity,
+    player_pos: &Position,
+    option: &InteractionOption,
+    event_bus_manager: &EventBusManager,
+) -> (bool, UiMode) {
+    let target = option.target;
+    match option.interaction {
+        // Trading doesn't take a turn, same as looking at the log.
+        Interaction::Trade => {
+            tracing::debug!(vendor = ?target, "Opening the trading screen");
+            (
+                false,
+                UiMode::Trading {
+                    vendor: target,
+                    selling: false,
+                },
+            )
+        }
+        Interaction::OpenChest => {
+            // Same as a locked door, no key means no turn goes by.
+            let locked = world
+                .get::<&Chest>(target)
+                .ok()
+                .and_then(|chest| chest.locked);
+            let has_key = locked.is_none_or(|key_id| {
+                world
+                    .get::<&Inventory>(player)
+                    .is_ok_and(|inventory| inventory.has_key(key_id))
+            });
+            if has_key {
+                event_bus_manager.enqueue(ChestOpened {
+                    chest: target,
+                    by: player,
+                });
+            } else {
+                event_bus_manager.enqueue(LogMessage {
+                    text: "Locked! You need a key.".to_string(),
+                });
+            }
+            (has_key, UiMode::Normal)
+        }
+        Interaction::OpenDoor => (
+            open_door(world, target, player, event_bus_manager),
+            UiMode::Normal,
+        ),
+        Interaction::CloseDoor => {
+            let blocked = world.get::<&Position>(target).is_ok_and(|pos| {
+                world
+                    .query::<&SpatialIndex>()
+                    .iter()
+                    .next()
+                    .is_some_and(|(_id, spatial_index)| spatial_index.is_occupied(&pos))
+            });
+            if blocked {
+                event_bus_manager.enqueue(LogMessage {
+                    text: "Something's in the way.".to_string(),
+                });
+            } else {
+                event_bus_manager.enqueue(CloseDoor { door: target });
+            }
+            (!blocked, UiMode::Normal)
+        }
+        Interaction::Shove => match shove_destination(world, player_pos, target) {
+            Ok(_destination) => {
+                event_bus_manager.enqueue(Shove { by: player, target });
+                (true, UiMode::Normal)
+            }
+            Err(text) => {
+                event_bus_manager.enqueue(LogMessage { text });
+                (false, UiMode::Normal)
+            }
+        },
+    }
 }
 
 pub struct InputSystem {
     input_state_entity_id: Option<Entity>,
+    key_bindings_entity_id: Option<Entity>,
+    spatial_index_entity_id: Option<Entity>,
+    ui_mode_entity_id: Option<Entity>,
+    engine_requests_entity_id: Option<Entity>,
+    base: SystemBase,
 }
 
 impl Default for InputSystem {
     fn default() -> InputSystem {
         InputSystem {
             input_state_entity_id: None,
+            key_bindings_entity_id: None,
+            spatial_index_entity_id: None,
+            ui_mode_entity_id: None,
+            engine_requests_entity_id: None,
+            base: SystemBase::default(),
         }
     }
 }
@@ -55,13 +480,56 @@ impl SystemFunc for InputSystem {
     fn call(
         &mut self,
         world: &mut World,
-        api: &mut dyn DoryenApi,
+        input: &dyn InputSource,
         event_bus_manager: &mut EventBusManager,
     ) -> DRResult<()> {
         tracing::trace!("InputSystem::call");
+        let holding_control = input.key("ControlLeft") || input.key("ControlRight");
+        if holding_control && input.key_pressed("KeyS") {
+            let text = match save_to_disk(world) {
+                Ok(path) => format!("Saved the game to {}.", path.display()),
+                Err(e) => {
+                    tracing::warn!("Could not save the game due to error {e}");
+                    format!("Couldn't save the game: {e}")
+                }
+            };
+            event_bus_manager.enqueue(LogMessage { text });
+            return Ok(());
+        }
+        if holding_control && input.key_pressed("KeyL") {
+            // Can't swap out the world from in here, since everything else is still running on it.
+            world
+                .get::<&mut EngineRequests>(
+                    self.engine_requests_entity_id
+                        .expect("Input System was not initialized!"),
+                )?
+                .load_game = true;
+            return Ok(());
+        }
+        let (screenshot_key, dump_key) = {
+            let key_bindings = world.get::<&KeyBindings>(
+                self.key_bindings_entity_id
+                    .expect("Input System was not initialized!"),
+            )?;
+            (
+                key_bindings.key_for(KeyAction::Screenshot).to_string(),
+                key_bindings.key_for(KeyAction::DumpWorld).to_string(),
+            )
+        };
+        if input.key_pressed(&screenshot_key) || input.key_pressed(&dump_key) {
+            // Only the engine can get at the screen, so leave it a note. The world dump goes
+            // through it too, since it's the one that knows how to describe everything.
+            let mut requests = world.get::<&mut EngineRequests>(
+                self.engine_requests_entity_id
+                    .expect("Input System was not initialized!"),
+            )?;
+            requests.screenshot |= input.key_pressed(&screenshot_key);
+            requests.world_dump |= input.key_pressed(&dump_key);
+            return Ok(());
+        }
         // let world = Arc::new(RefCell::new(world));
         // let mut binding = (*world).borrow_mut();
-        let entity_locations = get_entity_locations(world);
+        let config = GameConfig::from_world(world);
         let player_input_id = self
             .input_state_entity_id
             .ok_or(DRError::MissingEntity("player".to_string()))?;
@@ -71,53 +539,253 @@ impl SystemFunc for InputSystem {
                 tracing::warn!(
                     "Cannot find player! Got error {e} Was it added? Assuming game over."
                 );
-                return Err(DRError::GameOver);
+                // Same as if they'd died, so the run actually ends instead of erroring every frame.
+                event_bus_manager.enqueue(PlayerDeath {
+                    player: player_input_id,
+                });
+                return Ok(());
             }
         };
-        let input = api.input();
-
+        let is_targeting = world
+            .get::<&UiMode>(
+                self.ui_mode_entity_id
+                    .expect("Input System was not initialized!"),
+            )
+            .is_ok_and(|ui_mode| *ui_mode != UiMode::Normal);
+        if is_targeting || ai_turn_in_progress(world) {
+            // Either the targeting system has the keyboard, or the AIs haven't finished their
+            // turns yet. Either way, no turn goes by until it's done.
+            world
+                .get::<&mut InputState>(player_input_id)?
+                .was_input_handled_this_frame = false;
+            return Ok(());
+        }
         // let mut had_input = false;
         let mut player_pos = world.get::<&mut Position>(player.entity())?;
         let mut next_position = None;
+        let mut waited = false;
+        let mut searched = false;
+        let mut changed_equipment = false;
+        let mut interacted = false;
+        let mut bandaged = false;
 
-        if input.key("ArrowLeft") {
-            next_position = Some(player_pos.new_from_dx_dy(-1, 0));
-            // player_pos.x = (player_pos.x - 1).max(1);
-        } else if input.key("ArrowRight") {
-            next_position = Some(player_pos.new_from_dx_dy(1, 0));
-            // player_pos.x = (player_pos.x + 1).min((CONSOLE_WIDTH as i32 - 2) as isize);
-        } else if input.key("ArrowUp") {
-            next_position = Some(player_pos.new_from_dx_dy(0, -1));
-            // player_pos.y = (player_pos.y - 1).max(1);
-        } else if input.key("ArrowDown") {
-            next_position = Some(player_pos.new_from_dx_dy(0, 1));
-            // player_pos.y = (player_pos.y + 1).min((CONSOLE_HEIGHT as i32 - 2) as isize);
+        let key_bindings = world.get::<&KeyBindings>(
+            self.key_bindings_entity_id
+                .expect("Input System was not initialized!"),
+        )?;
+        let opening = input.key(key_bindings.key_for(KeyAction::Open));
+        let asleep = world.get::<&Sleeping>(player_input_id).is_ok();
+        let sim_input = match SimInput::from_input(input, &key_bindings) {
+            // Whatever gets pressed while asleep just lets another turn go by.
+            SimInput::Nothing => SimInput::Nothing,
+            _ if asleep => SimInput::Wait,
+            sim_input => sim_input,
+        };
+        match sim_input {
+            SimInput::Move { dx, dy } => {
+                // Confusion sends the player off in any direction, even the one they wanted.
+                let eighths = match world.get::<&Confused>(player_input_id) {
+                    Ok(_confused) => world
+                        .query::<&mut GameRng>()
+                        .iter()
+                        .next()
+                        .map_or(0, |(_id, rng)| rng.random_range(0..8)),
+                    Err(_) => 0,
+                };
+                let (dx, dy) = rotate_step((dx, dy), eighths);
+                next_position = Some(player_pos.new_from_dx_dy(dx, dy));
+            }
+            SimInput::Click(target) => {
+                // Attacks are handled below since the target tile is occupied.
+                let mut map_query = world.query::<&Map>();
+                let map = map_query.iter().next().map(|(_id, map)| map);
+                let spatial_index = world.get::<&SpatialIndex>(
+                    self.spatial_index_entity_id
+                        .expect("Input System was not initialized!"),
+                )?;
+                next_position = match resolve_click(
+                    &player_pos,
+                    &target,
+                    spatial_index.positions(),
+                    &config,
+                    map,
+                ) {
+                    Some(MoveOrAttack::Move(pos)) => Some(pos),
+                    Some(MoveOrAttack::Attack(_)) => Some(target),
+                    None => None,
+                };
+            }
+            SimInput::Wait => waited = true,
+            SimInput::Search => searched = true,
+            SimInput::Wear => {
+                let underfoot = world
+                    .query::<(&Position, &Equippable)>()
+                    .iter()
+                    .find(|(_id, (pos, _equippable))| **pos == *player_pos)
+                    .map(|(id, _)| id);
+                match underfoot {
+                    Some(item) => {
+                        changed_equipment = true;
+                        event_bus_manager.enqueue(EquipItem {
+                            wearer: player_input_id,
+                            item,
+                        });
+                    }
+                    None => event_bus_manager.enqueue(LogMessage {
+                        text: "There's nothing here to put on.".to_string(),
+                    }),
+                }
+            }
+            SimInput::Interact => {
+                let options = available_interactions(world, &player_pos);
+                let next_mode = match options.as_slice() {
+                    [] => {
+                        event_bus_manager.enqueue(LogMessage {
+                            text: "There's nothing here to open.".to_string(),
+                        });
+                        UiMode::Normal
+                    }
+                    [option] => {
+                        let (took_turn, next_mode) = interact(
+                            world,
+                            player_input_id,
+                            &player_pos,
+                            option,
+                            event_bus_manager,
+                        );
+                        interacted = took_turn;
+                        next_mode
+                    }
+                    _ => UiMode::Choosing { options },
+                };
+                if next_mode != UiMode::Normal {
+                    *world.get::<&mut UiMode>(
+                        self.ui_mode_entity_id
+                            .expect("Input System was not initialized!"),
+                    )? = next_mode;
+                }
+            }
+            SimInput::Bandage => {
+                let bleeding = world.get::<&Bleeding>(player_input_id).is_ok();
+                let mut inventory = world.get::<&mut Inventory>(player_input_id)?;
+                if !inventory.items.contains(&ItemKind::Bandage) {
+                    event_bus_manager.enqueue(LogMessage {
+                        text: "You don't have any bandages.".to_string(),
+                    });
+                } else if !bleeding {
+                    event_bus_manager.enqueue(LogMessage {
+                        text: "You're not bleeding.".to_string(),
+                    });
+                } else {
+                    bandaged = inventory.use_bandage();
+                    event_bus_manager.enqueue(UseItem {
+                        user: player_input_id,
+                        item: ItemKind::Bandage,
+                    });
+                }
+            }
+            SimInput::TakeOff => {
+                if all_equipped(world, player_input_id).is_empty() {
+                    event_bus_manager.enqueue(LogMessage {
+                        text: "You don't have anything on.".to_string(),
+                    });
+                } else {
+                    changed_equipment = true;
+                    event_bus_manager.enqueue(TakeOffEquipment {
+                        wearer: player_input_id,
+                    });
+                }
+            }
+            // Running keeps going on its own until the `RunningSystem` calls it off, and costs
+            // stamina for every step.
+            sim_input @ (SimInput::Run { .. } | SimInput::Nothing) => {
+                let direction = world
+                    .get::<&Running>(player_input_id)
+                    .ok()
+                    .filter(|running| running.active)
+                    .map(|running| running.direction);
+                if let Some((dx, dy)) = direction {
+                    if let Ok(mut stamina) = world.get::<&mut Stamina>(player_input_id) {
+                        stamina.try_spend(Stamina::SPRINT_COST);
+                    }
+                    next_position = Some(player_pos.new_from_dx_dy(dx, dy));
+                } else if let SimInput::Run { dx, dy } = sim_input {
+                    // Too tired to run, so it's just a step.
+                    next_position = Some(player_pos.new_from_dx_dy(dx, dy));
+                }
+            }
         }
 
+        // Only borrowed now, since interacting has to look things up in it too.
+        let mut spatial_index = world.get::<&mut SpatialIndex>(
+            self.spatial_index_entity_id
+                .expect("Input System was not initialized!"),
+        )?;
         // let input_state_query = world.query()
         let mut input_state = world.get::<&mut InputState>(
             self.input_state_entity_id
                 .expect("Input System was not initialized!"),
         )?;
-        input_state.was_input_handled_this_frame = false;
-        if let Some(next_position) = next_position {
-            if next_position.is_within_console_bounds()
-                && !entity_locations.contains_key(&next_position)
-            {
-                tracing::debug!("Flipping the input state!");
-                input_state.was_input_handled_this_frame = true;
-
-                player_pos.x = next_position.x;
-                player_pos.y = next_position.y;
-                drop(player_pos);
-            } else if let Some(entity) = entity_locations.get(&next_position) {
-                tracing::debug!("Attacking entity {entity:?}");
-                input_state.was_input_handled_this_frame = true;
-                event_bus_manager.enqueue(Damage {
-                    from: player_input_id,
-                    to: entity.clone(),
-                    damage: 2,
-                });
+        input_state.was_input_handled_this_frame =
+            waited || searched || changed_equipment || interacted || bandaged;
+        if searched {
+            event_bus_manager.enqueue(Searched {
+                around: player_pos.clone(),
+            });
+        }
+        if let Some(next_position) = next_position.as_ref().filter(|_| opening) {
+            match closed_door_at(world, &spatial_index, next_position) {
+                Some(door) => {
+                    input_state.was_input_handled_this_frame =
+                        open_door(world, door, player_input_id, event_bus_manager);
+                }
+                None => event_bus_manager.enqueue(LogMessage {
+                    text: "There's no door there to open.".to_string(),
+                }),
+            }
+        } else if let Some(next_position) = next_position {
+            match resolve_step(&next_position, spatial_index.positions(), &config) {
+                Some(MoveOrAttack::Move(next_position)) => {
+                    tracing::debug!("Flipping the input state!");
+                    input_state.was_input_handled_this_frame = true;
+
+                    spatial_index.move_entity(player_input_id, &next_position);
+                    event_bus_manager.enqueue(EntityMoved {
+                        entity: player_input_id,
+                        from: player_pos.clone(),
+                        to: next_position.clone(),
+                    });
+                    player_pos.x = next_position.x;
+                    player_pos.y = next_position.y;
+                    drop(player_pos);
+                    event_bus_manager
+                        .enqueue(AudioEvent::new(AudioKind::Footstep, Some(next_position)));
+                }
+                // Walking into a closed door opens it.
+                Some(MoveOrAttack::Attack(entity))
+                    if closed_door_at(world, &spatial_index, &next_position).is_some() =>
+                {
+                    input_state.was_input_handled_this_frame =
+                        open_door(world, entity, player_input_id, event_bus_manager);
+                }
+                Some(MoveOrAttack::Attack(entity)) => {
+                    tracing::debug!("Attacking entity {entity:?}");
+                    input_state.was_input_handled_this_frame = true;
+                    let damage = melee_damage(world, player_input_id, entity, PLAYER_BASE_DAMAGE);
+                    event_bus_manager.enqueue(Damage {
+                        from: player_input_id,
+                        to: entity,
+                        damage,
+                        kind: DamageKind::Physical,
+                    });
+                    event_bus_manager.enqueue(LogMessage::attack(
+                        world,
+                        Some(player_input_id),
+                        Some(entity),
+                        damage,
+                    ));
+                }
+                None => {}
             }
         }
 
@@ -133,47 +801,453 @@ impl SystemFunc for InputSystem {
                 .expect("InputState not found in world.")
                 .0,
         );
+        self.key_bindings_entity_id = Some(find_or_spawn_resource::<KeyBindings>(world));
+        self.ui_mode_entity_id = Some(find_or_spawn_resource::<UiMode>(world));
+        self.engine_requests_entity_id = Some(find_or_spawn_resource::<EngineRequests>(world));
+        self.spatial_index_entity_id = Some(find_or_build_spatial_index(world));
         // self.input_state_entity_id = Some(world.spawn((InputState::default(),)));
     }
 
-    fn get_name(&self) -> String {
-        "InputSystem".to_string()
+    fn on_reset(&mut self, world: &mut World) {
+        *self = InputSystem {
+            base: self.base.clone(),
+            ..InputSystem::default()
+        };
+    }
+
+    fn get_name(&self) -> &'static str {
+        "InputSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+}
+
+/// Starts the player running when they hold the run key with a direction, and calls it off once
+/// there's a reason to stop or any other key gets pressed. The `InputSystem` takes the steps.
+#[derive(Default)]
+pub struct RunningSystem {
+    player_entity_id: Option<Entity>,
+    key_bindings_entity_id: Option<Entity>,
+    base: SystemBase,
+}
+
+impl SystemFunc for RunningSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        let player_id = self
+            .player_entity_id
+            .ok_or(DRError::MissingEntity("player".to_string()))?;
+        let sim_input = {
+            let key_bindings = world.get::<&KeyBindings>(
+                self.key_bindings_entity_id
+                    .expect("Running System was not initialized!"),
+            )?;
+            SimInput::from_input(input, &key_bindings)
+        };
+        let running_towards = world
+            .get::<&Running>(player_id)
+            .ok()
+            .filter(|running| running.active)
+            .map(|running| running.direction);
+        let can_sprint = world
+            .get::<&Stamina>(player_id)
+            .is_ok_and(|stamina| stamina.can_afford(Stamina::SPRINT_COST));
+        let cancelled = match sim_input {
+            // Too tired, so the `InputSystem` just takes a normal step.
+            SimInput::Run { .. } if !can_sprint && running_towards.is_none() => return Ok(()),
+            SimInput::Run { dx, dy } if running_towards != Some((dx, dy)) => {
+                let running = Running::start(world, player_id, (dx, dy));
+                world.insert_one(player_id, running)?;
+                tracing::debug!(dx, dy, "Started running");
+                return Ok(());
+            }
+            // Still holding down whatever started it.
+            SimInput::Run { .. } => false,
+            _ if running_towards.is_none() => return Ok(()),
+            _ => input.any_key_pressed() || input.clicked_tile().is_some(),
+        };
+
+        let seen = world.get::<&Running>(player_id)?.seen.clone();
+        let stop_reason = if cancelled {
+            Some(StopReason::Cancelled)
+        } else if !can_sprint {
+            Some(StopReason::Exhausted)
+        } else {
+            should_stop_running(world, player_id, &seen)
+        };
+        let mut running = world.get::<&mut Running>(player_id)?;
+        match stop_reason {
+            Some(reason) => {
+                running.active = false;
+                tracing::debug!(?reason, "Stopped running");
+            }
+            None => running.remember(world, player_id),
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.player_entity_id = Some(
+            world
+                .query::<&Player>()
+                .iter()
+                .next()
+                .expect("Have not initialized player yet.")
+                .0,
+        );
+        self.key_bindings_entity_id = Some(find_or_spawn_resource::<KeyBindings>(world));
+    }
+
+    fn get_name(&self) -> &'static str {
+        "RunningSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+}
+
+/// Runs the cursor for throwing and firing, and the one for looking around. While either is up,
+/// no turns go by. Also flips the minimap on and off, which doesn't take a turn either.
+#[derive(Default)]
+pub struct TargetingSystem {
+    player_entity_id: Option<Entity>,
+    ui_mode_entity_id: Option<Entity>,
+    key_bindings_entity_id: Option<Entity>,
+    minimap_entity_id: Option<Entity>,
+    base: SystemBase,
+}
+
+/// Moves a targeting or examining cursor with the movement keys, staying on the map and within
+/// `max_range` of `origin`.
+fn cursor_after_input(
+    cursor: &Position,
+    origin: &Position,
+    max_range: u32,
+    map: Option<&Map>,
+    config: &GameConfig,
+    pressed: &impl Fn(KeyAction) -> bool,
+) -> Position {
+    let map_size = map.map(|map| (map.width, map.height)).unwrap_or((
+        config.console_width as usize,
+        config.console_height as usize,
+    ));
+    let mut cursor = cursor.clone();
+    for (action, delta) in [
+        (KeyAction::MoveUp, (0, -1)),
+        (KeyAction::MoveDown, (0, 1)),
+        (KeyAction::MoveLeft, (-1, 0)),
+        (KeyAction::MoveRight, (1, 0)),
+    ] {
+        if pressed(action) {
+            cursor = move_cursor(&cursor, delta, origin, max_range, map_size);
+        }
+    }
+    cursor
+}
+
+impl SystemFunc for TargetingSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        let player_id = self
+            .player_entity_id
+            .ok_or(DRError::MissingEntity("player".to_string()))?;
+        if ai_turn_in_progress(world) {
+            // Nothing gets picked until the AIs are done with the last turn.
+            return Ok(());
+        }
+        let key_bindings = world.get::<&KeyBindings>(
+            self.key_bindings_entity_id
+                .expect("Targeting System was not initialized!"),
+        )?;
+        let pressed = |action| input.key_pressed(key_bindings.key_for(action));
+        let config = GameConfig::from_world(world);
+        let mut ui_mode = world.get::<&mut UiMode>(
+            self.ui_mode_entity_id
+                .expect("Targeting System was not initialized!"),
+        )?;
+        let player_pos = world.get::<&Position>(player_id)?.deref().clone();
+
+        let mut next_mode = None;
+        match &mut *ui_mode {
+            UiMode::Normal if pressed(KeyAction::Examine) => {
+                tracing::debug!("Entering examine mode");
+                next_mode = Some(UiMode::Examining {
+                    cursor: player_pos.clone(),
+                });
+            }
+            UiMode::Normal if pressed(KeyAction::MessageLog) => {
+                tracing::debug!("Opening the message log");
+                next_mode = Some(UiMode::ReadingLog { scroll: 0 });
+            }
+            UiMode::Normal if pressed(KeyAction::Minimap) => {
+                let mut minimap = world.get::<&mut Minimap>(
+                    self.minimap_entity_id
+                        .expect("Targeting System was not initialized!"),
+                )?;
+                minimap.shown = !minimap.shown;
+            }
+            UiMode::Normal => {
+                let purpose = if pressed(KeyAction::Throw) {
+                    TargetPurpose::Throw
+                } else if pressed(KeyAction::Fire) {
+                    TargetPurpose::Fire
+                } else {
+                    return Ok(());
+                };
+                let has_rock = world.get::<&Inventory>(player_id).is_ok_and(|inventory| {
+                    inventory
+                        .items
+                        .iter()
+                        .any(|item| matches!(item, ItemKind::ThrowingRock { .. }))
+                });
+                if purpose == TargetPurpose::Throw && !has_rock {
+                    event_bus_manager.enqueue(LogMessage {
+                        text: "You don't have anything to throw.".to_string(),
+                    });
+                    return Ok(());
+                }
+                tracing::debug!(?purpose, "Entering targeting mode");
+                next_mode = Some(UiMode::Targeting {
+                    max_range: TARGETING_RANGE,
+                    purpose,
+                    cursor: player_pos.clone(),
+                });
+            }
+            UiMode::Targeting {
+                max_range,
+                purpose,
+                cursor,
+            } => {
+                let mut input_state = world.get::<&mut InputState>(player_id)?;
+                input_state.was_input_handled_this_frame = false;
+                let mut map_query = world.query::<&Map>();
+                let map = map_query.iter().next().map(|(_id, map)| map);
+
+                if pressed(KeyAction::Cancel) {
+                    next_mode = Some(UiMode::Normal);
+                } else if pressed(KeyAction::Confirm) {
+                    let is_valid = is_valid_target(&player_pos, cursor, *max_range, |pos| {
+                        map.is_some_and(|map| map.is_blocked(pos))
+                    });
+                    if is_valid {
+                        event_bus_manager.enqueue(TargetSelected {
+                            position: cursor.clone(),
+                            purpose: *purpose,
+                        });
+                        // Throwing takes a turn, same as moving.
+                        input_state.was_input_handled_this_frame = true;
+                        next_mode = Some(UiMode::Normal);
+                    } else {
+                        event_bus_manager.enqueue(LogMessage {
+                            text: "You can't hit that from here.".to_string(),
+                        });
+                    }
+                } else if pressed(KeyAction::CycleTarget) {
+                    let fov = world.get::<&Fov>(player_id).ok();
+                    let hostiles: Vec<Position> = world
+                        .query::<&Position>()
+                        .with::<&Ai>()
+                        .iter()
+                        .map(|(_id, pos)| pos.clone())
+                        .filter(|pos| {
+                            is_in_range(&player_pos, pos, *max_range)
+                                && fov.as_ref().is_none_or(|fov| fov.can_see(pos))
+                        })
+                        .collect();
+                    if let Some(next) = next_target(cursor, &player_pos, &hostiles) {
+                        *cursor = next;
+                    }
+                } else {
+                    *cursor =
+                        cursor_after_input(cursor, &player_pos, *max_range, map, &config, &pressed);
+                }
+            }
+            UiMode::Examining { cursor } => {
+                world
+                    .get::<&mut InputState>(player_id)?
+                    .was_input_handled_this_frame = false;
+                if pressed(KeyAction::Cancel) || pressed(KeyAction::Examine) {
+                    next_mode = Some(UiMode::Normal);
+                } else {
+                    // Anything the player could possibly see is fair game.
+                    let max_range = world
+                        .get::<&Fov>(player_id)
+                        .map_or(TARGETING_RANGE, |fov| fov.radius);
+                    let mut map_query = world.query::<&Map>();
+                    let map = map_query.iter().next().map(|(_id, map)| map);
+                    *cursor =
+                        cursor_after_input(cursor, &player_pos, max_range, map, &config, &pressed);
+                }
+            }
+            UiMode::ReadingLog { scroll } => {
+                world
+                    .get::<&mut InputState>(player_id)?
+                    .was_input_handled_this_frame = false;
+                if pressed(KeyAction::Cancel) || pressed(KeyAction::MessageLog) {
+                    next_mode = Some(UiMode::Normal);
+                } else if let Some((_id, game_log)) = world.query::<&GameLog>().iter().next() {
+                    let page_lines = GameLog::viewer_page_lines(config.console_height as usize);
+                    let page = page_lines as isize;
+                    for (action, by) in [
+                        (KeyAction::MoveUp, 1),
+                        (KeyAction::MoveDown, -1),
+                        (KeyAction::PageUp, page),
+                        (KeyAction::PageDown, -page),
+                    ] {
+                        if pressed(action) {
+                            *scroll = game_log.scroll_by(*scroll, by, page_lines);
+                        }
+                    }
+                }
+            }
+            UiMode::Trading { vendor, selling } => {
+                world
+                    .get::<&mut InputState>(player_id)?
+                    .was_input_handled_this_frame = false;
+                let picked = TRADE_KEYS.iter().position(|key| input.key_pressed(key));
+                if pressed(KeyAction::Cancel) || !world.contains(*vendor) {
+                    next_mode = Some(UiMode::Normal);
+                } else if pressed(KeyAction::Sell) {
+                    *selling = !*selling;
+                } else if let Some(i) = picked.filter(|_| *selling) {
+                    match sellable(world, player_id).get(i) {
+                        Some((item, _price)) => event_bus_manager.enqueue(ItemSold {
+                            seller: player_id,
+                            vendor: *vendor,
+                            item: *item,
+                        }),
+                        None => event_bus_manager.enqueue(LogMessage {
+                            text: "You don't have anything like that to sell.".to_string(),
+                        }),
+                    }
+                    *selling = false;
+                } else if let Some(i) = picked {
+                    let stocked = world
+                        .get::<&Vendor>(*vendor)
+                        .ok()
+                        .and_then(|vendor| vendor.inventory.get(i).copied());
+                    let gold = world
+                        .get::<&PlayerWallet>(player_id)
+                        .map_or(0, |wallet| wallet.total);
+                    match stocked {
+                        Some((item, price)) if price <= gold => {
+                            event_bus_manager.enqueue(ItemBought {
+                                buyer: player_id,
+                                vendor: *vendor,
+                                item,
+                            });
+                        }
+                        Some(_) => event_bus_manager.enqueue(LogMessage {
+                            text: "You can't afford that.".to_string(),
+                        }),
+                        None => event_bus_manager.enqueue(LogMessage {
+                            text: "They don't have anything like that.".to_string(),
+                        }),
+                    }
+                }
+            }
+            UiMode::Choosing { options } => {
+                let mut input_state = world.get::<&mut InputState>(player_id)?;
+                input_state.was_input_handled_this_frame = false;
+                let picked = TRADE_KEYS
+                    .iter()
+                    .position(|key| input.key_pressed(key))
+                    .and_then(|i| options.get(i));
+                if pressed(KeyAction::Cancel) {
+                    next_mode = Some(UiMode::Normal);
+                } else if let Some(option) = picked {
+                    let (took_turn, mode) =
+                        interact(world, player_id, &player_pos, option, event_bus_manager);
+                    input_state.was_input_handled_this_frame = took_turn;
+                    next_mode = Some(mode);
+                }
+            }
+        }
+        if let Some(next_mode) = next_mode {
+            *ui_mode = next_mode;
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.player_entity_id = Some(
+            world
+                .query::<&Player>()
+                .iter()
+                .next()
+                .expect("Have not initialized player yet.")
+                .0,
+        );
+        self.ui_mode_entity_id = Some(find_or_spawn_resource::<UiMode>(world));
+        self.key_bindings_entity_id = Some(find_or_spawn_resource::<KeyBindings>(world));
+        self.minimap_entity_id = Some(find_or_spawn_resource::<Minimap>(world));
+    }
+
+    fn get_name(&self) -> &'static str {
+        "TargetingSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![TypeId::of::<InputSystem>()]
     }
 }
 
 pub struct AiSystem {
     health_query: PreparedQuery<With<&'static Position, &'static Health>>,
-    ai_query: PreparedQuery<(
-        &'static mut Ai,
-        &'static mut Position,
-        &'static Health,
-        &'static Vision,
-    )>,
     player_entity_id: Option<Entity>,
+    rng_entity_id: Option<Entity>,
+    spatial_index_entity_id: Option<Entity>,
+    /// How many AI turns were close enough to the player to get thought through last turn.
+    active_ais: usize,
+    base: SystemBase,
 }
 
 impl AiSystem {
     pub fn new() -> AiSystem {
         Self {
             health_query: PreparedQuery::new(),
-            ai_query: PreparedQuery::new(),
             player_entity_id: None,
+            rng_entity_id: None,
+            spatial_index_entity_id: None,
+            active_ais: 0,
+            base: SystemBase::default(),
         }
     }
 
-    fn get_entity_locs(&mut self, world: &mut World) -> HashSet<Position> {
-        get_entity_locations(world)
-            .keys()
-            .into_iter()
-            .map(|pos| pos.clone())
-            .collect()
-    }
-
-    fn was_input_handled_this_frame(&self, world: &World) -> bool {
-        let input_state = world
-            .get::<&InputState>(self.player_entity_id.unwrap())
-            .expect("Could not get input state!");
-        input_state.was_input_handled_this_frame
+    fn was_input_handled_this_frame(&self, world: &World) -> DRResult<bool> {
+        let player_id = self
+            .player_entity_id
+            .ok_or(DRError::MissingEntity("player".to_string()))?;
+        // The player might have been despawned since we last looked.
+        let input_state = world.get::<&InputState>(player_id)?;
+        Ok(input_state.was_input_handled_this_frame)
         // let mut binding = world.query::<&InputState>();
         // let (_entity, input_state) = binding.into_iter().next().unwrap();
         // let was_input_handled_this_frame = input_state.was_input_handled_this_frame;
@@ -198,10 +1272,10 @@ impl SystemFunc for AiSystem {
     fn call(
         &mut self,
         world: &mut World,
-        api: &mut dyn DoryenApi,
+        input: &dyn InputSource,
         event_bus_manager: &mut EventBusManager,
     ) -> DRResult<()> {
-        if !self.was_input_handled_this_frame(&world) {
+        if !self.was_input_handled_this_frame(world)? && !ai_turn_in_progress(world) {
             tracing::trace!("Player didn't do any input so skipping AI...");
             return Ok(());
         }
@@ -213,7 +1287,15 @@ impl SystemFunc for AiSystem {
 
         // let world = Arc::new(RefCell::new(world));
 
-        let has_entity = self.get_entity_locs(world);
+        // Not kept in saves, so it might have to start over after a load.
+        let scheduler_id = find_or_spawn_resource::<TurnScheduler>(world);
+        let config = GameConfig::from_world(world);
+        let walls: HashSet<Position> = world
+            .query::<&Map>()
+            .iter()
+            .next()
+            .map(|(_id, map)| map.wall_positions().collect())
+            .unwrap_or_default();
 
         let player_id = self
             .player_entity_id
@@ -228,6 +1310,30 @@ impl SystemFunc for AiSystem {
             .ok_or(DRError::ComponentMissing("Position".to_string()))?
             .deref()
             .clone();
+
+        // The player acting moves time on, and whoever's turn comes up before theirs does next
+        // gets to go. There's only so many turns worth getting through in a frame though, so the
+        // rest wait until the next one.
+        let turns = {
+            let ai_ids: Vec<Entity> = world.query::<&Ai>().iter().map(|(id, _ai)| id).collect();
+            let mut map_query = world.query::<&Map>();
+            let map = map_query.iter().next().map(|(_id, map)| map);
+            let mut scheduler = world.get::<&mut TurnScheduler>(scheduler_id)?;
+            if scheduler.is_turn_complete() {
+                let now = scheduler.current_tick;
+                for id in ai_ids {
+                    scheduler.schedule(id, now);
+                }
+                scheduler.begin_turn(ticks_until_next_turn(world, map, player_id), |id| {
+                    world.get::<&Ai>(id).ok()?;
+                    if is_dead(world, id) {
+                        return None;
+                    }
+                    Some(ticks_until_next_turn(world, map, id))
+                });
+            }
+            scheduler.next_batch(config.max_ai_per_frame)
+        };
         // let mut player_health = world.get::<&mut Health>(
         //     self.player_entity_id
         //         .ok_or(DRError::MissingEntity("player".to_string()))?,
@@ -237,41 +1343,333 @@ impl SystemFunc for AiSystem {
         //     .get_player_pos_health(&world)
         //     .ok_or(DRError::ComponentMissing("Position/Health".to_string()))?;
 
-        let binding = self.ai_query.borrow_mut();
-        let ai_query = binding.query_mut(world);
+        let mut spatial_index = world.get::<&mut SpatialIndex>(
+            self.spatial_index_entity_id
+                .ok_or(DRError::MissingEntity("spatial index".to_string()))?,
+        )?;
+        let mut rng = world.get::<&mut GameRng>(
+            self.rng_entity_id
+                .ok_or(DRError::MissingEntity("rng".to_string()))?,
+        )?;
+
+        // Closed doors are in the spatial index, but monsters can still get through them.
+        // Locked ones stay shut.
+        let closed_doors: HashMap<Position, Entity> = world
+            .query::<(&Position, &Door)>()
+            .without::<&Locked>()
+            .iter()
+            .filter(|(_id, (_pos, door))| !door.open)
+            .map(|(id, (pos, _door))| (pos.clone(), id))
+            .collect();
+        // Nobody walks onto a trap they know is there.
+        let known_traps: HashSet<Position> = world
+            .query::<(&Position, &Trap)>()
+            .iter()
+            .filter(|(_id, (_pos, trap))| trap.is_visible())
+            .map(|(_id, (pos, _trap))| pos.clone())
+            .collect();
+
+        let light_levels = LightLevels::from_world(world);
+
+        // Everyone's chasing (or running from) the same player, so they can share one map.
+        let player_map = DijkstraMap::build(
+            &[player_pos.clone()],
+            |pos| {
+                pos.is_within_console_bounds(&config)
+                    && !walls.contains(pos)
+                    && !known_traps.contains(pos)
+            },
+            PLAYER_MAP_DEPTH,
+        );
+
+        // Everything that could pick a fight or get picked on, kept up to date as the AIs move.
+        let mut combatants: Vec<TargetCandidate> = world
+            .query::<(
+                &Position,
+                &Health,
+                Option<&Faction>,
+                Option<&Player>,
+                Option<&Ai>,
+            )>()
+            .iter()
+            .filter(|(_id, (_pos, _health, faction, player, ai))| {
+                faction.is_some() || player.is_some() || ai.is_some()
+            })
+            .map(|(id, (pos, health, faction, player, _ai))| {
+                let faction = match (faction, player) {
+                    (Some(faction), _) => *faction,
+                    (None, Some(_player)) => Faction::Player,
+                    (None, None) => Faction::default(),
+                };
+                TargetCandidate {
+                    id,
+                    pos: pos.clone(),
+                    faction,
+                    health: health.current_health(),
+                }
+            })
+            .collect();
+        // Only the player gets a map, anyone else just gets headed straight for.
+        let no_map = DijkstraMap::default();
+
+        // Can't add or take components off while everything's borrowed, so windups that start or
+        // finish get sorted out once all the turns are done.
+        let mut windups_started: Vec<(Entity, Telegraph)> = Vec::new();
+        let mut windups_finished: Vec<Entity> = Vec::new();
+        let mut no_longer_feared: Vec<Entity> = Vec::new();
+        let mut auras_going_off: Vec<Entity> = Vec::new();
+
         tracing::info!("Processing AIs...");
-        for (id, (ai, ai_pos, ai_health, ai_vision)) in ai_query {
-            let action = ai.get_next_action(&player_pos, ai_pos, ai_health, ai_vision);
+        self.active_ais = 0;
+        for id in turns {
+            if is_dead(world, id) {
+                // Killed this frame, but it doesn't get cleared away until the deaths go out.
+                continue;
+            }
+            if world.get::<&Sleeping>(id).is_ok() {
+                // Sleeps right through its turn, which is gone for good.
+                continue;
+            }
+            if world
+                .get::<&Aura>(id)
+                .is_ok_and(|aura| !aura.trigger_on_player_turn)
+            {
+                auras_going_off.push(id);
+            }
+            let Ok(mut ai_query) =
+                world.query_one::<(&mut Ai, &mut Position, &Health, &Vision)>(id)
+            else {
+                continue;
+            };
+            let Some((ai, ai_pos, ai_health, ai_vision)) = ai_query.get() else {
+                continue;
+            };
+            if !ai_vision.is_nearby(ai_pos, &player_pos) {
+                // Too far away to matter, so don't bother.
+                continue;
+            }
+            self.active_ais += 1;
+            // Anything lurking in the shadows doesn't see as far.
+            let lit_vision = ai_vision
+                .in_light(light_levels.at(ai_pos))
+                .with_range_change(stat_change(world, id, StatKind::VisionRange));
+            let my_faction = combatants
+                .iter()
+                .find(|candidate| candidate.id == id)
+                .map_or(Faction::default(), |candidate| candidate.faction);
+            let target = choose_target(id, ai_pos, my_faction, &lit_vision, &combatants, |pos| {
+                walls.contains(pos)
+            })
+            .map(|candidate| (&candidate.pos, candidate.id));
+            // Whoever else is out to get us, so running from one doesn't mean running into another.
+            let other_threats: Vec<Position> = combatants
+                .iter()
+                .filter(|candidate| {
+                    candidate.id != id
+                        && target.is_none_or(|(_pos, target)| candidate.id != target)
+                        && candidate.health > 0
+                        && are_hostile(my_faction, candidate.faction)
+                        && lit_vision.can_see(ai_pos, &candidate.pos, |pos| walls.contains(pos))
+                })
+                .map(|candidate| candidate.pos.clone())
+                .collect();
+            let target_map = match target {
+                Some((_pos, target)) if target == player_id => &player_map,
+                _ => &no_map,
+            };
+            // Anything winding up spends its turns on that, then does whatever it was winding up
+            // to do no matter what's changed since.
+            let telegraphed = match world.get::<&mut Telegraph>(id) {
+                Ok(mut telegraph) => {
+                    telegraph.turns_remaining = telegraph.turns_remaining.saturating_sub(1);
+                    if telegraph.turns_remaining > 0 {
+                        continue;
+                    }
+                    windups_finished.push(id);
+                    Some(telegraph.pending.clone())
+                }
+                Err(_) => None,
+            };
+            let finishing_windup = telegraphed.is_some();
+            let mut feared = world.get::<&mut Feared>(id).ok();
+            let action = match telegraphed {
+                Some(action) => action,
+                None => ai.get_next_action(
+                    ai_pos,
+                    ai_health,
+                    &lit_vision,
+                    AiContext {
+                        target,
+                        other_threats: &other_threats,
+                        target_map,
+                        is_walkable: &|pos| {
+                            pos.is_within_console_bounds(&config)
+                                && !walls.contains(pos)
+                                && !known_traps.contains(pos)
+                                && (!spatial_index.is_occupied(pos)
+                                    || closed_doors.contains_key(pos))
+                        },
+                        rng: &mut *rng,
+                        feared: feared.as_deref_mut(),
+                    },
+                ),
+            };
+            if feared.is_some_and(|feared| feared.remaining_turns == 0) {
+                no_longer_feared.push(id);
+            }
+            // Screamers scream at whoever they're after whenever they've got the breath for it.
+            let action = match world.get::<&mut Screamer>(id) {
+                Ok(mut screamer) if !finishing_windup => {
+                    screamer.cooldown_remaining = screamer.cooldown_remaining.saturating_sub(1);
+                    let after_someone = ai.curr_state == AiState::Angry
+                        && matches!(action, Action::GoTo(_) | Action::Attack(_));
+                    if after_someone && screamer.cooldown_remaining == 0 {
+                        screamer.cooldown_remaining = screamer.cooldown;
+                        Action::Scream
+                    } else {
+                        action
+                    }
+                }
+                _ => action,
+            };
+            // Anything confused stumbles off somewhere random, though it can still swing at
+            // whatever's next to it.
+            let action = match action {
+                Action::GoTo(_) if world.get::<&Confused>(id).is_ok() => {
+                    let neighbors = ai_pos.neighbors();
+                    Action::GoTo(neighbors[rng.random_range(0..neighbors.len())].clone())
+                }
+                action => action,
+            };
             tracing::debug!("Entity with ID {id:?} will do action {action:?}");
+            let windup = match action {
+                Action::Attack(_) if !finishing_windup => world
+                    .get::<&Windup>(id)
+                    .ok()
+                    .filter(|windup| windup.turns > 0),
+                _ => None,
+            };
+            if let Some(windup) = windup {
+                tracing::debug!(?id, ?action, "Winding up");
+                event_bus_manager.enqueue(LogMessage::about(world, id, &windup.warning));
+                windups_started.push((
+                    id,
+                    Telegraph {
+                        turns_remaining: windup.turns,
+                        pending: action,
+                    },
+                ));
+                continue;
+            }
             match action {
                 Action::GoTo(new_pos) => {
-                    // TODO: Add bounds/occupancy checking.
-                    let next_pos = ai_pos.go_towards(&new_pos);
-                    if !has_entity.contains(&next_pos) {
+                    // Doors don't count as in the way, since they can be opened.
+                    let next_pos = ai_pos.go_towards_avoiding(&new_pos, |pos| {
+                        !pos.is_within_console_bounds(&config)
+                            || walls.contains(pos)
+                            || (spatial_index.is_occupied(pos) && !closed_doors.contains_key(pos))
+                    });
+                    if let Some(door) = closed_doors.get(&next_pos) {
+                        // Takes a turn to get a door open.
+                        if ai.waiting_at_door.as_ref() == Some(&next_pos) {
+                            ai.waiting_at_door = None;
+                            event_bus_manager.enqueue(DoorOpened { door: *door });
+                        } else {
+                            ai.waiting_at_door = Some(next_pos);
+                        }
+                    } else if next_pos.is_within_console_bounds(&config)
+                        && !walls.contains(&next_pos)
+                        && !spatial_index.is_occupied(&next_pos)
+                    {
+                        spatial_index.move_entity(id, &next_pos);
+                        event_bus_manager.enqueue(EntityMoved {
+                            entity: id,
+                            from: ai_pos.clone(),
+                            to: next_pos.clone(),
+                        });
+                        if let Some(candidate) =
+                            combatants.iter_mut().find(|candidate| candidate.id == id)
+                        {
+                            candidate.pos = next_pos.clone();
+                        }
                         let Position { x, y } = next_pos;
                         ai_pos.x = x;
                         ai_pos.y = y;
                     }
                 }
                 Action::Wait => {} // Do Nothing.
+                Action::Scream => {
+                    if let Ok(screamer) = world.get::<&Screamer>(id) {
+                        event_bus_manager.enqueue(Scream {
+                            from: id,
+                            at: ai_pos.clone(),
+                            radius: screamer.radius,
+                            fear_turns: screamer.fear_turns,
+                        });
+                        event_bus_manager.enqueue(LogMessage::about(
+                            world,
+                            id,
+                            "lets out a bloodcurdling scream!",
+                        ));
+                    }
+                }
                 Action::Attack(pos_to_attack) => {
-                    if has_entity.contains(&pos_to_attack) {
+                    // Whoever's standing there takes the hit, whether or not they're who we were after.
+                    let victim = spatial_index
+                        .at(&pos_to_attack)
+                        .filter(|victim| *victim != id);
+                    if let Some(victim) = victim {
                         tracing::debug!(
-                            "Entity with ID {id:?} attacked the entity at {pos_to_attack:?}"
+                            "Entity with ID {id:?} attacked {victim:?} at {pos_to_attack:?}"
                         );
+                        let damage = melee_damage(world, id, victim, MONSTER_BASE_DAMAGE);
                         event_bus_manager.enqueue(Damage {
                             from: id,
-                            to: player_id.clone(),
-                            damage: 1,
+                            to: victim,
+                            damage,
+                            kind: DamageKind::Physical,
                         });
+                        let message = LogMessage::attack(world, Some(id), Some(victim), damage);
+                        if victim == player_id {
+                            event_bus_manager.enqueue(message);
+                        } else {
+                            // Monsters going at each other out of sight shouldn't give them away.
+                            event_bus_manager.enqueue(PerceivedMessage {
+                                at: pos_to_attack.clone(),
+                                seen: message.text,
+                                heard: "You hear fighting nearby.".to_string(),
+                            });
+                        }
                     } else {
                         tracing::debug!(
                             "Entity with ID {id:?} tried to attack the empty air at {pos_to_attack:?}."
-                        )
+                        );
+                        if finishing_windup {
+                            event_bus_manager.enqueue(LogMessage::about(
+                                world,
+                                id,
+                                "swings at nothing but air.",
+                            ));
+                        }
                     }
                 }
             }
         }
+        drop(spatial_index);
+        drop(rng);
+        for id in windups_finished {
+            let _ = world.remove_one::<Telegraph>(id);
+        }
+        for (id, telegraph) in windups_started {
+            let _ = world.insert_one(id, telegraph);
+        }
+        for id in no_longer_feared {
+            let _ = world.remove_one::<Feared>(id);
+        }
+        for id in auras_going_off {
+            pulse_aura(world, id, event_bus_manager)?;
+        }
+        tracing::debug!(active_ais = self.active_ais, "Finished processing AIs");
         Ok(())
     }
     fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
@@ -284,10 +1682,39 @@ impl SystemFunc for AiSystem {
                 .expect("Have not initialized player yet.")
                 .0,
         );
+        self.rng_entity_id = Some(
+            world
+                .query::<&GameRng>()
+                .iter()
+                .next()
+                .expect("Have not initialized the game RNG yet.")
+                .0,
+        );
+        self.spatial_index_entity_id = Some(find_or_build_spatial_index(world));
+    }
+
+    fn on_reset(&mut self, world: &mut World) {
+        self.player_entity_id = None;
+        self.rng_entity_id = None;
+        self.spatial_index_entity_id = None;
+        self.active_ais = 0;
     }
 
-    fn get_name(&self) -> String {
-        "AISystem".to_string()
+    fn get_name(&self) -> &'static str {
+        "AISystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        // Targeting decides whether the player's turn is over too.
+        vec![TypeId::of::<InputSystem>(), TypeId::of::<TargetingSystem>()]
     }
 }
 
@@ -303,8 +1730,33 @@ impl Default for DeadCollector {
 }
 
 impl EventHandler<DeadEntity> for DeadCollector {
-    fn handle(&self, event: &mut DeadEntity, world: &mut World) {
-        match world.despawn(event.entity) {
+    fn handle(&self, event: &mut DeadEntity, ctx: &mut EventCtx) {
+        let drops = match ctx
+            .world
+            .query_one_mut::<(&Position, &LootTable)>(event.entity)
+        {
+            Ok((pos, loot_table)) => Some((pos.clone(), loot_table.roll(&mut rand::rng()))),
+            Err(_) => None,
+        };
+        if let Some((pos, drops)) = drops {
+            for item in drops {
+                spawn_item(ctx.world, pos.clone(), item);
+            }
+        }
+        for item in all_equipped(ctx.world, event.entity) {
+            if let Err(e) = drop_item(ctx.world, event.entity, item) {
+                tracing::warn!("Could not drop {item:?} from dead entity due to error {e}");
+            }
+        }
+        if let Some((_id, spatial_index)) = ctx
+            .world
+            .query_mut::<&mut SpatialIndex>()
+            .into_iter()
+            .next()
+        {
+            spatial_index.remove(event.entity);
+        }
+        match ctx.world.despawn(event.entity) {
             Ok(()) => (),
             Err(e) => {
                 tracing::warn!("Could not despawn supposedly dead entity due to error {e}");
@@ -314,79 +1766,6619 @@ impl EventHandler<DeadEntity> for DeadCollector {
     }
 }
 
-// impl SystemFunc for DeadCollector {
-//     fn call(
-//         &mut self,
-//         world: &mut World,
-//         api: &mut dyn DoryenApi,
-//         event_bus_manager: &mut EventBusManager,
-//     ) -> DRResult<()> {
-//         let ones_to_remove: Vec<_> = self
-//             .dead_finder
-//             .query(world)
-//             .iter()
-//             .filter(|(_, health)| health.current_health <= 0)
-//             .map(|(id, _health)| id)
-//             .collect();
-//
-//         for id in ones_to_remove {
-//             world.despawn(id)?;
-//         }
-//         Ok(())
-//     }
-//     fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
-//         self.dead_finder = PreparedQuery::new();
-//     }
-//
-//     fn get_name(&self) -> String {
-//         "DeadCollector".to_string()
-//     }
-// }
+#[derive(Default)]
+pub struct DamageHandler;
 
-/// Deletes dead AIs and spawns new ones as needed.
-struct AiHandlerSystem;
+impl EventHandler<Damage> for DamageHandler {
+    fn handle(&self, event: &mut Damage, ctx: &mut EventCtx) {
+        let incoming = event.damage;
+        let multiplier = match ctx.world.get::<&Resistances>(event.to) {
+            Ok(resistances) => {
+                event.damage = resistances.apply(event.damage, event.kind);
+                resistances.multiplier(event.kind)
+            }
+            Err(_) => 1.0,
+        };
+        if incoming > 0 && multiplier != 1.0 {
+            let is_player = ctx.world.get::<&Player>(event.to).is_ok();
+            let message = match (multiplier < 1.0, is_player) {
+                (true, true) => LogMessage {
+                    text: "You shrug off most of the blow.".to_string(),
+                },
+                (false, true) => LogMessage {
+                    text: "You reel from the blow!".to_string(),
+                },
+                (true, false) => {
+                    LogMessage::about(ctx.world, event.to, "shrugs off most of the blow.")
+                }
+                (false, false) => LogMessage::about(ctx.world, event.to, "reels from the blow!"),
+            };
+            ctx.events.enqueue(message);
+        }
+        // Whether that killed it is up to the `DeathSystem`.
+        match ctx.world.get::<&mut Health>(event.to) {
+            Ok(mut health) => {
+                let outcome = health.apply_damage(event.damage);
+                tracing::debug!(?event, ?outcome, "Applied damage");
+            }
+            Err(e) => {
+                tracing::warn!("Could not apply damage {event:?} due to error {e}");
+                return;
+            }
+        }
+        let at = ctx
+            .world
+            .get::<&Position>(event.to)
+            .ok()
+            .map(|pos| Position::clone(&pos));
+        // Only cuts and bruises leave a mark.
+        let bled_on = at
+            .as_ref()
+            .filter(|_pos| event.damage > 0 && event.kind == DamageKind::Physical);
+        if let Some(pos) = bled_on {
+            let tile_overlay = find_or_spawn_resource::<TileOverlay>(ctx.world);
+            if let Ok(mut tile_overlay) = ctx.world.get::<&mut TileOverlay>(tile_overlay) {
+                tile_overlay.set_overlay(pos.clone(), BLOOD_COLOR, None);
+            }
+        }
+        ctx.events.enqueue(AudioEvent::new(AudioKind::Hit, at));
+        if event.kind == DamageKind::Fire && ctx.world.get::<&Regeneration>(event.to).is_ok() {
+            // Can't regrow what's on fire.
+            let _ = ctx.world.insert_one(
+                event.to,
+                RegenerationSuppressed {
+                    turns_remaining: BURN_TURNS,
+                },
+            );
+        }
+    }
+}
 
-impl SystemFunc for AiHandlerSystem {
-    fn call(
-        &mut self,
-        world: &mut World,
-        api: &mut dyn DoryenApi,
-        event_bus_manager: &mut EventBusManager,
-    ) -> DRResult<()> {
-        todo!()
+/// Splits an `AoeDamage` up into a `Damage` for everything it reaches.
+pub struct AoeDamageHandler;
+
+impl EventHandler<AoeDamage> for AoeDamageHandler {
+    fn handle(&self, event: &mut AoeDamage, ctx: &mut EventCtx) {
+        for (id, pos) in ctx.world.query_mut::<&Position>().with::<&Health>() {
+            if event.reaches(pos) {
+                ctx.events.enqueue(Damage {
+                    from: event.from,
+                    to: id,
+                    damage: event.amount,
+                    kind: event.kind,
+                });
+            }
+        }
     }
+}
+
+/// Splits a `ConeDamage` up into a `Damage` for everything it reaches.
+pub struct ConeDamageHandler;
 
-    fn get_name(&self) -> String {
-        "AiHandlerSystem".to_string()
+impl EventHandler<ConeDamage> for ConeDamageHandler {
+    fn handle(&self, event: &mut ConeDamage, ctx: &mut EventCtx) {
+        for (id, pos) in ctx.world.query_mut::<&Position>().with::<&Health>() {
+            if event.reaches(pos) {
+                ctx.events.enqueue(Damage {
+                    from: event.from,
+                    to: id,
+                    damage: event.amount,
+                    kind: event.kind,
+                });
+            }
+        }
     }
 }
 
 #[derive(Default)]
-pub struct DamageSystem {
-    damage_query: PreparedQuery<&'static Damage>,
+pub struct HealHandler;
+
+impl EventHandler<Heal> for HealHandler {
+    fn handle(&self, event: &mut Heal, ctx: &mut EventCtx) {
+        match ctx.world.get::<&mut Health>(event.to) {
+            Ok(mut health) => {
+                let healed = health.heal(event.amount);
+                tracing::debug!(
+                    ?event,
+                    healed,
+                    current_health = health.current_health(),
+                    "Applied healing"
+                );
+            }
+            Err(e) => tracing::warn!("Could not apply healing {event:?} due to error {e}"),
+        }
+    }
 }
 
-impl SystemFunc for DamageSystem {
-    fn call(
-        &mut self,
-        world: &mut World,
-        api: &mut dyn DoryenApi,
-        event_bus_manager: &mut EventBusManager,
-    ) -> DRResult<()> {
-        // Get all entities that need damage applied to them
-        // Then remove the health from them.
-        for (_, damage) in self.damage_query.query(&world).iter() {
-            let mut damaged_entity = world.get::<&mut Health>(damage.to)?;
-            damaged_entity.current_health -= damage.damage;
+/// Writes log messages into the `GameLog` so they can be drawn.
+pub struct GameLogHandler;
+
+impl EventHandler<LogMessage> for GameLogHandler {
+    fn handle(&self, event: &mut LogMessage, ctx: &mut EventCtx) {
+        tracing::info!("{}", event.text);
+        match ctx.world.query_mut::<&mut GameLog>().into_iter().next() {
+            Some((_id, game_log)) => game_log.push(event.text.clone()),
+            None => tracing::warn!("No game log to write {event:?} to"),
         }
+    }
+}
 
-        Ok(())
+/// Only passes a `PerceivedMessage` on to the log if the player would know about it, and only
+/// the vaguer version if they'd just hear it. The same noise only gets heard once a turn.
+pub struct PerceptionHandler;
+
+impl EventHandler<PerceivedMessage> for PerceptionHandler {
+    fn handle(&self, event: &mut PerceivedMessage, ctx: &mut EventCtx) {
+        let perception = Perception::new(GameConfig::from_world(ctx.world).hearing_radius);
+        let perceived = match ctx
+            .world
+            .query_mut::<(&Position, &Fov)>()
+            .with::<&Player>()
+            .into_iter()
+            .next()
+        {
+            Some((_id, (player_pos, fov))) => perception.classify(player_pos, &event.at, fov),
+            // Nothing to hide it from.
+            None => Perceived::Seen,
+        };
+        tracing::debug!(?event, ?perceived, "Perceived message");
+        let text = match perceived {
+            Perceived::Seen => event.seen.clone(),
+            Perceived::Heard => {
+                let turn = current_turn(ctx.world);
+                let heard_id = find_or_spawn_resource::<HeardThisTurn>(ctx.world);
+                let Ok(mut heard) = ctx.world.get::<&mut HeardThisTurn>(heard_id) else {
+                    return;
+                };
+                if !heard.first_time(turn, &event.heard) {
+                    return;
+                }
+                event.heard.clone()
+            }
+            Perceived::Unknown => return,
+        };
+        ctx.events.enqueue(LogMessage { text });
     }
-    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
-        self.damage_query = PreparedQuery::new();
+}
+
+/// Logs whatever dies, so long as the player's around to notice. Has to go before the
+/// `DeadCollector` gets rid of it.
+pub struct DeathLogHandler;
+
+impl EventHandler<DeadEntity> for DeathLogHandler {
+    fn handle(&self, event: &mut DeadEntity, ctx: &mut EventCtx) {
+        let Ok(at) = ctx
+            .world
+            .get::<&Position>(event.entity)
+            .map(|pos| Position::clone(&pos))
+        else {
+            return;
+        };
+        ctx.events.enqueue(PerceivedMessage {
+            at,
+            seen: LogMessage::about(ctx.world, event.entity, "dies.").text,
+            heard: "You hear something die nearby.".to_string(),
+        });
+    }
+}
+
+/// Forgets everything about the floor that's going away: what the player's seen of it, and
+/// whatever was on fire.
+pub struct WorldResetHandler;
+
+impl EventHandler<WorldReset> for WorldResetHandler {
+    fn handle(&self, event: &mut WorldReset, ctx: &mut EventCtx) {
+        if let Ok(mut fov) = ctx.world.get::<&mut Fov>(event.player) {
+            *fov = Fov::new(fov.radius);
+        }
+        for (_id, burning_tiles) in ctx.world.query_mut::<&mut BurningTiles>() {
+            burning_tiles.positions.clear();
+        }
+        for (_id, tile_overlay) in ctx.world.query_mut::<&mut TileOverlay>() {
+            tile_overlay.clear();
+        }
+    }
+}
+
+/// Despawns everything but `player`, along with whatever they're wearing or carrying and the
+/// world's resources, like for a new floor. A `WorldReset` goes out first so everything can let
+/// go of what's about to disappear, then anything still queued gets thrown away since it'd be
+/// about entities that are gone. Call `SystemFunc::on_reset` on every system before this.
+pub fn reset_world_keeping_player(
+    world: &mut World,
+    event_bus_manager: &mut EventBusManager,
+) -> DRResult<()> {
+    let player = world
+        .query::<&Player>()
+        .iter()
+        .next()
+        .map(|(id, _player)| id)
+        .ok_or(DRError::MissingEntity("player".to_string()))?;
+    event_bus_manager.enqueue(WorldReset { player });
+    event_bus_manager.dispatch_only::<WorldReset>(world);
+    event_bus_manager.clear_queue();
+
+    // Everything on the floor, and whatever it was holding onto that isn't.
+    let mut going: Vec<Entity> = world
+        .query::<()>()
+        .with::<&Position>()
+        .iter()
+        .map(|(id, ())| id)
+        .filter(|id| *id != player)
+        .collect();
+    let floor: HashSet<Entity> = going.iter().copied().collect();
+    for (id, (equipped, inventory, vendor)) in world
+        .query::<(Option<&Equipped>, Option<&Inventory>, Option<&Vendor>)>()
+        .iter()
+    {
+        if equipped.is_some_and(|equipped| floor.contains(&equipped.by)) {
+            going.push(id);
+        }
+        if !floor.contains(&id) {
+            continue;
+        }
+        if let Some(inventory) = inventory {
+            going.extend(&inventory.equipment);
+        }
+        if let Some(vendor) = vendor {
+            going.extend(vendor.inventory.iter().map(|(item, _price)| *item));
+        }
+    }
+    // Both of these are full of ids, and get built again from what's left.
+    going.extend(world.query::<&SpatialIndex>().iter().map(|(id, _)| id));
+    going.extend(world.query::<&TurnScheduler>().iter().map(|(id, _)| id));
+    tracing::info!(despawning = going.len(), "Resetting the world");
+    for id in going {
+        // Might've been counted twice.
+        let _ = world.despawn(id);
+    }
+
+    for (_id, inventory) in world.query::<&mut Inventory>().iter() {
+        inventory.forget_missing(|item| world.contains(item));
+    }
+    Ok(())
+}
+
+/// Ends the run once the player dies.
+pub struct GameOverHandler;
+
+impl EventHandler<PlayerDeath> for GameOverHandler {
+    fn handle(&self, event: &mut PlayerDeath, ctx: &mut EventCtx) {
+        tracing::info!(?event, "The player has died");
+        match ctx.world.query_mut::<&mut RunState>().into_iter().next() {
+            Some((_id, run_state)) => *run_state = RunState::GameOver,
+            None => tracing::warn!("No run state to end after {event:?}"),
+        }
+    }
+}
+
+/// Flashes whatever just got hit.
+pub struct HitFlashHandler;
+
+impl EventHandler<Damage> for HitFlashHandler {
+    fn handle(&self, event: &mut Damage, ctx: &mut EventCtx) {
+        if ctx.world.get::<&Renderable>(event.to).is_ok() {
+            let _ = ctx.world.insert_one(
+                event.to,
+                Animation::FlashColor {
+                    color: HIT_FLASH_COLOR,
+                    frames_remaining: HIT_FLASH_FRAMES,
+                },
+            );
+        }
+    }
+}
+
+/// Has whatever moved slide over to its new tile instead of jumping straight there.
+pub struct SlideHandler;
+
+impl SlideHandler {
+    fn slide(world: &mut World, entity: Entity, from: &Position, to: &Position) {
+        if world.get::<&Renderable>(entity).is_ok() {
+            let _ = world.insert_one(entity, RenderPosition::new(from.clone(), to.clone()));
+        }
+    }
+}
+
+impl EventHandler<EntityMoved> for SlideHandler {
+    fn handle(&self, event: &mut EntityMoved, ctx: &mut EventCtx) {
+        SlideHandler::slide(ctx.world, event.entity, &event.from, &event.to);
+    }
+}
+
+impl EventHandler<KnockbackOccurred> for SlideHandler {
+    fn handle(&self, event: &mut KnockbackOccurred, ctx: &mut EventCtx) {
+        SlideHandler::slide(ctx.world, event.entity, &event.from, &event.to);
+    }
+}
+
+/// Getting hit mid-windup might knock whatever it was out of it.
+pub struct WindupInterruptHandler;
+
+impl EventHandler<Damage> for WindupInterruptHandler {
+    fn handle(&self, event: &mut Damage, ctx: &mut EventCtx) {
+        if ctx.world.get::<&Telegraph>(event.to).is_err() {
+            return;
+        }
+        let interrupt_chance = ctx
+            .world
+            .get::<&Windup>(event.to)
+            .map_or(0.0, |windup| windup.interrupt_chance);
+        let interrupted = match ctx.world.query_mut::<&mut GameRng>().into_iter().next() {
+            Some((_id, rng)) => rng.random_bool(interrupt_chance.clamp(0.0, 1.0)),
+            None => false,
+        };
+        if interrupted {
+            tracing::debug!(?event, "Interrupted a windup");
+            let _ = ctx.world.remove_one::<Telegraph>(event.to);
+            ctx.events.enqueue(LogMessage::about(
+                ctx.world,
+                event.to,
+                "gets knocked out of its swing!",
+            ));
+        }
+    }
+}
+
+/// Wakes up anything that gets hurt in its sleep, unless it's sleeping too deeply for that.
+pub struct SleepWakeBreaker;
+
+impl EventHandler<Damage> for SleepWakeBreaker {
+    fn handle(&self, event: &mut Damage, ctx: &mut EventCtx) {
+        let wakes = event.damage > 0
+            && ctx
+                .world
+                .get::<&Sleeping>(event.to)
+                .is_ok_and(|sleeping| sleeping.wake_on_damage);
+        if wakes {
+            let _ = ctx.world.remove_one::<Sleeping>(event.to);
+            ctx.events.enqueue(LogMessage::about(ctx.world, event.to, "is jolted awake!"));
+        }
+    }
+}
+
+/// Might put whatever got hit by something with a `SlumberAttack` to sleep. Has to come after
+/// `SleepWakeBreaker`, or the same hit would wake them right back up.
+pub struct SlumberAttackHandler;
+
+impl EventHandler<Damage> for SlumberAttackHandler {
+    fn handle(&self, event: &mut Damage, ctx: &mut EventCtx) {
+        let Ok(slumber) = ctx
+            .world
+            .get::<&SlumberAttack>(event.from)
+            .map(|slumber| SlumberAttack::clone(&slumber))
+        else {
+            return;
+        };
+        if event.from == event.to || ctx.world.get::<&Health>(event.to).is_err() {
+            return;
+        }
+        let falls_asleep = match ctx.world.query_mut::<&mut GameRng>().into_iter().next() {
+            Some((_id, rng)) => rng.random_bool(slumber.chance.clamp(0.0, 1.0)),
+            None => false,
+        };
+        if falls_asleep {
+            let _ = ctx.world.insert_one(
+                event.to,
+                Sleeping {
+                    remaining_turns: slumber.turns,
+                    wake_on_damage: true,
+                },
+            );
+            ctx.events.enqueue(LogMessage::about(ctx.world, event.to, "falls fast asleep."));
+        }
+    }
+}
+
+/// The worst wound `attacker` can open up, out of its own `Bleed` and whatever it's got equipped.
+fn bleed_of(world: &World, attacker: Entity) -> Option<Bleed> {
+    let own = world.get::<&Bleed>(attacker).ok().map(|bleed| *bleed);
+    let from_equipment = all_equipped(world, attacker)
+        .into_iter()
+        .filter_map(|item| world.get::<&Bleed>(item).ok().map(|bleed| *bleed));
+    own.into_iter()
+        .chain(from_equipment)
+        .max_by_key(|bleed| (bleed.damage_per_stack, bleed.duration_turns))
+}
+
+/// Opens up a wound on whatever gets hit by something with a `Bleed`, adding to any that's already
+/// bleeding. Only hits that actually do damage count.
+pub struct BleedHandler;
+
+impl EventHandler<Damage> for BleedHandler {
+    fn handle(&self, event: &mut Damage, ctx: &mut EventCtx) {
+        if event.from == event.to || event.damage <= 0 || event.kind != DamageKind::Physical {
+            return;
+        }
+        let Some(bleed) = bleed_of(ctx.world, event.from) else {
+            return;
+        };
+        let alive = ctx
+            .world
+            .get::<&Health>(event.to)
+            .is_ok_and(|health| !health.is_dead());
+        if !alive {
+            return;
+        }
+        // Let go of the `Bleeding` before there's any chance of adding a new one.
+        let added_to = ctx
+            .world
+            .get::<&mut Bleeding>(event.to)
+            .map(|mut bleeding| {
+                bleeding.add_stack(&bleed);
+                bleeding.stacks
+            })
+            .ok();
+        let stacks = match added_to {
+            Some(stacks) => stacks,
+            None => {
+                let _ = ctx.world.insert_one(event.to, Bleeding::new(&bleed));
+                1
+            }
+        };
+        ctx.events.enqueue(LogMessage::about(
+            ctx.world,
+            event.to,
+            &format!("bleeds! (×{stacks})"),
+        ));
+    }
+}
+
+/// Stops the bleeding on whoever uses up a bandage.
+pub struct BandageHandler;
+
+impl EventHandler<UseItem> for BandageHandler {
+    fn handle(&self, event: &mut UseItem, ctx: &mut EventCtx) {
+        if event.item != ItemKind::Bandage {
+            return;
+        }
+        if ctx.world.remove_one::<Bleeding>(event.user).is_ok() {
+            ctx.events.enqueue(LogMessage::about(
+                ctx.world,
+                event.user,
+                "bandages up their wounds.",
+            ));
+        }
+    }
+}
+
+/// How far `attacker` shoves whatever it hits, going by the most out of its own `Knockback` and
+/// whatever it's got equipped. `None` if it doesn't shove at all.
+fn knockback_tiles(world: &World, attacker: Entity) -> Option<i32> {
+    let own = world
+        .get::<&Knockback>(attacker)
+        .ok()
+        .map(|knockback| knockback.tiles);
+    let from_equipment = all_equipped(world, attacker)
+        .into_iter()
+        .filter_map(|item| world.get::<&Knockback>(item).ok().map(|knockback| knockback.tiles));
+    own.into_iter().chain(from_equipment).max()
+}
+
+/// Shoves whatever got hit by something with `Knockback` straight away from it, stopping early at
+/// walls and anything else standing in the way. Getting slammed into a wall hurts, a point for
+/// every tile of shove that was left.
+pub struct KnockbackHandler;
+
+impl EventHandler<Damage> for KnockbackHandler {
+    fn handle(&self, event: &mut Damage, ctx: &mut EventCtx) {
+        if event.from == event.to {
+            return;
+        }
+        let Some(tiles) = knockback_tiles(ctx.world, event.from) else {
+            return;
+        };
+        if ctx
+            .world
+            .get::<&Health>(event.to)
+            .is_ok_and(|health| health.is_dead())
+        {
+            // It's getting despawned anyway.
+            return;
+        }
+        let (Ok(attacker_pos), Ok(start)) = (
+            ctx.world
+                .get::<&Position>(event.from)
+                .map(|pos| Position::clone(&pos)),
+            ctx.world
+                .get::<&Position>(event.to)
+                .map(|pos| Position::clone(&pos)),
+        ) else {
+            return;
+        };
+        let angle = attacker_pos.angle(&start);
+        let (landed, slammed) = {
+            let mut map_query = ctx.world.query::<&Map>();
+            let map = map_query.iter().next().map(|(_id, map)| map);
+            let mut spatial_index_query = ctx.world.query::<&SpatialIndex>();
+            let spatial_index = spatial_index_query
+                .iter()
+                .next()
+                .map(|(_id, spatial_index)| spatial_index);
+            let mut landed = start.clone();
+            let mut slammed = 0;
+            for distance in 1..=tiles {
+                let next = start.go_distance_theta(distance as f64, angle);
+                if map.is_some_and(|map| map.is_blocked(&next)) {
+                    slammed = tiles - distance + 1;
+                    break;
+                }
+                if spatial_index.is_some_and(|spatial_index| spatial_index.is_occupied(&next)) {
+                    break;
+                }
+                landed = next;
+            }
+            (landed, slammed)
+        };
+        if slammed > 0 {
+            // Nobody else gets the credit for the wall, so it can't set off another shove.
+            ctx.events.enqueue(Damage {
+                from: event.to,
+                to: event.to,
+                damage: slammed,
+                kind: DamageKind::Physical,
+            });
+        }
+        if landed == start {
+            return;
+        }
+        tracing::debug!(?event, ?start, ?landed, "Knocked back");
+        if let Ok(mut pos) = ctx.world.get::<&mut Position>(event.to) {
+            *pos = landed.clone();
+        }
+        if let Some((_id, spatial_index)) = ctx
+            .world
+            .query_mut::<&mut SpatialIndex>()
+            .into_iter()
+            .next()
+        {
+            spatial_index.move_entity(event.to, &landed);
+        }
+        ctx.events.enqueue(KnockbackOccurred {
+            entity: event.to,
+            from: start,
+            to: landed,
+        });
+    }
+}
+
+/// Leaves a copy of whatever died behind to fade away. Has to be subscribed before the
+/// `DeadCollector` since that despawns the real thing.
+pub struct DeathFadeHandler;
+
+impl EventHandler<DeadEntity> for DeathFadeHandler {
+    fn handle(&self, event: &mut DeadEntity, ctx: &mut EventCtx) {
+        let ghost = match ctx
+            .world
+            .query_one_mut::<(&Position, &Renderable)>(event.entity)
+        {
+            Ok((pos, renderable)) => (
+                pos.clone(),
+                Renderable {
+                    glyph: renderable.glyph,
+                    color: renderable.color,
+                    render_order: Renderable::FLOOR_ORDER,
+                    tint: None,
+                    bg: None,
+                },
+                Animation::FadeOut {
+                    frames_remaining: FADE_OUT_FRAMES,
+                },
+            ),
+            Err(_) => return,
+        };
+        ctx.world.spawn(ghost);
+    }
+}
+
+/// Moves whoever set off a teleport trap to a random open tile.
+pub struct TeleportHandler;
+
+impl EventHandler<TeleportTrap> for TeleportHandler {
+    fn handle(&self, event: &mut TeleportTrap, ctx: &mut EventCtx) {
+        let destination = {
+            let mut map_query = ctx.world.query::<&Map>();
+            let Some((_id, map)) = map_query.iter().next() else {
+                tracing::warn!("No map to teleport {event:?} around");
+                return;
+            };
+            let mut spatial_index_query = ctx.world.query::<&SpatialIndex>();
+            let spatial_index = spatial_index_query
+                .iter()
+                .next()
+                .map(|(_id, spatial_index)| spatial_index);
+            let open: Vec<Position> = (0..map.height)
+                .flat_map(|y| (0..map.width).map(move |x| Position::new(x as isize, y as isize)))
+                .filter(|pos| {
+                    !map.is_blocked(pos)
+                        && spatial_index.is_none_or(|spatial_index| !spatial_index.is_occupied(pos))
+                })
+                .collect();
+            let mut rng_query = ctx.world.query::<&mut GameRng>();
+            let Some((_id, rng)) = rng_query.iter().next() else {
+                tracing::warn!("No rng to teleport {event:?} with");
+                return;
+            };
+            if open.is_empty() {
+                return;
+            }
+            open[rng.random_range(0..open.len())].clone()
+        };
+        tracing::debug!(?event, ?destination, "Teleporting");
+        if let Ok(mut pos) = ctx.world.get::<&mut Position>(event.entity) {
+            *pos = destination.clone();
+        }
+        if let Some((_id, spatial_index)) = ctx
+            .world
+            .query_mut::<&mut SpatialIndex>()
+            .into_iter()
+            .next()
+        {
+            spatial_index.move_entity(event.entity, &destination);
+        }
+    }
+}
+
+/// Opens doors, so they stop blocking the way, and closes them again.
+pub struct DoorHandler;
+
+impl EventHandler<DoorOpened> for DoorHandler {
+    fn handle(&self, event: &mut DoorOpened, ctx: &mut EventCtx) {
+        match ctx
+            .world
+            .query_one_mut::<(&mut Door, &mut Renderable)>(event.door)
+        {
+            Ok((door, _renderable)) if door.open => return,
+            Ok((door, renderable)) => {
+                door.open = true;
+                renderable.glyph = door.glyph();
+            }
+            Err(e) => {
+                tracing::warn!("Could not open door {event:?} due to error {e}");
+                return;
+            }
+        }
+        tracing::debug!(?event, "Opened door");
+        let _ = ctx.world.remove_one::<BlocksTile>(event.door);
+        if let Some((_id, spatial_index)) = ctx
+            .world
+            .query_mut::<&mut SpatialIndex>()
+            .into_iter()
+            .next()
+        {
+            spatial_index.remove(event.door);
+        }
+        // Whoever could see the door can now see through it.
+        for (_id, fov) in ctx.world.query_mut::<&mut Fov>() {
+            fov.invalidate();
+        }
     }
+}
+
+impl EventHandler<CloseDoor> for DoorHandler {
+    fn handle(&self, event: &mut CloseDoor, ctx: &mut EventCtx) {
+        let pos = match ctx
+            .world
+            .query_one_mut::<(&mut Door, &mut Renderable, &Position)>(event.door)
+        {
+            Ok((door, _renderable, _pos)) if !door.open => return,
+            Ok((door, renderable, pos)) => {
+                door.open = false;
+                renderable.glyph = door.glyph();
+                pos.clone()
+            }
+            Err(e) => {
+                tracing::warn!("Could not close door {event:?} due to error {e}");
+                return;
+            }
+        };
+        tracing::debug!(?event, "Closed door");
+        let _ = ctx.world.insert_one(event.door, BlocksTile);
+        if let Some((_id, spatial_index)) = ctx
+            .world
+            .query_mut::<&mut SpatialIndex>()
+            .into_iter()
+            .next()
+        {
+            spatial_index.insert(event.door, pos);
+        }
+        // Nobody can see through it anymore.
+        for (_id, fov) in ctx.world.query_mut::<&mut Fov>() {
+            fov.invalidate();
+        }
+    }
+}
+
+impl EventHandler<DoorUnlocked> for DoorHandler {
+    fn handle(&self, event: &mut DoorUnlocked, ctx: &mut EventCtx) {
+        let Ok(locked) = ctx.world.get::<&Locked>(event.door).map(|locked| *locked) else {
+            return;
+        };
+        let used_key = ctx
+            .world
+            .get::<&mut Inventory>(event.by)
+            .is_ok_and(|mut inventory| inventory.use_key(locked.key_id));
+        if !used_key {
+            tracing::warn!(?event, "Tried to unlock a door without the key for it");
+            return;
+        }
+        ctx.events.enqueue(UseItem {
+            user: event.by,
+            item: ItemKind::Key {
+                key_id: locked.key_id,
+            },
+        });
+        let _ = ctx.world.remove_one::<Locked>(event.door);
+        if let Ok(mut renderable) = ctx.world.get::<&mut Renderable>(event.door) {
+            renderable.color = Door::COLOR;
+        }
+        tracing::debug!(?event, "Unlocked door");
+        ctx.events.enqueue(LogMessage {
+            text: "You unlock the door.".to_string(),
+        });
+        ctx.events.enqueue(DoorOpened { door: event.door });
+    }
+}
+
+/// Pushes things back a tile, as long as there's room for them to go.
+pub struct ShoveHandler;
+
+impl EventHandler<Shove> for ShoveHandler {
+    fn handle(&self, event: &mut Shove, ctx: &mut EventCtx) {
+        let Ok(from) = ctx
+            .world
+            .get::<&Position>(event.by)
+            .map(|pos| Position::clone(&pos))
+        else {
+            return;
+        };
+        // Something might have moved in behind them since the shove got sent.
+        let destination = match shove_destination(ctx.world, &from, event.target) {
+            Ok(destination) => destination,
+            Err(text) => {
+                ctx.events.enqueue(LogMessage { text });
+                return;
+            }
+        };
+        tracing::debug!(?event, ?destination, "Shoved");
+        if let Ok(mut pos) = ctx.world.get::<&mut Position>(event.target) {
+            *pos = destination.clone();
+        }
+        if let Some((_id, spatial_index)) = ctx
+            .world
+            .query_mut::<&mut SpatialIndex>()
+            .into_iter()
+            .next()
+        {
+            spatial_index.move_entity(event.target, &destination);
+        }
+        ctx.events
+            .enqueue(LogMessage::about(ctx.world, event.target, "stumbles back."));
+    }
+}
+
+/// Picks up keys, bandages and gold lying where something steps. Keys and bandages go into the
+/// inventory and gold goes into the wallet, so anything without one walks right over them.
+pub struct PickupHandler;
+
+impl EventHandler<EntityMoved> for PickupHandler {
+    fn handle(&self, event: &mut EntityMoved, ctx: &mut EventCtx) {
+        let has_inventory = ctx.world.get::<&Inventory>(event.entity).is_ok();
+        let has_wallet = ctx.world.get::<&PlayerWallet>(event.entity).is_ok();
+        if !has_inventory && !has_wallet {
+            return;
+        }
+        let pickups: Vec<(Entity, ItemKind)> = ctx
+            .world
+            .query::<(&Position, &ItemKind)>()
+            .iter()
+            .filter(|(_id, (pos, kind))| {
+                **pos == event.to
+                    && match kind {
+                        ItemKind::Key { .. } | ItemKind::Bandage => has_inventory,
+                        ItemKind::Gold { .. } => has_wallet,
+                        _ => false,
+                    }
+            })
+            .map(|(id, (_pos, kind))| (id, *kind))
+            .collect();
+        for (pickup, kind) in pickups {
+            let text = match kind {
+                ItemKind::Gold { amount } => {
+                    if let Ok(mut wallet) = ctx.world.get::<&mut PlayerWallet>(event.entity) {
+                        wallet.total += amount;
+                    }
+                    ctx.events.enqueue(GoldCollected {
+                        by: event.entity,
+                        amount,
+                    });
+                    format!("Picked up {amount} gold.")
+                }
+                _ => {
+                    if let Ok(mut inventory) = ctx.world.get::<&mut Inventory>(event.entity) {
+                        inventory.items.push(kind);
+                    }
+                    match kind {
+                        ItemKind::Bandage => "You pick up a bandage.".to_string(),
+                        _ => "You pick up a key.".to_string(),
+                    }
+                }
+            };
+            let _ = ctx.world.despawn(pickup);
+            ctx.events
+                .enqueue(AudioEvent::new(AudioKind::Pickup, Some(event.to.clone())));
+            ctx.events.enqueue(LogMessage { text });
+        }
+    }
+}
+
+/// Opens chests and spills out whatever was in them.
+pub struct ChestHandler;
+
+impl EventHandler<ChestOpened> for ChestHandler {
+    fn handle(&self, event: &mut ChestOpened, ctx: &mut EventCtx) {
+        let (pos, chest) = match ctx.world.query_one_mut::<(&Position, &Chest)>(event.chest) {
+            Ok((_pos, chest)) if chest.opened => return,
+            Ok((pos, chest)) => (pos.clone(), chest.clone()),
+            Err(e) => {
+                tracing::warn!("Could not open chest {event:?} due to error {e}");
+                return;
+            }
+        };
+        if let Some(key_id) = chest.locked {
+            let used_key = ctx
+                .world
+                .get::<&mut Inventory>(event.by)
+                .is_ok_and(|mut inventory| inventory.use_key(key_id));
+            if !used_key {
+                tracing::warn!(?event, "Tried to open a chest without the key for it");
+                return;
+            }
+            ctx.events.enqueue(UseItem {
+                user: event.by,
+                item: ItemKind::Key { key_id },
+            });
+        }
+        let drops = match ctx.world.query_mut::<&mut GameRng>().into_iter().next() {
+            Some((_id, rng)) => chest.loot_table.roll(rng),
+            None => chest.loot_table.roll(&mut rand::rng()),
+        };
+        for item in &drops {
+            spawn_item(ctx.world, pos.clone(), *item);
+        }
+        if let Ok((chest, renderable)) = ctx
+            .world
+            .query_one_mut::<(&mut Chest, &mut Renderable)>(event.chest)
+        {
+            chest.opened = true;
+            chest.locked = None;
+            renderable.glyph = chest.glyph();
+        }
+        tracing::debug!(?event, ?drops, "Opened chest");
+        let text = if drops.is_empty() {
+            "You open the chest. It's empty."
+        } else {
+            "You open the chest."
+        };
+        ctx.events.enqueue(LogMessage {
+            text: text.to_string(),
+        });
+    }
+}
+
+/// Puts on and takes off weapons and armor.
+pub struct EquipmentHandler;
+
+/// "leather armor", for the middle of a sentence.
+fn item_name(world: &World, item: Entity) -> String {
+    world
+        .get::<&EntityName>(item)
+        .map_or("thing".to_string(), |name| name.name.to_lowercase())
+}
+
+impl EventHandler<EquipItem> for EquipmentHandler {
+    fn handle(&self, event: &mut EquipItem, ctx: &mut EventCtx) {
+        let previous = match equip(ctx.world, event.wearer, event.item) {
+            Ok(previous) => previous,
+            Err(e) => {
+                tracing::warn!("Could not equip {event:?} due to error {e}");
+                return;
+            }
+        };
+        if ctx.world.get::<&Player>(event.wearer).is_err() {
+            return;
+        }
+        let name = item_name(ctx.world, event.item);
+        let text = match previous {
+            Some(previous) => format!(
+                "You put away the {} and put on the {name}.",
+                item_name(ctx.world, previous)
+            ),
+            None => format!("You put on the {name}."),
+        };
+        ctx.events.enqueue(LogMessage { text });
+    }
+}
+
+impl EventHandler<TakeOffEquipment> for EquipmentHandler {
+    fn handle(&self, event: &mut TakeOffEquipment, ctx: &mut EventCtx) {
+        for item in all_equipped(ctx.world, event.wearer) {
+            if let Err(e) = unequip(ctx.world, item) {
+                tracing::warn!("Could not take off {item:?} due to error {e}");
+                continue;
+            }
+            if ctx.world.get::<&Player>(event.wearer).is_ok() {
+                ctx.events.enqueue(LogMessage {
+                    text: format!("You take off the {}.", item_name(ctx.world, item)),
+                });
+            }
+        }
+    }
+}
+
+/// Settles up trades with merchants.
+pub struct TradeHandler;
+
+impl EventHandler<ItemBought> for TradeHandler {
+    fn handle(&self, event: &mut ItemBought, ctx: &mut EventCtx) {
+        // Rocks get used up into the inventory, so get the name while it's still there.
+        let name = item_name(ctx.world, event.item);
+        let text = match buy(ctx.world, event.buyer, event.vendor, event.item) {
+            Ok(Some(price)) => format!("You buy the {name} for {price} gold."),
+            Ok(None) => format!("You can't afford the {name}."),
+            Err(e) => {
+                tracing::warn!("Could not buy {event:?} due to error {e}");
+                return;
+            }
+        };
+        ctx.events.enqueue(LogMessage { text });
+    }
+}
+
+impl EventHandler<ItemSold> for TradeHandler {
+    fn handle(&self, event: &mut ItemSold, ctx: &mut EventCtx) {
+        match sell(ctx.world, event.seller, event.vendor, event.item) {
+            Ok(Some(price)) => ctx.events.enqueue(LogMessage {
+                text: format!(
+                    "You sell the {} for {price} gold.",
+                    item_name(ctx.world, event.item)
+                ),
+            }),
+            Ok(None) => tracing::warn!(?event, "Tried to sell something that can't be sold"),
+            Err(e) => tracing::warn!("Could not sell {event:?} due to error {e}"),
+        }
+    }
+}
+
+/// Sends every monster that heard a noise over to see what it was.
+pub struct NoiseHandler;
+
+impl EventHandler<NoiseEvent> for NoiseHandler {
+    fn handle(&self, event: &mut NoiseEvent, ctx: &mut EventCtx) {
+        for (_id, (ai, pos)) in ctx.world.query_mut::<(&mut Ai, &Position)>() {
+            if is_in_range(pos, &event.at, event.radius) {
+                ai.hear_noise(&event.at);
+            }
+        }
+    }
+}
+
+/// Scares everything that hears a scream. It's noise like any other too, so anything that isn't
+/// scared comes to look.
+pub struct ScreamHandler;
+
+impl EventHandler<Scream> for ScreamHandler {
+    fn handle(&self, event: &mut Scream, ctx: &mut EventCtx) {
+        let scared: Vec<Entity> = ctx
+            .world
+            .query::<&Position>()
+            .with::<&Ai>()
+            .iter()
+            .filter(|(id, pos)| *id != event.from && is_in_range(pos, &event.at, event.radius))
+            .map(|(id, _pos)| id)
+            .collect();
+        tracing::debug!(?event, ?scared, "Scream");
+        for id in scared {
+            let _ = ctx.world.insert_one(
+                id,
+                Feared {
+                    remaining_turns: event.fear_turns,
+                },
+            );
+        }
+        ctx.events.enqueue(NoiseEvent {
+            at: event.at.clone(),
+            radius: event.radius,
+        });
+    }
+}
+
+/// Keeps the player's `Score` up to date.
+pub struct ScoreTracker;
+
+impl ScoreTracker {
+    fn update(ctx: &mut EventCtx, change: impl FnOnce(&mut Score)) {
+        match ctx.world.query_mut::<&mut Score>().into_iter().next() {
+            Some((_id, score)) => {
+                change(score);
+                score.recompute();
+                tracing::debug!(?score, "Updated score");
+            }
+            None => tracing::warn!("No score to update"),
+        }
+    }
+}
+
+impl EventHandler<DeadEntity> for ScoreTracker {
+    fn handle(&self, _event: &mut DeadEntity, ctx: &mut EventCtx) {
+        Self::update(ctx, |score| score.kills += 1);
+    }
+}
+
+impl EventHandler<GoldCollected> for ScoreTracker {
+    fn handle(&self, event: &mut GoldCollected, ctx: &mut EventCtx) {
+        if ctx.world.get::<&Score>(event.by).is_err() {
+            return;
+        }
+        Self::update(ctx, |score| score.gold_collected += event.amount);
+    }
+}
+
+impl EventHandler<DescendFloor> for ScoreTracker {
+    fn handle(&self, _event: &mut DescendFloor, ctx: &mut EventCtx) {
+        Self::update(ctx, |score| score.floors_descended += 1);
+    }
+}
+
+/// Keeps the `RunStats` up to date for the end of the run. Has to be subscribed after the
+/// `DamageHandler` so resistances have already come off, and before the `DeadCollector` so the
+/// dead still have their names.
+pub struct RunStatsTracker;
+
+impl RunStatsTracker {
+    fn update(ctx: &mut EventCtx, change: impl FnOnce(&mut RunStats)) {
+        match ctx.world.query_mut::<&mut RunStats>().into_iter().next() {
+            Some((_id, run_stats)) => change(run_stats),
+            None => tracing::trace!("No run stats to update"),
+        }
+    }
+
+    fn is_player(ctx: &EventCtx, entity: Entity) -> bool {
+        ctx.world.get::<&Player>(entity).is_ok()
+    }
+}
+
+impl EventHandler<Damage> for RunStatsTracker {
+    fn handle(&self, event: &mut Damage, ctx: &mut EventCtx) {
+        // Negative damage is healing, which doesn't count.
+        let amount = event.damage.max(0) as u64;
+        // Hurting yourself doesn't count as dealing damage.
+        let (dealt, taken) = (
+            Self::is_player(ctx, event.from) && event.from != event.to,
+            Self::is_player(ctx, event.to),
+        );
+        Self::update(ctx, |run_stats| {
+            if dealt {
+                run_stats.damage_dealt += amount;
+            }
+            if taken {
+                run_stats.damage_taken += amount;
+            }
+        });
+    }
+}
+
+impl EventHandler<DeadEntity> for RunStatsTracker {
+    fn handle(&self, event: &mut DeadEntity, ctx: &mut EventCtx) {
+        if Self::is_player(ctx, event.entity) {
+            return;
+        }
+        let name = ctx
+            .world
+            .get::<&EntityName>(event.entity)
+            .map(|name| name.to_string())
+            .unwrap_or_else(|_| "something".to_string());
+        Self::update(ctx, |run_stats| {
+            *run_stats.kills_by_name.entry(name).or_default() += 1;
+        });
+    }
+}
+
+impl EventHandler<DescendFloor> for RunStatsTracker {
+    fn handle(&self, _event: &mut DescendFloor, ctx: &mut EventCtx) {
+        // There's no going back up, so every floor down is the deepest yet.
+        Self::update(ctx, |run_stats| run_stats.deepest_depth += 1);
+    }
+}
+
+impl EventHandler<UseItem> for RunStatsTracker {
+    fn handle(&self, event: &mut UseItem, ctx: &mut EventCtx) {
+        if Self::is_player(ctx, event.user) {
+            Self::update(ctx, |run_stats| run_stats.items_used += 1);
+        }
+    }
+}
+
+/// Turns up any hidden traps right around where the player searched.
+pub struct SearchHandler;
+
+impl EventHandler<Searched> for SearchHandler {
+    fn handle(&self, event: &mut Searched, ctx: &mut EventCtx) {
+        let mut found = Vec::new();
+        for (_id, (pos, trap)) in ctx.world.query_mut::<(&Position, &mut Trap)>() {
+            let is_next_to =
+                (pos.x - event.around.x).abs() <= 1 && (pos.y - event.around.y).abs() <= 1;
+            if is_next_to && !trap.is_visible() {
+                trap.hidden = false;
+                found.push(trap.trap_type.name());
+            }
+        }
+        let text = if found.is_empty() {
+            "You don't find anything.".to_string()
+        } else {
+            format!("You find a {}!", found.join(" and a "))
+        };
+        ctx.events.enqueue(LogMessage { text });
+    }
+}
+
+/// Throws a rock at whatever the player targeted.
+pub struct ThrowHandler;
+
+impl EventHandler<TargetSelected> for ThrowHandler {
+    fn handle(&self, event: &mut TargetSelected, ctx: &mut EventCtx) {
+        if event.purpose != TargetPurpose::Throw {
+            return;
+        }
+        let Some((player, inventory)) = ctx
+            .world
+            .query_mut::<&mut Inventory>()
+            .with::<&Player>()
+            .into_iter()
+            .next()
+        else {
+            return;
+        };
+        let Some(ItemKind::ThrowingRock { damage }) = inventory.take_throwing_rock() else {
+            ctx.events.enqueue(LogMessage {
+                text: "You don't have anything to throw.".to_string(),
+            });
+            return;
+        };
+        ctx.events.enqueue(UseItem {
+            user: player,
+            item: ItemKind::ThrowingRock { damage },
+        });
+
+        let target = ctx
+            .world
+            .query_mut::<&SpatialIndex>()
+            .into_iter()
+            .next()
+            .and_then(|(_id, spatial_index)| spatial_index.at(&event.position));
+        match target {
+            Some(target) => {
+                ctx.events.enqueue(Damage {
+                    from: player,
+                    to: target,
+                    damage,
+                    kind: DamageKind::Physical,
+                });
+                ctx.events.enqueue(LogMessage::attack(
+                    ctx.world,
+                    Some(player),
+                    Some(target),
+                    damage,
+                ));
+            }
+            None => ctx.events.enqueue(LogMessage {
+                text: "The rock hits the ground.".to_string(),
+            }),
+        }
+        // It lands wherever it was thrown so it can be picked back up.
+        spawn_item(
+            ctx.world,
+            event.position.clone(),
+            ItemKind::ThrowingRock { damage },
+        );
+    }
+}
+
+/// Shoots whatever the player targeted. Melee weapons don't help here, so it's only ever
+/// `PLAYER_BASE_DAMAGE`.
+pub struct FireHandler;
+
+impl EventHandler<TargetSelected> for FireHandler {
+    fn handle(&self, event: &mut TargetSelected, ctx: &mut EventCtx) {
+        if event.purpose != TargetPurpose::Fire {
+            return;
+        }
+        let Some((player, _player)) = ctx.world.query_mut::<&Player>().into_iter().next() else {
+            return;
+        };
+        let target = ctx
+            .world
+            .query_mut::<&SpatialIndex>()
+            .into_iter()
+            .next()
+            .and_then(|(_id, spatial_index)| spatial_index.at(&event.position));
+        match target {
+            Some(target) => {
+                ctx.events.enqueue(Damage {
+                    from: player,
+                    to: target,
+                    damage: PLAYER_BASE_DAMAGE,
+                    kind: DamageKind::Physical,
+                });
+                ctx.events.enqueue(LogMessage::attack(
+                    ctx.world,
+                    Some(player),
+                    Some(target),
+                    PLAYER_BASE_DAMAGE,
+                ));
+            }
+            None => ctx.events.enqueue(LogMessage {
+                text: "Your shot hits nothing.".to_string(),
+            }),
+        }
+    }
+}
+
+/// Hands sounds off to the `AudioOutput`, after working out how far they are from the player.
+pub struct AudioDispatchHandler;
+
+impl EventHandler<AudioEvent> for AudioDispatchHandler {
+    fn handle(&self, event: &mut AudioEvent, ctx: &mut EventCtx) {
+        let player_pos = ctx
+            .world
+            .query_mut::<&Position>()
+            .with::<&Player>()
+            .into_iter()
+            .next()
+            .map(|(_id, pos)| pos.clone());
+        event.distance = event
+            .at
+            .as_ref()
+            .zip(player_pos)
+            .map(|(at, player_pos)| at.distance_squared(&player_pos).sqrt());
+        match ctx.world.query_mut::<&mut AudioOutput>().into_iter().next() {
+            Some((_id, audio_output)) => audio_output.play(event),
+            None => tracing::trace!(?event, "Nowhere to play sound"),
+        }
+    }
+}
+
+/// Lets `NaturalRegen` know its owner just got hurt.
+#[derive(Default)]
+pub struct NaturalRegenResetHandler;
+
+impl EventHandler<Damage> for NaturalRegenResetHandler {
+    fn handle(&self, event: &mut Damage, ctx: &mut EventCtx) {
+        if let Ok(mut natural_regen) = ctx.world.get::<&mut NaturalRegen>(event.to) {
+            natural_regen.turns_since_damage = 0;
+        }
+    }
+}
+
+/// Moves the `TurnCounter` on once the player's action has been dealt with, and counts it
+/// towards their `Score`.
+#[derive(Default)]
+pub struct TurnCounterSystem {
+    player_entity_id: Option<Entity>,
+    turn_counter_entity_id: Option<Entity>,
+    base: SystemBase,
+}
+
+impl SystemFunc for TurnCounterSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        let player_id = self
+            .player_entity_id
+            .ok_or(DRError::MissingEntity("player".to_string()))?;
+        let took_turn = world
+            .get::<&InputState>(player_id)
+            .is_ok_and(|input_state| input_state.was_input_handled_this_frame);
+        if took_turn {
+            world
+                .get::<&mut TurnCounter>(
+                    self.turn_counter_entity_id
+                        .expect("Turn Counter System was not initialized!"),
+                )?
+                .0 += 1;
+            world.get::<&mut Score>(player_id)?.turns_survived += 1;
+            if let Some((_id, run_stats)) = world.query_mut::<&mut RunStats>().into_iter().next() {
+                run_stats.turns_survived += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.player_entity_id = Some(
+            world
+                .query::<&Player>()
+                .iter()
+                .next()
+                .expect("Have not initialized player yet.")
+                .0,
+        );
+        self.turn_counter_entity_id = Some(find_or_spawn_resource::<TurnCounter>(world));
+    }
+
+    fn get_name(&self) -> &'static str {
+        "TurnCounterSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        // Targeting can end the turn too.
+        vec![TypeId::of::<InputSystem>(), TypeId::of::<TargetingSystem>()]
+    }
+}
+
+/// Gives every merchant a few new things to sell whenever the player goes down a floor.
+#[derive(Default)]
+pub struct VendorRestockSystem {
+    /// How deep the player was last time around.
+    last_depth: Option<u32>,
+    base: SystemBase,
+}
+
+impl SystemFunc for VendorRestockSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        let depth = current_depth(world);
+        let descended = self.last_depth.is_some_and(|last_depth| depth > last_depth);
+        self.last_depth = Some(depth);
+        if !descended {
+            return Ok(());
+        }
+        let vendors: Vec<Entity> = world
+            .query::<&Vendor>()
+            .iter()
+            .map(|(id, _vendor)| id)
+            .collect();
+        for vendor in vendors {
+            let kinds = match world.query_mut::<&mut GameRng>().into_iter().next() {
+                Some((_id, rng)) => roll_restock(RESTOCK_ITEMS, rng),
+                None => roll_restock(RESTOCK_ITEMS, &mut rand::rng()),
+            };
+            for kind in kinds {
+                if world.get::<&Vendor>(vendor)?.is_full() {
+                    break;
+                }
+                let item = spawn_item_for_sale(world, kind);
+                world
+                    .get::<&mut Vendor>(vendor)?
+                    .inventory
+                    .push((item, kind.value()));
+            }
+            tracing::debug!(?vendor, depth, "Restocked vendor");
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.last_depth = Some(current_depth(world));
+    }
+
+    fn get_name(&self) -> &'static str {
+        "VendorRestockSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+}
+
+/// The one place anything gets declared dead. Picks up whatever ran out of health while the last
+/// turn's events went out, so nothing that deals damage has to care about dying.
+#[derive(Default)]
+pub struct DeathSystem {
+    base: SystemBase,
+}
+
+impl SystemFunc for DeathSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        for (id, (health, pos, player)) in world
+            .query::<(&Health, Option<&Position>, Option<&Player>)>()
+            .iter()
+        {
+            if !health.is_dead() {
+                continue;
+            }
+            event_bus_manager.enqueue(AudioEvent::new(AudioKind::Death, pos.cloned()));
+            // The player dying is a whole different thing.
+            if player.is_some() {
+                event_bus_manager.enqueue(PlayerDeath { player: id });
+            } else {
+                event_bus_manager.enqueue(DeadEntity { entity: id });
+            }
+        }
+        Ok(())
+    }
+
+    fn get_name(&self) -> &'static str {
+        "DeathSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+}
+
+/// Sets off whatever trap is on the tile something just stepped onto.
+pub struct TrapHandler;
+
+impl EventHandler<EntityMoved> for TrapHandler {
+    fn handle(&self, event: &mut EntityMoved, ctx: &mut EventCtx) {
+        let Some((trap_id, trap)) = ctx
+            .world
+            .query_mut::<(&Position, &mut Trap)>()
+            .into_iter()
+            .find(|(_id, (pos, _trap))| **pos == event.to)
+            .map(|(id, (_pos, trap))| {
+                trap.hidden = false;
+                (id, trap.clone())
+            })
+        else {
+            return;
+        };
+        tracing::debug!(?trap, ?event, "Trap went off");
+        let text = if ctx.world.get::<&Player>(event.entity).is_ok() {
+            format!("You step on a {}!", trap.trap_type.name())
+        } else {
+            let name = ctx
+                .world
+                .get::<&EntityName>(event.entity)
+                .map_or("something".to_string(), |name| name.name.clone());
+            format!("The {name} steps on a {}!", trap.trap_type.name())
+        };
+        ctx.events.enqueue(LogMessage { text });
+        match trap.trap_type {
+            TrapType::Spike(damage) => ctx.events.enqueue(Damage {
+                from: trap_id,
+                to: event.entity,
+                damage,
+                kind: DamageKind::Physical,
+            }),
+            TrapType::Poison(magnitude, turns) => {
+                let poison = Effect {
+                    kind: EffectKind::Poison,
+                    turns_remaining: turns,
+                    magnitude,
+                };
+                let had_effects = ctx
+                    .world
+                    .get::<&mut Effects>(event.entity)
+                    .map(|mut effects| effects.active.push(poison.clone()))
+                    .is_ok();
+                if !had_effects {
+                    let _ = ctx.world.insert_one(
+                        event.entity,
+                        Effects {
+                            active: vec![poison],
+                        },
+                    );
+                }
+            }
+            TrapType::Teleport => ctx.events.enqueue(TeleportTrap {
+                entity: event.entity,
+            }),
+            TrapType::Alarm => ctx.events.enqueue(NoiseEvent {
+                at: event.to.clone(),
+                radius: ALARM_RADIUS,
+            }),
+        }
+        if trap.single_use {
+            let _ = ctx.world.despawn(trap_id);
+        }
+    }
+}
+
+/// Gives the player a chance every turn to spot hidden traps next to them without searching.
+#[derive(Default)]
+pub struct TrapSystem {
+    player_entity_id: Option<Entity>,
+    /// The `TurnCounter` as of the last time this ran.
+    last_turn: u64,
+    rng_entity_id: Option<Entity>,
+    base: SystemBase,
+}
+
+impl SystemFunc for TrapSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        let player_id = self
+            .player_entity_id
+            .ok_or(DRError::MissingEntity("player".to_string()))?;
+        if !turn_went_by(world, &mut self.last_turn) {
+            return Ok(());
+        }
+        let player_pos = world.get::<&Position>(player_id)?.deref().clone();
+        let mut rng = world.get::<&mut GameRng>(
+            self.rng_entity_id
+                .ok_or(DRError::MissingEntity("rng".to_string()))?,
+        )?;
+        for (_id, (pos, trap)) in world.query::<(&Position, &mut Trap)>().iter() {
+            let is_next_to = (pos.x - player_pos.x).abs() <= 1 && (pos.y - player_pos.y).abs() <= 1;
+            if is_next_to && trap.hidden && rng.random_bool(NOTICE_CHANCE) {
+                trap.hidden = false;
+                event_bus_manager.enqueue(LogMessage {
+                    text: format!("You notice a {}!", trap.trap_type.name()),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.player_entity_id = Some(
+            world
+                .query::<&Player>()
+                .iter()
+                .next()
+                .expect("Have not initialized player yet.")
+                .0,
+        );
+        self.last_turn = current_turn(world);
+        self.rng_entity_id = Some(
+            world
+                .query::<&GameRng>()
+                .iter()
+                .next()
+                .expect("Have not initialized the game RNG yet.")
+                .0,
+        );
+    }
+
+    fn get_name(&self) -> &'static str {
+        "TrapSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        // Looks around wherever the player ended up, once the turn's been counted.
+        vec![
+            TypeId::of::<InputSystem>(),
+            TypeId::of::<TurnCounterSystem>(),
+        ]
+    }
+}
+
+/// Deletes dead AIs and spawns new ones as needed.
+struct AiHandlerSystem;
+
+impl SystemFunc for AiHandlerSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        todo!()
+    }
+
+    fn get_name(&self) -> &'static str {
+        "AiHandlerSystem"
+    }
+}
+
+#[derive(Default)]
+pub struct DamageSystem {
+    damage_query: PreparedQuery<&'static Damage>,
+    base: SystemBase,
+}
+
+impl SystemFunc for DamageSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        // Get all entities that need damage applied to them
+        // Then remove the health from them.
+        for (_, damage) in self.damage_query.query(&world).iter() {
+            let mut damaged_entity = world.get::<&mut Health>(damage.to)?;
+            damaged_entity.apply_damage(damage.damage);
+        }
+
+        Ok(())
+    }
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.damage_query = PreparedQuery::new();
+    }
+
+    fn get_name(&self) -> &'static str {
+        "DamageSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+}
+
+/// Works out what the player can see, but only when they've moved or the map has changed.
+#[derive(Default)]
+pub struct FovSystem {
+    player_entity_id: Option<Entity>,
+    /// How many times the FOV has actually been worked out.
+    recomputes: usize,
+    base: SystemBase,
+}
+
+impl SystemFunc for FovSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        let player_id = self
+            .player_entity_id
+            .ok_or(DRError::MissingEntity("player".to_string()))?;
+        let player_pos = world.get::<&Position>(player_id)?.deref().clone();
+        let mut fov = world.get::<&mut Fov>(player_id)?;
+        let mut map_query = world.query::<&Map>();
+        let map = map_query.iter().next().map(|(_id, map)| map);
+        let map_revision = map.map(|map| map.revision()).unwrap_or_default();
+        if !fov.is_stale(&player_pos, map_revision) {
+            return Ok(());
+        }
+
+        let closed_doors: HashSet<Position> = world
+            .query::<(&Door, &Position)>()
+            .iter()
+            .filter(|(_id, (door, _pos))| !door.open)
+            .map(|(_id, (_door, pos))| pos.clone())
+            .collect();
+        let visible = if world.get::<&Blinded>(player_id).is_ok() {
+            HashSet::new()
+        } else {
+            let radius = fov.radius as i32 + stat_change(world, player_id, StatKind::VisionRange);
+            compute_fov(&player_pos, radius.max(0) as u32, |pos| {
+                map.is_some_and(|map| map.is_blocked(pos)) || closed_doors.contains(pos)
+            })
+        };
+        fov.update(&player_pos, map_revision, visible);
+        self.recomputes += 1;
+        tracing::debug!(recomputes = self.recomputes, "Recomputed FOV");
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.player_entity_id = Some(
+            world
+                .query::<&Player>()
+                .iter()
+                .next()
+                .expect("Have not initialized player yet.")
+                .0,
+        );
+    }
+
+    fn get_name(&self) -> &'static str {
+        "FovSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![TypeId::of::<InputSystem>()]
+    }
+}
+
+/// Works out how lit every tile is for drawing, but only when a light has moved or changed.
+#[derive(Default)]
+pub struct LightingSystem {
+    light_map_entity_id: Option<Entity>,
+    base: SystemBase,
+}
+
+impl SystemFunc for LightingSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        let mut map_query = world.query::<&Map>();
+        let Some((_id, map)) = map_query.iter().next() else {
+            return Ok(());
+        };
+        let levels = LightLevels::from_world(world);
+        let mut light_map = world.get::<&mut LightMap>(
+            self.light_map_entity_id
+                .ok_or(DRError::MissingEntity("light map".to_string()))?,
+        )?;
+        if light_map.is_stale(&levels, map) {
+            light_map.update(&levels, map);
+            tracing::debug!("Recomputed light map");
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.light_map_entity_id = Some(find_or_spawn_resource::<LightMap>(world));
+    }
+
+    fn get_name(&self) -> &'static str {
+        "LightingSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        // Lights move with whoever's carrying them.
+        vec![TypeId::of::<InputSystem>(), TypeId::of::<AiSystem>()]
+    }
+}
+
+/// Moves every animation along by a frame. Runs every frame, turn or not, and never blocks anything.
+#[derive(Default)]
+pub struct AnimationSystem {
+    frame_counter_entity_id: Option<Entity>,
+    base: SystemBase,
+}
+
+impl SystemFunc for AnimationSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        if let Some(frame_counter) = self.frame_counter_entity_id {
+            if let Ok(mut frame_counter) = world.get::<&mut FrameCounter>(frame_counter) {
+                frame_counter.0 += 1;
+            }
+        }
+        let mut finished = Vec::new();
+        for (id, (animation, renderable)) in world.query_mut::<(&mut Animation, &mut Renderable)>()
+        {
+            if animation.tick(renderable) {
+                finished.push((id, matches!(animation, Animation::FadeOut { .. })));
+            }
+        }
+        for (id, faded_out) in finished {
+            if faded_out {
+                // Nothing's left once it's faded away.
+                let _ = world.despawn(id);
+            } else {
+                let _ = world.remove_one::<Animation>(id);
+            }
+        }
+        let arrived: Vec<Entity> = world
+            .query_mut::<&mut RenderPosition>()
+            .into_iter()
+            .filter_map(|(id, slide)| slide.tick().then_some(id))
+            .collect();
+        for id in arrived {
+            let _ = world.remove_one::<RenderPosition>(id);
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.frame_counter_entity_id = Some(find_or_spawn_resource::<FrameCounter>(world));
+    }
+
+    fn get_name(&self) -> &'static str {
+        "AnimationSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+}
+
+/// Applies one turn's worth of every effect on `entity` and drops the ones that ran out.
+fn tick_effects(entity: Entity, effects: &mut Effects, event_bus_manager: &EventBusManager) {
+    for effect in effects.active.iter_mut() {
+        match effect.kind {
+            EffectKind::Poison => event_bus_manager.enqueue(Damage {
+                from: entity,
+                to: entity,
+                damage: effect.magnitude,
+                kind: DamageKind::Poison,
+            }),
+            EffectKind::Regen => event_bus_manager.enqueue(Heal {
+                to: entity,
+                amount: effect.magnitude.max(0) as u32,
+            }),
+        }
+        effect.turns_remaining = effect.turns_remaining.saturating_sub(1);
+    }
+    effects.active.retain(|effect| effect.turns_remaining > 0);
+}
+
+/// The one place timed statuses tick down, once per player turn, so they all run out in the same
+/// order: poison and the rest of `Effects`, burning, bleeding, confusion, sleep, slowness, and
+/// timed `StatModifiers`. Anything burning, bleeding or poisoned gets hurt for it first. Each status comes off as soon
+/// as it runs out.
+///
+/// Blindness and fear tick down elsewhere. Blindness has to give sight back before the AIs go,
+/// so that's the `BlindSystem`, and fear counts the frightened thing's own turns, however many of
+/// those it gets in one of the player's.
+#[derive(Default)]
+pub struct StatusEffectSystem {
+    /// The `TurnCounter` as of the last time this ran.
+    last_turn: u64,
+    base: SystemBase,
+}
+
+impl SystemFunc for StatusEffectSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        if !turn_went_by(world, &mut self.last_turn) {
+            return Ok(());
+        }
+        let mut worn_off = Vec::new();
+        for (id, effects) in world.query_mut::<&mut Effects>() {
+            tick_effects(id, effects, event_bus_manager);
+            if effects.active.is_empty() {
+                worn_off.push(id);
+            }
+        }
+        for id in worn_off {
+            world.remove_one::<Effects>(id)?;
+        }
+        for (id, burning) in world.query::<&Burning>().iter() {
+            if !is_fireproof(world, id) {
+                event_bus_manager.enqueue(Damage {
+                    from: id,
+                    to: id,
+                    damage: burning.damage_per_turn,
+                    kind: DamageKind::Fire,
+                });
+            }
+        }
+        for id in tick_status::<Burning>(world, |burning| &mut burning.remaining_turns)? {
+            event_bus_manager.enqueue(LogMessage::about(world, id, "stops burning."));
+        }
+        for (id, bleeding) in world.query::<&Bleeding>().iter() {
+            event_bus_manager.enqueue(Damage {
+                from: id,
+                to: id,
+                damage: bleeding.damage(),
+                kind: DamageKind::Physical,
+            });
+        }
+        for id in tick_status::<Bleeding>(world, |bleeding| &mut bleeding.duration_turns)? {
+            event_bus_manager.enqueue(LogMessage::about(world, id, "stops bleeding."));
+        }
+        tick_status::<Confused>(world, |confused| &mut confused.remaining_turns)?;
+        for id in tick_status::<Sleeping>(world, |sleeping| &mut sleeping.remaining_turns)? {
+            event_bus_manager.enqueue(LogMessage::about(world, id, "wakes up."));
+        }
+        for id in tick_status::<Slowed>(world, |slowed| &mut slowed.remaining_turns)? {
+            event_bus_manager.enqueue(LogMessage::about(world, id, "speeds back up."));
+        }
+        // `ApplyStatModifiersSystem` catches up on whatever ran out here.
+        let mut unmodified = Vec::new();
+        for (id, modifiers) in world.query_mut::<&mut StatModifiers>() {
+            modifiers.tick();
+            if modifiers.active.is_empty() {
+                unmodified.push(id);
+            }
+        }
+        for id in unmodified {
+            world.remove_one::<StatModifiers>(id)?;
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.last_turn = current_turn(world);
+    }
+
+    fn get_name(&self) -> &'static str {
+        "StatusEffectSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        // Has to know the turn's over before it can tell one went by.
+        vec![TypeId::of::<TurnCounterSystem>()]
+    }
+}
+
+/// What `kind` is for `entity` before any `StatModifiers`, going by its own components. Nothing
+/// has a crit chance of its own yet.
+fn base_stat(world: &World, entity: Entity, kind: StatKind) -> i32 {
+    match kind {
+        StatKind::AttackMin => world
+            .get::<&Attack>(entity)
+            .map_or(0, |attack| attack.damage_min),
+        StatKind::AttackMax => world
+            .get::<&Attack>(entity)
+            .map_or(0, |attack| attack.damage_max),
+        StatKind::Defense => world
+            .get::<&Defense>(entity)
+            .map_or(0, |defense| defense.value),
+        StatKind::Speed => speed_of(world, entity).base as i32,
+        StatKind::VisionRange => match world.get::<&Vision>(entity) {
+            Ok(vision) => vision.view_range() as i32,
+            Err(_) => world.get::<&Fov>(entity).map_or(0, |fov| fov.radius as i32),
+        },
+        StatKind::CritChance => 0,
+    }
+}
+
+/// Sums up every entity's `StatModifiers` into its `EffectiveStats`, every frame, so it's up to
+/// date before anything attacks or moves with it. Anything that's lost all its modifiers loses
+/// its `EffectiveStats` too.
+#[derive(Default)]
+pub struct ApplyStatModifiersSystem {
+    base: SystemBase,
+}
+
+impl SystemFunc for ApplyStatModifiersSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        let mut recomputed = Vec::new();
+        for (id, modifiers) in world.query::<&StatModifiers>().iter() {
+            let base = StatKind::ALL
+                .iter()
+                .map(|kind| (*kind, base_stat(world, id, *kind)))
+                .collect();
+            recomputed.push((id, EffectiveStats::new(base, modifiers)));
+        }
+        let unmodified: Vec<Entity> = world
+            .query::<&EffectiveStats>()
+            .without::<&StatModifiers>()
+            .iter()
+            .map(|(id, _stats)| id)
+            .collect();
+        for id in unmodified {
+            world.remove_one::<EffectiveStats>(id)?;
+            if let Ok(mut fov) = world.get::<&mut Fov>(id) {
+                fov.invalidate();
+            }
+        }
+        for (id, stats) in recomputed {
+            let vision_changed = world.get::<&EffectiveStats>(id).ok().is_none_or(|old| {
+                old.get(StatKind::VisionRange) != stats.get(StatKind::VisionRange)
+            });
+            if let Some(mut fov) = world.get::<&mut Fov>(id).ok().filter(|_| vision_changed) {
+                fov.invalidate();
+            }
+            world.insert_one(id, stats)?;
+        }
+        Ok(())
+    }
+
+    fn get_name(&self) -> &'static str {
+        "ApplyStatModifiersSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+}
+
+/// Sets off `source`'s `Aura` on everything in reach of it. Heals only go to whoever `source` gets
+/// along with, and everything else only lands on whoever it's hostile to.
+fn pulse_aura(
+    world: &mut World,
+    source: Entity,
+    event_bus_manager: &mut EventBusManager,
+) -> DRResult<()> {
+    let (center, aura) = {
+        let mut query = world.query_one::<(&Position, &Aura)>(source)?;
+        let Some((pos, aura)) = query.get() else {
+            return Ok(());
+        };
+        (pos.clone(), aura.clone())
+    };
+    let nearby = match world.query::<&SpatialIndex>().iter().next() {
+        Some((_id, spatial_index)) => spatial_index.neighbors(&center, aura.radius as u32),
+        None => SpatialIndex::from_world(world).neighbors(&center, aura.radius as u32),
+    };
+    let faction_of = |id| {
+        world
+            .get::<&Faction>(id)
+            .map_or(Faction::default(), |faction| *faction)
+    };
+    let my_faction = faction_of(source);
+    let (foes, friends): (Vec<Entity>, Vec<Entity>) = nearby
+        .into_iter()
+        .filter(|(pos, id)| *id != source && aura.reaches(&center, pos))
+        .map(|(_pos, id)| id)
+        .partition(|id| are_hostile(my_faction, faction_of(*id)));
+    tracing::debug!(?source, ?aura, ?foes, ?friends, "Aura went off");
+    match aura.effect {
+        AuraEffect::HealNearby(amount) => {
+            for to in friends {
+                event_bus_manager.enqueue(Heal {
+                    to,
+                    amount: amount.max(0) as u32,
+                });
+            }
+        }
+        AuraEffect::DamageNearby(damage, kind) => {
+            for to in foes {
+                event_bus_manager.enqueue(Damage {
+                    from: source,
+                    to,
+                    damage,
+                    kind,
+                });
+            }
+        }
+        AuraEffect::SlowNearby(factor, turns) => {
+            // Staying in it keeps the slow going, but doesn't stack it up.
+            for to in foes {
+                let remaining_turns = world
+                    .get::<&Slowed>(to)
+                    .map_or(0, |slowed| slowed.remaining_turns);
+                world.insert_one(
+                    to,
+                    Slowed {
+                        factor,
+                        remaining_turns: remaining_turns.max(turns),
+                    },
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sets off every `Aura` that goes by the player's turns, once a turn. The rest go off on their
+/// owners' own turns, in the `AiSystem`.
+#[derive(Default)]
+pub struct AuraSystem {
+    /// The `TurnCounter` as of the last time this ran.
+    last_turn: u64,
+    base: SystemBase,
+}
+
+impl SystemFunc for AuraSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        if !turn_went_by(world, &mut self.last_turn) {
+            return Ok(());
+        }
+        let sources: Vec<Entity> = world
+            .query::<&Aura>()
+            .iter()
+            .filter(|(_id, aura)| aura.trigger_on_player_turn)
+            .map(|(id, _aura)| id)
+            .collect();
+        for source in sources {
+            pulse_aura(world, source, event_bus_manager)?;
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.last_turn = current_turn(world);
+    }
+
+    fn get_name(&self) -> &'static str {
+        "AuraSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        // A slow put on after the statuses tick would lose its first turn straight away.
+        vec![
+            TypeId::of::<TurnCounterSystem>(),
+            TypeId::of::<StatusEffectSystem>(),
+        ]
+    }
+}
+
+/// Every tile the player could hit from where they're standing, while they're targeting.
+fn targeting_highlights(world: &World) -> Vec<(Position, Color)> {
+    let mut ui_mode_query = world.query::<&UiMode>();
+    let Some((_id, UiMode::Targeting { max_range, .. })) = ui_mode_query.iter().next() else {
+        return Vec::new();
+    };
+    let mut player_query = world.query::<&Position>().with::<&Player>();
+    let Some((_id, player_pos)) = player_query.iter().next() else {
+        return Vec::new();
+    };
+    let mut map_query = world.query::<&Map>();
+    let map = map_query.iter().next().map(|(_id, map)| map);
+    let reach = *max_range as isize;
+    (-reach..=reach)
+        .flat_map(|dy| (-reach..=reach).map(move |dx| player_pos.new_from_dx_dy(dx, dy)))
+        .filter(|pos| {
+            is_valid_target(player_pos, pos, *max_range, |pos| {
+                map.is_some_and(|map| map.is_blocked(pos))
+            })
+        })
+        .map(|pos| (pos, TARGETING_RANGE_COLOR))
+        .collect()
+}
+
+/// Keeps the `TileOverlay` up to date. Whatever's timed runs down once a turn, and the highlights
+/// for where the player's aiming and where anything winding up is about to hit get redone every
+/// frame.
+#[derive(Default)]
+pub struct TileDecorationSystem {
+    /// The `TurnCounter` as of the last time this ran.
+    last_turn: u64,
+    base: SystemBase,
+}
+
+impl SystemFunc for TileDecorationSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        let new_turn = turn_went_by(world, &mut self.last_turn);
+        let mut highlights = targeting_highlights(world);
+        highlights.extend(
+            world
+                .query::<&Telegraph>()
+                .iter()
+                .filter_map(|(_id, telegraph)| match &telegraph.pending {
+                    Action::Attack(pos) => Some((pos.clone(), TELEGRAPH_COLOR)),
+                    _ => None,
+                }),
+        );
+        let tile_overlay = find_or_spawn_resource::<TileOverlay>(world);
+        let mut tile_overlay = world.get::<&mut TileOverlay>(tile_overlay)?;
+        if new_turn {
+            tile_overlay.tick();
+        }
+        tile_overlay.set_highlights(highlights);
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.last_turn = current_turn(world);
+    }
+
+    fn get_name(&self) -> &'static str {
+        "TileDecorationSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        // Any windup that just started should get its warning straight away.
+        vec![TypeId::of::<TurnCounterSystem>(), TypeId::of::<AiSystem>()]
+    }
+}
+
+/// Takes the sight away from anything that's just been `Blinded`, and gives it back once the
+/// blindness wears off. Goes before the AIs so they don't get one last look around.
+#[derive(Default)]
+pub struct BlindSystem {
+    player_entity_id: Option<Entity>,
+    /// The `TurnCounter` as of the last time this ran.
+    last_turn: u64,
+    base: SystemBase,
+}
+
+impl SystemFunc for BlindSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        let player_id = self
+            .player_entity_id
+            .ok_or(DRError::MissingEntity("player".to_string()))?;
+
+        // Whatever just got blinded loses its sight straight away, turn or not.
+        let mut newly_blinded = Vec::new();
+        for (id, (blinded, vision, fov, ai)) in world.query_mut::<(
+            &mut Blinded,
+            Option<&mut Vision>,
+            Option<&mut Fov>,
+            Option<&mut Ai>,
+        )>() {
+            if blinded.saved_range.is_some() {
+                continue;
+            }
+            let saved_range = match (vision, fov) {
+                (Some(vision), _) => {
+                    let view_range = vision.view_range();
+                    vision.set_view_range(0);
+                    view_range
+                }
+                (None, Some(fov)) => {
+                    let radius = fov.radius;
+                    fov.radius = 0;
+                    fov.invalidate();
+                    radius as usize
+                }
+                (None, None) => 0,
+            };
+            blinded.saved_range = Some(saved_range);
+            // Nothing to be angry at or look for when you can't see.
+            if let Some(ai) = ai {
+                ai.curr_state = AiState::Idling;
+            }
+            newly_blinded.push(id);
+        }
+        for id in newly_blinded {
+            event_bus_manager.enqueue(blindness_message(
+                world,
+                player_id,
+                id,
+                "Blinded!",
+                "is blinded!",
+            ));
+        }
+
+        if !turn_went_by(world, &mut self.last_turn) {
+            return Ok(());
+        }
+        let mut cleared = Vec::new();
+        for (id, (blinded, vision, fov)) in
+            world.query_mut::<(&mut Blinded, Option<&mut Vision>, Option<&mut Fov>)>()
+        {
+            blinded.remaining_turns = blinded.remaining_turns.saturating_sub(1);
+            if blinded.remaining_turns > 0 {
+                continue;
+            }
+            let saved_range = blinded.saved_range.unwrap_or_default();
+            match (vision, fov) {
+                (Some(vision), _) => vision.set_view_range(saved_range),
+                (None, Some(fov)) => {
+                    fov.radius = saved_range as u32;
+                    fov.invalidate();
+                }
+                (None, None) => {}
+            }
+            cleared.push(id);
+        }
+        for id in cleared {
+            world.remove_one::<Blinded>(id)?;
+            event_bus_manager.enqueue(blindness_message(
+                world,
+                player_id,
+                id,
+                "Your vision clears.",
+                "can see again.",
+            ));
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.player_entity_id = Some(
+            world
+                .query::<&Player>()
+                .iter()
+                .next()
+                .expect("Have not initialized player yet.")
+                .0,
+        );
+        self.last_turn = current_turn(world);
+    }
+
+    fn get_name(&self) -> &'static str {
+        "BlindSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        vec![TypeId::of::<TurnCounterSystem>()]
+    }
+}
+
+/// `for_player` as is if it's about the player, otherwise `for_others` about whoever it was.
+fn blindness_message(
+    world: &World,
+    player: Entity,
+    id: Entity,
+    for_player: &str,
+    for_others: &str,
+) -> LogMessage {
+    if id == player {
+        LogMessage {
+            text: for_player.to_string(),
+        }
+    } else {
+        LogMessage::about(world, id, for_others)
+    }
+}
+
+/// Whether fire can't hurt `entity` at all.
+fn is_fireproof(world: &World, entity: Entity) -> bool {
+    world
+        .get::<&Resistances>(entity)
+        .is_ok_and(|resistances| resistances.apply(1, DamageKind::Fire) == 0)
+}
+
+/// Spreads fire across the floor once per player turn, and sets alight whatever's standing in it.
+#[derive(Default)]
+pub struct FireSpreadSystem {
+    /// The `TurnCounter` as of the last time this ran.
+    last_turn: u64,
+    burning_tiles_entity_id: Option<Entity>,
+    base: SystemBase,
+}
+
+impl SystemFunc for FireSpreadSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        if !turn_went_by(world, &mut self.last_turn) {
+            return Ok(());
+        }
+        let burning_tiles_id = self
+            .burning_tiles_entity_id
+            .expect("Fire Spread System was not initialized!");
+        // Whatever's standing in the fire when the turn starts catches, before any of it goes out.
+        let caught_fire: Vec<Entity> = {
+            let burning_tiles = world.get::<&BurningTiles>(burning_tiles_id)?;
+            world
+                .query::<&Position>()
+                .with::<&Health>()
+                .without::<&Burning>()
+                .iter()
+                .filter(|(id, pos)| {
+                    burning_tiles.positions.contains(pos) && !is_fireproof(world, *id)
+                })
+                .map(|(id, _pos)| id)
+                .collect()
+        };
+        for id in caught_fire {
+            world.insert_one(
+                id,
+                Burning {
+                    damage_per_turn: FIRE_DAMAGE,
+                    remaining_turns: FIRE_TURNS,
+                },
+            )?;
+            event_bus_manager.enqueue(LogMessage::about(world, id, "catches fire!"));
+        }
+        let mut map_query = world.query::<&Map>();
+        let Some((_id, map)) = map_query.iter().next() else {
+            return Ok(());
+        };
+        let mut rng_query = world.query::<&mut GameRng>();
+        let Some((_id, rng)) = rng_query.iter().next() else {
+            return Ok(());
+        };
+        world
+            .get::<&mut BurningTiles>(burning_tiles_id)?
+            .spread(map, rng);
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.last_turn = current_turn(world);
+        self.burning_tiles_entity_id = Some(find_or_spawn_resource::<BurningTiles>(world));
+    }
+
+    fn get_name(&self) -> &'static str {
+        "FireSpreadSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        // Has to know the turn's over before it can tell one went by.
+        vec![TypeId::of::<TurnCounterSystem>()]
+    }
+}
+
+/// Ticks a status that lasts for `remaining_turns` down by a turn, taking it off anything it's run
+/// out on. Hands back whatever it came off.
+fn tick_status<T: Component>(
+    world: &mut World,
+    turns_of: impl Fn(&mut T) -> &mut u32,
+) -> DRResult<Vec<Entity>> {
+    let mut run_out = Vec::new();
+    for (id, status) in world.query_mut::<&mut T>() {
+        let remaining_turns = turns_of(status);
+        *remaining_turns = remaining_turns.saturating_sub(1);
+        if *remaining_turns == 0 {
+            run_out.push(id);
+        }
+    }
+    for id in &run_out {
+        world.remove_one::<T>(*id)?;
+    }
+    Ok(run_out)
+}
+
+/// Heals `entity` by the regeneration amount, unless it's being suppressed.
+/// Returns whether the suppression has run out and should be removed.
+fn regenerate(
+    entity: Entity,
+    regeneration: &Regeneration,
+    suppressed: Option<&mut RegenerationSuppressed>,
+    event_bus_manager: &EventBusManager,
+) -> bool {
+    if let Some(suppressed) = suppressed {
+        suppressed.turns_remaining = suppressed.turns_remaining.saturating_sub(1);
+        return suppressed.turns_remaining == 0;
+    }
+    event_bus_manager.enqueue(Heal {
+        to: entity,
+        amount: regeneration.hp_per_turn.max(0) as u32,
+    });
+    false
+}
+
+/// Heals everything with `Regeneration` once per player turn.
+#[derive(Default)]
+pub struct RegenerationSystem {
+    /// The `TurnCounter` as of the last time this ran.
+    last_turn: u64,
+    base: SystemBase,
+}
+
+impl SystemFunc for RegenerationSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        if !turn_went_by(world, &mut self.last_turn) {
+            return Ok(());
+        }
+        let mut no_longer_suppressed = Vec::new();
+        for (id, (regeneration, suppressed)) in world
+            .query_mut::<(&Regeneration, Option<&mut RegenerationSuppressed>)>()
+            .with::<&Health>()
+        {
+            if regenerate(id, regeneration, suppressed, event_bus_manager) {
+                no_longer_suppressed.push(id);
+            }
+        }
+        for id in no_longer_suppressed {
+            world.remove_one::<RegenerationSuppressed>(id)?;
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.last_turn = current_turn(world);
+    }
+
+    fn get_name(&self) -> &'static str {
+        "RegenerationSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        // Has to know the turn's over before it can tell one went by.
+        vec![TypeId::of::<TurnCounterSystem>()]
+    }
+}
+
+/// Gives everything with `Stamina` a turn's worth back once per player turn.
+#[derive(Default)]
+pub struct StaminaRegenSystem {
+    /// The `TurnCounter` as of the last time this ran.
+    last_turn: u64,
+    base: SystemBase,
+}
+
+impl SystemFunc for StaminaRegenSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        if !turn_went_by(world, &mut self.last_turn) {
+            return Ok(());
+        }
+        for (_id, stamina) in world.query_mut::<&mut Stamina>() {
+            stamina.regen();
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.last_turn = current_turn(world);
+    }
+
+    fn get_name(&self) -> &'static str {
+        "StaminaRegenSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        // Whatever the player spent this turn comes off before any of it comes back.
+        vec![TypeId::of::<AiSystem>(), TypeId::of::<TurnCounterSystem>()]
+    }
+}
+
+/// Counts one more turn without damage. Returns whether it's time to heal a point, which it never
+/// is for the dead.
+fn natural_regen(health: &Health, natural_regen: &mut NaturalRegen) -> bool {
+    natural_regen.turns_since_damage = natural_regen.turns_since_damage.saturating_add(1);
+    let Some(turns_out_of_combat) = natural_regen
+        .turns_since_damage
+        .checked_sub(OUT_OF_COMBAT_TURNS)
+    else {
+        return false;
+    };
+    if health.is_dead()
+        || turns_out_of_combat == 0
+        || turns_out_of_combat % natural_regen.turns_per_hp.max(1) != 0
+    {
+        return false;
+    }
+    // Nothing to do at full health.
+    health.current_health() < health.total_health() as i32
+}
+
+/// Heals the player a bit at a time once they've been out of combat for a while.
+#[derive(Default)]
+pub struct NaturalRegenSystem {
+    /// The `TurnCounter` as of the last time this ran.
+    last_turn: u64,
+    base: SystemBase,
+}
+
+impl SystemFunc for NaturalRegenSystem {
+    fn call(
+        &mut self,
+        world: &mut World,
+        input: &dyn InputSource,
+        event_bus_manager: &mut EventBusManager,
+    ) -> DRResult<()> {
+        if !turn_went_by(world, &mut self.last_turn) {
+            return Ok(());
+        }
+        for (id, (health, regen)) in world.query_mut::<(&Health, &mut NaturalRegen)>() {
+            if natural_regen(health, regen) {
+                event_bus_manager.enqueue(Heal { to: id, amount: 1 });
+            }
+        }
+        Ok(())
+    }
+
+    fn init(&mut self, world: &mut World, event_bus_manager: &mut EventBusManager) {
+        self.last_turn = current_turn(world);
+    }
+
+    fn get_name(&self) -> &'static str {
+        "NaturalRegenSystem"
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.base.is_enabled()
+    }
+
+    fn set_enabled(&mut self, enabled: bool) {
+        self.base.set_enabled(enabled);
+    }
+
+    fn dependencies(&self) -> Vec<TypeId> {
+        // Has to know the turn's over before it can tell one went by.
+        vec![TypeId::of::<TurnCounterSystem>()]
+    }
+}
+
+mod tests {
+    use super::*;
+    use crate::audio::AudioSink;
+    use crate::entities::{
+        STARTING_ROCKS, boss_chest_loot_table, spawn_banshee, spawn_chest, spawn_door,
+        spawn_goblin_at, spawn_gold, spawn_locked_door, spawn_merchant, spawn_necromancer,
+        spawn_paladin, spawn_player, spawn_rat_at, spawn_trap, spawn_troll,
+    };
+    use crate::input_source::MockInput;
+    use crate::models::EntityName;
+    use crate::models::equipment::Equipped;
+    use crate::models::input::KeyAction;
+    use crate::models::light::{AmbientLight, LightSource};
+    use crate::models::map::TileType;
+    use crate::models::{DistanceMetric, DungeonDepth};
+    use std::collections::BTreeMap;
+    use std::f64::consts::PI;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_resolve_click_adjacent_empty_tile_moves() {
+        let player_pos = Position::new(10, 10);
+        let target = Position::new(11, 10);
+        let entity_locations = HashMap::new();
+
+        let action = resolve_click(
+            &player_pos,
+            &target,
+            &entity_locations,
+            &GameConfig::default(),
+            None,
+        );
+        assert_eq!(action, Some(MoveOrAttack::Move(target)));
+    }
+
+    #[test]
+    fn test_resolve_click_adjacent_occupied_tile_attacks() {
+        let mut world = World::new();
+        let goblin = world.spawn((Position::new(10, 9), BlocksTile));
+        let player_pos = Position::new(10, 10);
+        let target = Position::new(10, 9);
+        let entity_locations = HashMap::from([(target.clone(), goblin)]);
+
+        let action = resolve_click(
+            &player_pos,
+            &target,
+            &entity_locations,
+            &GameConfig::default(),
+            None,
+        );
+        assert_eq!(action, Some(MoveOrAttack::Attack(goblin)));
+    }
+
+    #[test]
+    fn test_resolve_click_far_tile_steps_along_path() {
+        let player_pos = Position::new(10, 10);
+        let target = Position::new(13, 10);
+        let entity_locations = HashMap::new();
+
+        let action = resolve_click(
+            &player_pos,
+            &target,
+            &entity_locations,
+            &GameConfig::default(),
+            None,
+        );
+        assert_eq!(action, Some(MoveOrAttack::Move(Position::new(11, 10))));
+    }
+
+    #[test]
+    fn test_resolve_click_outside_map_is_ignored() {
+        let player_pos = Position::new(1, 1);
+        let target = Position::new(0, 1);
+        let entity_locations = HashMap::new();
+
+        assert_eq!(
+            resolve_click(
+                &player_pos,
+                &target,
+                &entity_locations,
+                &GameConfig::default(),
+                None,
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_non_blocking_entity_does_not_block_movement() {
+        let mut world = World::new();
+        let potion_pos = Position::new(5, 5);
+        world.spawn((potion_pos.clone(),));
+
+        let spatial_index = SpatialIndex::from_world(&world);
+        assert_eq!(
+            resolve_step(
+                &potion_pos,
+                spatial_index.positions(),
+                &GameConfig::default()
+            ),
+            Some(MoveOrAttack::Move(potion_pos.clone()))
+        );
+        // It's still there for things like picking it up though.
+        assert_eq!(get_all_entity_locations(&world)[&potion_pos].len(), 1);
+    }
+
+    #[test]
+    fn test_blocking_entity_blocks_movement() {
+        let mut world = World::new();
+        let goblin_pos = Position::new(5, 5);
+        let goblin = world.spawn((goblin_pos.clone(), BlocksTile));
+
+        let spatial_index = SpatialIndex::from_world(&world);
+        assert_eq!(
+            resolve_step(
+                &goblin_pos,
+                spatial_index.positions(),
+                &GameConfig::default()
+            ),
+            Some(MoveOrAttack::Attack(goblin))
+        );
+    }
+
+    #[test]
+    fn test_attack_hits_blocking_entity_over_corpse() {
+        let mut world = World::new();
+        let pos = Position::new(5, 5);
+        // The corpse gets spawned first so it'd be the first thing found on the tile.
+        world.spawn((pos.clone(),));
+        let goblin = world.spawn((pos.clone(), BlocksTile));
+
+        let spatial_index = SpatialIndex::from_world(&world);
+        assert_eq!(
+            resolve_step(&pos, spatial_index.positions(), &GameConfig::default()),
+            Some(MoveOrAttack::Attack(goblin))
+        );
+        assert_eq!(get_all_entity_locations(&world)[&pos].len(), 2);
+    }
+
+    #[test]
+    fn test_poison_deals_damage_each_turn_then_stops() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        let goblin = world.spawn((
+            Health::new(10),
+            Effects {
+                active: vec![Effect {
+                    kind: EffectKind::Poison,
+                    turns_remaining: 3,
+                    magnitude: 2,
+                }],
+            },
+        ));
+
+        for expected_health in [8, 6, 4, 4, 4] {
+            for (id, effects) in world.query_mut::<&mut Effects>() {
+                tick_effects(id, effects, &event_bus_manager);
+            }
+            event_bus_manager.dispatch_all(&mut world);
+            let health = world.get::<&Health>(goblin).unwrap();
+            assert_eq!(health.current_health(), expected_health);
+        }
+        assert!(world.get::<&Effects>(goblin).unwrap().active.is_empty());
+    }
+
+    #[test]
+    fn test_regen_heals_through_events() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(HealHandler));
+        let mut health = Health::new(10);
+        health.apply_damage(5);
+        let goblin = world.spawn((
+            health,
+            Effects {
+                active: vec![Effect {
+                    kind: EffectKind::Regen,
+                    turns_remaining: 2,
+                    magnitude: 1,
+                }],
+            },
+        ));
+
+        for expected_health in [6, 7, 7] {
+            for (id, effects) in world.query_mut::<&mut Effects>() {
+                tick_effects(id, effects, &event_bus_manager);
+            }
+            event_bus_manager.dispatch_all(&mut world);
+            assert_eq!(
+                world.get::<&Health>(goblin).unwrap().current_health(),
+                expected_health
+            );
+        }
+    }
+
+    #[test]
+    fn test_regeneration_clamps_to_total_health() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(HealHandler));
+        let mut health = Health::new(50);
+        health.apply_damage(1);
+        let troll = world.spawn((health,));
+        let regeneration = Regeneration { hp_per_turn: 2 };
+
+        assert!(!regenerate(troll, &regeneration, None, &event_bus_manager));
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(world.get::<&Health>(troll).unwrap().current_health(), 50);
+    }
+
+    #[test]
+    fn test_fire_suppresses_regeneration() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe(Arc::new(HealHandler));
+        let troll = spawn_troll(&mut world, Position::new(5, 5), &mut rand::rng());
+
+        event_bus_manager.enqueue(Damage {
+            from: troll,
+            to: troll,
+            damage: 10,
+            kind: DamageKind::Fire,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+        // Trolls take double from fire.
+        assert_eq!(world.get::<&Health>(troll).unwrap().current_health(), 30);
+
+        for turn in 1..=BURN_TURNS {
+            let (regeneration, suppressed) = world
+                .query_one_mut::<(&Regeneration, Option<&mut RegenerationSuppressed>)>(troll)
+                .unwrap();
+            let suppression_over = regenerate(troll, regeneration, suppressed, &event_bus_manager);
+            event_bus_manager.dispatch_all(&mut world);
+            assert_eq!(world.get::<&Health>(troll).unwrap().current_health(), 30);
+            assert_eq!(suppression_over, turn == BURN_TURNS);
+        }
+        world.remove_one::<RegenerationSuppressed>(troll).unwrap();
+
+        let regeneration = (*world.get::<&Regeneration>(troll).unwrap()).clone();
+        regenerate(troll, &regeneration, None, &event_bus_manager);
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(world.get::<&Health>(troll).unwrap().current_health(), 32);
+    }
+
+    #[test]
+    fn test_troll_resists_physical_damage() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        let troll = spawn_troll(&mut world, Position::new(5, 5), &mut rand::rng());
+
+        event_bus_manager.enqueue(Damage {
+            from: troll,
+            to: troll,
+            damage: 10,
+            kind: DamageKind::Physical,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(world.get::<&Health>(troll).unwrap().current_health(), 45);
+        // Physical damage doesn't stop regeneration.
+        assert!(world.get::<&RegenerationSuppressed>(troll).is_err());
+    }
+
+    #[test]
+    fn test_resistances_change_damage_and_get_logged() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        let player = spawn_player(&mut world, Position::new(4, 5));
+        let troll = spawn_troll(&mut world, Position::new(5, 5), &mut GameRng::seeded(1));
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut GameRng::seeded(1));
+        for id in [troll, goblin] {
+            *world.get::<&mut Health>(id).unwrap() = Health::new(50);
+        }
+        world
+            .insert_one(player, Resistances::new([(DamageKind::Poison, 0.5)]))
+            .unwrap();
+        let health = |world: &World, id| world.get::<&Health>(id).unwrap().current_health();
+
+        let hits = [
+            (troll, 5, DamageKind::Physical, 47),
+            (troll, 5, DamageKind::Fire, 37),
+            (troll, 5, DamageKind::Pure, 32),
+            (troll, 0, DamageKind::Fire, 32),
+            // Nothing to resist with, so it all gets through.
+            (goblin, 5, DamageKind::Physical, 45),
+            (goblin, 5, DamageKind::Fire, 40),
+            (player, 4, DamageKind::Poison, health(&world, player) - 2),
+        ];
+        for (to, damage, kind, expected) in hits {
+            event_bus_manager.enqueue(Damage {
+                from: player,
+                to,
+                damage,
+                kind,
+            });
+            event_bus_manager.dispatch_all(&mut world);
+            assert_eq!(health(&world, to), expected, "{damage} {kind:?} damage");
+        }
+        assert_eq!(
+            game_log(&world),
+            vec![
+                "The Troll shrugs off most of the blow.",
+                "The Troll reels from the blow!",
+                "You shrug off most of the blow.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_killing_blow_despawns_monster_but_not_player() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe(Arc::new(DeadCollector::default()));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let goblin = world.spawn((Position::new(6, 5), Health::new(3)));
+
+        for to in [goblin, goblin, player] {
+            event_bus_manager.enqueue(Damage {
+                from: player,
+                to,
+                damage: 20,
+                kind: DamageKind::Physical,
+            });
+        }
+        event_bus_manager.dispatch_all(&mut world);
+        // Nothing dies until the death system notices.
+        assert!(world.contains(goblin));
+
+        DeathSystem::default()
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(event_bus_manager.queued_len_of::<PlayerDeath>(), 1);
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert!(!world.contains(goblin));
+        assert!(world.contains(player));
+    }
+
+    #[test]
+    fn test_death_system_only_takes_the_dead() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let mut dead = Health::new(10);
+        dead.apply_damage(10);
+        world.spawn((Position::new(1, 1), dead));
+        let mut barely_alive = Health::new(10);
+        barely_alive.apply_damage(9);
+        world.spawn((Position::new(2, 1), barely_alive));
+
+        DeathSystem::default()
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(event_bus_manager.queued_len_of::<DeadEntity>(), 1);
+        assert_eq!(event_bus_manager.queued_len_of::<PlayerDeath>(), 0);
+    }
+
+    #[test]
+    fn test_hit_flash_wears_off() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(HitFlashHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut GameRng::seeded(1));
+        let goblin_color = world.get::<&Renderable>(goblin).unwrap().color;
+
+        event_bus_manager.enqueue(Damage {
+            from: player,
+            to: goblin,
+            damage: 1,
+            kind: DamageKind::Physical,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+
+        let mut animation_system = AnimationSystem::default();
+        for _ in 0..HIT_FLASH_FRAMES {
+            animation_system
+                .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+            assert_eq!(
+                world.get::<&Renderable>(goblin).unwrap().drawn_color(),
+                HIT_FLASH_COLOR
+            );
+        }
+        animation_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert!(world.get::<&Animation>(goblin).is_err());
+        assert_eq!(
+            world.get::<&Renderable>(goblin).unwrap().drawn_color(),
+            goblin_color
+        );
+    }
+
+    #[test]
+    fn test_dead_entity_fades_away() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DeathFadeHandler));
+        event_bus_manager.subscribe(Arc::new(DeadCollector::default()));
+        // No loot table, so the only thing left behind is the one fading away.
+        let goblin = world.spawn((
+            Position::new(6, 5),
+            Renderable {
+                glyph: 'g',
+                color: (0, 200, 0, 255),
+                render_order: Renderable::ACTOR_ORDER,
+                tint: None,
+                bg: None,
+            },
+        ));
+
+        event_bus_manager.enqueue(DeadEntity { entity: goblin });
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(!world.contains(goblin));
+        let count_fading = |world: &mut World| world.query_mut::<&Animation>().into_iter().count();
+        assert_eq!(count_fading(&mut world), 1);
+
+        let mut animation_system = AnimationSystem::default();
+        for _ in 0..FADE_OUT_FRAMES {
+            animation_system
+                .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+        }
+        assert_eq!(count_fading(&mut world), 1);
+        animation_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(count_fading(&mut world), 0);
+        assert_eq!(world.len(), 0);
+    }
+
+    #[test]
+    fn test_dead_troll_drops_gold() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DeadCollector::default()));
+        let troll = spawn_troll(&mut world, Position::new(6, 5), &mut GameRng::seeded(1));
+
+        event_bus_manager.enqueue(DeadEntity { entity: troll });
+        event_bus_manager.dispatch_all(&mut world);
+
+        let gold: Vec<(Position, u32)> = world
+            .query::<(&Position, &Gold)>()
+            .iter()
+            .map(|(_id, (pos, gold))| (pos.clone(), gold.amount))
+            .collect();
+        assert_eq!(gold, vec![(Position::new(6, 5), 40)]);
+    }
+
+    #[test]
+    fn test_aoe_damage_only_hits_whats_in_range() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(AoeDamageHandler));
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        let center = Position::new(10, 10);
+        let [at_center, nearby, far_away] = [(10, 10), (12, 11), (14, 10)]
+            .map(|(x, y)| world.spawn((Position::new(x, y), Health::new(10))));
+        // Not everything with a position can get hurt.
+        world.spawn((Position::new(11, 10),));
+
+        event_bus_manager.enqueue(AoeDamage {
+            from: at_center,
+            center,
+            radius: 3,
+            amount: 4,
+            kind: DamageKind::Fire,
+            metric: DistanceMetric::Euclidean,
+        });
+        assert_eq!(event_bus_manager.queued_len(), 1);
+        event_bus_manager.dispatch_all(&mut world);
+
+        let health = |entity| world.get::<&Health>(entity).unwrap().current_health();
+        assert_eq!(health(at_center), 6);
+        assert_eq!(health(nearby), 6);
+        assert_eq!(health(far_away), 10);
+    }
+
+    #[test]
+    fn test_aoe_damage_reach_depends_on_metric() {
+        let aoe = |metric| AoeDamage {
+            from: Entity::DANGLING,
+            center: Position::new(0, 0),
+            radius: 2,
+            amount: 1,
+            kind: DamageKind::Physical,
+            metric,
+        };
+        let diagonal = Position::new(1, 1);
+        let corner = Position::new(2, 1);
+        assert!(aoe(DistanceMetric::Manhattan).reaches(&diagonal));
+        assert!(!aoe(DistanceMetric::Manhattan).reaches(&corner));
+        assert!(!aoe(DistanceMetric::Euclidean).reaches(&corner));
+        assert!(aoe(DistanceMetric::EuclideanSquared).reaches(&Position::new(2, 0)));
+        assert!(!aoe(DistanceMetric::EuclideanSquared).reaches(&corner));
+    }
+
+    #[test]
+    fn test_cone_damage_only_hits_whats_in_the_cone() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(ConeDamageHandler));
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        let dragon = world.spawn((Position::new(10, 10), Health::new(10)));
+        // Straight ahead, a little too far off to the side, and too far away.
+        let [inside, outside_spread, out_of_range] = [(13, 11), (12, 12), (16, 10)]
+            .map(|(x, y)| world.spawn((Position::new(x, y), Health::new(10))));
+
+        event_bus_manager.enqueue(ConeDamage {
+            from: dragon,
+            origin: Position::new(10, 10),
+            direction_radians: 0.0,
+            spread_radians: PI / 6.0,
+            range: 5,
+            amount: 4,
+            kind: DamageKind::Fire,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+
+        let health = |entity| world.get::<&Health>(entity).unwrap().current_health();
+        assert_eq!(health(inside), 6);
+        assert_eq!(health(outside_spread), 10);
+        assert_eq!(health(out_of_range), 10);
+        assert_eq!(health(dragon), 10);
+    }
+
+    #[test]
+    fn test_cone_damage_wraps_around_pi() {
+        // Pointing west, where the angles flip over from PI to -PI.
+        let cone = ConeDamage {
+            from: Entity::DANGLING,
+            origin: Position::new(10, 10),
+            direction_radians: PI,
+            spread_radians: PI / 4.0,
+            range: 5,
+            amount: 1,
+            kind: DamageKind::Fire,
+        };
+        assert!(cone.reaches(&Position::new(7, 10)));
+        assert!(cone.reaches(&Position::new(7, 9)));
+        assert!(cone.reaches(&Position::new(7, 11)));
+        assert!(!cone.reaches(&Position::new(13, 10)));
+
+        let cone = ConeDamage {
+            direction_radians: -PI,
+            ..cone
+        };
+        assert!(cone.reaches(&Position::new(7, 9)));
+        assert!(cone.reaches(&Position::new(7, 11)));
+    }
+
+    #[test]
+    fn test_knockback_pushes_target_away_from_attacker() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe(Arc::new(KnockbackHandler));
+        world.spawn((Map::new_bordered(20, 20),));
+        let troll = spawn_troll(&mut world, Position::new(4, 5), &mut GameRng::seeded(1));
+        world.insert_one(troll, Knockback { tiles: 2 }).unwrap();
+        let goblin = spawn_goblin_at(&mut world, Position::new(5, 5), &mut GameRng::seeded(1));
+        find_or_build_spatial_index(&mut world);
+
+        event_bus_manager.enqueue(Damage {
+            from: troll,
+            to: goblin,
+            damage: 1,
+            kind: DamageKind::Physical,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert_eq!(*world.get::<&Position>(goblin).unwrap(), Position::new(7, 5));
+        let spatial_index_id = find_or_build_spatial_index(&mut world);
+        let spatial_index = world.get::<&SpatialIndex>(spatial_index_id).unwrap();
+        assert_eq!(spatial_index.at(&Position::new(7, 5)), Some(goblin));
+        assert!(!spatial_index.is_occupied(&Position::new(5, 5)));
+    }
+
+    #[test]
+    fn test_knockback_stops_at_walls() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe(Arc::new(KnockbackHandler));
+        let mut map = Map::new_bordered(20, 20);
+        map.set(&Position::new(7, 5), TileType::Wall);
+        world.spawn((map,));
+        let troll = spawn_troll(&mut world, Position::new(4, 5), &mut GameRng::seeded(1));
+        world.insert_one(troll, Knockback { tiles: 3 }).unwrap();
+        let goblin = spawn_goblin_at(&mut world, Position::new(5, 5), &mut GameRng::seeded(1));
+        find_or_build_spatial_index(&mut world);
+
+        let full_health = world.get::<&Health>(goblin).unwrap().current_health();
+
+        event_bus_manager.enqueue(Damage {
+            from: troll,
+            to: goblin,
+            damage: 1,
+            kind: DamageKind::Physical,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert_eq!(*world.get::<&Position>(goblin).unwrap(), Position::new(6, 5));
+        // The hit, then the two tiles of shove it didn't get to go.
+        assert_eq!(
+            world.get::<&Health>(goblin).unwrap().current_health(),
+            full_health - 3
+        );
+    }
+
+    #[test]
+    fn test_warhammer_knocks_back() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe(Arc::new(KnockbackHandler));
+        world.spawn((Map::new_bordered(20, 20),));
+        let player = spawn_player(&mut world, Position::new(4, 5));
+        let warhammer = spawn_item(&mut world, Position::new(4, 5), ItemKind::Warhammer);
+        equip(&mut world, player, warhammer).unwrap();
+        let goblin = spawn_goblin_at(&mut world, Position::new(5, 5), &mut GameRng::seeded(1));
+        find_or_build_spatial_index(&mut world);
+        let moves = Arc::new(Mutex::new(Vec::new()));
+        event_bus_manager.subscribe(Arc::new(RecordingKnockbacks(moves.clone())));
+
+        event_bus_manager.enqueue(Damage {
+            from: player,
+            to: goblin,
+            damage: 1,
+            kind: DamageKind::Physical,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert_eq!(*world.get::<&Position>(goblin).unwrap(), Position::new(7, 5));
+        assert_eq!(
+            *moves.lock().unwrap(),
+            vec![(goblin, Position::new(5, 5), Position::new(7, 5))]
+        );
+    }
+
+    /// Writes down every knockback that happens.
+    struct RecordingKnockbacks(Arc<Mutex<Vec<(Entity, Position, Position)>>>);
+
+    impl EventHandler<KnockbackOccurred> for RecordingKnockbacks {
+        fn handle(&self, event: &mut KnockbackOccurred, _ctx: &mut EventCtx) {
+            self.0
+                .lock()
+                .unwrap()
+                .push((event.entity, event.from.clone(), event.to.clone()));
+        }
+    }
+
+    /// Does nothing, just here to get sorted.
+    struct OrderingSystem<const N: usize> {
+        dependencies: Vec<TypeId>,
+    }
+
+    impl<const N: usize> OrderingSystem<N> {
+        fn boxed(dependencies: Vec<TypeId>) -> Box<dyn SystemFunc> {
+            Box::new(OrderingSystem::<N> { dependencies })
+        }
+    }
+
+    impl<const N: usize> SystemFunc for OrderingSystem<N> {
+        fn call(
+            &mut self,
+            world: &mut World,
+            input: &dyn InputSource,
+            event_bus_manager: &mut EventBusManager,
+        ) -> DRResult<()> {
+            Ok(())
+        }
+
+        fn get_name(&self) -> &'static str {
+            [
+                "OrderingSystem0",
+                "OrderingSystem1",
+                "OrderingSystem2",
+                "OrderingSystem3",
+            ][N]
+        }
+
+        fn dependencies(&self) -> Vec<TypeId> {
+            self.dependencies.clone()
+        }
+    }
+
+    fn names(systems: &[Box<dyn SystemFunc>]) -> Vec<&'static str> {
+        systems.iter().map(|system| system.get_name()).collect()
+    }
+
+    #[test]
+    fn test_sort_by_dependencies_keeps_independent_order() {
+        let systems = sort_by_dependencies(vec![
+            OrderingSystem::<0>::boxed(vec![]),
+            OrderingSystem::<1>::boxed(vec![]),
+            OrderingSystem::<2>::boxed(vec![]),
+        ]);
+        assert_eq!(
+            names(&systems),
+            vec!["OrderingSystem0", "OrderingSystem1", "OrderingSystem2"]
+        );
+    }
+
+    #[test]
+    fn test_sort_by_dependencies_moves_dependents_later() {
+        let systems = sort_by_dependencies(vec![
+            OrderingSystem::<0>::boxed(vec![TypeId::of::<OrderingSystem<2>>()]),
+            OrderingSystem::<1>::boxed(vec![]),
+            OrderingSystem::<2>::boxed(vec![TypeId::of::<OrderingSystem<1>>()]),
+            OrderingSystem::<3>::boxed(vec![]),
+        ]);
+        assert_eq!(
+            names(&systems),
+            vec![
+                "OrderingSystem1",
+                "OrderingSystem2",
+                "OrderingSystem0",
+                "OrderingSystem3"
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Dependency cycle between systems: OrderingSystem0, OrderingSystem1")]
+    fn test_sort_by_dependencies_panics_on_cycle() {
+        sort_by_dependencies(vec![
+            OrderingSystem::<0>::boxed(vec![TypeId::of::<OrderingSystem<1>>()]),
+            OrderingSystem::<1>::boxed(vec![TypeId::of::<OrderingSystem<0>>()]),
+            OrderingSystem::<2>::boxed(vec![]),
+        ]);
+    }
+
+    #[test]
+    fn test_sort_by_dependencies_ai_after_input() {
+        let systems = sort_by_dependencies(vec![
+            Box::new(AiSystem::new()),
+            Box::new(TargetingSystem::default()),
+            Box::new(InputSystem::default()),
+        ]);
+        assert_eq!(
+            names(&systems),
+            vec!["InputSystem", "TargetingSystem", "AISystem"]
+        );
+    }
+
+    #[test]
+    fn test_fov_only_recomputes_when_needed() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let map_id = world.spawn((Map::new_bordered(20, 20),));
+        let mut fov_system = FovSystem::default();
+        fov_system.init(&mut world, &mut event_bus_manager);
+        let mut tick = |world: &mut World| {
+            fov_system
+                .call(world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+            fov_system.recomputes
+        };
+
+        assert_eq!(tick(&mut world), 1);
+        // Standing still just reuses what was already worked out.
+        assert_eq!(tick(&mut world), 1);
+
+        world.get::<&mut Position>(player).unwrap().x = 6;
+        assert_eq!(tick(&mut world), 2);
+        assert_eq!(tick(&mut world), 2);
+
+        world
+            .get::<&mut Map>(map_id)
+            .unwrap()
+            .set(&Position::new(8, 5), TileType::Wall);
+        assert_eq!(tick(&mut world), 3);
+        let fov = world.get::<&Fov>(player).unwrap();
+        assert!(fov.can_see(&Position::new(8, 5)));
+        assert!(!fov.can_see(&Position::new(10, 5)));
+    }
+
+    #[test]
+    fn test_closed_door_blocks_sight_until_opened() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe::<DoorOpened>(Arc::new(DoorHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        world.spawn((Map::new_bordered(20, 20),));
+        let door = spawn_door(&mut world, Position::new(7, 5), false);
+        let behind_door = Position::new(9, 5);
+        let mut fov_system = FovSystem::default();
+        fov_system.init(&mut world, &mut event_bus_manager);
+
+        fov_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        {
+            let fov = world.get::<&Fov>(player).unwrap();
+            assert!(fov.can_see(&Position::new(7, 5)));
+            assert!(!fov.can_see(&behind_door));
+        }
+
+        event_bus_manager.enqueue(DoorOpened { door });
+        event_bus_manager.dispatch_all(&mut world);
+        fov_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(fov_system.recomputes, 2);
+        assert!(world.get::<&Fov>(player).unwrap().can_see(&behind_door));
+        let spatial_index_id = find_or_build_spatial_index(&mut world);
+        let spatial_index = world.get::<&SpatialIndex>(spatial_index_id).unwrap();
+        assert!(matches!(
+            resolve_step(
+                &Position::new(7, 5),
+                spatial_index.positions(),
+                &GameConfig::default()
+            ),
+            Some(MoveOrAttack::Move(_))
+        ));
+    }
+
+    #[test]
+    fn test_heal_at_full_health_does_nothing() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(HealHandler));
+        let goblin = world.spawn((Health::new(10),));
+
+        event_bus_manager.enqueue(Heal {
+            to: goblin,
+            amount: 5,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(world.get::<&Health>(goblin).unwrap().current_health(), 10);
+    }
+
+    #[test]
+    fn test_heal_never_goes_over_total_health() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(HealHandler));
+        let mut health = Health::new(10);
+        health.apply_damage(7);
+        let goblin = world.spawn((health,));
+
+        event_bus_manager.enqueue(Heal {
+            to: goblin,
+            amount: 4,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(world.get::<&Health>(goblin).unwrap().current_health(), 7);
+
+        event_bus_manager.enqueue(Heal {
+            to: goblin,
+            amount: 100,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(world.get::<&Health>(goblin).unwrap().current_health(), 10);
+    }
+
+    #[test]
+    fn test_log_message_uses_entity_names() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        let game_log = world.spawn((GameLog::default(),));
+        let goblin = world.spawn((EntityName {
+            name: "Goblin".to_string(),
+        },));
+        let player = world.spawn((EntityName {
+            name: "Adventurer".to_string(),
+        },));
+        let nameless = world.spawn((Position::new(0, 0),));
+
+        event_bus_manager.enqueue(LogMessage::attack(&world, Some(goblin), Some(player), 2));
+        event_bus_manager.enqueue(LogMessage::attack(&world, Some(nameless), Some(goblin), 1));
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert_eq!(
+            world
+                .get::<&GameLog>(game_log)
+                .unwrap()
+                .lines()
+                .collect::<Vec<_>>(),
+            vec![
+                "The Goblin attacks Adventurer for 2 damage!",
+                "The something attacks Goblin for 1 damage!",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_input_system_moves_player_with_mock_input() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &MockInput::pressing("ArrowRight"),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+
+        assert_eq!(
+            *world.get::<&Position>(player).unwrap(),
+            Position::new(6, 5)
+        );
+        assert!(was_input_handled_this_frame(&world, player));
+
+        input_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(
+            *world.get::<&Position>(player).unwrap(),
+            Position::new(6, 5)
+        );
+        assert!(!was_input_handled_this_frame(&world, player));
+    }
+
+    #[test]
+    fn test_dump_key_asks_the_engine_for_a_world_dump() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(&mut world, &MockInput::pressing("F5"), &mut event_bus_manager)
+            .unwrap();
+
+        let (_id, requests) = world.query_mut::<&EngineRequests>().into_iter().next().unwrap();
+        assert!(requests.world_dump);
+        assert!(!requests.screenshot);
+        // Doesn't take a turn.
+        assert!(!was_input_handled_this_frame(&world, player));
+    }
+
+    /// Moves the `TurnCounter` on, as if the player had just done something.
+    fn take_turn(world: &mut World) {
+        let turn_counter = find_or_spawn_resource::<TurnCounter>(world);
+        world.get::<&mut TurnCounter>(turn_counter).unwrap().0 += 1;
+    }
+
+    /// `input` pressed with the default key bindings.
+    fn scripted(input: SimInput) -> impl InputSource {
+        input.pressed_with(KeyBindings::default())
+    }
+
+    /// Runs `turns` turns of natural regen on `player`, returning which turns healed.
+    fn run_natural_regen(world: &mut World, player: Entity, turns: u32) -> Vec<u32> {
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(HealHandler));
+        (1..=turns)
+            .filter(|_| {
+                let (health, regen) = world
+                    .query_one_mut::<(&Health, &mut NaturalRegen)>(player)
+                    .unwrap();
+                let healing = natural_regen(health, regen);
+                if healing {
+                    event_bus_manager.enqueue(Heal {
+                        to: player,
+                        amount: 1,
+                    });
+                    event_bus_manager.dispatch_all(world);
+                }
+                healing
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_spatial_index_only_tracks_blockers() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        world.spawn((Position::new(3, 3),));
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &MockInput::pressing("ArrowRight"),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+
+        let (_id, spatial_index) = world
+            .query_mut::<&SpatialIndex>()
+            .into_iter()
+            .next()
+            .unwrap();
+        // The player's move is picked up without having to rebuild anything.
+        assert_eq!(spatial_index.positions().len(), 1);
+        assert_eq!(spatial_index.at(&Position::new(6, 5)), Some(player));
+        assert!(!spatial_index.is_occupied(&Position::new(5, 5)));
+        assert!(!spatial_index.is_occupied(&Position::new(3, 3)));
+    }
+
+    #[test]
+    fn test_dead_entities_leave_spatial_index() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DeadCollector::default()));
+        let goblin_pos = Position::new(6, 5);
+        let goblin = world.spawn((goblin_pos.clone(), BlocksTile));
+        let spatial_index_id = find_or_build_spatial_index(&mut world);
+        assert_eq!(
+            world
+                .get::<&SpatialIndex>(spatial_index_id)
+                .unwrap()
+                .at(&goblin_pos),
+            Some(goblin)
+        );
+
+        event_bus_manager.enqueue(DeadEntity { entity: goblin });
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(
+            !world
+                .get::<&SpatialIndex>(spatial_index_id)
+                .unwrap()
+                .is_occupied(&goblin_pos)
+        );
+    }
+
+    #[test]
+    fn test_ai_system_skips_far_away_ais() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        world
+            .get::<&mut InputState>(player)
+            .unwrap()
+            .was_input_handled_this_frame = true;
+        let mut rng = GameRng::seeded(3);
+        spawn_goblin_at(&mut world, Position::new(8, 5), &mut rng);
+        let far_goblin = spawn_goblin_at(&mut world, Position::new(60, 30), &mut rng);
+        world.spawn((rng,));
+        let mut ai_system = AiSystem::new();
+        ai_system.init(&mut world, &mut event_bus_manager);
+
+        for _ in 0..20 {
+            ai_system
+                .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+            assert_eq!(ai_system.active_ais, 1);
+        }
+        assert_eq!(
+            *world.get::<&Position>(far_goblin).unwrap(),
+            Position::new(60, 30)
+        );
+    }
+
+    #[test]
+    fn test_fast_ais_act_more_often_than_slow_ones() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(3);
+        let fast = spawn_goblin_at(&mut world, Position::new(8, 5), &mut rng);
+        let slow = spawn_goblin_at(&mut world, Position::new(5, 8), &mut rng);
+        world.insert_one(fast, EntitySpeed::new(20)).unwrap();
+        world.insert_one(slow, EntitySpeed::new(5)).unwrap();
+        world.spawn((rng,));
+        let mut ai_system = AiSystem::new();
+        ai_system.init(&mut world, &mut event_bus_manager);
+
+        // Two turns for the fast one every time, and one every other time for the slow one.
+        for expected in [3, 2, 3, 2] {
+            world
+                .get::<&mut InputState>(player)
+                .unwrap()
+                .was_input_handled_this_frame = true;
+            ai_system
+                .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+            assert_eq!(ai_system.active_ais, expected);
+        }
+    }
+
+    #[test]
+    fn test_wading_through_water_gives_everything_else_more_time() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut map = Map::new_bordered(20, 12);
+        map.set(&Position::new(5, 5), TileType::Water);
+        world.spawn((map,));
+        let mut rng = GameRng::seeded(3);
+        spawn_goblin_at(&mut world, Position::new(8, 5), &mut rng);
+        world.spawn((rng,));
+        let mut ai_system = AiSystem::new();
+        ai_system.init(&mut world, &mut event_bus_manager);
+
+        world
+            .get::<&mut InputState>(player)
+            .unwrap()
+            .was_input_handled_this_frame = true;
+        ai_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        // Water takes three times as long to get through, so the goblin gets three turns.
+        assert_eq!(ai_system.active_ais, 3);
+    }
+
+    #[test]
+    fn test_ai_turns_get_spread_over_frames() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(40, 22));
+        world.spawn((GameConfig {
+            max_ai_per_frame: 64,
+            ..GameConfig::default()
+        },));
+        let mut rng = GameRng::seeded(3);
+        for x in (15..65).step_by(2) {
+            for y in (2..42).step_by(2) {
+                let goblin = spawn_goblin_at(&mut world, Position::new(x, y), &mut rng);
+                // Far-sighted enough that every one of them is worth thinking about.
+                world.insert_one(goblin, Vision::new(20)).unwrap();
+            }
+        }
+        assert_eq!(world.query::<&Ai>().iter().count(), 500);
+        world.spawn((rng,));
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+        let mut ai_system = AiSystem::new();
+        ai_system.init(&mut world, &mut event_bus_manager);
+
+        for _turn in 0..2 {
+            world
+                .get::<&mut InputState>(player)
+                .unwrap()
+                .was_input_handled_this_frame = true;
+            let mut frames = 0;
+            let mut turns_taken = 0;
+            loop {
+                ai_system
+                    .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                    .unwrap();
+                frames += 1;
+                turns_taken += ai_system.active_ais;
+                assert!(ai_system.active_ais <= 64);
+                if !ai_turn_in_progress(&world) {
+                    break;
+                }
+                // The player has to wait for everyone else to finish.
+                input_system
+                    .call(
+                        &mut world,
+                        &MockInput::pressing("ArrowUp"),
+                        &mut event_bus_manager,
+                    )
+                    .unwrap();
+                assert!(!was_input_handled_this_frame(&world, player));
+            }
+            // Everyone goes exactly once, and it takes as many frames as the budget says.
+            assert_eq!(turns_taken, 500);
+            assert_eq!(frames, 8);
+        }
+    }
+
+    #[test]
+    fn test_a_crowd_of_goblins_shares_one_map_to_the_player() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(40, 22));
+        let mut rng = GameRng::seeded(3);
+        let mut goblins = Vec::new();
+        for x in (31..51).step_by(2) {
+            for y in (12..32).step_by(2) {
+                let goblin = spawn_goblin_at(&mut world, Position::new(x, y), &mut rng);
+                world.insert_one(goblin, Vision::new(20)).unwrap();
+                world.get::<&mut Ai>(goblin).unwrap().curr_state = AiState::Angry;
+                goblins.push(goblin);
+            }
+        }
+        assert_eq!(goblins.len(), 100);
+        world.spawn((rng,));
+        let mut ai_system = AiSystem::new();
+        ai_system.init(&mut world, &mut event_bus_manager);
+        let config = GameConfig::from_world(&world);
+        let player_map = DijkstraMap::build(
+            &[Position::new(40, 22)],
+            |pos| pos.is_within_console_bounds(&config),
+            PLAYER_MAP_DEPTH,
+        );
+        let positions = |world: &World| -> Vec<Position> {
+            goblins
+                .iter()
+                .map(|goblin| (*world.get::<&Position>(*goblin).unwrap()).clone())
+                .collect()
+        };
+        let before = positions(&world);
+
+        world
+            .get::<&mut InputState>(player)
+            .unwrap()
+            .was_input_handled_this_frame = true;
+        ai_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+
+        // Everyone got their turn, and nobody went uphill on the player's map. Anything that couldn't
+        // go straight down, with someone else in the way, went sideways instead.
+        assert_eq!(ai_system.active_ais, 100);
+        let cost = |positions: &[Position]| -> u32 {
+            positions
+                .iter()
+                .map(|pos| player_map.cost(pos).unwrap())
+                .sum()
+        };
+        let after = positions(&world);
+        for (from, to) in before.iter().zip(&after) {
+            assert!(
+                player_map.cost(to) <= player_map.cost(from),
+                "Goblin went from {from:?} to {to:?}"
+            );
+        }
+        assert!(cost(&after) < cost(&before));
+    }
+
+    #[test]
+    fn test_goblin_sees_farther_when_lit() {
+        let goblin_notices_player = |lit: bool| {
+            let mut world = World::new();
+            let mut event_bus_manager = EventBusManager::new();
+            let player = spawn_player(&mut world, Position::new(5, 5));
+            world
+                .get::<&mut InputState>(player)
+                .unwrap()
+                .was_input_handled_this_frame = true;
+            let mut rng = GameRng::seeded(3);
+            let goblin = spawn_goblin_at(&mut world, Position::new(9, 5), &mut rng);
+            world.spawn((rng,));
+            world.spawn((AmbientLight(0),));
+            if lit {
+                world.spawn((
+                    Position::new(9, 5),
+                    LightSource {
+                        radius: 3,
+                        color: (255, 255, 255, 255),
+                    },
+                ));
+            }
+            let mut ai_system = AiSystem::new();
+            ai_system.init(&mut world, &mut event_bus_manager);
+            ai_system
+                .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+            let state = world.get::<&Ai>(goblin).unwrap().curr_state.clone();
+            state == AiState::Angry
+        };
+
+        assert!(goblin_notices_player(true));
+        assert!(!goblin_notices_player(false));
+    }
+
+    /// Runs the AIs for `turns` turns, as if the player did something each time, and deals out
+    /// whatever damage and healing they did.
+    fn run_ai_turns(world: &mut World, player: Entity, turns: usize) {
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe(Arc::new(HealHandler));
+        let mut ai_system = AiSystem::new();
+        ai_system.init(world, &mut event_bus_manager);
+        for _ in 0..turns {
+            world
+                .get::<&mut InputState>(player)
+                .unwrap()
+                .was_input_handled_this_frame = true;
+            ai_system
+                .call(world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+            event_bus_manager.dispatch_all(world);
+        }
+    }
+
+    #[test]
+    fn test_confused_goblin_still_attacks() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(3);
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut rng);
+        world
+            .insert_one(goblin, Confused { remaining_turns: 10 })
+            .unwrap();
+        world.get::<&mut Ai>(goblin).unwrap().curr_state = AiState::Angry;
+        world.spawn((rng,));
+        let full_health = world.get::<&Health>(player).unwrap().current_health();
+
+        run_ai_turns(&mut world, player, 1);
+        assert!(world.get::<&Health>(player).unwrap().current_health() < full_health);
+    }
+
+    #[test]
+    fn test_confused_goblin_wanders_off_then_gives_chase_again() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(3);
+        let goblin = spawn_goblin_at(&mut world, Position::new(13, 5), &mut rng);
+        world
+            .insert(goblin, (Vision::new(20), Confused { remaining_turns: 4 }))
+            .unwrap();
+        world.get::<&mut Ai>(goblin).unwrap().curr_state = AiState::Angry;
+        world.spawn((rng,));
+        let mut ai_system = AiSystem::new();
+        ai_system.init(&mut world, &mut event_bus_manager);
+        let mut status_effect_system = StatusEffectSystem::default();
+        status_effect_system.init(&mut world, &mut event_bus_manager);
+        // How many steps the goblin is from the player, diagonals included.
+        let steps_away = |world: &World| {
+            let pos = world.get::<&Position>(goblin).unwrap();
+            (pos.x - 5).abs().max((pos.y - 5).abs())
+        };
+        let mut run_turn = |world: &mut World| {
+            world
+                .get::<&mut InputState>(player)
+                .unwrap()
+                .was_input_handled_this_frame = true;
+            ai_system
+                .call(world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+            take_turn(world);
+            status_effect_system
+                .call(world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+            event_bus_manager.dispatch_all(world);
+        };
+
+        let mut wandered_off = false;
+        for _ in 0..4 {
+            let before = steps_away(&world);
+            run_turn(&mut world);
+            wandered_off |= steps_away(&world) >= before;
+        }
+        assert!(wandered_off);
+        assert!(world.get::<&Confused>(goblin).is_err());
+
+        // Thinking straight again, so every step is towards the player.
+        for _ in 0..2 {
+            let before = steps_away(&world);
+            run_turn(&mut world);
+            assert_eq!(steps_away(&world), before - 1);
+        }
+    }
+
+    #[test]
+    fn test_feared_goblin_runs_instead_of_attacking() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(3);
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut rng);
+        world
+            .insert_one(goblin, Feared { remaining_turns: 1 })
+            .unwrap();
+        world.get::<&mut Ai>(goblin).unwrap().curr_state = AiState::Angry;
+        world.spawn((rng,));
+        let full_health = world.get::<&Health>(player).unwrap().current_health();
+
+        run_ai_turns(&mut world, player, 1);
+        assert_eq!(
+            world.get::<&Health>(player).unwrap().current_health(),
+            full_health
+        );
+        assert!(world.get::<&Position>(goblin).unwrap().x > 6);
+        // Only scared for the one turn.
+        assert!(world.get::<&Feared>(goblin).is_err());
+        assert_eq!(world.get::<&Ai>(goblin).unwrap().curr_state, AiState::Angry);
+    }
+
+    #[test]
+    fn test_banshee_scream_scares_everything_nearby() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(ScreamHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        world
+            .get::<&mut InputState>(player)
+            .unwrap()
+            .was_input_handled_this_frame = true;
+        let mut rng = GameRng::seeded(3);
+        let banshee = spawn_banshee(&mut world, Position::new(8, 5), &mut rng);
+        world.get::<&mut Ai>(banshee).unwrap().curr_state = AiState::Angry;
+        let near = spawn_goblin_at(&mut world, Position::new(9, 7), &mut rng);
+        let far = spawn_goblin_at(&mut world, Position::new(20, 5), &mut rng);
+        world.spawn((rng,));
+        let mut ai_system = AiSystem::new();
+        ai_system.init(&mut world, &mut event_bus_manager);
+
+        ai_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(event_bus_manager.queued_len_of::<Scream>(), 1);
+        assert_eq!(
+            world.get::<&Screamer>(banshee).unwrap().cooldown_remaining,
+            10
+        );
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert_eq!(
+            *world.get::<&Feared>(near).unwrap(),
+            Feared { remaining_turns: 4 }
+        );
+        assert!(world.get::<&Feared>(far).is_err());
+        assert!(world.get::<&Feared>(banshee).is_err());
+    }
+
+    #[test]
+    fn test_goblin_killed_this_frame_does_not_take_its_turn() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(3);
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut rng);
+        world.get::<&mut Ai>(goblin).unwrap().curr_state = AiState::Angry;
+        world.spawn((rng,));
+        let full_health = world.get::<&Health>(player).unwrap().current_health();
+        // Dead, but DeathSystem hasn't got to it yet.
+        world.get::<&mut Health>(goblin).unwrap().apply_damage(1000);
+
+        run_ai_turns(&mut world, player, 2);
+        assert_eq!(
+            world.get::<&Health>(player).unwrap().current_health(),
+            full_health
+        );
+    }
+
+    #[test]
+    fn test_sleeping_goblin_skips_its_turn() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(3);
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut rng);
+        world.get::<&mut Ai>(goblin).unwrap().curr_state = AiState::Angry;
+        world
+            .insert_one(
+                goblin,
+                Sleeping {
+                    remaining_turns: 2,
+                    wake_on_damage: true,
+                },
+            )
+            .unwrap();
+        world.spawn((rng,));
+        let full_health = world.get::<&Health>(player).unwrap().current_health();
+
+        run_ai_turns(&mut world, player, 2);
+        assert_eq!(
+            world.get::<&Health>(player).unwrap().current_health(),
+            full_health
+        );
+
+        // Slept through those turns, and doesn't get them back once it's up.
+        world.remove_one::<Sleeping>(goblin).unwrap();
+        run_ai_turns(&mut world, player, 1);
+        assert!(world.get::<&Health>(player).unwrap().current_health() < full_health);
+    }
+
+    #[test]
+    fn test_blinded_goblin_cannot_find_the_player() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(3);
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut rng);
+        world.get::<&mut Ai>(goblin).unwrap().curr_state = AiState::Angry;
+        world
+            .insert_one(
+                goblin,
+                Blinded {
+                    remaining_turns: 2,
+                    saved_range: None,
+                },
+            )
+            .unwrap();
+        world.spawn((rng,));
+        let full_health = world.get::<&Health>(player).unwrap().current_health();
+        let mut blind_system = BlindSystem::default();
+        blind_system.init(&mut world, &mut event_bus_manager);
+
+        blind_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(world.get::<&Vision>(goblin).unwrap().view_range(), 0);
+        assert_eq!(world.get::<&Blinded>(goblin).unwrap().saved_range, Some(6));
+        assert_eq!(
+            world.get::<&Ai>(goblin).unwrap().curr_state,
+            AiState::Idling
+        );
+        run_ai_turns(&mut world, player, 1);
+        assert_eq!(
+            world.get::<&Health>(player).unwrap().current_health(),
+            full_health
+        );
+
+        for _ in 0..2 {
+            take_turn(&mut world);
+            blind_system
+                .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+        }
+        assert!(world.get::<&Blinded>(goblin).is_err());
+        assert_eq!(world.get::<&Vision>(goblin).unwrap().view_range(), 6);
+    }
+
+    #[test]
+    fn test_blinded_player_sees_nothing_until_it_clears() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let radius = world.get::<&Fov>(player).unwrap().radius;
+        world
+            .insert_one(
+                player,
+                Blinded {
+                    remaining_turns: 1,
+                    saved_range: None,
+                },
+            )
+            .unwrap();
+        let mut blind_system = BlindSystem::default();
+        blind_system.init(&mut world, &mut event_bus_manager);
+        let mut fov_system = FovSystem::default();
+        fov_system.init(&mut world, &mut event_bus_manager);
+
+        blind_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        fov_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        {
+            let fov = world.get::<&Fov>(player).unwrap();
+            assert!(!fov.can_see(&Position::new(5, 5)));
+            assert!(!fov.can_see(&Position::new(6, 5)));
+        }
+
+        world
+            .get::<&mut InputState>(player)
+            .unwrap()
+            .was_input_handled_this_frame = true;
+        take_turn(&mut world);
+        blind_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        fov_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert!(world.get::<&Blinded>(player).is_err());
+        let fov = world.get::<&Fov>(player).unwrap();
+        assert_eq!(fov.radius, radius);
+        assert!(fov.can_see(&Position::new(6, 5)));
+        let mut game_log_query = world.query::<&GameLog>();
+        let (_id, game_log) = game_log_query.iter().next().unwrap();
+        assert_eq!(
+            game_log.lines().collect::<Vec<_>>(),
+            vec!["Blinded!", "Your vision clears."]
+        );
+    }
+
+    #[test]
+    fn test_getting_hit_can_put_you_to_sleep_and_wake_you_up() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(SleepWakeBreaker));
+        event_bus_manager.subscribe(Arc::new(SlumberAttackHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(3);
+        let rat = spawn_rat_at(&mut world, Position::new(6, 5), &mut rng);
+        world.get::<&mut SlumberAttack>(rat).unwrap().chance = 1.0;
+        world.spawn((rng,));
+        let hit = |from, to| Damage {
+            from,
+            to,
+            damage: 1,
+            kind: DamageKind::Physical,
+        };
+
+        event_bus_manager.enqueue(hit(rat, player));
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(
+            *world.get::<&Sleeping>(player).unwrap(),
+            Sleeping {
+                remaining_turns: 3,
+                wake_on_damage: true,
+            }
+        );
+
+        // Getting bitten again wakes them, then knocks them right back out.
+        event_bus_manager.enqueue(hit(rat, player));
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(world.get::<&Sleeping>(player).is_ok());
+
+        event_bus_manager.enqueue(hit(player, player));
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(world.get::<&Sleeping>(player).is_err());
+
+        // Some sleep is too deep to be woken from.
+        world
+            .insert_one(
+                player,
+                Sleeping {
+                    remaining_turns: 3,
+                    wake_on_damage: false,
+                },
+            )
+            .unwrap();
+        event_bus_manager.enqueue(hit(player, player));
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(world.get::<&Sleeping>(player).is_ok());
+    }
+
+    #[test]
+    fn test_goblin_goes_after_rat_when_player_is_out_of_sight() {
+        let mut world = World::new();
+        // Close enough for the goblin to be thinking, too far for it to see.
+        let player = spawn_player(&mut world, Position::new(14, 5));
+        let mut rng = GameRng::seeded(3);
+        let goblin = spawn_goblin_at(&mut world, Position::new(5, 5), &mut rng);
+        let rat = spawn_rat_at(&mut world, Position::new(6, 5), &mut rng);
+        world.spawn((rng,));
+        let rat_health = world.get::<&Health>(rat).unwrap().current_health();
+
+        // One turn to notice, one to attack.
+        run_ai_turns(&mut world, player, 2);
+        assert_eq!(world.get::<&Ai>(goblin).unwrap().curr_state, AiState::Angry);
+        assert!(world.get::<&Health>(rat).unwrap().current_health() < rat_health);
+        assert_eq!(world.get::<&Health>(player).unwrap().current_health(), 15);
+    }
+
+    #[test]
+    fn test_fleeing_goblin_keeps_away_from_other_threats_too() {
+        // Running straight away from the rat next to it is a toss-up between going up or down, so
+        // where the other rat is decides it.
+        let flee_with_other_rat_at = |other_rat: Position| {
+            let mut world = World::new();
+            // Close enough for the goblin to be thinking, too far for it to see.
+            let player = spawn_player(&mut world, Position::new(10, 2));
+            let mut rng = GameRng::seeded(3);
+            let goblin = spawn_goblin_at(&mut world, Position::new(10, 10), &mut rng);
+            world.get::<&mut Ai>(goblin).unwrap().curr_state = AiState::Afraid;
+            for pos in [Position::new(9, 10), other_rat] {
+                let rat = spawn_rat_at(&mut world, pos, &mut rng);
+                // Just standing there being scary.
+                world.remove_one::<Ai>(rat).unwrap();
+            }
+            world.spawn((rng,));
+
+            run_ai_turns(&mut world, player, 1);
+            (*world.get::<&Position>(goblin).unwrap()).clone()
+        };
+
+        assert_eq!(
+            flee_with_other_rat_at(Position::new(13, 13)),
+            Position::new(11, 9)
+        );
+        assert_eq!(
+            flee_with_other_rat_at(Position::new(13, 7)),
+            Position::new(11, 11)
+        );
+    }
+
+    #[test]
+    fn test_opposing_factions_fight_and_allies_dont() {
+        let mut world = World::new();
+        // Close enough for everyone to be thinking, too far for anyone to see.
+        let player = spawn_player(&mut world, Position::new(12, 5));
+        let mut rng = GameRng::seeded(3);
+        let rat = spawn_rat_at(&mut world, Position::new(5, 5), &mut rng);
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut rng);
+        // Out of sight of the other two.
+        let allies = [
+            spawn_goblin_at(&mut world, Position::new(5, 14), &mut rng),
+            spawn_goblin_at(&mut world, Position::new(6, 14), &mut rng),
+        ];
+        world.spawn((rng,));
+
+        run_ai_turns(&mut world, player, 1);
+        for (id, other) in [(rat, goblin), (goblin, rat)] {
+            let ai = world.get::<&Ai>(id).unwrap();
+            assert_eq!(ai.curr_state, AiState::Angry);
+            assert_eq!(
+                ai.last_seen.as_ref(),
+                Some(&*world.get::<&Position>(other).unwrap())
+            );
+        }
+        for id in allies {
+            let ai = world.get::<&Ai>(id).unwrap();
+            assert_eq!(ai.curr_state, AiState::Idling);
+            assert_eq!(ai.last_seen, None);
+        }
+    }
+
+    /// A player at (5, 5) who can only see the 5 by 5 square around them, with something logging
+    /// whatever they perceive.
+    fn perceiving_player() -> (World, EventBusManager, Entity) {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(PerceptionHandler));
+        event_bus_manager.subscribe(Arc::new(DeathLogHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        let player_pos = Position::new(5, 5);
+        let player = spawn_player(&mut world, player_pos.clone());
+        let visible = (3..=7)
+            .flat_map(|x| (3..=7).map(move |y| Position::new(x, y)))
+            .collect();
+        world
+            .get::<&mut Fov>(player)
+            .unwrap()
+            .update(&player_pos, 0, visible);
+        (world, event_bus_manager, player)
+    }
+
+    fn fight_at(x: isize, y: isize) -> PerceivedMessage {
+        PerceivedMessage {
+            at: Position::new(x, y),
+            seen: "The Goblin attacks Rat for 2 damage!".to_string(),
+            heard: "You hear fighting nearby.".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_only_perceived_fights_get_logged() {
+        let (mut world, event_bus_manager, _player) = perceiving_player();
+
+        event_bus_manager.enqueue(fight_at(6, 4));
+        event_bus_manager.enqueue(fight_at(12, 5));
+        // Way too far off to hear.
+        event_bus_manager.enqueue(fight_at(30, 30));
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(
+            game_log(&world),
+            vec![
+                "The Goblin attacks Rat for 2 damage!",
+                "You hear fighting nearby.",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hearing_the_same_fight_collapses_each_turn() {
+        let (mut world, event_bus_manager, _player) = perceiving_player();
+        take_turn(&mut world);
+        for x in [10, 11, 12] {
+            event_bus_manager.enqueue(fight_at(x, 5));
+        }
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(game_log(&world), vec!["You hear fighting nearby."]);
+
+        take_turn(&mut world);
+        event_bus_manager.enqueue(fight_at(10, 5));
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(game_log(&world), vec!["You hear fighting nearby. (x2)"]);
+    }
+
+    #[test]
+    fn test_deaths_get_logged_when_perceived() {
+        let (mut world, event_bus_manager, _player) = perceiving_player();
+        let mut rng = GameRng::seeded(1);
+        let seen = spawn_goblin_at(&mut world, Position::new(4, 4), &mut rng);
+        let heard = spawn_goblin_at(&mut world, Position::new(5, 12), &mut rng);
+        let unknown = spawn_goblin_at(&mut world, Position::new(40, 30), &mut rng);
+
+        for entity in [seen, heard, unknown] {
+            event_bus_manager.enqueue(DeadEntity { entity });
+        }
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(
+            game_log(&world),
+            vec!["The Goblin dies.", "You hear something die nearby."]
+        );
+    }
+
+    /// A troll right next to the player, who it hasn't noticed yet.
+    fn troll_next_to_player() -> (World, Entity, Entity) {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(3);
+        let troll = spawn_troll(&mut world, Position::new(6, 5), &mut rng);
+        world.spawn((rng,));
+        (world, player, troll)
+    }
+
+    #[test]
+    fn test_troll_winds_up_before_hitting() {
+        let (mut world, player, troll) = troll_next_to_player();
+        let full_health = world.get::<&Health>(player).unwrap().current_health();
+
+        // One turn to notice, one to wind up.
+        run_ai_turns(&mut world, player, 2);
+        assert_eq!(
+            *world.get::<&Telegraph>(troll).unwrap(),
+            Telegraph {
+                turns_remaining: 1,
+                pending: Action::Attack(Position::new(5, 5)),
+            }
+        );
+        assert_eq!(
+            world.get::<&Health>(player).unwrap().current_health(),
+            full_health
+        );
+
+        run_ai_turns(&mut world, player, 1);
+        assert!(world.get::<&Telegraph>(troll).is_err());
+        assert!(world.get::<&Health>(player).unwrap().current_health() < full_health);
+    }
+
+    #[test]
+    fn test_stepping_away_from_a_windup_makes_it_miss() {
+        let (mut world, player, troll) = troll_next_to_player();
+        let full_health = world.get::<&Health>(player).unwrap().current_health();
+        run_ai_turns(&mut world, player, 2);
+        assert!(world.get::<&Telegraph>(troll).is_ok());
+
+        let spatial_index_id = find_or_build_spatial_index(&mut world);
+        world
+            .get::<&mut SpatialIndex>(spatial_index_id)
+            .unwrap()
+            .move_entity(player, &Position::new(4, 5));
+        *world.get::<&mut Position>(player).unwrap() = Position::new(4, 5);
+
+        run_ai_turns(&mut world, player, 1);
+        assert!(world.get::<&Telegraph>(troll).is_err());
+        assert_eq!(
+            world.get::<&Health>(player).unwrap().current_health(),
+            full_health
+        );
+    }
+
+    #[test]
+    fn test_getting_hit_can_interrupt_a_windup() {
+        let (mut world, player, troll) = troll_next_to_player();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(WindupInterruptHandler));
+        run_ai_turns(&mut world, player, 2);
+        let hit = || Damage {
+            from: player,
+            to: troll,
+            damage: 1,
+            kind: DamageKind::Physical,
+        };
+
+        world.get::<&mut Windup>(troll).unwrap().interrupt_chance = 0.0;
+        event_bus_manager.enqueue(hit());
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(world.get::<&Telegraph>(troll).is_ok());
+
+        world.get::<&mut Windup>(troll).unwrap().interrupt_chance = 1.0;
+        event_bus_manager.enqueue(hit());
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(world.get::<&Telegraph>(troll).is_err());
+    }
+
+    #[test]
+    fn test_goblins_never_attack_each_other() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(17, 5));
+        let mut rng = GameRng::seeded(3);
+        let goblins = [
+            spawn_goblin_at(&mut world, Position::new(5, 5), &mut rng),
+            spawn_goblin_at(&mut world, Position::new(6, 5), &mut rng),
+        ];
+        world.spawn((rng,));
+        let health: Vec<i32> = goblins
+            .iter()
+            .map(|goblin| world.get::<&Health>(*goblin).unwrap().current_health())
+            .collect();
+
+        // Long enough that they would've come to blows by now.
+        run_ai_turns(&mut world, player, 2);
+        for (goblin, health) in goblins.iter().zip(health) {
+            assert_eq!(
+                world.get::<&Ai>(*goblin).unwrap().curr_state,
+                AiState::Idling
+            );
+            assert_eq!(
+                world.get::<&Health>(*goblin).unwrap().current_health(),
+                health
+            );
+        }
+    }
+
+    #[test]
+    fn test_natural_regen_waits_until_out_of_combat() {
+        let mut world = World::new();
+        let mut health = Health::new(15);
+        health.apply_damage(5);
+        let player = world.spawn((health, NaturalRegen::new(3)));
+
+        let healed_on = run_natural_regen(&mut world, player, 20);
+        assert_eq!(healed_on, vec![13, 16, 19]);
+        assert_eq!(world.get::<&Health>(player).unwrap().current_health(), 13);
+    }
+
+    #[test]
+    fn test_natural_regen_resets_on_damage() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe(Arc::new(NaturalRegenResetHandler));
+        let mut health = Health::new(15);
+        health.apply_damage(5);
+        let player = world.spawn((health, NaturalRegen::new(1)));
+        let goblin = world.spawn((Health::new(5),));
+
+        assert_eq!(run_natural_regen(&mut world, player, 11), vec![11]);
+
+        event_bus_manager.enqueue(Damage {
+            from: goblin,
+            to: player,
+            damage: 2,
+            kind: DamageKind::Physical,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(
+            world
+                .get::<&NaturalRegen>(player)
+                .unwrap()
+                .turns_since_damage,
+            0
+        );
+
+        // Back to waiting out the full cooldown.
+        assert_eq!(run_natural_regen(&mut world, player, 12), vec![11, 12]);
+        assert_eq!(world.get::<&Health>(player).unwrap().current_health(), 11);
+
+        // Damage to something else doesn't count.
+        event_bus_manager.enqueue(Damage {
+            from: player,
+            to: goblin,
+            damage: 2,
+            kind: DamageKind::Physical,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(run_natural_regen(&mut world, player, 1), vec![1]);
+    }
+
+    #[test]
+    fn test_natural_regen_clamps_and_never_revives() {
+        let mut full_health = Health::new(15);
+        let mut regen = NaturalRegen::new(1);
+        regen.turns_since_damage = OUT_OF_COMBAT_TURNS;
+        assert!(!natural_regen(&full_health, &mut regen));
+        assert_eq!(full_health.current_health(), 15);
+
+        let mut dead = Health::new(15);
+        dead.apply_damage(15);
+        assert!(!natural_regen(&dead, &mut regen));
+        assert_eq!(dead.current_health(), 0);
+    }
+
+    #[test]
+    fn test_confused_player_stumbles_around() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(10, 10));
+        world
+            .insert_one(player, Confused { remaining_turns: 10 })
+            .unwrap();
+        world.spawn((GameRng::seeded(3),));
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        let mut steps = HashSet::new();
+        for _ in 0..8 {
+            let before = (*world.get::<&Position>(player).unwrap()).clone();
+            input_system
+                .call(
+                    &mut world,
+                    &MockInput::pressing("ArrowRight"),
+                    &mut event_bus_manager,
+                )
+                .unwrap();
+            let after = (*world.get::<&Position>(player).unwrap()).clone();
+            let step = (after.x - before.x, after.y - before.y);
+            assert!(step != (0, 0) && step.0.abs() <= 1 && step.1.abs() <= 1);
+            steps.insert(step);
+        }
+        assert!(steps.len() > 1);
+    }
+
+    #[test]
+    fn test_sleeping_player_cannot_move() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        world
+            .insert_one(
+                player,
+                Sleeping {
+                    remaining_turns: 3,
+                    wake_on_damage: true,
+                },
+            )
+            .unwrap();
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &MockInput::pressing("ArrowRight"),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        assert_eq!(
+            *world.get::<&Position>(player).unwrap(),
+            Position::new(5, 5)
+        );
+        // The turn still goes by, so everything else gets to act.
+        assert!(was_input_handled_this_frame(&world, player));
+    }
+
+    #[test]
+    fn test_confusion_wears_off() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        world
+            .insert_one(player, Confused { remaining_turns: 2 })
+            .unwrap();
+        let mut status_effect_system = StatusEffectSystem::default();
+        status_effect_system.init(&mut world, &mut event_bus_manager);
+
+        for remaining_turns in [1, 0] {
+            take_turn(&mut world);
+            status_effect_system
+                .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+            assert_eq!(
+                world.get::<&Confused>(player).ok().map(|c| c.remaining_turns),
+                (remaining_turns > 0).then_some(remaining_turns)
+            );
+        }
+    }
+
+    #[test]
+    fn test_stat_modifiers_count_until_they_run_out() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut GameRng::seeded(1));
+        let stone_skin = StatModifier {
+            target_stat: StatKind::Defense,
+            additive: 1,
+            multiplicative: 1.0,
+            remaining_turns: Some(1),
+            source_id: 7,
+        };
+        let haste = StatModifier {
+            target_stat: StatKind::Speed,
+            additive: 0,
+            multiplicative: 2.0,
+            ..stone_skin.clone()
+        };
+        world
+            .insert_one(
+                goblin,
+                StatModifiers {
+                    active: vec![stone_skin, haste],
+                },
+            )
+            .unwrap();
+        let mut apply_stat_modifiers_system = ApplyStatModifiersSystem::default();
+        let mut status_effect_system = StatusEffectSystem::default();
+        status_effect_system.init(&mut world, &mut event_bus_manager);
+
+        apply_stat_modifiers_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(
+            world
+                .get::<&EffectiveStats>(goblin)
+                .unwrap()
+                .get(StatKind::Defense),
+            1
+        );
+        assert_eq!(
+            melee_damage(&world, player, goblin, PLAYER_BASE_DAMAGE),
+            PLAYER_BASE_DAMAGE - 1
+        );
+        assert_eq!(modified_speed_of(&world, goblin), EntitySpeed::new(20));
+
+        take_turn(&mut world);
+        status_effect_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        apply_stat_modifiers_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert!(world.get::<&StatModifiers>(goblin).is_err());
+        assert!(world.get::<&EffectiveStats>(goblin).is_err());
+        assert_eq!(
+            melee_damage(&world, player, goblin, PLAYER_BASE_DAMAGE),
+            PLAYER_BASE_DAMAGE
+        );
+        assert_eq!(modified_speed_of(&world, goblin), EntitySpeed::default());
+    }
+
+    #[test]
+    fn test_necromancer_aura_only_hurts_its_enemies() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(1);
+        spawn_necromancer(&mut world, Position::new(7, 5), &mut rng);
+        let goblin = spawn_goblin_at(&mut world, Position::new(9, 6), &mut rng);
+        let health_of = |world: &World, id| world.get::<&Health>(id).unwrap().current_health();
+        let before = [player, goblin].map(|id| health_of(&world, id));
+        let mut aura_system = AuraSystem::default();
+        aura_system.init(&mut world, &mut event_bus_manager);
+
+        // Nothing until a turn goes by.
+        aura_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(event_bus_manager.queued_len(), 0);
+
+        take_turn(&mut world);
+        aura_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(health_of(&world, player), before[0] - 1);
+        // Close enough, but on its side.
+        assert_eq!(health_of(&world, goblin), before[1]);
+    }
+
+    #[test]
+    fn test_paladin_heals_its_side_on_its_own_turns() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(1);
+        spawn_paladin(&mut world, Position::new(8, 5), &mut rng);
+        let goblin = spawn_goblin_at(&mut world, Position::new(9, 7), &mut rng);
+        world.spawn((rng,));
+        for id in [player, goblin] {
+            world.get::<&mut Health>(id).unwrap().apply_damage(3);
+        }
+        let health_of = |world: &World, id| world.get::<&Health>(id).unwrap().current_health();
+        let before = [player, goblin].map(|id| health_of(&world, id));
+
+        run_ai_turns(&mut world, player, 1);
+        assert_eq!(health_of(&world, goblin), before[1] + 2);
+        assert_eq!(health_of(&world, player), before[0]);
+    }
+
+    #[test]
+    fn test_paladin_aura_does_not_bring_back_the_dead() {
+        let mut world = World::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(1);
+        spawn_paladin(&mut world, Position::new(8, 5), &mut rng);
+        let goblin = spawn_goblin_at(&mut world, Position::new(9, 7), &mut rng);
+        world.spawn((rng,));
+        // Killed, but still lying there waiting on DeathSystem.
+        world.get::<&mut Health>(goblin).unwrap().apply_damage(1000);
+
+        run_ai_turns(&mut world, player, 2);
+        assert!(world.get::<&Health>(goblin).unwrap().is_dead());
+    }
+
+    #[test]
+    fn test_burning_hurts_every_turn_until_it_goes_out() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        spawn_player(&mut world, Position::new(5, 5));
+        let goblin = spawn_goblin_at(&mut world, Position::new(2, 2), &mut GameRng::seeded(1));
+        let fireproof = spawn_goblin_at(&mut world, Position::new(3, 3), &mut GameRng::seeded(1));
+        world
+            .insert_one(fireproof, Resistances::new([(DamageKind::Fire, 0.0)]))
+            .unwrap();
+        for id in [goblin, fireproof] {
+            world
+                .insert_one(
+                    id,
+                    Burning {
+                        damage_per_turn: 2,
+                        remaining_turns: 2,
+                    },
+                )
+                .unwrap();
+        }
+        let health = |world: &World, id| world.get::<&Health>(id).unwrap().current_health();
+        let full_health = health(&world, goblin);
+        let mut status_effect_system = StatusEffectSystem::default();
+        status_effect_system.init(&mut world, &mut event_bus_manager);
+
+        // The fireproof one doesn't take so much as a zero damage hit.
+        for hits in [1, 1, 0] {
+            take_turn(&mut world);
+            status_effect_system
+                .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+            assert_eq!(event_bus_manager.queued_len_of::<Damage>(), hits);
+            event_bus_manager.dispatch_all(&mut world);
+        }
+        assert_eq!(health(&world, goblin), full_health - 4);
+        assert!(world.get::<&Burning>(goblin).is_err());
+        assert_eq!(health(&world, fireproof), full_health);
+    }
+
+    #[test]
+    fn test_dagger_cuts_stack_up_and_bleed_out() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe(Arc::new(BleedHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let dagger = spawn_item(&mut world, Position::new(5, 5), ItemKind::Dagger);
+        equip(&mut world, player, dagger).unwrap();
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut GameRng::seeded(1));
+        *world.get::<&mut Health>(goblin).unwrap() = Health::new(30);
+        let slash = |damage| Damage {
+            from: player,
+            to: goblin,
+            damage,
+            kind: DamageKind::Physical,
+        };
+
+        // A hit that doesn't get through doesn't cut anything.
+        for damage in [2, 2, 0] {
+            event_bus_manager.enqueue(slash(damage));
+            event_bus_manager.dispatch_all(&mut world);
+        }
+        assert_eq!(
+            *world.get::<&Bleeding>(goblin).unwrap(),
+            Bleeding {
+                stacks: 2,
+                damage_per_stack: 1,
+                duration_turns: 3,
+            }
+        );
+        assert_eq!(
+            game_log(&world),
+            vec!["The Goblin bleeds! (×1)", "The Goblin bleeds! (×2)"]
+        );
+
+        let mut status_effect_system = StatusEffectSystem::default();
+        status_effect_system.init(&mut world, &mut event_bus_manager);
+        let health = |world: &World| world.get::<&Health>(goblin).unwrap().current_health();
+        for expected in [24, 22, 20] {
+            take_turn(&mut world);
+            status_effect_system
+                .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+            event_bus_manager.dispatch_all(&mut world);
+            assert_eq!(health(&world), expected);
+        }
+        assert!(world.get::<&Bleeding>(goblin).is_err());
+        // Bleeding doesn't open up any more wounds of its own.
+        assert_eq!(game_log(&world)[2..], ["The Goblin stops bleeding."]);
+    }
+
+    #[test]
+    fn test_bandage_stops_the_bleeding() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(BandageHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+        let mut bandage = |world: &mut World| {
+            input_system
+                .call(world, &MockInput::pressing("KeyB"), &mut event_bus_manager)
+                .unwrap();
+            event_bus_manager.dispatch_all(world);
+            was_input_handled_this_frame(world, player)
+        };
+
+        assert!(!bandage(&mut world));
+        assert_eq!(
+            game_log(&world).last().unwrap(),
+            "You don't have any bandages."
+        );
+
+        world
+            .get::<&mut Inventory>(player)
+            .unwrap()
+            .items
+            .push(ItemKind::Bandage);
+        assert!(!bandage(&mut world));
+        assert_eq!(game_log(&world).last().unwrap(), "You're not bleeding.");
+
+        world
+            .insert_one(
+                player,
+                Bleeding {
+                    stacks: 4,
+                    damage_per_stack: 1,
+                    duration_turns: 5,
+                },
+            )
+            .unwrap();
+        assert!(bandage(&mut world));
+        assert!(world.get::<&Bleeding>(player).is_err());
+        assert!(
+            !world
+                .get::<&Inventory>(player)
+                .unwrap()
+                .items
+                .contains(&ItemKind::Bandage)
+        );
+    }
+
+    #[test]
+    fn test_walking_into_fire_sets_things_alight() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let map = Map::new_bordered(10, 10);
+        let mut burning_tiles = BurningTiles::default();
+        for pos in [Position::new(2, 2), Position::new(3, 3)] {
+            burning_tiles.ignite(&map, pos);
+        }
+        world.spawn((map,));
+        world.spawn((burning_tiles,));
+        world.spawn((GameRng::seeded(1),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let goblin = spawn_goblin_at(&mut world, Position::new(2, 2), &mut GameRng::seeded(1));
+        let fireproof = spawn_goblin_at(&mut world, Position::new(3, 3), &mut GameRng::seeded(1));
+        world
+            .insert_one(fireproof, Resistances::new([(DamageKind::Fire, 0.0)]))
+            .unwrap();
+        let mut fire_spread_system = FireSpreadSystem::default();
+        fire_spread_system.init(&mut world, &mut event_bus_manager);
+
+        fire_spread_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        // Nothing happens until a turn goes by.
+        assert!(world.get::<&Burning>(goblin).is_err());
+
+        take_turn(&mut world);
+        fire_spread_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(
+            *world.get::<&Burning>(goblin).unwrap(),
+            Burning {
+                damage_per_turn: FIRE_DAMAGE,
+                remaining_turns: FIRE_TURNS,
+            }
+        );
+        assert!(world.get::<&Burning>(fireproof).is_err());
+        assert!(world.get::<&Burning>(player).is_err());
+    }
+
+    #[test]
+    fn test_input_system_uses_key_bindings() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut key_bindings = KeyBindings::default();
+        key_bindings.rebind(KeyAction::MoveLeft, "KeyA");
+        world.spawn((key_bindings,));
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &MockInput::pressing("ArrowLeft"),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        assert_eq!(
+            *world.get::<&Position>(player).unwrap(),
+            Position::new(5, 5)
+        );
+
+        input_system
+            .call(
+                &mut world,
+                &MockInput::pressing("KeyA"),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        assert_eq!(
+            *world.get::<&Position>(player).unwrap(),
+            Position::new(4, 5)
+        );
+    }
+
+    fn ui_mode(world: &World) -> UiMode {
+        world
+            .query::<&UiMode>()
+            .iter()
+            .next()
+            .map(|(_id, mode)| mode.clone())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_targeting_mode_picks_a_target() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut targeting_system = TargetingSystem::default();
+        targeting_system.init(&mut world, &mut event_bus_manager);
+
+        let mut press = |key: &str, world: &mut World| {
+            targeting_system
+                .call(world, &MockInput::pressing(key), &mut event_bus_manager)
+                .unwrap();
+        };
+        press("KeyT", &mut world);
+        assert_eq!(
+            ui_mode(&world),
+            UiMode::Targeting {
+                max_range: TARGETING_RANGE,
+                purpose: TargetPurpose::Throw,
+                cursor: Position::new(5, 5),
+            }
+        );
+
+        press("ArrowRight", &mut world);
+        press("ArrowRight", &mut world);
+        assert!(matches!(
+            ui_mode(&world),
+            UiMode::Targeting { cursor, .. } if cursor == Position::new(7, 5)
+        ));
+        assert!(!was_input_handled_this_frame(&world, player));
+
+        press("Enter", &mut world);
+        assert_eq!(ui_mode(&world), UiMode::Normal);
+        assert!(was_input_handled_this_frame(&world, player));
+        assert_eq!(event_bus_manager.queued_len_of::<TargetSelected>(), 1);
+    }
+
+    #[test]
+    fn test_targeting_mode_can_be_cancelled() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        spawn_player(&mut world, Position::new(5, 5));
+        let mut targeting_system = TargetingSystem::default();
+        targeting_system.init(&mut world, &mut event_bus_manager);
+
+        for key in ["KeyT", "ArrowUp", "Escape"] {
+            targeting_system
+                .call(
+                    &mut world,
+                    &MockInput::pressing(key),
+                    &mut event_bus_manager,
+                )
+                .unwrap();
+        }
+        assert_eq!(ui_mode(&world), UiMode::Normal);
+        assert_eq!(event_bus_manager.queued_len_of::<TargetSelected>(), 0);
+    }
+
+    #[test]
+    fn test_examine_mode() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut targeting_system = TargetingSystem::default();
+        targeting_system.init(&mut world, &mut event_bus_manager);
+
+        for key in ["KeyX", "ArrowDown", "ArrowLeft"] {
+            targeting_system
+                .call(
+                    &mut world,
+                    &MockInput::pressing(key),
+                    &mut event_bus_manager,
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            ui_mode(&world),
+            UiMode::Examining {
+                cursor: Position::new(4, 6)
+            }
+        );
+        assert!(!was_input_handled_this_frame(&world, player));
+
+        targeting_system
+            .call(
+                &mut world,
+                &MockInput::pressing("KeyX"),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        assert_eq!(ui_mode(&world), UiMode::Normal);
+    }
+
+    #[test]
+    fn test_message_log_viewer_scrolls() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut game_log = GameLog::default();
+        let page_lines = GameLog::viewer_page_lines(GameConfig::default().console_height as usize);
+        for i in 0..page_lines + 10 {
+            game_log.push(format!("Message {i}"));
+        }
+        world.spawn((game_log,));
+        let mut targeting_system = TargetingSystem::default();
+        targeting_system.init(&mut world, &mut event_bus_manager);
+        let mut press = |key: &str, world: &mut World| {
+            targeting_system
+                .call(world, &MockInput::pressing(key), &mut event_bus_manager)
+                .unwrap();
+        };
+
+        press("KeyL", &mut world);
+        assert_eq!(ui_mode(&world), UiMode::ReadingLog { scroll: 0 });
+        press("ArrowDown", &mut world);
+        assert_eq!(ui_mode(&world), UiMode::ReadingLog { scroll: 0 });
+        press("ArrowUp", &mut world);
+        press("ArrowUp", &mut world);
+        assert_eq!(ui_mode(&world), UiMode::ReadingLog { scroll: 2 });
+        // Can't go back any further than the oldest message.
+        press("PageUp", &mut world);
+        assert_eq!(ui_mode(&world), UiMode::ReadingLog { scroll: 10 });
+        press("PageDown", &mut world);
+        assert_eq!(ui_mode(&world), UiMode::ReadingLog { scroll: 0 });
+        assert!(!was_input_handled_this_frame(&world, player));
+
+        press("Escape", &mut world);
+        assert_eq!(ui_mode(&world), UiMode::Normal);
+    }
+
+    #[test]
+    fn test_minimap_key_toggles_it() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut targeting_system = TargetingSystem::default();
+        targeting_system.init(&mut world, &mut event_bus_manager);
+        let shown = |world: &mut World| {
+            let (_id, minimap) = world.query_mut::<&Minimap>().into_iter().next().unwrap();
+            minimap.shown
+        };
+        assert!(!shown(&mut world));
+
+        for expected in [true, false] {
+            targeting_system
+                .call(
+                    &mut world,
+                    &MockInput::pressing("KeyM"),
+                    &mut event_bus_manager,
+                )
+                .unwrap();
+            assert_eq!(shown(&mut world), expected);
+            assert_eq!(ui_mode(&world), UiMode::Normal);
+            assert!(!was_input_handled_this_frame(&world, player));
+        }
+    }
+
+    #[test]
+    fn test_cannot_throw_without_rocks() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        world.get::<&mut Inventory>(player).unwrap().items.clear();
+        let mut targeting_system = TargetingSystem::default();
+        targeting_system.init(&mut world, &mut event_bus_manager);
+
+        targeting_system
+            .call(
+                &mut world,
+                &MockInput::pressing("KeyT"),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        assert_eq!(ui_mode(&world), UiMode::Normal);
+    }
+
+    #[test]
+    fn test_thrown_rock_hits_whatever_is_there() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(ThrowHandler));
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let goblin = spawn_goblin_at(&mut world, Position::new(8, 5), &mut GameRng::seeded(1));
+        find_or_build_spatial_index(&mut world);
+        let starting_health = world.get::<&Health>(goblin).unwrap().current_health();
+
+        event_bus_manager.enqueue(TargetSelected {
+            position: Position::new(8, 5),
+            purpose: TargetPurpose::Throw,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert!(world.get::<&Health>(goblin).unwrap().current_health() < starting_health);
+        assert_eq!(
+            world.get::<&Inventory>(player).unwrap().items.len(),
+            STARTING_ROCKS - 1
+        );
+        // The rock ends up on the floor under the goblin.
+        assert_eq!(
+            world
+                .query::<(&Position, &ItemKind)>()
+                .iter()
+                .filter(|(_id, (pos, _item))| **pos == Position::new(8, 5))
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_firing_hits_whatever_is_at_the_cursor() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(FireHandler));
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let goblin = spawn_goblin_at(&mut world, Position::new(7, 5), &mut GameRng::seeded(1));
+        find_or_build_spatial_index(&mut world);
+        let starting_health = world.get::<&Health>(goblin).unwrap().current_health();
+        let mut targeting_system = TargetingSystem::default();
+        targeting_system.init(&mut world, &mut event_bus_manager);
+
+        for key in ["KeyF", "ArrowRight", "ArrowRight", "Enter"] {
+            targeting_system
+                .call(
+                    &mut world,
+                    &MockInput::pressing(key),
+                    &mut event_bus_manager,
+                )
+                .unwrap();
+        }
+        assert_eq!(ui_mode(&world), UiMode::Normal);
+        assert!(was_input_handled_this_frame(&world, player));
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert_eq!(
+            world.get::<&Health>(goblin).unwrap().current_health(),
+            starting_health - PLAYER_BASE_DAMAGE
+        );
+        // Nothing gets used up firing.
+        assert_eq!(
+            world.get::<&Inventory>(player).unwrap().items.len(),
+            STARTING_ROCKS
+        );
+    }
+
+    /// Keeps everything it's given so tests can look at it after the sink is swapped out.
+    #[derive(Default, Clone)]
+    struct RecordingSink {
+        played: Arc<Mutex<Vec<AudioEvent>>>,
+    }
+
+    impl AudioSink for RecordingSink {
+        fn play(&mut self, event: &AudioEvent) {
+            self.played.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_damage_plays_a_hit_sound() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe(Arc::new(AudioDispatchHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let goblin = spawn_goblin_at(&mut world, Position::new(8, 9), &mut GameRng::seeded(1));
+        let sink = RecordingSink::default();
+        world.spawn((AudioOutput::new(Box::new(sink.clone())),));
+
+        event_bus_manager.enqueue(Damage {
+            from: player,
+            to: goblin,
+            damage: 1,
+            kind: DamageKind::Physical,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+
+        let played = sink.played.lock().unwrap();
+        assert_eq!(played.len(), 1);
+        assert_eq!(played[0].kind, AudioKind::Hit);
+        assert_eq!(played[0].at, Some(Position::new(8, 9)));
+        assert_eq!(played[0].distance, Some(5.0));
+    }
+
+    #[test]
+    fn test_swapping_audio_sinks() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(AudioDispatchHandler));
+        let first = RecordingSink::default();
+        let second = RecordingSink::default();
+        let audio_output = world.spawn((AudioOutput::new(Box::new(first.clone())),));
+
+        event_bus_manager.enqueue(AudioEvent::new(AudioKind::Footstep, None));
+        event_bus_manager.dispatch_all(&mut world);
+        world
+            .get::<&mut AudioOutput>(audio_output)
+            .unwrap()
+            .set_sink(Box::new(second.clone()));
+        event_bus_manager.enqueue(AudioEvent::new(AudioKind::Death, None));
+        event_bus_manager.dispatch_all(&mut world);
+
+        let first_played = first.played.lock().unwrap();
+        assert_eq!(first_played.len(), 1);
+        assert_eq!(first_played[0].kind, AudioKind::Footstep);
+        let second_played = second.played.lock().unwrap();
+        assert_eq!(second_played.len(), 1);
+        assert_eq!(second_played[0].kind, AudioKind::Death);
+        // Nobody's around to hear it.
+        assert_eq!(second_played[0].distance, None);
+    }
+
+    #[test]
+    fn test_stepping_on_a_trap_sets_it_off() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(TrapHandler));
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let trap = spawn_trap(
+            &mut world,
+            Position::new(6, 5),
+            Trap::new(TrapType::Spike(4), true, false),
+        );
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Move { dx: 1, dy: 0 }),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        assert_eq!(event_bus_manager.queued_len_of::<EntityMoved>(), 1);
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert!(world.get::<&Trap>(trap).unwrap().is_visible());
+        let health = world.get::<&Health>(player).unwrap();
+        assert_eq!(health.current_health(), health.total_health() as i32 - 4);
+        let mut game_log_query = world.query::<&GameLog>();
+        let (_id, game_log) = game_log_query.iter().next().unwrap();
+        assert_eq!(
+            game_log.lines().collect::<Vec<_>>(),
+            vec!["You step on a spike trap!"]
+        );
+    }
+
+    #[test]
+    fn test_single_use_traps_are_used_up() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(TrapHandler));
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        let pos = Position::new(3, 3);
+        let single_use = spawn_trap(
+            &mut world,
+            pos.clone(),
+            Trap::new(TrapType::Spike(2), true, true),
+        );
+        let goblin = spawn_goblin_at(&mut world, pos.clone(), &mut GameRng::seeded(1));
+        let full_health = world.get::<&Health>(goblin).unwrap().current_health();
+        let stepped_on = || EntityMoved {
+            entity: goblin,
+            from: Position::new(2, 3),
+            to: pos.clone(),
+        };
+
+        event_bus_manager.enqueue(stepped_on());
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(!world.contains(single_use));
+        event_bus_manager.enqueue(stepped_on());
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(
+            world.get::<&Health>(goblin).unwrap().current_health(),
+            full_health - 2
+        );
+    }
+
+    #[test]
+    fn test_reusable_traps_keep_going_off() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(TrapHandler));
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        let pos = Position::new(3, 3);
+        let reusable = spawn_trap(
+            &mut world,
+            pos.clone(),
+            Trap::new(TrapType::Spike(2), false, false),
+        );
+        let goblin = spawn_goblin_at(&mut world, pos.clone(), &mut GameRng::seeded(1));
+        let full_health = world.get::<&Health>(goblin).unwrap().current_health();
+
+        for _ in 0..2 {
+            event_bus_manager.enqueue(EntityMoved {
+                entity: goblin,
+                from: Position::new(2, 3),
+                to: pos.clone(),
+            });
+            event_bus_manager.dispatch_all(&mut world);
+        }
+        assert!(world.contains(reusable));
+        assert_eq!(
+            world.get::<&Health>(goblin).unwrap().current_health(),
+            full_health - 4
+        );
+    }
+
+    #[test]
+    fn test_poison_trap_poisons() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(TrapHandler));
+        let pos = Position::new(3, 3);
+        spawn_trap(
+            &mut world,
+            pos.clone(),
+            Trap::new(TrapType::Poison(2, 3), false, true),
+        );
+        // Standing on a trap doesn't do anything, it's stepping onto it that sets it off.
+        spawn_trap(
+            &mut world,
+            Position::new(4, 3),
+            Trap::new(TrapType::Spike(4), false, true),
+        );
+        let player = spawn_player(&mut world, Position::new(4, 3));
+
+        event_bus_manager.enqueue(EntityMoved {
+            entity: player,
+            from: Position::new(4, 3),
+            to: pos,
+        });
+        assert_eq!(event_bus_manager.queued_len_of::<Damage>(), 0);
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(
+            world.get::<&Effects>(player).unwrap().active,
+            vec![Effect {
+                kind: EffectKind::Poison,
+                turns_remaining: 3,
+                magnitude: 2,
+            }]
+        );
+        assert_eq!(world.query::<&Trap>().iter().count(), 1);
+    }
+
+    #[test]
+    fn test_alarm_trap_wakes_monsters_in_earshot() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(TrapHandler));
+        event_bus_manager.subscribe(Arc::new(NoiseHandler));
+        let pos = Position::new(3, 3);
+        spawn_trap(
+            &mut world,
+            pos.clone(),
+            Trap::new(TrapType::Alarm, false, true),
+        );
+        let player = spawn_player(&mut world, pos.clone());
+        let mut rng = GameRng::seeded(1);
+        let near = spawn_goblin_at(&mut world, Position::new(6, 3), &mut rng);
+        let far = spawn_goblin_at(&mut world, Position::new(40, 3), &mut rng);
+
+        event_bus_manager.enqueue(EntityMoved {
+            entity: player,
+            from: Position::new(2, 3),
+            to: pos.clone(),
+        });
+        event_bus_manager.dispatch_all(&mut world);
+
+        let near_ai = world.get::<&Ai>(near).unwrap();
+        assert_eq!(near_ai.curr_state, AiState::Searching);
+        assert_eq!(near_ai.last_seen, Some(pos));
+        assert_eq!(world.get::<&Ai>(far).unwrap().curr_state, AiState::Idling);
+    }
+
+    #[test]
+    fn test_noticing_traps_without_setting_them_off() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        spawn_player(&mut world, Position::new(5, 5));
+        world.spawn((GameRng::seeded(3),));
+        let next_to = spawn_trap(
+            &mut world,
+            Position::new(6, 6),
+            Trap::new(TrapType::Spike(4), true, true),
+        );
+        let far_away = spawn_trap(
+            &mut world,
+            Position::new(8, 5),
+            Trap::new(TrapType::Spike(4), true, true),
+        );
+        let mut trap_system = TrapSystem::default();
+        trap_system.init(&mut world, &mut event_bus_manager);
+
+        // Nothing gets noticed while no turns are going by.
+        for _ in 0..100 {
+            trap_system
+                .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+        }
+        assert!(!world.get::<&Trap>(next_to).unwrap().is_visible());
+
+        for _ in 0..100 {
+            take_turn(&mut world);
+            trap_system
+                .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+        }
+        assert!(world.get::<&Trap>(next_to).unwrap().is_visible());
+        assert!(!world.get::<&Trap>(far_away).unwrap().is_visible());
+        assert_eq!(event_bus_manager.queued_len_of::<LogMessage>(), 1);
+        assert_eq!(event_bus_manager.queued_len_of::<Damage>(), 0);
+    }
+
+    #[test]
+    fn test_searching_finds_hidden_traps_next_to_player() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(SearchHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let next_to = spawn_trap(
+            &mut world,
+            Position::new(6, 6),
+            Trap::new(TrapType::Teleport, true, true),
+        );
+        let far_away = spawn_trap(
+            &mut world,
+            Position::new(8, 5),
+            Trap::new(TrapType::Alarm, true, true),
+        );
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Search),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert!(world.get::<&Trap>(next_to).unwrap().is_visible());
+        assert!(!world.get::<&Trap>(far_away).unwrap().is_visible());
+        // Searching takes a turn.
+        assert!(was_input_handled_this_frame(&world, player));
+        let mut game_log_query = world.query::<&GameLog>();
+        let (_id, game_log) = game_log_query.iter().next().unwrap();
+        assert_eq!(
+            game_log.lines().collect::<Vec<_>>(),
+            vec!["You find a teleport trap!"]
+        );
+    }
+
+    #[test]
+    fn test_wearing_a_dagger_makes_attacks_hit_harder() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe::<EquipItem>(Arc::new(EquipmentHandler));
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let dagger = spawn_item(&mut world, Position::new(5, 5), ItemKind::Dagger);
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut GameRng::seeded(1));
+        let full_health = world.get::<&Health>(goblin).unwrap().current_health();
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Wear),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(was_input_handled_this_frame(&world, player));
+        assert_eq!(world.get::<&Equipped>(dagger).unwrap().by, player);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Move { dx: 1, dy: 0 }),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(
+            world.get::<&Health>(goblin).unwrap().current_health(),
+            full_health - PLAYER_BASE_DAMAGE - 1
+        );
+
+        // Nothing left on the floor to put on, so no turn goes by.
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Wear),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        assert!(!was_input_handled_this_frame(&world, player));
+    }
+
+    #[test]
+    fn test_walking_into_closed_door_opens_it() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe::<DoorOpened>(Arc::new(DoorHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let door = spawn_door(&mut world, Position::new(6, 5), false);
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        let step_right = scripted(SimInput::Move { dx: 1, dy: 0 });
+        input_system
+            .call(&mut world, &step_right, &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(event_bus_manager.queued_len_of::<Damage>(), 0);
+        event_bus_manager.dispatch_all(&mut world);
+        // Opening it used up the turn.
+        assert!(was_input_handled_this_frame(&world, player));
+        assert_eq!(
+            *world.get::<&Position>(player).unwrap(),
+            Position::new(5, 5)
+        );
+        assert!(world.get::<&Door>(door).unwrap().open);
+        assert!(world.get::<&BlocksTile>(door).is_err());
+        assert_eq!(
+            world.get::<&Renderable>(door).unwrap().glyph,
+            Door::OPEN_GLYPH
+        );
+
+        input_system
+            .call(&mut world, &step_right, &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(
+            *world.get::<&Position>(player).unwrap(),
+            Position::new(6, 5)
+        );
+    }
+
+    #[test]
+    fn test_opening_a_door_without_walking_into_it() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe::<DoorOpened>(Arc::new(DoorHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let door = spawn_door(&mut world, Position::new(5, 4), false);
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        // Nothing there, so nothing happens.
+        let mut input = MockInput::default();
+        input.keys_down.insert("KeyO".to_string());
+        input.keys_down.insert("ArrowDown".to_string());
+        input_system
+            .call(&mut world, &input, &mut event_bus_manager)
+            .unwrap();
+        assert!(!was_input_handled_this_frame(&world, player));
+        assert_eq!(
+            *world.get::<&Position>(player).unwrap(),
+            Position::new(5, 5)
+        );
+
+        input.keys_down.remove("ArrowDown");
+        input.keys_down.insert("ArrowUp".to_string());
+        input_system
+            .call(&mut world, &input, &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(event_bus_manager.queued_len_of::<DoorOpened>(), 1);
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(world.get::<&Door>(door).unwrap().open);
+        assert_eq!(
+            *world.get::<&Position>(player).unwrap(),
+            Position::new(5, 5)
+        );
+    }
+
+    /// A player next to a door locked with key 7, holding `keys`.
+    fn locked_door_setup(keys: &[u32]) -> (World, EventBusManager, Entity, Entity) {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe::<DoorOpened>(Arc::new(DoorHandler));
+        event_bus_manager.subscribe::<DoorUnlocked>(Arc::new(DoorHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        world.get::<&mut Inventory>(player).unwrap().items = keys
+            .iter()
+            .map(|key_id| ItemKind::Key { key_id: *key_id })
+            .collect();
+        let door = spawn_locked_door(&mut world, Position::new(6, 5), 7);
+        (world, event_bus_manager, player, door)
+    }
+
+    fn game_log(world: &World) -> Vec<String> {
+        let mut game_log_query = world.query::<&GameLog>();
+        let (_id, game_log) = game_log_query.iter().next().unwrap();
+        game_log.lines().collect()
+    }
+
+    #[test]
+    fn test_reset_keeps_only_the_player_and_what_they_have() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(WorldResetHandler));
+        world.spawn((GameRng::seeded(1),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let dagger = spawn_item(&mut world, Position::new(5, 5), ItemKind::Dagger);
+        equip(&mut world, player, dagger).unwrap();
+        let goblin = spawn_goblin_at(&mut world, Position::new(8, 8), &mut GameRng::seeded(1));
+        let club = spawn_item(&mut world, Position::new(8, 8), ItemKind::Warhammer);
+        equip(&mut world, goblin, club).unwrap();
+        let for_sale = spawn_item_for_sale(&mut world, ItemKind::Sword);
+        let merchant = spawn_merchant(&mut world, Position::new(3, 3), vec![(for_sale, 10)]);
+        // Carrying something that's already gone somehow.
+        let lost = world.spawn(());
+        world
+            .get::<&mut Inventory>(player)
+            .unwrap()
+            .equipment
+            .push(lost);
+        world.despawn(lost).unwrap();
+        let burning_tiles = find_or_spawn_resource::<BurningTiles>(&mut world);
+        world
+            .get::<&mut BurningTiles>(burning_tiles)
+            .unwrap()
+            .positions
+            .insert(Position::new(9, 9));
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+        let old_index = input_system.spatial_index_entity_id.unwrap();
+
+        event_bus_manager.enqueue(Damage {
+            from: goblin,
+            to: player,
+            damage: 3,
+            kind: DamageKind::Physical,
+        });
+        event_bus_manager.enqueue_after(DeadEntity { entity: goblin }, 2);
+        input_system.on_reset(&mut world);
+        reset_world_keeping_player(&mut world, &mut event_bus_manager).unwrap();
+
+        assert_eq!(event_bus_manager.queued_len(), 0);
+        assert_eq!(event_bus_manager.scheduled_len(), 0);
+        assert_eq!(input_system.spatial_index_entity_id, None);
+        for gone in [goblin, club, merchant, for_sale, old_index] {
+            assert!(!world.contains(gone), "{gone:?}");
+        }
+        assert!(world.contains(player));
+        assert_eq!(world.get::<&Equipped>(dagger).unwrap().by, player);
+        assert!(
+            world
+                .get::<&Inventory>(player)
+                .unwrap()
+                .equipment
+                .is_empty()
+        );
+        assert!(
+            world
+                .get::<&BurningTiles>(burning_tiles)
+                .unwrap()
+                .positions
+                .is_empty()
+        );
+        assert!(world.get::<&Fov>(player).unwrap().revealed().is_empty());
+
+        // Whatever gets spawned next can have the old ids back, so nothing should still be
+        // pointing at them.
+        let rat = spawn_rat_at(&mut world, Position::new(6, 5), &mut GameRng::seeded(1));
+        input_system.init(&mut world, &mut event_bus_manager);
+        let index = input_system.spatial_index_entity_id.unwrap();
+        assert_eq!(
+            world
+                .get::<&SpatialIndex>(index)
+                .unwrap()
+                .at(&Position::new(6, 5)),
+            Some(rat)
+        );
+    }
+
+    #[test]
+    fn test_blood_stays_until_the_floor_changes() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe(Arc::new(WorldResetHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut GameRng::seeded(1));
+        let mut tile_decoration_system = TileDecorationSystem::default();
+        tile_decoration_system.init(&mut world, &mut event_bus_manager);
+        event_bus_manager.enqueue(Damage {
+            from: player,
+            to: goblin,
+            damage: 1,
+            kind: DamageKind::Physical,
+        });
+        event_bus_manager.enqueue(Damage {
+            from: player,
+            to: player,
+            damage: 1,
+            kind: DamageKind::Fire,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+        let tile_overlay = find_or_spawn_resource::<TileOverlay>(&mut world);
+        world
+            .get::<&mut TileOverlay>(tile_overlay)
+            .unwrap()
+            .set_overlay(Position::new(1, 1), TELEGRAPH_COLOR, Some(2));
+        let color_at = |world: &World, pos| {
+            world
+                .get::<&TileOverlay>(tile_overlay)
+                .unwrap()
+                .color_at(&pos)
+        };
+
+        for _ in 0..3 {
+            take_turn(&mut world);
+            tile_decoration_system
+                .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+        }
+        assert_eq!(color_at(&world, Position::new(6, 5)), Some(BLOOD_COLOR));
+        // Burns don't bleed.
+        assert_eq!(color_at(&world, Position::new(5, 5)), None);
+        assert_eq!(color_at(&world, Position::new(1, 1)), None);
+
+        reset_world_keeping_player(&mut world, &mut event_bus_manager).unwrap();
+        assert!(
+            world
+                .get::<&TileOverlay>(tile_overlay)
+                .unwrap()
+                .colored()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_windups_and_targeting_get_highlighted() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        spawn_player(&mut world, Position::new(5, 5));
+        let goblin = spawn_goblin_at(&mut world, Position::new(8, 5), &mut GameRng::seeded(1));
+        world
+            .insert_one(
+                goblin,
+                Telegraph {
+                    turns_remaining: 1,
+                    pending: Action::Attack(Position::new(7, 5)),
+                },
+            )
+            .unwrap();
+        let mut tile_decoration_system = TileDecorationSystem::default();
+        tile_decoration_system.init(&mut world, &mut event_bus_manager);
+        let mut update = |world: &mut World| {
+            tile_decoration_system
+                .call(world, &MockInput::default(), &mut event_bus_manager)
+                .unwrap();
+        };
+        let color_at = |world: &World, pos| {
+            let mut tile_overlay_query = world.query::<&TileOverlay>();
+            let (_id, tile_overlay) = tile_overlay_query.iter().next().unwrap();
+            tile_overlay.color_at(&pos)
+        };
+
+        update(&mut world);
+        assert_eq!(color_at(&world, Position::new(7, 5)), Some(TELEGRAPH_COLOR));
+
+        world.remove_one::<Telegraph>(goblin).unwrap();
+        let ui_mode = world.spawn((UiMode::Targeting {
+            max_range: 2,
+            purpose: TargetPurpose::Throw,
+            cursor: Position::new(5, 5),
+        },));
+        update(&mut world);
+        assert_eq!(
+            color_at(&world, Position::new(7, 5)),
+            Some(TARGETING_RANGE_COLOR)
+        );
+        assert_eq!(color_at(&world, Position::new(8, 5)), None);
+        assert_eq!(color_at(&world, Position::new(5, 5)), None);
+
+        *world.get::<&mut UiMode>(ui_mode).unwrap() = UiMode::Normal;
+        update(&mut world);
+        assert_eq!(color_at(&world, Position::new(7, 5)), None);
+    }
+
+    #[test]
+    fn test_unlocking_a_door_uses_up_the_key() {
+        let (mut world, mut event_bus_manager, player, door) = locked_door_setup(&[3, 7]);
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Move { dx: 1, dy: 0 }),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert!(was_input_handled_this_frame(&world, player));
+        assert!(world.get::<&Door>(door).unwrap().open);
+        assert!(world.get::<&Locked>(door).is_err());
+        assert_eq!(world.get::<&Renderable>(door).unwrap().color, Door::COLOR);
+        assert_eq!(
+            world.get::<&Inventory>(player).unwrap().items,
+            vec![ItemKind::Key { key_id: 3 }]
+        );
+        assert_eq!(game_log(&world), vec!["You unlock the door."]);
+    }
+
+    #[test]
+    fn test_locked_door_needs_the_right_key() {
+        let (mut world, mut event_bus_manager, player, door) = locked_door_setup(&[3]);
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Move { dx: 1, dy: 0 }),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert!(!was_input_handled_this_frame(&world, player));
+        assert!(!world.get::<&Door>(door).unwrap().open);
+        assert_eq!(world.get::<&Locked>(door).unwrap().key_id, 7);
+        assert!(world.get::<&Inventory>(player).unwrap().has_key(3));
+        assert_eq!(game_log(&world), vec!["Locked! You need a key."]);
+    }
+
+    #[test]
+    fn test_walking_onto_a_key_picks_it_up() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(PickupHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let key = spawn_item(&mut world, Position::new(6, 5), ItemKind::Key { key_id: 7 });
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Move { dx: 1, dy: 0 }),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert!(!world.contains(key));
+        assert!(world.get::<&Inventory>(player).unwrap().has_key(7));
+    }
+
+    #[test]
+    fn test_walking_onto_gold_puts_it_in_the_wallet() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(PickupHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let gold = spawn_gold(&mut world, Position::new(6, 5), 12);
+        let more_gold = spawn_gold(&mut world, Position::new(7, 5), 3);
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        for _ in 0..2 {
+            input_system
+                .call(
+                    &mut world,
+                    &scripted(SimInput::Move { dx: 1, dy: 0 }),
+                    &mut event_bus_manager,
+                )
+                .unwrap();
+            event_bus_manager.dispatch_all(&mut world);
+        }
+
+        assert!(!world.contains(gold));
+        assert!(!world.contains(more_gold));
+        assert_eq!(world.get::<&PlayerWallet>(player).unwrap().total, 15);
+        assert_eq!(
+            game_log(&world),
+            vec!["Picked up 12 gold.", "Picked up 3 gold."]
+        );
+    }
+
+    #[test]
+    fn test_score_adds_up_kills_gold_and_floors() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe::<DeadEntity>(Arc::new(ScoreTracker));
+        event_bus_manager.subscribe::<GoldCollected>(Arc::new(ScoreTracker));
+        event_bus_manager.subscribe::<DescendFloor>(Arc::new(ScoreTracker));
+        event_bus_manager.subscribe(Arc::new(PickupHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(1);
+        for x in [7, 8] {
+            let goblin = spawn_goblin_at(&mut world, Position::new(x, 5), &mut rng);
+            event_bus_manager.enqueue(DeadEntity { entity: goblin });
+        }
+        spawn_gold(&mut world, Position::new(6, 5), 25);
+        event_bus_manager.enqueue(EntityMoved {
+            entity: player,
+            from: Position::new(5, 5),
+            to: Position::new(6, 5),
+        });
+        event_bus_manager.enqueue(DescendFloor { player });
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert_eq!(
+            *world.get::<&Score>(player).unwrap(),
+            Score {
+                kills: 2,
+                gold_collected: 25,
+                floors_descended: 1,
+                total_score: 2 * 10 + 25 + 100,
+                turns_survived: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_run_stats_add_up_a_run() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(DamageHandler));
+        event_bus_manager.subscribe::<Damage>(Arc::new(RunStatsTracker));
+        event_bus_manager.subscribe::<DeadEntity>(Arc::new(RunStatsTracker));
+        event_bus_manager.subscribe(Arc::new(DeadCollector::default()));
+        event_bus_manager.subscribe::<DescendFloor>(Arc::new(RunStatsTracker));
+        event_bus_manager.subscribe::<UseItem>(Arc::new(RunStatsTracker));
+        let run_stats_id = world.spawn((RunStats::default(),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let mut rng = GameRng::seeded(1);
+        let goblins = [7, 8].map(|x| spawn_goblin_at(&mut world, Position::new(x, 5), &mut rng));
+        let rat = spawn_rat_at(&mut world, Position::new(9, 5), &mut rng);
+        let troll = spawn_troll(&mut world, Position::new(5, 7), &mut rng);
+
+        let hit = |from, to, damage| Damage {
+            from,
+            to,
+            damage,
+            kind: DamageKind::Physical,
+        };
+        event_bus_manager.enqueue(hit(player, goblins[0], 3));
+        // Trolls shrug off half of it.
+        event_bus_manager.enqueue(hit(player, troll, 10));
+        event_bus_manager.enqueue(hit(goblins[1], player, 2));
+        event_bus_manager.enqueue(hit(troll, player, 5));
+        // Healing isn't damage taken.
+        event_bus_manager.enqueue(hit(player, player, -5));
+        // Nor is anything that doesn't involve the player.
+        event_bus_manager.enqueue(hit(goblins[0], rat, 1));
+        for entity in [goblins[0], goblins[1], rat] {
+            event_bus_manager.enqueue(DeadEntity { entity });
+        }
+        for _ in 0..2 {
+            event_bus_manager.enqueue(DescendFloor { player });
+        }
+        event_bus_manager.enqueue(UseItem {
+            user: player,
+            item: ItemKind::ThrowingRock { damage: 2 },
+        });
+        event_bus_manager.enqueue(UseItem {
+            user: goblins[0],
+            item: ItemKind::Key { key_id: 1 },
+        });
+        event_bus_manager.dispatch_all(&mut world);
+
+        let run_stats = world.get::<&RunStats>(run_stats_id).unwrap();
+        assert_eq!(run_stats.damage_dealt, 3 + 5);
+        assert_eq!(run_stats.damage_taken, 2 + 5);
+        assert_eq!(
+            run_stats.kills_by_name,
+            BTreeMap::from([("Goblin".to_string(), 2), ("Rat".to_string(), 1)])
+        );
+        assert_eq!(run_stats.total_kills(), 3);
+        assert_eq!(run_stats.deepest_depth, 3);
+        assert_eq!(run_stats.items_used, 1);
+    }
+
+    #[test]
+    fn test_thrown_rocks_count_as_used() {
+        let mut world = World::new();
+        let event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(ThrowHandler));
+        event_bus_manager.subscribe::<UseItem>(Arc::new(RunStatsTracker));
+        let run_stats_id = world.spawn((RunStats::default(),));
+        spawn_player(&mut world, Position::new(5, 5));
+        find_or_build_spatial_index(&mut world);
+
+        event_bus_manager.enqueue(TargetSelected {
+            position: Position::new(8, 5),
+            purpose: TargetPurpose::Throw,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+
+        assert_eq!(world.get::<&RunStats>(run_stats_id).unwrap().items_used, 1);
+    }
+
+    #[test]
+    fn test_chests_only_give_up_their_loot_once() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(ChestHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        world.spawn((GameRng::seeded(1),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let chest_pos = Position::new(6, 6);
+        let chest = spawn_chest(&mut world, chest_pos.clone(), boss_chest_loot_table(), None);
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+        let loot_on_chest = |world: &World| -> Vec<ItemKind> {
+            world
+                .query::<(&Position, &ItemKind)>()
+                .iter()
+                .filter(|(_id, (pos, _kind))| **pos == chest_pos)
+                .map(|(_id, (_pos, kind))| *kind)
+                .collect()
+        };
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Interact),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(was_input_handled_this_frame(&world, player));
+        assert!(world.get::<&Chest>(chest).unwrap().opened);
+        assert_eq!(
+            world.get::<&Renderable>(chest).unwrap().glyph,
+            Chest::OPEN_GLYPH
+        );
+        let loot = loot_on_chest(&world);
+        // The boss chest always has a sword in it.
+        assert!(loot.contains(&ItemKind::Sword));
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Interact),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(!was_input_handled_this_frame(&world, player));
+        assert_eq!(loot_on_chest(&world), loot);
+        assert_eq!(
+            game_log(&world),
+            vec!["You open the chest.", "There's nothing here to open."]
+        );
+    }
+
+    #[test]
+    fn test_locked_chest_needs_its_key() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(ChestHandler));
+        world.spawn((GameRng::seeded(1),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let chest = spawn_chest(
+            &mut world,
+            Position::new(5, 4),
+            LootTable::default(),
+            Some(4),
+        );
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Interact),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(!was_input_handled_this_frame(&world, player));
+        assert!(!world.get::<&Chest>(chest).unwrap().opened);
+
+        world
+            .get::<&mut Inventory>(player)
+            .unwrap()
+            .items
+            .push(ItemKind::Key { key_id: 4 });
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Interact),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(was_input_handled_this_frame(&world, player));
+        assert!(world.get::<&Chest>(chest).unwrap().opened);
+        assert!(!world.get::<&Inventory>(player).unwrap().has_key(4));
+    }
+
+    #[test]
+    fn test_buying_and_selling_with_a_merchant() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe::<ItemBought>(Arc::new(TradeHandler));
+        event_bus_manager.subscribe::<ItemSold>(Arc::new(TradeHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        world.get::<&mut PlayerWallet>(player).unwrap().total = 45;
+        let dagger = spawn_item_for_sale(&mut world, ItemKind::Dagger);
+        world
+            .get::<&mut Inventory>(player)
+            .unwrap()
+            .equipment
+            .push(dagger);
+        let sword = spawn_item_for_sale(&mut world, ItemKind::Sword);
+        let merchant = spawn_merchant(&mut world, Position::new(5, 4), vec![(sword, 40)]);
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+        let mut targeting_system = TargetingSystem::default();
+        targeting_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Interact),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        assert_eq!(
+            ui_mode(&world),
+            UiMode::Trading {
+                vendor: merchant,
+                selling: false,
+            }
+        );
+        assert!(!was_input_handled_this_frame(&world, player));
+
+        let mut press = |key: &str, world: &mut World| {
+            targeting_system
+                .call(world, &MockInput::pressing(key), &mut event_bus_manager)
+                .unwrap();
+            event_bus_manager.dispatch_all(world);
+        };
+
+        press("Digit1", &mut world);
+        assert_eq!(world.get::<&PlayerWallet>(player).unwrap().total, 5);
+        assert_eq!(
+            world.get::<&Inventory>(player).unwrap().equipment,
+            vec![dagger, sword]
+        );
+        assert!(world.get::<&Vendor>(merchant).unwrap().inventory.is_empty());
+
+        press("KeyS", &mut world);
+        press("Digit1", &mut world);
+        assert_eq!(
+            world.get::<&PlayerWallet>(player).unwrap().total,
+            5 + ItemKind::Dagger.value() / 2
+        );
+        assert_eq!(
+            world.get::<&Vendor>(merchant).unwrap().inventory,
+            vec![(dagger, ItemKind::Dagger.value())]
+        );
+        // Selling goes back to buying afterwards.
+        assert_eq!(
+            ui_mode(&world),
+            UiMode::Trading {
+                vendor: merchant,
+                selling: false,
+            }
+        );
+        // Can't afford to buy it back.
+        press("Digit1", &mut world);
+        assert_eq!(world.get::<&Vendor>(merchant).unwrap().inventory.len(), 1);
+        assert!(!was_input_handled_this_frame(&world, player));
+
+        press("Escape", &mut world);
+        assert_eq!(ui_mode(&world), UiMode::Normal);
+    }
+
+    #[test]
+    fn test_interacting_with_just_a_door_closes_it() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe::<CloseDoor>(Arc::new(DoorHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let door = spawn_door(&mut world, Position::new(5, 4), true);
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Interact),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+        // Only one thing to do, so it just happens.
+        assert_eq!(ui_mode(&world), UiMode::Normal);
+        assert!(was_input_handled_this_frame(&world, player));
+        assert!(!world.get::<&Door>(door).unwrap().open);
+        assert!(world.get::<&BlocksTile>(door).is_ok());
+        assert_eq!(
+            world.get::<&Renderable>(door).unwrap().glyph,
+            Door::CLOSED_GLYPH
+        );
+        let mut spatial_index_query = world.query::<&SpatialIndex>();
+        let (_id, spatial_index) = spatial_index_query.iter().next().unwrap();
+        assert_eq!(spatial_index.at(&Position::new(5, 4)), Some(door));
+    }
+
+    #[test]
+    fn test_cannot_close_a_door_on_someone() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe::<CloseDoor>(Arc::new(DoorHandler));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let door = spawn_door(&mut world, Position::new(5, 4), true);
+        world.spawn((Position::new(5, 4), BlocksTile));
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Interact),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        assert_eq!(event_bus_manager.queued_len_of::<CloseDoor>(), 0);
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(!was_input_handled_this_frame(&world, player));
+        assert!(world.get::<&Door>(door).unwrap().open);
+    }
+
+    #[test]
+    fn test_picking_between_interactions() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe::<CloseDoor>(Arc::new(DoorHandler));
+        event_bus_manager.subscribe(Arc::new(ShoveHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        world.spawn((Map::new_bordered(10, 10),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let door = spawn_door(&mut world, Position::new(4, 5), true);
+        let goblin = spawn_goblin_at(&mut world, Position::new(6, 5), &mut GameRng::seeded(1));
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+        let mut targeting_system = TargetingSystem::default();
+        targeting_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Interact),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        assert_eq!(
+            ui_mode(&world),
+            UiMode::Choosing {
+                options: vec![
+                    InteractionOption {
+                        interaction: Interaction::CloseDoor,
+                        target: door,
+                    },
+                    InteractionOption {
+                        interaction: Interaction::Shove,
+                        target: goblin,
+                    },
+                ],
+            }
+        );
+        assert!(!was_input_handled_this_frame(&world, player));
+
+        // Nothing third on the list, so nothing happens.
+        targeting_system
+            .call(
+                &mut world,
+                &MockInput::pressing("Digit3"),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        assert!(matches!(ui_mode(&world), UiMode::Choosing { .. }));
+
+        targeting_system
+            .call(
+                &mut world,
+                &MockInput::pressing("Digit2"),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(ui_mode(&world), UiMode::Normal);
+        assert!(was_input_handled_this_frame(&world, player));
+        assert!(world.get::<&Door>(door).unwrap().open);
+        assert_eq!(
+            *world.get::<&Position>(goblin).unwrap(),
+            Position::new(7, 5)
+        );
+        assert_eq!(game_log(&world), vec!["The Goblin stumbles back."]);
+    }
+
+    #[test]
+    fn test_shoving_into_a_wall_fails() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        event_bus_manager.subscribe(Arc::new(ShoveHandler));
+        event_bus_manager.subscribe(Arc::new(GameLogHandler));
+        world.spawn((GameLog::default(),));
+        world.spawn((Map::new_bordered(10, 10),));
+        let player = spawn_player(&mut world, Position::new(2, 5));
+        let goblin = spawn_goblin_at(&mut world, Position::new(1, 5), &mut GameRng::seeded(1));
+        let mut input_system = InputSystem::default();
+        input_system.init(&mut world, &mut event_bus_manager);
+
+        input_system
+            .call(
+                &mut world,
+                &scripted(SimInput::Interact),
+                &mut event_bus_manager,
+            )
+            .unwrap();
+        event_bus_manager.dispatch_all(&mut world);
+        assert!(!was_input_handled_this_frame(&world, player));
+        assert_eq!(
+            *world.get::<&Position>(goblin).unwrap(),
+            Position::new(1, 5)
+        );
+        assert_eq!(game_log(&world), vec!["There's a wall in the way."]);
+
+        // Even if it gets sent anyway, nothing ends up in the wall.
+        event_bus_manager.enqueue(Shove {
+            by: player,
+            target: goblin,
+        });
+        event_bus_manager.dispatch_all(&mut world);
+        assert_eq!(
+            *world.get::<&Position>(goblin).unwrap(),
+            Position::new(1, 5)
+        );
+    }
+
+    #[test]
+    fn test_merchants_restock_when_going_down_a_floor() {
+        let mut world = World::new();
+        let mut event_bus_manager = EventBusManager::new();
+        world.spawn((GameRng::seeded(1),));
+        let player = spawn_player(&mut world, Position::new(5, 5));
+        let merchant = spawn_merchant(&mut world, Position::new(5, 4), Vec::new());
+        let mut restock_system = VendorRestockSystem::default();
+        restock_system.init(&mut world, &mut event_bus_manager);
+        let stock = |world: &World| world.get::<&Vendor>(merchant).unwrap().inventory.len();
+
+        restock_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(stock(&world), 0);
 
-    fn get_name(&self) -> String {
-        "DamageSystem".to_string()
+        world.get::<&mut DungeonDepth>(player).unwrap().0 = 2;
+        restock_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(stock(&world), RESTOCK_ITEMS);
+        // Only once per floor.
+        restock_system
+            .call(&mut world, &MockInput::default(), &mut event_bus_manager)
+            .unwrap();
+        assert_eq!(stock(&world), RESTOCK_ITEMS);
     }
 }